@@ -0,0 +1,275 @@
+// https://www.nesdev.org/wiki/APU
+//
+// No sound is produced yet. The APU keeps the state games can observe
+// through $4015: the length counters, the DMC byte counter and the two IRQ
+// flags, clocked by the frame counter. The DMC does fetch its samples,
+// since the DMA that does it stalls the CPU.
+
+/// Length counter loads, indexed by bits 3-7 of $4003/$4007/$400B/$400F
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, //
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+/// CPU cycles per DMC output bit, indexed by the low nibble of $4010 (NTSC)
+const DMC_RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+/// A frame counter step: the CPU cycle into the sequence it happens on,
+/// whether it clocks the length counters and whether it raises the IRQ.
+/// The last step of a sequence starts the next one.
+type FrameStep = (u64, bool, bool);
+
+/// The IRQ flag is raised on the last three cycles of a four-step sequence
+const FOUR_STEP_SEQUENCE: [FrameStep; 4] = [
+    (14913, true, false),
+    (29828, false, true),
+    (29829, true, true),
+    (29830, false, true),
+];
+const FIVE_STEP_SEQUENCE: [FrameStep; 3] = [
+    (14913, true, false),
+    (37281, true, false),
+    (37282, false, false),
+];
+
+const PULSE_1: usize = 0;
+const PULSE_2: usize = 1;
+const TRIANGLE: usize = 2;
+const NOISE: usize = 3;
+
+bitflags! {
+    /// $4015 as read back by the CPU. Bit 5 is not driven.
+    #[derive(Debug, Clone, Copy)]
+    pub struct ApuStatus: u8 {
+        const PULSE_1     = 0b00000001;
+        const PULSE_2     = 0b00000010;
+        const TRIANGLE    = 0b00000100;
+        const NOISE       = 0b00001000;
+        const DMC         = 0b00010000;
+        const FRAME_IRQ   = 0b01000000;
+        const DMC_IRQ     = 0b10000000;
+    }
+}
+
+#[derive(Default)]
+pub struct Apu {
+    /// Last value written to each of $4000-$4017
+    registers: [u8; 0x18],
+    /// Pulse 1, pulse 2, triangle and noise
+    length_counters: [u8; 4],
+    /// Channels enabled through $4015, in `ApuStatus` bit order
+    enabled: u8,
+
+    /// Address of the next sample byte to fetch
+    dmc_address: u16,
+    dmc_bytes_left: u16,
+    /// The fetched sample byte waiting to be played, if any
+    dmc_buffer: Option<u8>,
+    /// CPU cycles until the DMC finishes its current output bit
+    dmc_timer: u16,
+    /// Output bits left in the current byte
+    dmc_bits_left: u8,
+    dmc_irq: bool,
+
+    five_step_mode: bool,
+    frame_irq_inhibit: bool,
+    frame_irq: bool,
+    /// Index of the next frame counter step in the current sequence
+    frame_step: usize,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Apu::default()
+    }
+
+    /// Handles a CPU write to $4000-$4013, $4015 or $4017
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        let index = (addr - 0x4000) as usize;
+        if index >= self.registers.len() {
+            return;
+        }
+        self.registers[index] = data;
+
+        match addr {
+            0x4003 => self.load_length_counter(PULSE_1, data),
+            0x4007 => self.load_length_counter(PULSE_2, data),
+            0x400B => self.load_length_counter(TRIANGLE, data),
+            0x400F => self.load_length_counter(NOISE, data),
+            0x4010 if data & 0b1000_0000 == 0 => self.dmc_irq = false,
+            0x4015 => {
+                self.enabled = data & 0b0001_1111;
+                for (channel, counter) in self.length_counters.iter_mut().enumerate() {
+                    if self.enabled & (1 << channel) == 0 {
+                        *counter = 0;
+                    }
+                }
+                if self.enabled & ApuStatus::DMC.bits() == 0 {
+                    self.dmc_bytes_left = 0;
+                } else if self.dmc_bytes_left == 0 {
+                    self.restart_dmc();
+                }
+                self.dmc_irq = false;
+            }
+            0x4017 => {
+                self.five_step_mode = data & 0b1000_0000 != 0;
+                self.frame_irq_inhibit = data & 0b0100_0000 != 0;
+                if self.frame_irq_inhibit {
+                    self.frame_irq = false;
+                }
+                self.frame_step = 0;
+                // switching to five steps clocks the counters right away
+                if self.five_step_mode {
+                    self.clock_length_counters();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Reads $4015. This acknowledges the frame IRQ but not the DMC one.
+    pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_irq = false;
+        status.bits()
+    }
+
+    /// $4015 without acknowledging anything, for debuggers
+    pub fn peek_status(&self) -> ApuStatus {
+        let mut status = ApuStatus::empty();
+        status.set(ApuStatus::PULSE_1, self.length_counters[PULSE_1] > 0);
+        status.set(ApuStatus::PULSE_2, self.length_counters[PULSE_2] > 0);
+        status.set(ApuStatus::TRIANGLE, self.length_counters[TRIANGLE] > 0);
+        status.set(ApuStatus::NOISE, self.length_counters[NOISE] > 0);
+        status.set(ApuStatus::DMC, self.dmc_bytes_left > 0);
+        status.set(ApuStatus::FRAME_IRQ, self.frame_irq);
+        status.set(ApuStatus::DMC_IRQ, self.dmc_irq);
+        status
+    }
+
+    /// Last value written to an APU register, for debuggers
+    pub fn register(&self, addr: u16) -> u8 {
+        self.registers[(addr - 0x4000) as usize]
+    }
+
+    pub fn frame_irq(&self) -> bool {
+        self.frame_irq
+    }
+
+    pub fn dmc_irq(&self) -> bool {
+        self.dmc_irq
+    }
+
+    /// Runs the DMC for `cycles` CPU cycles. The frame counter isn't
+    /// clocked here: it only acts on a few cycles per frame, so the Bus
+    /// schedules `frame_counter_step` for those instead.
+    pub fn tick(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            self.tick_dmc();
+        }
+    }
+
+    fn frame_sequence(&self) -> &'static [FrameStep] {
+        if self.five_step_mode {
+            &FIVE_STEP_SEQUENCE
+        } else {
+            &FOUR_STEP_SEQUENCE
+        }
+    }
+
+    /// CPU cycles from the previous frame counter step, or from the start
+    /// of the sequence, to the next one
+    pub fn frame_step_delay(&self) -> u64 {
+        let sequence = self.frame_sequence();
+        match self.frame_step {
+            0 => sequence[0].0,
+            step => sequence[step].0 - sequence[step - 1].0,
+        }
+    }
+
+    /// Performs the frame counter step that is due
+    pub fn frame_counter_step(&mut self) {
+        let sequence = self.frame_sequence();
+        let (_, clocks_length, raises_irq) = sequence[self.frame_step];
+        if clocks_length {
+            self.clock_length_counters();
+        }
+        if raises_irq && !self.frame_irq_inhibit {
+            self.frame_irq = true;
+        }
+        self.frame_step = (self.frame_step + 1) % sequence.len();
+    }
+
+    /// Plays one output bit every rate period. Starting a new byte empties
+    /// the sample buffer, which makes the DMC ask for the next fetch.
+    fn tick_dmc(&mut self) {
+        if self.dmc_timer > 1 {
+            self.dmc_timer -= 1;
+            return;
+        }
+        self.dmc_timer = DMC_RATE_TABLE[(self.registers[0x10] & 0x0F) as usize];
+
+        self.dmc_bits_left = self.dmc_bits_left.saturating_sub(1);
+        if self.dmc_bits_left == 0 {
+            self.dmc_bits_left = 8;
+            self.dmc_buffer.take();
+        }
+    }
+
+    /// Address of the sample byte the DMC needs fetched, while its buffer is
+    /// empty and the sample has bytes left
+    pub fn dmc_dma_request(&self) -> Option<u16> {
+        (self.dmc_buffer.is_none() && self.dmc_bytes_left > 0).then_some(self.dmc_address)
+    }
+
+    /// Fills the sample buffer with the byte a DMC DMA fetched. The last
+    /// byte of a sample loops it or raises the DMC IRQ.
+    pub fn dmc_dma_complete(&mut self, data: u8) {
+        self.dmc_buffer = Some(data);
+        // the address wraps from $FFFF back to $8000
+        self.dmc_address = self.dmc_address.checked_add(1).unwrap_or(0x8000);
+        self.dmc_bytes_left -= 1;
+        if self.dmc_bytes_left > 0 {
+            return;
+        }
+
+        let flags = self.registers[0x10];
+        if flags & 0b0100_0000 != 0 {
+            self.restart_dmc();
+        } else if flags & 0b1000_0000 != 0 {
+            self.dmc_irq = true;
+        }
+    }
+
+    fn restart_dmc(&mut self) {
+        // $4012 gives the start in units of 64 bytes from $C000, and $4013
+        // the length in units of 16 bytes, plus one
+        self.dmc_address = 0xC000 + self.registers[0x12] as u16 * 64;
+        self.dmc_bytes_left = self.registers[0x13] as u16 * 16 + 1;
+    }
+
+    fn load_length_counter(&mut self, channel: usize, data: u8) {
+        if self.enabled & (1 << channel) != 0 {
+            self.length_counters[channel] = LENGTH_TABLE[(data >> 3) as usize];
+        }
+    }
+
+    fn clock_length_counters(&mut self) {
+        for channel in [PULSE_1, PULSE_2, TRIANGLE, NOISE] {
+            if !self.length_counter_halted(channel) {
+                self.length_counters[channel] = self.length_counters[channel].saturating_sub(1);
+            }
+        }
+    }
+
+    /// The halt flag doubles as the envelope loop flag, or as the linear
+    /// counter control flag on the triangle
+    fn length_counter_halted(&self, channel: usize) -> bool {
+        match channel {
+            TRIANGLE => self.registers[0x08] & 0b1000_0000 != 0,
+            _ => self.registers[channel * 4] & 0b0010_0000 != 0,
+        }
+    }
+}
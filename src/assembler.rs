@@ -0,0 +1,394 @@
+use crate::opcodes::*;
+use std::collections::HashMap;
+
+/// Where [`assemble`] places code, the same address `Cpu::load` uses
+pub const DEFAULT_ORIGIN: u16 = 0x8600;
+
+/// Assembles 6502 source placed at [`DEFAULT_ORIGIN`].
+///
+/// ```text
+///         LDX #$08
+/// loop:   DEX          ; labels end with a colon
+///         STX $0200,X
+///         BNE loop
+///         BRK
+/// ```
+///
+/// Numbers are `$hex`, `%binary` or decimal. A number written with at most
+/// two hex digits picks the zero page form when there is one; labels always
+/// use the absolute form. `.byte` and `.word` emit comma separated data.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    assemble_at(DEFAULT_ORIGIN, source)
+}
+
+/// Assembles 6502 source placed at `origin`, which matters for labels and
+/// branch offsets.
+pub fn assemble_at(origin: u16, source: &str) -> Result<Vec<u8>, String> {
+    let mut labels = HashMap::new();
+    let mut items = vec![];
+    let mut pc = origin;
+
+    // first pass: pick every opcode, which fixes every address
+    for (index, line) in source.lines().enumerate() {
+        let line_no = index + 1;
+        let error = |msg: String| format!("line {}: {}", line_no, msg);
+
+        let mut text = line.split(';').next().unwrap().trim();
+        if let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if !is_identifier(label) {
+                return Err(error(format!("invalid label `{}`", label)));
+            }
+            if labels.insert(label.to_string(), pc).is_some() {
+                return Err(error(format!("label `{}` is defined twice", label)));
+            }
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let item = parse_line(text).map_err(error)?;
+        pc = pc.wrapping_add(item.len());
+        items.push((line_no, pc, item));
+    }
+
+    // second pass: resolve labels and emit
+    let mut program = vec![];
+    for (line_no, next_pc, item) in items {
+        let error = |msg: String| format!("line {}: {}", line_no, msg);
+        let resolve = |expr: &Expr| expr.resolve(&labels).map_err(error);
+
+        match item {
+            Item::Bytes(values) => {
+                for value in &values {
+                    program.push(to_byte(resolve(value)?).map_err(error)?);
+                }
+            }
+            Item::Words(values) => {
+                for value in &values {
+                    program.extend_from_slice(&resolve(value)?.to_le_bytes());
+                }
+            }
+            Item::Instruction(ops, None) => program.push(ops.code),
+            Item::Instruction(ops, Some(value)) => {
+                let value = resolve(&value)?;
+                program.push(ops.code);
+                match (ops.len, ops.mode) {
+                    (_, AddressingMode::Relative) => {
+                        let offset = value.wrapping_sub(next_pc) as i16;
+                        if !(-128..=127).contains(&offset) {
+                            return Err(error(format!("branch to ${:04X} is out of range", value)));
+                        }
+                        program.push(offset as u8);
+                    }
+                    (2, _) => program.push(to_byte(value).map_err(error)?),
+                    _ => program.extend_from_slice(&value.to_le_bytes()),
+                }
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+enum Item {
+    Instruction(&'static Opcode, Option<Expr>),
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+}
+
+impl Item {
+    fn len(&self) -> u16 {
+        match self {
+            Item::Instruction(ops, _) => ops.len as u16,
+            Item::Bytes(values) => values.len() as u16,
+            Item::Words(values) => values.len() as u16 * 2,
+        }
+    }
+}
+
+enum Expr {
+    /// `wide` when written with more digits than fit in a byte, like `$0010`
+    Number {
+        value: u16,
+        wide: bool,
+    },
+    Label(String),
+}
+
+impl Expr {
+    fn parse(text: &str) -> Result<Expr, String> {
+        let (digits, radix, byte_digits) = if let Some(hex) = text.strip_prefix('$') {
+            (hex, 16, 2)
+        } else if let Some(bin) = text.strip_prefix('%') {
+            (bin, 2, 8)
+        } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+            (text, 10, 3)
+        } else if is_identifier(text) {
+            return Ok(Expr::Label(text.to_string()));
+        } else {
+            return Err(format!("invalid operand `{}`", text));
+        };
+
+        let value =
+            u16::from_str_radix(digits, radix).map_err(|_| format!("invalid number `{}`", text))?;
+        Ok(Expr::Number {
+            value,
+            wide: value > 0xFF || digits.len() > byte_digits,
+        })
+    }
+
+    fn is_zero_page(&self) -> bool {
+        matches!(self, Expr::Number { wide: false, .. })
+    }
+
+    fn resolve(&self, labels: &HashMap<String, u16>) -> Result<u16, String> {
+        match self {
+            Expr::Number { value, .. } => Ok(*value),
+            Expr::Label(name) => labels
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("undefined label `{}`", name)),
+        }
+    }
+}
+
+/// The operand forms as written, before an opcode is picked for them
+enum Operand {
+    Implied,
+    Accumulator,
+    Immediate(Expr),
+    Address(Expr, Option<char>),
+    Indirect(Expr),
+    IndirectX(Expr),
+    IndirectY(Expr),
+}
+
+impl Operand {
+    fn parse(text: &str) -> Result<Operand, String> {
+        let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let upper = text.to_ascii_uppercase();
+
+        if text.is_empty() {
+            return Ok(Operand::Implied);
+        }
+        if upper == "A" {
+            return Ok(Operand::Accumulator);
+        }
+        if let Some(value) = text.strip_prefix('#') {
+            return Ok(Operand::Immediate(Expr::parse(value)?));
+        }
+        if let Some(inner) = text.strip_prefix('(') {
+            let inner_len = inner.len();
+            return if upper.ends_with(",X)") {
+                Ok(Operand::IndirectX(Expr::parse(&inner[..inner_len - 3])?))
+            } else if upper.ends_with("),Y") {
+                Ok(Operand::IndirectY(Expr::parse(&inner[..inner_len - 3])?))
+            } else if let Some(pointer) = inner.strip_suffix(')') {
+                Ok(Operand::Indirect(Expr::parse(pointer)?))
+            } else {
+                Err(format!("unbalanced parenthesis in `{}`", text))
+            };
+        }
+        if upper.ends_with(",X") || upper.ends_with(",Y") {
+            let (addr, index) = text.split_at(text.len() - 2);
+            let index = index.chars().last().unwrap().to_ascii_uppercase();
+            return Ok(Operand::Address(Expr::parse(addr)?, Some(index)));
+        }
+        Ok(Operand::Address(Expr::parse(&text)?, None))
+    }
+}
+
+fn parse_line(text: &str) -> Result<Item, String> {
+    let (name, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let rest = rest.trim();
+
+    if name.starts_with('.') {
+        let values = rest
+            .split(',')
+            .map(|value| Expr::parse(value.trim()))
+            .collect::<Result<Vec<_>, _>>()?;
+        return match name.to_ascii_lowercase().as_str() {
+            ".byte" => Ok(Item::Bytes(values)),
+            ".word" => Ok(Item::Words(values)),
+            _ => Err(format!("unknown directive `{}`", name)),
+        };
+    }
+
+    let mnemonic: Mnemonic = name.parse()?;
+    if !CPU_OPS_CODES
+        .iter()
+        .any(|ops| ops.mnemonic == mnemonic && !ops.unofficial)
+    {
+        return Err(format!("`{}` is not an official instruction", mnemonic));
+    }
+
+    let find = |mode: AddressingMode| {
+        CPU_OPS_CODES
+            .iter()
+            .find(|ops| ops.mnemonic == mnemonic && ops.mode == mode && !ops.unofficial)
+    };
+    // zero page when the operand allows it and the opcode exists, else absolute
+    let find_address = |expr: &Expr, zero_page: AddressingMode, absolute: AddressingMode| {
+        expr.is_zero_page()
+            .then(|| find(zero_page))
+            .flatten()
+            .or_else(|| find(absolute))
+    };
+
+    let operand = Operand::parse(rest)?;
+    let (ops, value) = match operand {
+        // `ASL` is accepted as well as `ASL A`
+        Operand::Implied => (
+            find(AddressingMode::NoneAddressing).or_else(|| find(AddressingMode::Accumulator)),
+            None,
+        ),
+        Operand::Accumulator => (find(AddressingMode::Accumulator), None),
+        Operand::Immediate(expr) => (find(AddressingMode::Immediate), Some(expr)),
+        Operand::Address(expr, None) => {
+            let ops = find(AddressingMode::Relative).or_else(|| {
+                find_address(&expr, AddressingMode::ZeroPage, AddressingMode::Absolute)
+            });
+            (ops, Some(expr))
+        }
+        Operand::Address(expr, Some('X')) => {
+            let ops = find_address(
+                &expr,
+                AddressingMode::ZeroPage_X,
+                AddressingMode::Absolute_X,
+            );
+            (ops, Some(expr))
+        }
+        Operand::Address(expr, Some(_)) => {
+            let ops = find_address(
+                &expr,
+                AddressingMode::ZeroPage_Y,
+                AddressingMode::Absolute_Y,
+            );
+            (ops, Some(expr))
+        }
+        Operand::Indirect(expr) => (find(AddressingMode::Indirect), Some(expr)),
+        Operand::IndirectX(expr) => (find(AddressingMode::Indirect_X), Some(expr)),
+        Operand::IndirectY(expr) => (find(AddressingMode::Indirect_Y), Some(expr)),
+    };
+
+    let ops = ops.ok_or_else(|| format!("`{}` does not take operand `{}`", mnemonic, rest))?;
+    Ok(Item::Instruction(ops, value))
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn to_byte(value: u16) -> Result<u8, String> {
+    u8::try_from(value).map_err(|_| format!("${:04X} does not fit in a byte", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_addressing_mode() {
+        let source = "
+            CLC
+            ASL A
+            ASL
+            LDA #$10
+            LDA $10
+            LDA $10,X
+            LDX $10,Y
+            LDA $1234
+            LDA $1234,X
+            LDA $1234,Y
+            JMP ($1234)
+            LDA ($10,X)
+            LDA ($10),Y
+            BNE $8000
+        ";
+        #[rustfmt::skip]
+        let expected = vec![
+            0x18,
+            0x0A,
+            0x0A,
+            0xA9, 0x10,
+            0xA5, 0x10,
+            0xB5, 0x10,
+            0xB6, 0x10,
+            0xAD, 0x34, 0x12,
+            0xBD, 0x34, 0x12,
+            0xB9, 0x34, 0x12,
+            0x6C, 0x34, 0x12,
+            0xA1, 0x10,
+            0xB1, 0x10,
+            0xD0, 0xE3,
+        ];
+        assert_eq!(assemble_at(0x8000, source), Ok(expected));
+    }
+
+    #[test]
+    fn zero_page_only_for_short_numbers() {
+        assert_eq!(assemble("LDA $10"), Ok(vec![0xA5, 0x10]));
+        assert_eq!(assemble("LDA 16"), Ok(vec![0xA5, 0x10]));
+        assert_eq!(assemble("LDA %00010000"), Ok(vec![0xA5, 0x10]));
+        assert_eq!(assemble("LDA $0010"), Ok(vec![0xAD, 0x10, 0x00]));
+        assert_eq!(assemble("LDA 300"), Ok(vec![0xAD, 0x2C, 0x01]));
+        // there is no zero page,Y form of LDA
+        assert_eq!(assemble("LDA $10,Y"), Ok(vec![0xB9, 0x10, 0x00]));
+        // labels are absolute even when they land in the zero page
+        assert_eq!(
+            assemble_at(0x0000, "LDA data\ndata: .byte 7"),
+            Ok(vec![0xAD, 0x03, 0x00, 0x07])
+        );
+    }
+
+    #[test]
+    fn labels_can_be_used_before_they_are_defined() {
+        let source = "
+            start:  JMP end
+                    BEQ end
+                    JSR start
+            end:    BRK
+        ";
+        #[rustfmt::skip]
+        let expected = vec![
+            0x4C, 0x08, 0x80,
+            0xF0, 0x03,
+            0x20, 0x00, 0x80,
+            0x00,
+        ];
+        assert_eq!(assemble_at(0x8000, source), Ok(expected));
+    }
+
+    #[test]
+    fn branches_reach_128_bytes_back_and_127_forward() {
+        assert_eq!(assemble_at(0x8000, "BNE $7F82"), Ok(vec![0xD0, 0x80]));
+        assert_eq!(assemble_at(0x8000, "BNE $8081"), Ok(vec![0xD0, 0x7F]));
+        assert_eq!(
+            assemble_at(0x8000, "BNE $7F81"),
+            Err("line 1: branch to $7F81 is out of range".to_string())
+        );
+        assert_eq!(
+            assemble_at(0x8000, "BNE $8082"),
+            Err("line 1: branch to $8082 is out of range".to_string())
+        );
+    }
+
+    #[test]
+    fn data_directives() {
+        let source = "
+            table:  .byte $01, 2, %11, table
+                    .word $1234, table
+        ";
+        assert_eq!(
+            assemble_at(0x0040, source),
+            Ok(vec![0x01, 0x02, 0x03, 0x40, 0x34, 0x12, 0x40, 0x00])
+        );
+        assert_eq!(
+            assemble(".byte $100"),
+            Err("line 1: $0100 does not fit in a byte".to_string())
+        );
+    }
+}
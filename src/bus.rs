@@ -1,7 +1,22 @@
+use rand::Rng;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
 use crate::{
+    apu::Apu,
+    cheats::Cheats,
+    clock::{Clock, ClockEvent, NTSC_CPU_DIVIDER},
     core::Mem,
+    coverage::{Coverage, CoverageFlags},
+    expansion::{ExpansionDevice, EXPANSION_DATA_MASK},
+    frame::PixelFormat,
     joypad::Joypad,
-    ppu::{NesPPU, PPU},
+    mapper::{self, SharedMapper},
+    ppu::{Layer, NesPPU, RenderMode, PPU},
+    render::Palette,
     rom::*,
 };
 
@@ -36,100 +51,803 @@ const RAM: u16 = 0x0000;
 const RAM_MIRRORS_END: u16 = 0x1FFF;
 const PPU_REGISTERS: u16 = 0x2000;
 const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+const POST_RENDER_SCANLINE: u16 = 240;
+
+bitflags! {
+    /// Devices that can pull the shared /IRQ line low. The line stays
+    /// asserted while any source is set, and each device acknowledges its
+    /// own bit.
+    #[derive(Debug, Clone, Copy)]
+    pub struct IrqSource: u8 {
+        const APU_FRAME_COUNTER = 0b00000001;
+        const APU_DMC           = 0b00000010;
+        const MAPPER            = 0b00000100;
+    }
+}
+
+/// Sees a CPU read in its range along with the value read, and can make
+/// the CPU see another value instead by returning `Some`
+pub type ReadHook<'call> = Box<dyn FnMut(u16, u8) -> Option<u8> + 'call>;
+
+/// Sees a CPU write in its range, and can drop it by returning `false`.
+/// A dropped write still drives the open bus.
+pub type WriteHook<'call> = Box<dyn FnMut(u16, u8) -> bool + 'call>;
+
+/// Sees the finished frame at the start of vblank, along with both
+/// controllers to update
+pub type GameloopCallback<'call> = Box<dyn FnMut(&NesPPU, &mut Joypad, &mut Joypad) + 'call>;
+
+/// Sees the PPU as it starts each scanline, along with the line number
+pub type ScanlineCallback<'call> = Box<dyn FnMut(u16, &NesPPU) + 'call>;
+
+/// Identifies a registered hook so it can be removed again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HookId(usize);
+
+bitflags! {
+    /// The kinds of access a watchpoint triggers on
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct WatchKind: u8 {
+        const READ    = 0b001;
+        const WRITE   = 0b010;
+        const EXECUTE = 0b100;
+    }
+}
+
+/// The access that triggered a watchpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchpointHit {
+    pub id: HookId,
+    pub kind: WatchKind,
+    pub addr: u16,
+    /// The byte read, written or about to be executed
+    pub value: u8,
+    /// Address of the instruction that made the access
+    pub pc: u16,
+}
+
+impl fmt::Display for WatchpointHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let access = if self.kind.contains(WatchKind::EXECUTE) {
+            "execute"
+        } else if self.kind.contains(WatchKind::WRITE) {
+            "write"
+        } else {
+            "read"
+        };
+        write!(
+            f,
+            "{} of ${:02X} at ${:04X} by instruction at ${:04X}",
+            access, self.value, self.addr, self.pc
+        )
+    }
+}
+
+/// What the 2 KiB of work RAM holds at power-on. Real consoles come up with
+/// mostly unpredictable contents, which some games accidentally depend on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RamInitPattern {
+    Zeroed,
+    Filled(u8),
+    /// Alternating runs of four $00 and four $FF bytes, as seen on many consoles
+    Striped,
+    Random,
+}
 
 pub struct Bus<'call> {
     pub cpu_vram: [u8; 2048],
-    prg_rom: Vec<u8>,
+    mapper: SharedMapper,
     ppu: NesPPU,
+    apu: Apu,
+    clock: Clock,
 
+    /// CPU cycles since power-on, overclock cycles included
     cycles: usize,
-    gameloop_callback: Box<dyn FnMut(&NesPPU, &mut Joypad) + 'call>,
+    irq_line: IrqSource,
+    oam_dma_page: Option<u8>,
+    overclock_scanlines: u16,
+    coverage: Option<Coverage>,
+    /// PPU dots of the current frame's overclock still to run
+    overclock_dots_left: u32,
+    /// Last value driven on the CPU data bus. Reads nothing answers (unmapped
+    /// space, undriven register bits) see this instead.
+    open_bus: u8,
+    gameloop_callback: GameloopCallback<'call>,
+    scanline_callback: Option<ScanlineCallback<'call>>,
     joypad1: Joypad,
+    joypad2: Joypad,
+    expansion: Option<Box<dyn ExpansionDevice + 'call>>,
+    read_hooks: Vec<(HookId, RangeInclusive<u16>, ReadHook<'call>)>,
+    write_hooks: Vec<(HookId, RangeInclusive<u16>, WriteHook<'call>)>,
+    next_hook_id: usize,
+    cheats: Cheats,
+    watchpoints: Vec<(HookId, RangeInclusive<u16>, WatchKind)>,
+    /// First watchpoint hit since the last `take_watchpoint_hit`
+    watchpoint_hit: Option<WatchpointHit>,
+    /// Address of the instruction the CPU is executing
+    instruction_pc: u16,
+    /// Keeps the reads the CPU repeats during a DMC DMA from clocking the
+    /// controllers
+    dmc_read_glitch_mitigation: bool,
 }
 
 impl<'a> Bus<'a> {
-    pub fn new<'call, F>(rom: Rom, gameloop_callback: F) -> Bus<'call>
+    /// Fails when the ROM needs a mapper that isn't implemented
+    pub fn new<'call, F>(rom: Rom, gameloop_callback: F) -> Result<Bus<'call>, RomError>
     where
-        F: FnMut(&NesPPU, &mut Joypad) + 'call,
+        F: FnMut(&NesPPU, &mut Joypad, &mut Joypad) + 'call,
     {
-        let ppu = NesPPU::new(rom.chr_rom, rom.screen_mirroring);
+        let region = rom.region;
+        let mapper = Bus::plug_in(rom)?;
+        let mut ppu = NesPPU::new(mapper.clone());
+        ppu.set_region(region);
 
-        Bus {
+        let mut bus = Bus {
             cpu_vram: [0; 2048],
-            prg_rom: rom.prg_rom,
+            mapper,
             ppu: ppu,
+            apu: Apu::new(),
+            clock: Clock::new(),
             cycles: 0,
-            gameloop_callback: Box::from(gameloop_callback),
+            irq_line: IrqSource::empty(),
+            oam_dma_page: None,
+            overclock_scanlines: 0,
+            coverage: None,
+            overclock_dots_left: 0,
+            open_bus: 0,
+            gameloop_callback: Box::new(gameloop_callback),
+            scanline_callback: None,
             joypad1: Joypad::new(),
+            joypad2: Joypad::new(),
+            expansion: None,
+            read_hooks: vec![],
+            write_hooks: vec![],
+            next_hook_id: 0,
+            cheats: Cheats::new(),
+            watchpoints: vec![],
+            watchpoint_hit: None,
+            instruction_pc: 0,
+            dmc_read_glitch_mitigation: false,
+        };
+        bus.schedule_frame_counter();
+        Ok(bus)
+    }
+
+    /// Builds the cartridge's mapper, with the trainer loaded at $7000
+    fn plug_in(mut rom: Rom) -> Result<SharedMapper, RomError> {
+        let trainer = rom.trainer.take();
+        let mapper = mapper::from_rom(rom)?;
+        if let Some(trainer) = trainer {
+            // $7000 is 4 KiB into PRG-RAM
+            let mut mapper = mapper.borrow_mut();
+            if let Some(ram) = mapper.prg_ram_mut().get_mut(0x1000..0x1000 + trainer.len()) {
+                ram.copy_from_slice(&trainer);
+            }
         }
+        Ok(mapper)
     }
 
-    fn read_prg_rom(&self, mut addr: u16) -> u8 {
-        addr -= 0x8000;
-        if self.prg_rom.len() == 0x4000 && addr >= 0x4000 {
-            //mirror if needed
-            addr = addr % 0x4000;
+    /// Swaps the cartridge for `rom`, as if the console was switched off,
+    /// the cartridge changed and the console switched back on. The PPU, APU
+    /// and clock start over, and so do the cheats and coverage maps, which
+    /// only make sense for the old game. The gameloop and scanline
+    /// callbacks, controllers, expansion device, hooks, render mode and
+    /// pixel format stay. The CPU has to be powered on again afterwards, see
+    /// `Cpu::insert_cartridge`.
+    ///
+    /// On error the old cartridge stays in.
+    pub fn insert_cartridge(&mut self, rom: Rom) -> Result<(), RomError> {
+        let region = rom.region;
+        self.mapper = Bus::plug_in(rom)?;
+        let render_mode = self.ppu.render_mode();
+        let sprite_limit = self.ppu.sprite_limit();
+        let indexed_output = self.ppu.frame().indexed.is_some();
+        let pixel_format = self.ppu.frame().format();
+        let palette = self.ppu.palette().clone();
+        let layers =
+            [Layer::Background, Layer::Sprites].map(|layer| (layer, self.ppu.layer_visible(layer)));
+        self.ppu = NesPPU::new(self.mapper.clone());
+        self.ppu.set_region(region);
+        self.ppu.set_render_mode(render_mode);
+        self.ppu.set_sprite_limit(sprite_limit);
+        self.ppu.set_indexed_output(indexed_output);
+        self.ppu.set_pixel_format(pixel_format);
+        self.ppu.set_palette(palette);
+        for (layer, visible) in layers {
+            self.ppu.set_layer_visible(layer, visible);
+        }
+        self.apu = Apu::new();
+        self.clock = Clock::new();
+        self.cycles = 0;
+        self.irq_line = IrqSource::empty();
+        self.oam_dma_page = None;
+        self.overclock_dots_left = 0;
+        self.open_bus = 0;
+        self.cheats.clear();
+        self.watchpoint_hit = None;
+        if self.coverage.is_some() {
+            self.set_coverage_tracking(true);
         }
-        self.prg_rom[addr as usize]
+        self.schedule_frame_counter();
+        Ok(())
     }
 
     pub fn tick(&mut self, cycles: u8) {
         self.cycles += cycles as usize;
 
+        if self.overclock_dots_left > 0 {
+            // the rest of the console is frozen on the post-render line
+            // while the CPU runs, so the master clock doesn't move
+            self.overclock_dots_left = self.overclock_dots_left.saturating_sub(cycles as u32 * 3);
+            return;
+        }
+
+        self.clock.advance_cpu(cycles);
+        {
+            let mut mapper = self.mapper.borrow_mut();
+            for _ in 0..cycles {
+                mapper.cpu_tick();
+            }
+        }
+        let apu_cycles = self.clock.apu_cycles_due();
+        self.apu.tick(apu_cycles);
+        while let Some((at, event)) = self.clock.pop_due() {
+            self.handle_clock_event(at, event);
+        }
+        self.update_apu_irq();
+
+        let scanline_before = self.ppu.scanline;
         let nmi_before = self.ppu.nmi_interrupt.is_some();
-        self.ppu.tick(cycles * 3);
+        let dots = self.clock.ppu_dots_due();
+        self.ppu.tick(dots as u8);
         let nmi_after = self.ppu.nmi_interrupt.is_some();
 
+        // the visible lines and the pre-render line fetch tiles
+        let rendered_line = scanline_before < POST_RENDER_SCANLINE || scanline_before == 261;
+        if self.ppu.scanline != scanline_before && rendered_line && self.ppu.rendering_enabled() {
+            // boards counting A12 have already been told through the PPU
+            let mut mapper = self.mapper.borrow_mut();
+            if !mapper.counts_a12() {
+                mapper.scanline_tick();
+            }
+        }
+        self.update_mapper_irq();
+
+        if scanline_before != POST_RENDER_SCANLINE && self.ppu.scanline == POST_RENDER_SCANLINE {
+            self.overclock_dots_left = self.overclock_scanlines as u32 * 341;
+        }
+
+        if self.ppu.scanline != scanline_before {
+            if let Some(callback) = &mut self.scanline_callback {
+                callback(self.ppu.scanline, &self.ppu);
+            }
+        }
+
         if !nmi_before && nmi_after {
-            (self.gameloop_callback)(&self.ppu, &mut self.joypad1);
+            self.apply_ram_cheats();
+            (self.gameloop_callback)(&self.ppu, &mut self.joypad1, &mut self.joypad2);
+        }
+    }
+
+    fn handle_clock_event(&mut self, at: u64, event: ClockEvent) {
+        match event {
+            ClockEvent::ApuFrameCounter => {
+                self.apu.frame_counter_step();
+                let next = at + self.apu.frame_step_delay() * NTSC_CPU_DIVIDER;
+                self.clock.schedule_at(next, ClockEvent::ApuFrameCounter);
+            }
+        }
+    }
+
+    /// Restarts the frame counter's schedule after its sequence was reset
+    fn schedule_frame_counter(&mut self) {
+        self.clock.cancel(ClockEvent::ApuFrameCounter);
+        self.clock
+            .schedule_in(self.apu.frame_step_delay(), ClockEvent::ApuFrameCounter);
+    }
+
+    /// Overclocks the CPU by inserting `scanlines` extra scanlines' worth of
+    /// CPU time after the last visible line of every frame, before vblank.
+    /// Games get more time for their logic, which cuts down on slowdown,
+    /// while rendering and the NMI/vblank timing the game sees stay intact.
+    /// Timing-sensitive games may still misbehave, so 0 (off) is the default.
+    pub fn set_overclock_scanlines(&mut self, scanlines: u16) {
+        self.overclock_scanlines = scanlines;
+    }
+
+    /// CPU cycles elapsed since power-on
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    pub fn set_cycles(&mut self, cycles: usize) {
+        self.cycles = cycles;
+    }
+
+    pub fn ppu(&self) -> &NesPPU {
+        &self.ppu
+    }
+
+    /// Picks between drawing each line as the PPU reaches it, which shows
+    /// mid-frame effects, and drawing whole frames at vblank, which is
+    /// faster
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.ppu.set_render_mode(mode);
+    }
+
+    /// Lets every sprite on a line show instead of the first eight, which
+    /// gets rid of the flicker games use to work around the limit
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.ppu.set_sprite_limit(enabled);
+    }
+
+    /// Hides or shows the background or the sprites, for debugging
+    /// graphics and for recording one layer on its own
+    pub fn set_layer_visible(&mut self, layer: Layer, visible: bool) {
+        self.ppu.set_layer_visible(layer, visible);
+    }
+
+    /// Has the PPU draw the picture as palette entries and emphasis bits
+    /// too, for frontends that turn those into colours themselves
+    pub fn set_indexed_output(&mut self, enabled: bool) {
+        self.ppu.set_indexed_output(enabled);
+    }
+
+    /// Has the PPU store the picture as `format`, so frontends can upload
+    /// `Frame::data` to a texture of that format, `Frame::pitch` bytes a
+    /// row, as it is
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.ppu.set_pixel_format(format);
+    }
+
+    /// Draws the picture in the colours of `palette` from now on
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.ppu.set_palette(palette);
+    }
+
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    /// Reads memory without side effects, for debuggers and tracing. I/O
+    /// registers are not touched and read back as 0xFF.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            RAM..=RAM_MIRRORS_END => self.cpu_vram[(addr & 0b00000111_11111111) as usize],
+            0x4020..=0xFFFF => self.mapper.borrow().cpu_read(addr).unwrap_or(0xFF),
+            _ => 0xFF,
+        }
+    }
+
+    pub fn init_ram(&mut self, pattern: RamInitPattern) {
+        match pattern {
+            RamInitPattern::Zeroed => self.cpu_vram.fill(0),
+            RamInitPattern::Filled(value) => self.cpu_vram.fill(value),
+            RamInitPattern::Striped => {
+                for (i, byte) in self.cpu_vram.iter_mut().enumerate() {
+                    *byte = if i & 0b100 == 0 { 0x00 } else { 0xFF };
+                }
+            }
+            RamInitPattern::Random => rand::thread_rng().fill(&mut self.cpu_vram[..]),
+        }
+    }
+
+    /// Plugs `device` into the controller ports' expansion lines, replacing
+    /// whatever was there
+    pub fn attach_expansion_device<D>(&mut self, device: D)
+    where
+        D: ExpansionDevice + 'a,
+    {
+        self.expansion = Some(Box::new(device));
+    }
+
+    pub fn detach_expansion_device(&mut self) -> Option<Box<dyn ExpansionDevice + 'a>> {
+        self.expansion.take()
+    }
+
+    fn read_expansion(&mut self, port: u8) -> u8 {
+        self.expansion
+            .as_mut()
+            .map_or(0, |device| device.read(port) & EXPANSION_DATA_MASK)
+    }
+
+    /// Address of the sample byte the DMC is waiting for. The CPU stalls on
+    /// its next read to let the DMA fetch it.
+    pub fn dmc_dma_request(&self) -> Option<u16> {
+        self.apu.dmc_dma_request()
+    }
+
+    /// The DMA's own read of the sample byte at `addr`
+    pub fn dmc_dma(&mut self, addr: u16) {
+        let data = self.mem_read(addr);
+        self.apu.dmc_dma_complete(data);
+        self.update_apu_irq();
+    }
+
+    /// A read the CPU repeats while halted for a DMC DMA. Each repeat of a
+    /// $4016/$4017 read shifts the controller, so games that poll input
+    /// while samples play can see corrupted buttons. Real consoles do
+    /// that; with the mitigation on, the repeats are dropped.
+    pub fn dmc_halted_read(&mut self, addr: u16) {
+        if self.dmc_read_glitch_mitigation && matches!(addr, 0x4016 | 0x4017) {
+            return;
+        }
+        self.mem_read(addr);
+    }
+
+    pub fn set_dmc_read_glitch_mitigation(&mut self, enabled: bool) {
+        self.dmc_read_glitch_mitigation = enabled;
+    }
+
+    /// Page of a pending OAM DMA, if $4014 was written since the last call
+    pub fn take_oam_dma(&mut self) -> Option<u8> {
+        self.oam_dma_page.take()
+    }
+
+    /// Index of the 16 KiB PRG-ROM bank mapped at `addr`, for debuggers
+    pub fn prg_bank(&self, addr: u16) -> u8 {
+        self.prg_offset(addr)
+            .map_or(0, |offset| (offset / 0x4000) as u8)
+    }
+
+    /// Offset into PRG-ROM of the byte mapped at CPU address `addr`
+    pub fn prg_offset(&self, addr: u16) -> Option<usize> {
+        self.mapper.borrow().prg_offset(addr)
+    }
+
+    /// Starts or stops recording which PRG-ROM bytes get executed or read,
+    /// and which CHR-ROM bytes get drawn or read. Stopping throws the
+    /// collected maps away.
+    pub fn set_coverage_tracking(&mut self, enabled: bool) {
+        let prg_rom_len = self.mapper.borrow().prg_rom_len();
+        self.coverage = enabled.then(|| Coverage::new(prg_rom_len));
+        self.ppu.set_chr_coverage_tracking(enabled);
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    pub fn mark_coverage(&mut self, addr: u16, flags: CoverageFlags) {
+        if self.coverage.is_none() {
+            return;
+        }
+        if let (Some(offset), Some(coverage)) = (self.prg_offset(addr), &mut self.coverage) {
+            coverage.mark(offset, addr, flags);
         }
     }
 
+    /// The coverage maps as an FCEUX code/data log: one byte per PRG-ROM
+    /// byte, then one per CHR-ROM byte. `None` while tracking is off.
+    pub fn cdl(&self) -> Option<Vec<u8>> {
+        let mut cdl = self.coverage.as_ref()?.to_cdl();
+        if let Some(chr) = self.ppu.chr_coverage() {
+            cdl.extend(chr.to_cdl());
+        }
+        Some(cdl)
+    }
+
+    /// Writes memory without side effects, for loaders and debuggers. Unlike
+    /// `mem_write` this can patch PRG-ROM; I/O registers are left alone.
+    pub fn poke(&mut self, addr: u16, data: u8) {
+        match addr {
+            RAM..=RAM_MIRRORS_END => self.cpu_vram[(addr & 0b00000111_11111111) as usize] = data,
+            0x4020..=0xFFFF => self.mapper.borrow_mut().poke_prg(addr, data),
+            _ => {}
+        }
+    }
+
+    /// Whether the cartridge has battery-backed PRG-RAM, or other save
+    /// memory, worth saving
+    pub fn has_battery(&self) -> bool {
+        self.mapper.borrow().has_battery()
+    }
+
+    /// Restores PRG-RAM, or whatever the board saves to, from a `.sav`
+    /// file. A file of the wrong size loads as much as fits.
+    pub fn load_prg_ram(&mut self, path: &Path) -> io::Result<()> {
+        let data = fs::read(path)?;
+        let mut mapper = self.mapper.borrow_mut();
+        let ram = mapper.save_data_mut();
+        let len = data.len().min(ram.len());
+        ram[..len].copy_from_slice(&data[..len]);
+        Ok(())
+    }
+
+    /// Writes PRG-RAM, or whatever the board saves to, out as a `.sav` file
+    pub fn save_prg_ram(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.mapper.borrow().save_data())
+    }
+
+    /// Disk sides the Famicom Disk System drive can take; 0 for cartridges
+    pub fn disk_sides(&self) -> usize {
+        self.mapper.borrow().disk_sides()
+    }
+
+    /// The disk side in the drive, if any
+    pub fn inserted_disk(&self) -> Option<usize> {
+        self.mapper.borrow().inserted_disk()
+    }
+
+    /// Puts disk side `side` in the drive, or takes the disk out with
+    /// `None`. A new disk takes a moment to show up, so the BIOS sees the
+    /// drive empty in between, as it expects when disks are changed.
+    pub fn insert_disk(&mut self, side: Option<usize>) {
+        self.mapper.borrow_mut().insert_disk(side);
+    }
+
     pub fn poll_nmi_status(&mut self) -> Option<u8> {
         self.ppu.poll_nmi_interrupt()
     }
+
+    pub fn cheats(&self) -> &Cheats {
+        &self.cheats
+    }
+
+    pub fn cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.cheats
+    }
+
+    /// Forces the Pro Action Replay values into RAM, once per frame. Codes
+    /// for $6000-$7FFF go straight into PRG-RAM, not through the mapper,
+    /// where they could land on registers; boards without any skip them.
+    fn apply_ram_cheats(&mut self) {
+        for (addr, value) in self.cheats.ram_writes() {
+            match addr {
+                RAM..=RAM_MIRRORS_END => {
+                    self.cpu_vram[(addr & 0b00000111_11111111) as usize] = value
+                }
+                0x6000..=0x7FFF => {
+                    let mut mapper = self.mapper.borrow_mut();
+                    let prg_ram = mapper.prg_ram_mut();
+                    if !prg_ram.is_empty() {
+                        let len = prg_ram.len();
+                        prg_ram[(addr - 0x6000) as usize % len] = value;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Calls `callback` as the PPU starts each scanline, pre-render and
+    /// vblank lines included, so tools can watch what changes mid-frame.
+    /// It runs on the video timeline the way the gameloop callback runs once
+    /// a frame.
+    pub fn set_scanline_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut(u16, &NesPPU) + 'a,
+    {
+        self.scanline_callback = Some(Box::new(callback));
+    }
+
+    pub fn clear_scanline_callback(&mut self) {
+        self.scanline_callback = None;
+    }
+
+    /// Calls `hook` on every CPU read in `range`, dummy reads included.
+    /// Debugger reads through `peek` don't go through hooks.
+    pub fn add_read_hook<F>(&mut self, range: RangeInclusive<u16>, hook: F) -> HookId
+    where
+        F: FnMut(u16, u8) -> Option<u8> + 'a,
+    {
+        let id = self.new_hook_id();
+        self.read_hooks.push((id, range, Box::new(hook)));
+        id
+    }
+
+    /// Calls `hook` on every CPU write in `range`, before the write lands
+    pub fn add_write_hook<F>(&mut self, range: RangeInclusive<u16>, hook: F) -> HookId
+    where
+        F: FnMut(u16, u8) -> bool + 'a,
+    {
+        let id = self.new_hook_id();
+        self.write_hooks.push((id, range, Box::new(hook)));
+        id
+    }
+
+    /// Removes a hook or watchpoint. Returns whether one with that id was
+    /// registered.
+    pub fn remove_hook(&mut self, id: HookId) -> bool {
+        let count =
+            |bus: &Self| bus.read_hooks.len() + bus.write_hooks.len() + bus.watchpoints.len();
+        let before = count(self);
+        self.read_hooks.retain(|(hook_id, _, _)| *hook_id != id);
+        self.write_hooks.retain(|(hook_id, _, _)| *hook_id != id);
+        self.watchpoints.retain(|(hook_id, _, _)| *hook_id != id);
+        before != count(self)
+    }
+
+    /// Watches `range` for the kinds of access in `kind`. The CPU stops
+    /// after the instruction that triggers it, and the hit is reported by
+    /// `watchpoint_hit`. Execute watchpoints trigger on opcode fetches only.
+    pub fn add_watchpoint(&mut self, range: RangeInclusive<u16>, kind: WatchKind) -> HookId {
+        let id = self.new_hook_id();
+        self.watchpoints.push((id, range, kind));
+        id
+    }
+
+    pub fn watchpoint_hit(&self) -> Option<&WatchpointHit> {
+        self.watchpoint_hit.as_ref()
+    }
+
+    pub fn take_watchpoint_hit(&mut self) -> Option<WatchpointHit> {
+        self.watchpoint_hit.take()
+    }
+
+    /// Called by the CPU before it fetches the opcode at `pc`
+    pub fn begin_instruction(&mut self, pc: u16) {
+        self.instruction_pc = pc;
+        if !self.watchpoints.is_empty() {
+            let opcode = self.peek(pc);
+            self.check_watchpoints(WatchKind::EXECUTE, pc, opcode);
+        }
+    }
+
+    /// Records the first matching watchpoint, unless a hit is already
+    /// waiting to be reported
+    fn check_watchpoints(&mut self, kind: WatchKind, addr: u16, value: u8) {
+        if self.watchpoint_hit.is_some() {
+            return;
+        }
+        let watchpoint = self
+            .watchpoints
+            .iter()
+            .find(|(_, range, watched)| watched.contains(kind) && range.contains(&addr));
+        if let Some((id, _, _)) = watchpoint {
+            self.watchpoint_hit = Some(WatchpointHit {
+                id: *id,
+                kind,
+                addr,
+                value,
+                pc: self.instruction_pc,
+            });
+        }
+    }
+
+    fn new_hook_id(&mut self) -> HookId {
+        self.next_hook_id += 1;
+        HookId(self.next_hook_id)
+    }
+
+    fn run_read_hooks(&mut self, addr: u16, mut data: u8) -> u8 {
+        for (_, range, hook) in self.read_hooks.iter_mut() {
+            if range.contains(&addr) {
+                data = hook(addr, data).unwrap_or(data);
+            }
+        }
+        data
+    }
+
+    /// Every hook in range sees the write, even after one has dropped it
+    fn run_write_hooks(&mut self, addr: u16, data: u8) -> bool {
+        let mut allowed = true;
+        for (_, range, hook) in self.write_hooks.iter_mut() {
+            if range.contains(&addr) {
+                allowed &= hook(addr, data);
+            }
+        }
+        allowed
+    }
+
+    /// Asserts or releases the IRQ line on behalf of `source`.
+    pub fn set_irq(&mut self, source: IrqSource, asserted: bool) {
+        self.irq_line.set(source, asserted);
+    }
+
+    fn update_apu_irq(&mut self) {
+        self.set_irq(IrqSource::APU_FRAME_COUNTER, self.apu.frame_irq());
+        self.set_irq(IrqSource::APU_DMC, self.apu.dmc_irq());
+    }
+
+    fn update_mapper_irq(&mut self) {
+        let pending = self.mapper.borrow().irq_pending();
+        self.set_irq(IrqSource::MAPPER, pending);
+    }
+
+    /// Whether the cartridge is asserting IRQ
+    pub fn poll_mapper_irq(&self) -> bool {
+        self.irq_line.contains(IrqSource::MAPPER)
+    }
+
+    /// Makes the cartridge release IRQ, as its acknowledge register would
+    pub fn acknowledge_mapper_irq(&mut self) {
+        self.mapper.borrow_mut().irq_ack();
+        self.update_mapper_irq();
+    }
+
+    /// IRQ is level-triggered: it stays pending until every source that
+    /// asserted it has been acknowledged.
+    pub fn poll_irq_status(&self) -> bool {
+        !self.irq_line.is_empty()
+    }
 }
 
 impl Mem for Bus<'_> {
     fn mem_read(&mut self, addr: u16) -> u8 {
+        let mut data = self.read_device(addr);
+        // Game Genie codes sit between the cartridge and the CPU
+        if addr >= 0x8000 && !self.cheats.is_empty() {
+            data = self.cheats.patch_read(addr, data);
+        }
+        // with no hooks registered this check is all they cost
+        if !self.read_hooks.is_empty() {
+            data = self.run_read_hooks(addr, data);
+        }
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(WatchKind::READ, addr, data);
+        }
+        if let PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END = addr {
+            self.ppu.latch_io(data);
+        }
+        // $4015 is read inside the CPU chip, so the external bus keeps
+        // what was last on it
+        if addr != 0x4015 {
+            self.open_bus = data;
+        }
+        data
+    }
+
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        self.open_bus = data;
+        if let PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END = addr {
+            self.ppu.latch_io(data);
+        }
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(WatchKind::WRITE, addr, data);
+        }
+        if !self.write_hooks.is_empty() && !self.run_write_hooks(addr, data) {
+            return;
+        }
+        self.write_device(addr, data);
+    }
+}
+
+impl Bus<'_> {
+    fn read_device(&mut self, addr: u16) -> u8 {
         match addr {
             RAM..=RAM_MIRRORS_END => {
                 let mirror_down_addr = addr & 0b00000111_11111111;
                 self.cpu_vram[mirror_down_addr as usize]
             }
-            0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 | 0x4014 => {
-                // panic!("Attempt to read from write-only PPU address {:x}", addr);
-                0
-            }
+            // write-only PPU registers read back the PPU's own bus latch
+            0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 => self.ppu.io_latch(),
             0x2002 => self.ppu.read_status(),
             0x2004 => self.ppu.read_oam_data(),
             0x2007 => self.ppu.read_data(),
 
-            0x4000..=0x4015 => {
-                //ignore APU
-                0
+            // the APU registers are write-only, apart from the status
+            0x4000..=0x4014 => self.open_bus,
+            // bit 5 of the APU status is not driven
+            0x4015 => {
+                let status = self.apu.read_status();
+                self.update_apu_irq();
+                status | (self.open_bus & 0b0010_0000)
             }
 
-            0x4016 => self.joypad1.read(),
+            // the controller ports only drive the low bits
+            0x4016 => (self.open_bus & 0b1110_0000) | self.joypad1.read() | self.read_expansion(0),
 
-            0x4017 => {
-                // ignore joypad 2
-                0
-            }
+            0x4017 => (self.open_bus & 0b1110_0000) | self.joypad2.read() | self.read_expansion(1),
             0x2008..=PPU_REGISTERS_MIRRORS_END => {
                 let mirror_down_addr = addr & 0b00100000_00000111;
-                self.mem_read(mirror_down_addr)
+                self.read_device(mirror_down_addr)
+            }
+            0x4020..=0xFFFF => {
+                let mut mapper = self.mapper.borrow_mut();
+                let data = mapper.cpu_read(addr);
+                mapper.after_cpu_read(addr);
+                data.unwrap_or(self.open_bus)
             }
-            0x8000..=0xFFFF => self.read_prg_rom(addr),
 
             _ => {
                 // println!("Ignoring mem access at {:x}", addr);
-                0
+                self.open_bus
             }
         }
     }
 
-    fn mem_write(&mut self, addr: u16, data: u8) {
+    fn write_device(&mut self, addr: u16, data: u8) {
         match addr {
             RAM..=RAM_MIRRORS_END => {
                 let mirror_down_addr = addr & 0b11111111111;
@@ -142,7 +860,8 @@ impl Mem for Bus<'_> {
                 self.ppu.write_to_mask(data);
             }
 
-            0x2002 => panic!("attempt to write to PPU status register"),
+            // read-only, but the value still lands on the PPU bus
+            0x2002 => {}
 
             0x2003 => {
                 self.ppu.write_to_oam_addr(data);
@@ -161,41 +880,48 @@ impl Mem for Bus<'_> {
                 self.ppu.write_to_data(data);
             }
             0x4000..=0x4013 | 0x4015 => {
-                //ignore APU
+                self.apu.write_register(addr, data);
+                self.update_apu_irq();
             }
 
+            // one strobe line latches both controllers
             0x4016 => {
                 self.joypad1.write(data);
+                self.joypad2.write(data);
+                if let Some(device) = &mut self.expansion {
+                    device.write(data);
+                }
             }
 
+            // the frame counter shares its address with the second
+            // controller port, which is read-only
             0x4017 => {
-                // ignore joypad 2
+                self.apu.write_register(addr, data);
+                self.schedule_frame_counter();
+                self.update_apu_irq();
             }
 
             // https://wiki.nesdev.com/w/index.php/PPU_programmer_reference#OAM_DMA_.28.244014.29_.3E_write
             0x4014 => {
-                let mut buffer: [u8; 256] = [0; 256];
-                let hi: u16 = (data as u16) << 8;
-                for i in 0..256u16 {
-                    buffer[i as usize] = self.mem_read(hi + i);
-                }
-
-                self.ppu.write_oam_dma(&buffer);
-
-                // todo: handle this eventually
-                // let add_cycles: u16 = if self.cycles % 2 == 1 { 514 } else { 513 };
-                // self.tick(add_cycles); //todo this will cause weird effects as PPU will have 513/514 * 3 ticks
+                // the CPU performs the copy once the current instruction ends
+                self.oam_dma_page = Some(data);
             }
 
             0x2008..=PPU_REGISTERS_MIRRORS_END => {
                 let mirror_down_addr = addr & 0b00100000_00000111;
-                self.mem_write(mirror_down_addr, data);
+                self.write_device(mirror_down_addr, data);
                 // todo!("PPU is not supported yet");
             }
-            0x8000..=0xFFFF => panic!("Attempt to write to Cartridge ROM space: {:x}", addr),
+            // a write can acknowledge or arm the cartridge's IRQ
+            0x4020..=0xFFFF => {
+                // a bank switch shows from the dot it happens on
+                self.ppu.catch_up();
+                self.mapper.borrow_mut().cpu_write(addr, data);
+                self.update_mapper_irq();
+            }
 
             _ => {
-                println!("Ignoring mem write-access at {:x}", addr);
+                // nothing is mapped here, the write only drives the bus
             }
         }
     }
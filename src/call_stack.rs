@@ -0,0 +1,61 @@
+/// What pushed a call frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    Subroutine,
+    Nmi,
+    Irq,
+    Brk,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    pub kind: CallKind,
+    /// Where execution went
+    pub target: u16,
+    /// Where execution continues once the frame returns
+    pub return_addr: u16,
+    /// PRG bank mapped at `target` when the call was made
+    pub bank: u8,
+    /// Stack pointer right after the return address was pushed
+    pub stack_pointer: u8,
+}
+
+/// Shadow of the 6502 stack that only records calls, for backtraces.
+///
+/// Games don't always return the way they were called: they pull return
+/// addresses with PLA, reset S with TXS, or RTS into a pushed jump table. So
+/// rather than pairing RTS with JSR, a frame is dropped as soon as S moves
+/// above the slot its return address was pushed to.
+#[derive(Debug, Default)]
+pub struct CallStack {
+    frames: Vec<CallFrame>,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        CallStack { frames: Vec::new() }
+    }
+
+    pub fn push(&mut self, frame: CallFrame) {
+        self.frames.push(frame);
+    }
+
+    /// Drops the frames whose return address is no longer on the stack.
+    pub fn sync(&mut self, stack_pointer: u8) {
+        while let Some(frame) = self.frames.last() {
+            if frame.stack_pointer >= stack_pointer {
+                break;
+            }
+            self.frames.pop();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Outermost call first
+    pub fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+}
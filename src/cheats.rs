@@ -0,0 +1,197 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Game Genie letters, in the order of the values they stand for
+const GAME_GENIE_LETTERS: &str = "APZLGITYEOXUKSVN";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cheat {
+    /// Reads of `addr` in $8000-$FFFF return `value` instead of the ROM
+    /// byte. With a `compare` byte, only while the ROM holds that byte, so
+    /// the code doesn't hit other banks mapped at the same address.
+    GameGenie {
+        addr: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// `addr` in RAM is set to `value` once every frame
+    ProActionReplay { addr: u16, value: u8 },
+}
+
+impl Cheat {
+    /// Decodes a six or eight letter Game Genie code, such as `SXIOPO`
+    pub fn from_game_genie(code: &str) -> Result<Cheat, String> {
+        let n = code
+            .chars()
+            .map(|c| {
+                GAME_GENIE_LETTERS
+                    .find(c.to_ascii_uppercase())
+                    .map(|value| value as u16)
+                    .ok_or_else(|| format!("`{}` is not a Game Genie letter", c))
+            })
+            .collect::<Result<Vec<u16>, String>>()?;
+        if n.len() != 6 && n.len() != 8 {
+            return Err(format!(
+                "Game Genie code `{}` is not 6 or 8 letters long",
+                code
+            ));
+        }
+
+        // https://www.nesdev.org/wiki/Game_Genie
+        let addr = 0x8000
+            | ((n[3] & 7) << 12)
+            | ((n[5] & 7) << 8)
+            | ((n[4] & 8) << 8)
+            | ((n[2] & 7) << 4)
+            | ((n[1] & 8) << 4)
+            | (n[4] & 7)
+            | (n[3] & 8);
+        let byte = |high: u16, low: u16, top: u16| {
+            (((high & 7) << 4) | ((low & 8) << 4) | (low & 7) | (top & 8)) as u8
+        };
+
+        Ok(if n.len() == 6 {
+            Cheat::GameGenie {
+                addr,
+                value: byte(n[1], n[0], n[5]),
+                compare: None,
+            }
+        } else {
+            Cheat::GameGenie {
+                addr,
+                value: byte(n[1], n[0], n[7]),
+                compare: Some(byte(n[7], n[6], n[5])),
+            }
+        })
+    }
+
+    /// Decodes a Pro Action Replay code: a RAM address and a value in hex,
+    /// written `AAAAVV` or `AAAA:VV`
+    pub fn from_pro_action_replay(code: &str) -> Result<Cheat, String> {
+        let digits = code.replace(':', "");
+        let error = || format!("`{}` is not a Pro Action Replay code", code);
+        if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(error());
+        }
+        Ok(Cheat::ProActionReplay {
+            addr: u16::from_str_radix(&digits[..4], 16).map_err(|_| error())?,
+            value: u8::from_str_radix(&digits[4..], 16).map_err(|_| error())?,
+        })
+    }
+}
+
+/// Parses either kind of code: letters are Game Genie, hex digits are
+/// Pro Action Replay
+impl FromStr for Cheat {
+    type Err = String;
+
+    fn from_str(code: &str) -> Result<Cheat, String> {
+        let code = code.trim();
+        if code.chars().all(|c| c.is_ascii_alphabetic()) {
+            Cheat::from_game_genie(code)
+        } else {
+            Cheat::from_pro_action_replay(code)
+        }
+    }
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cheat::GameGenie {
+                addr,
+                value,
+                compare: Some(compare),
+            } => write!(f, "${:04X}?{:02X}:{:02X}", addr, compare, value),
+            Cheat::GameGenie { addr, value, .. } | Cheat::ProActionReplay { addr, value } => {
+                write!(f, "${:04X}:{:02X}", addr, value)
+            }
+        }
+    }
+}
+
+/// Identifies a cheat in a `Cheats` list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheatId(usize);
+
+/// The cheats the Bus applies, each of which can be switched on and off
+/// while the game runs
+#[derive(Debug, Default)]
+pub struct Cheats {
+    entries: Vec<(CheatId, Cheat, bool)>,
+    next_id: usize,
+}
+
+impl Cheats {
+    pub fn new() -> Self {
+        Cheats::default()
+    }
+
+    /// Adds an enabled cheat
+    pub fn add(&mut self, cheat: Cheat) -> CheatId {
+        self.next_id += 1;
+        let id = CheatId(self.next_id);
+        self.entries.push((id, cheat, true));
+        id
+    }
+
+    pub fn remove(&mut self, id: CheatId) -> Option<Cheat> {
+        let index = self.entries.iter().position(|(entry, _, _)| *entry == id)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    /// Returns whether the cheat exists
+    pub fn set_enabled(&mut self, id: CheatId, enabled: bool) -> bool {
+        match self.entries.iter_mut().find(|(entry, _, _)| *entry == id) {
+            Some((_, _, state)) => {
+                *state = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Every cheat with whether it is enabled
+    pub fn iter(&self) -> impl Iterator<Item = (CheatId, &Cheat, bool)> {
+        self.entries
+            .iter()
+            .map(|(id, cheat, enabled)| (*id, cheat, *enabled))
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = &Cheat> {
+        self.entries
+            .iter()
+            .filter(|(_, _, enabled)| *enabled)
+            .map(|(_, cheat, _)| cheat)
+    }
+
+    /// The byte the CPU sees when it reads `data` from `addr` in PRG-ROM
+    pub fn patch_read(&self, addr: u16, data: u8) -> u8 {
+        self.enabled()
+            .find_map(|cheat| match *cheat {
+                Cheat::GameGenie {
+                    addr: target,
+                    value,
+                    compare,
+                } if target == addr && compare.is_none_or(|compare| compare == data) => Some(value),
+                _ => None,
+            })
+            .unwrap_or(data)
+    }
+
+    /// The RAM writes to make once per frame
+    pub fn ram_writes(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.enabled().filter_map(|cheat| match *cheat {
+            Cheat::ProActionReplay { addr, value } => Some((addr, value)),
+            _ => None,
+        })
+    }
+}
@@ -0,0 +1,163 @@
+// Gameplay clips. The frames are kept in memory while recording and saved
+// as an animated GIF or PNG once it stops.
+
+use crate::frame::Frame;
+use crate::rom::Region;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum ClipError {
+    /// There were no frames to save
+    Empty,
+    /// The file name doesn't end in .gif, .png or .apng
+    UnknownFormat,
+    Io(io::Error),
+    Gif(image::ImageError),
+    Png(png::EncodingError),
+}
+
+impl fmt::Display for ClipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClipError::Empty => write!(f, "no frames were recorded"),
+            ClipError::UnknownFormat => write!(f, "clips are saved as .gif, .png or .apng"),
+            ClipError::Io(err) => write!(f, "could not write clip: {}", err),
+            ClipError::Gif(err) => write!(f, "could not encode GIF: {}", err),
+            ClipError::Png(err) => write!(f, "could not encode PNG: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ClipError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClipError::Io(err) => Some(err),
+            ClipError::Gif(err) => Some(err),
+            ClipError::Png(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ClipError {
+    fn from(err: io::Error) -> Self {
+        ClipError::Io(err)
+    }
+}
+
+impl From<image::ImageError> for ClipError {
+    fn from(err: image::ImageError) -> Self {
+        ClipError::Gif(err)
+    }
+}
+
+impl From<png::EncodingError> for ClipError {
+    fn from(err: png::EncodingError) -> Self {
+        ClipError::Png(err)
+    }
+}
+
+/// Collects frames for a clip. Each frame lasts as long as it does on the
+/// console, so clips play back at the speed the game ran.
+pub struct Recorder {
+    /// Frames per second, as a numerator and a denominator
+    frame_rate: (u32, u32),
+    /// RGB, in the layout of `Frame::data`
+    frames: Vec<Vec<u8>>,
+}
+
+impl Recorder {
+    pub fn new(region: Region) -> Self {
+        Recorder {
+            frame_rate: region.frame_rate(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, frame: &Frame) {
+        self.frames.push(frame.to_rgb24().into_owned());
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// How long the clip plays for, in seconds
+    pub fn duration(&self) -> f64 {
+        let (frames, seconds) = self.frame_rate;
+        self.frames.len() as f64 * seconds as f64 / frames as f64
+    }
+
+    /// Saves as an animated GIF or PNG, going by the extension of `path`
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ClipError> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        match extension.to_ascii_lowercase().as_str() {
+            "gif" => self.save_gif(path),
+            "png" | "apng" => self.save_apng(path),
+            _ => Err(ClipError::UnknownFormat),
+        }
+    }
+
+    /// GIF times frames in hundredths of a second, and most viewers slow
+    /// down anything shown for less than two, so only every other frame is
+    /// kept. Each one is shown for however many hundredths keep the clip in
+    /// step with the console.
+    pub fn save_gif(&self, path: impl AsRef<Path>) -> Result<(), ClipError> {
+        if self.frames.is_empty() {
+            return Err(ClipError::Empty);
+        }
+        let (frames_per, seconds) = (self.frame_rate.0 as u64, self.frame_rate.1 as u64);
+        let mut encoder = image::codecs::gif::GifEncoder::new(BufWriter::new(File::create(path)?));
+        encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+
+        let mut shown_until = 0;
+        for (i, data) in self.frames.iter().enumerate().step_by(2) {
+            // in hundredths of a second, rounded
+            let ends_at = ((i as u64 + 2) * seconds * 100 + frames_per / 2) / frames_per;
+            let delay = image::Delay::from_numer_denom_ms((ends_at - shown_until) as u32 * 10, 1);
+            shown_until = ends_at;
+
+            let rgba = data
+                .chunks_exact(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 0xff])
+                .collect();
+            let buffer = image::RgbaImage::from_raw(Frame::WIDTH as u32, Frame::HIGHT as u32, rgba)
+                .expect("frames are 256x240");
+            encoder.encode_frame(image::Frame::from_parts(buffer, 0, 0, delay))?;
+        }
+        Ok(())
+    }
+
+    /// APNG gives each frame's time as a fraction of a second with 16-bit
+    /// parts, which can't hold the frame rate exactly but is within a few
+    /// hundredths of a percent
+    pub fn save_apng(&self, path: impl AsRef<Path>) -> Result<(), ClipError> {
+        if self.frames.is_empty() {
+            return Err(ClipError::Empty);
+        }
+        let (frames_per, seconds) = (self.frame_rate.0 as u64, self.frame_rate.1 as u64);
+        let delay = (u16::MAX as u64 * seconds + frames_per / 2) / frames_per;
+
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, Frame::WIDTH as u32, Frame::HIGHT as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        // plays forever
+        encoder.set_animated(self.frames.len() as u32, 0)?;
+        encoder.set_frame_delay(delay as u16, u16::MAX)?;
+        let mut writer = encoder.write_header()?;
+        for data in &self.frames {
+            writer.write_image_data(data)?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+}
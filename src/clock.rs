@@ -0,0 +1,83 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Master clock ticks per CPU cycle on NTSC consoles (21.477 MHz / 12)
+pub const NTSC_CPU_DIVIDER: u64 = 12;
+/// Master clock ticks per PPU dot on NTSC consoles (21.477 MHz / 4)
+pub const NTSC_PPU_DIVIDER: u64 = 4;
+
+/// Something that is due at a known point in time, rather than being
+/// checked for on every cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ClockEvent {
+    /// The APU frame counter reaches its next step
+    ApuFrameCounter,
+}
+
+/// The console's master clock. Every component is a fixed divider of it,
+/// so each one is told how far to catch up rather than keeping its own
+/// count of how far the CPU got.
+#[derive(Default)]
+pub struct Clock {
+    master_cycle: u64,
+    /// Master cycle the PPU has been run up to
+    ppu_master_cycle: u64,
+    /// Master cycle the APU has been run up to
+    apu_master_cycle: u64,
+    events: BinaryHeap<Reverse<(u64, ClockEvent)>>,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Clock::default()
+    }
+
+    pub fn master_cycle(&self) -> u64 {
+        self.master_cycle
+    }
+
+    /// Moves time forward by `cycles` CPU cycles
+    pub fn advance_cpu(&mut self, cycles: u8) {
+        self.master_cycle += cycles as u64 * NTSC_CPU_DIVIDER;
+    }
+
+    /// PPU dots owed since the last call
+    pub fn ppu_dots_due(&mut self) -> u64 {
+        let dots = (self.master_cycle - self.ppu_master_cycle) / NTSC_PPU_DIVIDER;
+        self.ppu_master_cycle += dots * NTSC_PPU_DIVIDER;
+        dots
+    }
+
+    /// APU cycles owed since the last call. The APU counts in CPU cycles.
+    pub fn apu_cycles_due(&mut self) -> u64 {
+        let cycles = (self.master_cycle - self.apu_master_cycle) / NTSC_CPU_DIVIDER;
+        self.apu_master_cycle += cycles * NTSC_CPU_DIVIDER;
+        cycles
+    }
+
+    /// Queues `event` to fire at master cycle `at`
+    pub fn schedule_at(&mut self, at: u64, event: ClockEvent) {
+        self.events.push(Reverse((at, event)));
+    }
+
+    /// Queues `event` to fire `cycles` CPU cycles from now
+    pub fn schedule_in(&mut self, cycles: u64, event: ClockEvent) {
+        self.schedule_at(self.master_cycle + cycles * NTSC_CPU_DIVIDER, event);
+    }
+
+    /// Drops every queued `event`
+    pub fn cancel(&mut self, event: ClockEvent) {
+        self.events.retain(|Reverse((_, queued))| *queued != event);
+    }
+
+    /// Takes the earliest event that is due, if any, along with the master
+    /// cycle it was due on
+    pub fn pop_due(&mut self) -> Option<(u64, ClockEvent)> {
+        match self.events.peek() {
+            Some(Reverse((at, _))) if *at <= self.master_cycle => {
+                self.events.pop().map(|Reverse(due)| due)
+            }
+            _ => None,
+        }
+    }
+}
@@ -1,11 +1,50 @@
 use crate::bus;
 use crate::bus::*;
+use crate::call_stack::*;
+use crate::coverage::CoverageFlags;
 use crate::opcodes::*;
+use crate::rom::{Rom, RomError};
+use crate::symbols::Symbols;
 
-use std::fmt::Debug;
+use crate::trace;
+
+use std::fmt::{self, Debug};
+use std::io::Write;
 
 const STACK: u16 = 0x0100;
-const STACK_RESET: u8 = 0xfd;
+
+// LXA and XAA OR the accumulator with a chip-dependent constant before the
+// AND; 0xEE is the value most NES CPUs settle on.
+const UNSTABLE_MAGIC: u8 = 0xEE;
+
+mod interrupt {
+    #[derive(Clone, Copy)]
+    pub(super) struct Interrupt {
+        pub(super) vector_addr: u16,
+        pub(super) b_flag_mask: u8,
+        /// Reads of PC the CPU spends before pushing the return address
+        pub(super) dummy_reads: u8,
+    }
+
+    pub(super) const NMI: Interrupt = Interrupt {
+        vector_addr: 0xFFFA,
+        b_flag_mask: 0b00100000,
+        dummy_reads: 2,
+    };
+
+    pub(super) const IRQ: Interrupt = Interrupt {
+        vector_addr: 0xFFFE,
+        b_flag_mask: 0b00100000,
+        dummy_reads: 2,
+    };
+
+    // BRK has already fetched its opcode and padding byte
+    pub(super) const BRK: Interrupt = Interrupt {
+        vector_addr: 0xFFFE,
+        b_flag_mask: 0b00110000,
+        dummy_reads: 0,
+    };
+}
 
 pub trait Mem {
     fn mem_read(&mut self, addr: u16) -> u8;
@@ -26,6 +65,148 @@ pub trait Mem {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CpuError {
+    /// A KIL opcode locked up the CPU; only a reset recovers it
+    Jammed { pc: u16, opcode: u8 },
+    /// An operand was resolved for a mode that has no memory address
+    NoOperandAddress { mode: AddressingMode, addr: u16 },
+    /// The stack pointer wrapped while stack faults are trapped
+    StackFault { fault: StackFault, pc: u16 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackFault {
+    /// A push wrapped S from $00 to $FF
+    Overflow,
+    /// A pull wrapped S from $FF to $00
+    Underflow,
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuError::Jammed { pc, opcode } => {
+                write!(f, "CPU jammed by opcode 0x{:02X} at 0x{:04X}", opcode, pc)
+            }
+            CpuError::NoOperandAddress { mode, addr } => write!(
+                f,
+                "addressing mode {:?} has no operand address (operand at 0x{:04X})",
+                mode, addr
+            ),
+            CpuError::StackFault { fault, pc } => {
+                let kind = match fault {
+                    StackFault::Overflow => "overflow",
+                    StackFault::Underflow => "underflow",
+                };
+                write!(f, "stack {} at 0x{:04X}", kind, pc)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
+/// The register file plus the cycle counter, as one plain value that tools
+/// can compare against or patch in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CpuState {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    pub pc: u16,
+    pub cycles: u64,
+}
+
+fn page_cross(addr1: u16, addr2: u16) -> bool {
+    addr1 & 0xFF00 != addr2 & 0xFF00
+}
+
+/// One cycle of CPU work. Every cycle makes exactly one bus access, so an
+/// instruction is a chain of steps, each reading or writing one byte as the
+/// 6502 sequences it, with dead cycles as dummy reads. Steps are named after
+/// what their access is for; what earlier steps fetched is latched in the
+/// `Cpu` until the instruction is done with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Opcode,
+    /// Single byte instructions still read the byte after the opcode
+    Implied,
+    Immediate,
+    ZeroPage,
+    /// Base of a zero page indexed address
+    ZeroPageBase,
+    /// The base is read again while the index is added
+    ZeroPageIndex,
+    AbsoluteLow,
+    AbsoluteHigh,
+    /// Reading the indexed address before its high byte was fixed up
+    Fixup,
+    /// Zero page pointer of (ind,X) or (ind),Y
+    PointerBase,
+    /// The pointer is read again while X is added
+    PointerIndex,
+    PointerLow,
+    PointerHigh,
+    /// JMP (ind) fetching its target
+    IndirectLow,
+    IndirectHigh,
+    /// The operand access proper
+    Read,
+    Write,
+    /// Read-modify-write writes the operand back unmodified before the result
+    ModifyRead,
+    ModifyDummyWrite,
+    ModifyWrite,
+    BranchOffset,
+    BranchTaken,
+    BranchFixup,
+    JsrLow,
+    JsrHigh,
+    PushReturnHigh,
+    PushReturnLow,
+    StackDummy,
+    Push,
+    Pull,
+    PullLow,
+    PullHigh,
+    ReturnIncrement,
+    /// Reads of PC an NMI or IRQ spends before pushing, counting down
+    InterruptDummy(u8),
+    PushPcHigh,
+    PushPcLow,
+    PushStatus,
+    VectorLow,
+    VectorHigh,
+    /// The reset sequence, by cycle
+    Reset(u8),
+    /// OAM DMA: a halt cycle, maybe an alignment cycle, then 256 pairs of
+    /// reading the page and writing $2004
+    OamHalt,
+    OamAlign,
+    OamRead,
+    OamWrite,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read(u16),
+    Write(u16, u8),
+}
+
+/// A DMC sample fetch holding up the CPU's read of `addr`
+#[derive(Debug, Clone, Copy)]
+struct DmcStall {
+    addr: u16,
+    sample_addr: u16,
+    halted_reads: u8,
+}
+
+type Tracer<'a> = Box<dyn FnMut(&str) + 'a>;
+type StackFaultHandler<'a> = Box<dyn FnMut(StackFault, u16) + 'a>;
+
 pub struct Cpu<'a> {
     pub register_a: u8,
     pub register_x: u8,
@@ -38,6 +219,50 @@ pub struct Cpu<'a> {
     // Cpu only has 2 KiB of RAM, NEW has 64 KiB of memory
     // Program starts at 0x8000 to 0xFFFF
     bus: Bus<'a>,
+
+    halted: bool,
+    stop_on_brk: bool,
+    entry_point: Option<u16>,
+    call_stack: Option<CallStack>,
+    symbols: Option<Symbols>,
+    /// Set when S wrapped during the current instruction
+    stack_fault: Option<StackFault>,
+    stack_fault_handler: Option<StackFaultHandler<'a>>,
+    trap_stack_faults: bool,
+    /// Cycles `run_for_cycles` ran past its last budget
+    cycle_overshoot: u64,
+
+    /// An NMI edge was detected and has not been serviced yet
+    nmi_pending: bool,
+    /// IRQ asserted while I was clear, as of the end of the last cycle
+    irq_pending: bool,
+    /// Interrupt state as of the end of the previous cycle. The 6502 polls
+    /// during an instruction's last cycle, so these decide whether an
+    /// interrupt is taken once the current instruction finishes.
+    nmi_poll: bool,
+    irq_poll: bool,
+    /// The instruction being executed
+    operation: Opcode,
+    /// PC of the instruction being executed
+    opcode_addr: u16,
+    /// What the next `clock` does
+    step: Step,
+    /// The tracer and watchpoints have seen the instruction at PC
+    instruction_started: bool,
+    /// Latched by earlier steps: the operand address (or the vector, or the
+    /// branch target), a zero page pointer, and a byte fetched or about to
+    /// be written
+    address: u16,
+    pointer: u8,
+    data: u8,
+    /// Indexing carried into the high byte of `address`
+    page_crossed: bool,
+    /// Interrupt polls as of a taken branch's offset fetch
+    branch_polls: (bool, bool),
+    /// The NMI, IRQ or BRK being serviced
+    interrupt: interrupt::Interrupt,
+    dmc_stall: Option<DmcStall>,
+    tracer: Option<Tracer<'a>>,
 }
 
 impl Debug for Cpu<'_> {
@@ -63,13 +288,6 @@ impl Mem for Cpu<'_> {
     fn mem_write(&mut self, addr: u16, data: u8) {
         self.bus.mem_write(addr, data)
     }
-    fn mem_read_u16(&mut self, pos: u16) -> u16 {
-        self.bus.mem_read_u16(pos)
-    }
-
-    fn mem_write_u16(&mut self, pos: u16, data: u16) {
-        self.bus.mem_write_u16(pos, data)
-    }
 }
 
 impl<'a> Cpu<'a> {
@@ -78,63 +296,169 @@ impl<'a> Cpu<'a> {
             register_a: 0,
             register_x: 0,
             register_y: 0,
-            stack_pointer: STACK_RESET,
+            // power-on state; the reset sequence then moves SP down to $FD
+            stack_pointer: 0,
             program_counter: 0,
             status: CpuFlags::from_bits_truncate(0b100100),
             bus: bus,
+            halted: false,
+            stop_on_brk: false,
+            entry_point: None,
+            call_stack: None,
+            symbols: None,
+            stack_fault: None,
+            stack_fault_handler: None,
+            trap_stack_faults: false,
+            cycle_overshoot: 0,
+            nmi_pending: false,
+            irq_pending: false,
+            nmi_poll: false,
+            irq_poll: false,
+            operation: OPCODE_TABLE[0],
+            opcode_addr: 0,
+            step: Step::Opcode,
+            instruction_started: false,
+            address: 0,
+            pointer: 0,
+            data: 0,
+            page_crossed: false,
+            branch_polls: (false, false),
+            interrupt: interrupt::IRQ,
+            dmc_stall: None,
+            tracer: None,
         }
     }
 
-    fn get_operand_address(&mut self, mode: &AddressingMode) -> u16 {
-        // match mode {
-        //     AddressingMode::Immediate => self.program_counter,
-
-        //     AddressingMode::ZeroPage => self.mem_read(self.program_counter) as u16,
-
-        //     AddressingMode::Absolute => self.mem_read_u16(self.program_counter),
-
-        //     AddressingMode::ZeroPage_X => {
-        //         let pos = self.mem_read(self.program_counter);
-        //         pos.wrapping_add(self.register_x) as u16
-        //     }
-        //     AddressingMode::ZeroPage_Y => {
-        //         let pos = self.mem_read(self.program_counter);
-        //         pos.wrapping_add(self.register_y) as u16
-        //     }
-
-        //     AddressingMode::Absolute_X => {
-        //         let base = self.mem_read_u16(self.program_counter);
-        //         base.wrapping_add(self.register_x as u16)
-        //     }
-        //     AddressingMode::Absolute_Y => {
-        //         let base = self.mem_read_u16(self.program_counter);
-        //         base.wrapping_add(self.register_y as u16)
-        //     }
-
-        //     AddressingMode::Indirect_X => {
-        //         let base = self.mem_read(self.program_counter);
-
-        //         let ptr: u8 = base.wrapping_add(self.register_x);
-        //         let lo = self.mem_read(ptr as u16);
-        //         let hi = self.mem_read(ptr.wrapping_add(1) as u16);
-        //         (hi as u16) << 8 | (lo as u16)
-        //     }
-        //     AddressingMode::Indirect_Y => {
-        //         let base = self.mem_read(self.program_counter);
-
-        //         let lo = self.mem_read(base as u16);
-        //         let hi = self.mem_read(base.wrapping_add(1) as u16);
-        //         let deref_base = (hi as u16) << 8 | (lo as u16);
-        //         deref_base.wrapping_add(self.register_y as u16)
-        //     }
-
-        //     AddressingMode::NoneAddressing => {
-        //         panic!("mode {:?} is not supported", mode);
-        //     }
-        // }
-        match mode {
-            AddressingMode::Immediate => self.program_counter,
-            _ => self.get_absolute_address(mode, self.program_counter),
+    /// Makes `run`/`run_with_callback` return before the next instruction.
+    /// Can be called from the run callback; `reset` clears it.
+    pub fn stop(&mut self) {
+        self.halted = true;
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.halted
+    }
+
+    /// When enabled, BRK stops the run loop instead of vectoring through
+    /// $FFFE, which is convenient for running small test programs.
+    pub fn set_stop_on_brk(&mut self, stop_on_brk: bool) {
+        self.stop_on_brk = stop_on_brk;
+    }
+
+    pub fn bus(&self) -> &Bus<'a> {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut Bus<'a> {
+        &mut self.bus
+    }
+
+    /// Lets the run loop continue after `stop` or a watchpoint hit, which
+    /// is dropped.
+    pub fn resume(&mut self) {
+        self.bus.take_watchpoint_hit();
+        self.halted = false;
+    }
+
+    /// Hands every executed instruction to `tracer` as a nestest.log
+    /// formatted line, before the instruction runs.
+    pub fn set_tracer<F>(&mut self, tracer: F)
+    where
+        F: FnMut(&str) + 'a,
+    {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    /// Writes the nestest-style trace to `writer`, one line per instruction.
+    pub fn set_trace_writer<W: Write + 'a>(&mut self, mut writer: W) {
+        self.set_tracer(move |line| {
+            // a broken trace sink shouldn't take the emulation down with it
+            let _ = writeln!(writer, "{}", line);
+        });
+    }
+
+    pub fn clear_tracer(&mut self) {
+        self.tracer = None;
+    }
+
+    pub fn save_state(&self) -> CpuState {
+        CpuState {
+            a: self.register_a,
+            x: self.register_x,
+            y: self.register_y,
+            p: self.status.bits(),
+            sp: self.stack_pointer,
+            pc: self.program_counter,
+            cycles: self.bus.cycles() as u64,
+        }
+    }
+
+    /// Overwrites every register and the cycle counter. Memory, the PPU and
+    /// any pending interrupt are left as they are.
+    pub fn load_state(&mut self, state: &CpuState) {
+        self.register_a = state.a;
+        self.register_x = state.x;
+        self.register_y = state.y;
+        self.status = CpuFlags::from_bits_truncate(state.p);
+        self.stack_pointer = state.sp;
+        self.program_counter = state.pc;
+        self.bus.set_cycles(state.cycles as usize);
+    }
+
+    /// Calls `handler` with the fault and the PC of the offending instruction
+    /// whenever a push or pull wraps the stack pointer. Wrapping is legal on
+    /// the 6502, so this is purely a debugging aid for runaway recursion.
+    pub fn set_stack_fault_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(StackFault, u16) + 'a,
+    {
+        self.stack_fault_handler = Some(Box::new(handler));
+    }
+
+    pub fn clear_stack_fault_handler(&mut self) {
+        self.stack_fault_handler = None;
+    }
+
+    /// When enabled, a stack pointer wrap makes `step` and the run loops
+    /// return `CpuError::StackFault` once the instruction completes.
+    pub fn set_trap_stack_faults(&mut self, trap: bool) {
+        self.trap_stack_faults = trap;
+    }
+
+    /// Starts or stops recording subroutine and interrupt frames for
+    /// `call_stack`. Off by default since it costs a little per call.
+    pub fn set_call_stack_tracking(&mut self, enabled: bool) {
+        self.call_stack = enabled.then(CallStack::new);
+    }
+
+    /// Calls that haven't returned yet, outermost first. Always empty while
+    /// tracking is off.
+    pub fn call_stack(&self) -> &[CallFrame] {
+        self.call_stack.as_ref().map_or(&[], |stack| stack.frames())
+    }
+
+    /// Labels for the trace output to show in place of raw addresses
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = Some(symbols);
+    }
+
+    pub fn clear_symbols(&mut self) {
+        self.symbols = None;
+    }
+
+    pub fn symbols(&self) -> Option<&Symbols> {
+        self.symbols.as_ref()
+    }
+
+    fn push_call_frame(&mut self, kind: CallKind, return_addr: u16) {
+        if let Some(stack) = &mut self.call_stack {
+            stack.push(CallFrame {
+                kind,
+                target: self.program_counter,
+                return_addr,
+                bank: self.bus.prg_bank(self.program_counter),
+                stack_pointer: self.stack_pointer,
+            });
         }
     }
 
@@ -161,88 +485,107 @@ impl<'a> Cpu<'a> {
         }
     }
 
-    pub fn reset(&mut self) {
+    /// Cold boot: clears the registers, fills RAM with `pattern`, then runs
+    /// the reset sequence.
+    pub fn power_on(&mut self, pattern: RamInitPattern) {
+        self.bus.init_ram(pattern);
         self.register_a = 0;
         self.register_x = 0;
+        self.register_y = 0;
+        self.stack_pointer = 0;
         self.status = CpuFlags::from_bits_truncate(0b100100);
-
-        self.program_counter = self.mem_read_u16(0xFFFC);
+        self.reset();
     }
 
-    pub fn load(&mut self, program: Vec<u8>) {
-        for i in 0..(program.len() as u16) {
-            self.mem_write(0x8600 + i, program[i as usize]);
+    /// The reset button. Like an interrupt without the pushes, this takes 7
+    /// cycles: the stack writes turn into reads, so SP still drops by 3. The
+    /// other registers keep their values and I is set. Whatever the CPU was
+    /// in the middle of is abandoned.
+    pub fn reset(&mut self) {
+        self.halted = false;
+        self.nmi_pending = false;
+        self.dmc_stall = None;
+        if let Some(stack) = &mut self.call_stack {
+            stack.clear();
         }
-        self.mem_write_u16(0xFFFC, 0x8600);
-    }
 
-    pub fn load_and_run(&mut self, program: Vec<u8>) {
-        self.load(program);
-        self.reset();
-        self.run();
+        self.step = Step::Reset(0);
+        while !self.at_instruction_boundary() {
+            // none of the reset cycles can fail
+            let _ = self.clock();
+        }
     }
 
-    fn stack_pop(&mut self) -> u8 {
-        self.stack_pointer = self.stack_pointer.wrapping_add(1);
-        self.mem_read((STACK) + self.stack_pointer as u16)
+    /// Swaps the cartridge for `rom` and powers the console back on with
+    /// RAM filled with `pattern`. The tracer, hooks and controllers carry
+    /// over, while the symbols belonged to the old game and are dropped. On
+    /// error the old cartridge keeps running untouched.
+    pub fn insert_cartridge(&mut self, rom: Rom, pattern: RamInitPattern) -> Result<(), RomError> {
+        self.bus.insert_cartridge(rom)?;
+        self.symbols = None;
+        self.entry_point = None;
+        self.stack_fault = None;
+        self.cycle_overshoot = 0;
+        self.irq_pending = false;
+        self.nmi_poll = false;
+        self.irq_poll = false;
+        self.power_on(pattern);
+        Ok(())
     }
 
-    fn stack_push(&mut self, data: u8) {
-        self.mem_write((STACK) + self.stack_pointer as u16, data);
-        self.stack_pointer = self.stack_pointer.wrapping_sub(1)
+    pub fn load(&mut self, program: Vec<u8>) {
+        self.load_at(0x8600, &program);
+        self.set_entry_point(0x8600);
     }
 
-    fn stack_push_u16(&mut self, data: u16) {
-        let hi = (data >> 8) as u8;
-        let lo = (data & 0xff) as u8;
-        self.stack_push(hi);
-        self.stack_push(lo);
+    /// Copies `program` into memory starting at `addr`, without running any
+    /// bus side effects. Bytes landing outside RAM and PRG-ROM are dropped.
+    pub fn load_at(&mut self, addr: u16, program: &[u8]) {
+        for (i, byte) in program.iter().enumerate() {
+            self.bus.poke(addr.wrapping_add(i as u16), *byte);
+        }
     }
 
-    fn stack_pop_u16(&mut self) -> u16 {
-        let lo = self.stack_pop() as u16;
-        let hi = self.stack_pop() as u16;
-
-        hi << 8 | lo
+    /// Makes `reset` start execution at `addr` instead of the reset vector.
+    pub fn set_entry_point(&mut self, addr: u16) {
+        self.entry_point = Some(addr);
     }
 
-    fn lda(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let value = self.mem_read(addr);
-
-        self.register_a = value;
-        self.update_zero_and_negative_flag(self.register_a);
+    pub fn load_and_run(&mut self, program: Vec<u8>) -> Result<(), CpuError> {
+        self.load(program);
+        self.reset();
+        self.run()
     }
 
-    fn ldx(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let value = self.mem_read(addr);
-
-        self.register_x = value;
-        self.update_zero_and_negative_flag(self.register_x);
+    /// S moves down once a push has written the stack top
+    fn after_push(&mut self) {
+        if self.stack_pointer == 0x00 {
+            self.stack_fault.get_or_insert(StackFault::Overflow);
+        }
+        self.stack_pointer = self.stack_pointer.wrapping_sub(1)
     }
 
-    fn ldy(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let value = self.mem_read(addr);
-
-        self.register_y = value;
-        self.update_zero_and_negative_flag(self.register_y);
+    /// A pull reads the byte above S, then moves S up onto it
+    fn after_pull(&mut self) {
+        if self.stack_pointer == 0xFF {
+            self.stack_fault.get_or_insert(StackFault::Underflow);
+        }
+        self.stack_pointer = self.stack_pointer.wrapping_add(1);
     }
 
-    fn sta(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        self.mem_write(addr, self.register_a);
+    fn lda(&mut self, data: u8) {
+        self.register_a = data;
+        self.update_zero_and_negative_flag(self.register_a);
     }
 
-    fn stx(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        self.mem_write(addr, self.register_x);
+    fn ldx(&mut self, data: u8) {
+        self.register_x = data;
+        self.update_zero_and_negative_flag(self.register_x);
     }
 
-    fn sty(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        self.mem_write(addr, self.register_y);
+    fn ldy(&mut self, data: u8) {
+        self.register_y = data;
+        self.update_zero_and_negative_flag(self.register_y);
     }
 
     fn tax(&mut self) {
@@ -290,15 +633,71 @@ impl<'a> Cpu<'a> {
         self.update_zero_and_negative_flag(self.register_a);
     }
 
-    fn adc(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let value = self.mem_read(addr);
-        self.add_to_register_a(value);
+    /// ADC, shared with the unofficial opcodes built on it
+    fn add_with_carry(&mut self, data: u8) {
+        #[cfg(feature = "decimal")]
+        if self.status.contains(CpuFlags::DECIMAL_MODE) {
+            self.add_decimal(data);
+            return;
+        }
+        self.add_to_register_a(data);
+    }
+
+    /// SBC, shared with the unofficial opcodes built on it
+    fn subtract_with_carry(&mut self, data: u8) {
+        #[cfg(feature = "decimal")]
+        if self.status.contains(CpuFlags::DECIMAL_MODE) {
+            self.subtract_decimal(data);
+            return;
+        }
+        self.add_to_register_a(((data as i8).wrapping_neg().wrapping_sub(1)) as u8);
+    }
+
+    /// NMOS 6502 BCD addition. Z comes from the binary sum, while N and V are
+    /// taken before the high nibble is adjusted.
+    #[cfg(feature = "decimal")]
+    fn add_decimal(&mut self, data: u8) {
+        let a = self.register_a as u16;
+        let b = data as u16;
+        let carry = self.status.contains(CpuFlags::CARRY) as u16;
+
+        let mut lo = (a & 0x0F) + (b & 0x0F) + carry;
+        if lo >= 0x0A {
+            lo = ((lo + 0x06) & 0x0F) + 0x10;
+        }
+        let mut sum = (a & 0xF0) + (b & 0xF0) + lo;
+
+        self.status.set(CpuFlags::ZERO, (a + b + carry) as u8 == 0);
+        self.status.set(CpuFlags::NEGATIVE, sum & 0x80 != 0);
+        self.status
+            .set(CpuFlags::OVERFLOW, (a ^ sum) & (b ^ sum) & 0x80 != 0);
+
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+        self.status.set(CpuFlags::CARRY, sum >= 0x100);
+        self.register_a = sum as u8;
     }
 
-    fn sre(&mut self, mode: &AddressingMode) {
-        let data = self.lsr(mode);
-        self.xor_with_register_a(data);
+    /// NMOS 6502 BCD subtraction. All flags behave as in binary mode, only
+    /// the result is adjusted.
+    #[cfg(feature = "decimal")]
+    fn subtract_decimal(&mut self, data: u8) {
+        let a = self.register_a as i16;
+        let b = data as i16;
+        let borrow = !self.status.contains(CpuFlags::CARRY) as i16;
+
+        self.add_to_register_a(((data as i8).wrapping_neg().wrapping_sub(1)) as u8);
+
+        let mut lo = (a & 0x0F) - (b & 0x0F) - borrow;
+        if lo < 0 {
+            lo = ((lo - 0x06) & 0x0F) - 0x10;
+        }
+        let mut diff = (a & 0xF0) - (b & 0xF0) + lo;
+        if diff < 0 {
+            diff -= 0x60;
+        }
+        self.register_a = diff as u8;
     }
 
     fn add_to_register_a(&mut self, data: u8) {
@@ -329,139 +728,64 @@ impl<'a> Cpu<'a> {
         self.set_register_a(result);
     }
 
-    fn sbc(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let data = self.mem_read(addr);
-        self.add_to_register_a(((data as i8).wrapping_neg().wrapping_sub(1)) as u8);
-    }
-
-    fn php(&mut self) {
+    fn php(&self) -> u8 {
         //http://wiki.nesdev.com/w/index.php/CPU_status_flag_behavior
         let mut flags = self.status.clone();
         flags.insert(CpuFlags::BREAK);
         flags.insert(CpuFlags::BREAK2);
-        self.stack_push(flags.bits());
+        flags.bits()
     }
 
-    fn plp(&mut self) {
-        self.status = CpuFlags::from_bits(self.stack_pop()).unwrap();
+    fn plp(&mut self, data: u8) {
+        self.status = CpuFlags::from_bits(data).unwrap();
         self.status.remove(CpuFlags::BREAK);
         self.status.insert(CpuFlags::BREAK2);
     }
 
-    fn pla(&mut self) {
-        let data = self.stack_pop();
-        self.set_register_a(data);
-    }
-
-    fn and(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let data = self.mem_read(addr);
+    fn and(&mut self, data: u8) {
         self.set_register_a(data & self.register_a);
     }
 
-    fn inc(&mut self, mode: &AddressingMode) -> u8 {
-        let addr = self.get_operand_address(mode);
-        let mut data = self.mem_read(addr);
-        data = data.wrapping_add(1);
-        self.mem_write(addr, data);
-        self.update_zero_and_negative_flag(data);
-        data
+    fn increment(&mut self, data: u8) -> u8 {
+        let result = data.wrapping_add(1);
+        self.update_zero_and_negative_flag(result);
+        result
     }
 
-    fn lsr(&mut self, mode: &AddressingMode) -> u8 {
-        match mode {
-            AddressingMode::NoneAddressing => {
-                let mut data = self.register_a;
-                if data & 1 == 1 {
-                    self.status.insert(CpuFlags::CARRY);
-                } else {
-                    self.status.remove(CpuFlags::CARRY);
-                }
-                data >>= 1;
-                return data;
-            }
-            _ => {
-                let addr = self.get_operand_address(mode);
-                let mut data = self.mem_read(addr);
-                if data & 1 == 1 {
-                    self.status.insert(CpuFlags::CARRY);
-                } else {
-                    self.status.remove(CpuFlags::CARRY);
-                }
-                data >>= 1;
-                self.mem_write(addr, data);
-                self.update_zero_and_negative_flag(data);
-                self.set_register_a(data);
-                return 0;
-            }
-        }
+    fn decrement(&mut self, data: u8) -> u8 {
+        let result = data.wrapping_sub(1);
+        self.update_zero_and_negative_flag(result);
+        result
     }
 
-    fn asl(&mut self, mode: &AddressingMode) -> u8 {
-        match mode {
-            AddressingMode::NoneAddressing => {
-                let mut data = self.register_a;
-                if data >> 7 == 1 {
-                    self.status.insert(CpuFlags::CARRY)
-                } else {
-                    self.status.remove(CpuFlags::CARRY)
-                }
-                data <<= 1;
-                return data;
-            }
-            _ => {
-                let addr = self.get_operand_address(mode);
-                let mut data = self.mem_read(addr);
-                if data >> 7 == 1 {
-                    self.status.insert(CpuFlags::CARRY)
-                } else {
-                    self.status.remove(CpuFlags::CARRY)
-                }
-                data <<= 1;
-                self.mem_write(addr, data);
-                self.update_zero_and_negative_flag(data);
-                return 0;
-            }
-        }
+    fn shift_left(&mut self, data: u8) -> u8 {
+        self.status.set(CpuFlags::CARRY, data >> 7 == 1);
+        let result = data << 1;
+        self.update_zero_and_negative_flag(result);
+        result
     }
 
-    fn rol(&mut self, mode: &AddressingMode) -> u8 {
-        match mode {
-            AddressingMode::NoneAddressing => {
-                let mut data = self.register_a;
-                let old_carry = self.status.contains(CpuFlags::CARRY);
+    fn shift_right(&mut self, data: u8) -> u8 {
+        self.status.set(CpuFlags::CARRY, data & 1 == 1);
+        let result = data >> 1;
+        self.update_zero_and_negative_flag(result);
+        result
+    }
 
-                if data >> 7 == 1 {
-                    self.status.insert(CpuFlags::CARRY);
-                } else {
-                    self.status.remove(CpuFlags::CARRY);
-                }
-                data <<= 1;
-                if old_carry {
-                    data |= 1;
-                }
-                return data;
-            }
-            _ => {
-                let addr = self.get_operand_address(mode);
-                let mut data = self.mem_read(addr);
-                let old_carry = self.status.contains(CpuFlags::CARRY);
+    fn rotate_left(&mut self, data: u8) -> u8 {
+        let old_carry = self.status.contains(CpuFlags::CARRY) as u8;
+        self.status.set(CpuFlags::CARRY, data >> 7 == 1);
+        let result = data << 1 | old_carry;
+        self.update_zero_and_negative_flag(result);
+        result
+    }
 
-                if data >> 7 == 1 {
-                    self.status.insert(CpuFlags::CARRY);
-                } else {
-                    self.status.remove(CpuFlags::CARRY);
-                }
-                data <<= 1;
-                if old_carry {
-                    data |= 1;
-                }
-                self.mem_write(addr, data);
-                self.update_zero_and_negative_flag(data);
-                return 0;
-            }
-        }
+    fn rotate_right(&mut self, data: u8) -> u8 {
+        let old_carry = self.status.contains(CpuFlags::CARRY) as u8;
+        self.status.set(CpuFlags::CARRY, data & 1 == 1);
+        let result = data >> 1 | old_carry << 7;
+        self.update_zero_and_negative_flag(result);
+        result
     }
 
     fn and_with_register_a(&mut self, data: u8) {
@@ -472,97 +796,11 @@ impl<'a> Cpu<'a> {
         self.set_register_a(data ^ self.register_a);
     }
 
-    fn rla(&mut self, mode: &AddressingMode) {
-        let data = self.rol(mode);
-        self.and_with_register_a(data);
-    }
-
-    fn rra(&mut self, mode: &AddressingMode) {
-        let data = self.ror(mode);
-        self.add_to_register_a(data);
-    }
-
-    fn ror(&mut self, mode: &AddressingMode) -> u8 {
-        match mode {
-            AddressingMode::NoneAddressing => {
-                let mut data = self.register_a;
-                let old_carry = self.status.contains(CpuFlags::CARRY);
-
-                if data & 1 == 1 {
-                    self.status.insert(CpuFlags::CARRY);
-                } else {
-                    self.status.remove(CpuFlags::CARRY);
-                }
-                data = data >> 1;
-                if old_carry {
-                    data = data | 0b10000000;
-                }
-                self.set_register_a(data);
-                0
-            }
-            _ => {
-                let addr = self.get_operand_address(mode);
-                let mut data = self.mem_read(addr);
-                let old_carry = self.status.contains(CpuFlags::CARRY);
-
-                if data & 1 == 1 {
-                    self.status.insert(CpuFlags::CARRY);
-                } else {
-                    self.status.remove(CpuFlags::CARRY);
-                }
-                data = data >> 1;
-                if old_carry {
-                    data = data | 0b10000000;
-                }
-                self.mem_write(addr, data);
-                self.update_negative_flags(data);
-                data
-            }
-        }
-    }
-
-    fn update_negative_flags(&mut self, result: u8) {
-        if result >> 7 == 1 {
-            self.status.insert(CpuFlags::NEGATIVE)
-        } else {
-            self.status.remove(CpuFlags::NEGATIVE)
-        }
-    }
-
-    fn jsr(&mut self) {
-        self.stack_push_u16(self.program_counter + 2 - 1);
-        let target_address = self.mem_read_u16(self.program_counter);
-        self.program_counter = target_address
-    }
-
-    fn rts(&mut self) {
-        self.program_counter = self.stack_pop_u16() + 1;
-    }
-
-    // Not sure if correct lol
-    fn rti(&mut self) {
-        self.status = CpuFlags::from_bits(self.stack_pop()).expect("Couldn't create bitflag");
-        self.program_counter = self.stack_pop_u16();
-
-        self.status.remove(CpuFlags::BREAK);
-        self.status.insert(CpuFlags::BREAK2);
-    }
-
-    fn branch(&mut self, condition: bool) {
-        if condition {
-            let jump: i8 = self.mem_read(self.program_counter) as i8;
-            let jump_addr = self
-                .program_counter
-                .wrapping_add(1)
-                .wrapping_add(jump as u16);
-
-            self.program_counter = jump_addr;
-        }
+    fn or_with_register_a(&mut self, data: u8) {
+        self.set_register_a(data | self.register_a);
     }
 
-    fn compare(&mut self, mode: &AddressingMode, compare_with: u8) {
-        let addr = self.get_operand_address(mode);
-        let data = self.mem_read(addr);
+    fn compare(&mut self, data: u8, compare_with: u8) {
         // println!("data: {:8b}, compare with: {}", data, compare_with);
         if data <= compare_with {
             self.status.insert(CpuFlags::CARRY);
@@ -573,9 +811,7 @@ impl<'a> Cpu<'a> {
         self.update_zero_and_negative_flag(compare_with.wrapping_sub(data));
     }
 
-    fn bit(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let data = self.mem_read(addr);
+    fn bit(&mut self, data: u8) {
         if self.register_a & data == 0 {
             self.status.insert(CpuFlags::ZERO);
         } else {
@@ -586,43 +822,6 @@ impl<'a> Cpu<'a> {
         self.status.set(CpuFlags::OVERFLOW, data & 0b01000000 > 0);
     }
 
-    fn jmp(&mut self, mode: &AddressingMode) {
-        match mode {
-            AddressingMode::NoneAddressing => {
-                let mem_address = self.mem_read_u16(self.program_counter);
-                // let indirect_ref = self.mem_read_u16(mem_address);
-                //6502 bug mode with with page boundary:
-                //  if address $3000 contains $40, $30FF contains $80, and $3100 contains $50,
-                // the result of JMP ($30FF) will be a transfer of control to $4080 rather than $5080 as you intended
-                // i.e. the 6502 took the low byte of the address from $30FF and the high byte from $3000
-
-                let indirect_ref = if mem_address & 0x00FF == 0x00FF {
-                    let lo = self.mem_read(mem_address);
-                    let hi = self.mem_read(mem_address & 0xFF00);
-                    (hi as u16) << 8 | (lo as u16)
-                } else {
-                    self.mem_read_u16(mem_address)
-                };
-
-                self.program_counter = indirect_ref;
-            }
-            AddressingMode::Absolute => {
-                let mem_address = self.mem_read_u16(self.program_counter);
-                self.program_counter = mem_address;
-            }
-            _ => (),
-        }
-    }
-
-    fn dec(&mut self, mode: &AddressingMode) -> u8 {
-        let addr = self.get_operand_address(mode);
-        let mut data = self.mem_read(addr);
-        data = data.wrapping_sub(1);
-        self.mem_write(addr, data);
-        self.update_zero_and_negative_flag(data);
-        data
-    }
-
     fn txs(&mut self) {
         self.stack_pointer = self.register_x;
     }
@@ -632,236 +831,992 @@ impl<'a> Cpu<'a> {
         self.update_zero_and_negative_flag(self.register_x);
     }
 
-    fn eor(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let data = self.mem_read(addr);
-        self.set_register_a(data ^ self.register_a);
+    fn lax(&mut self, data: u8) {
+        self.set_register_a(data);
+        self.register_x = self.register_a;
     }
 
-    fn ora(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let data = self.mem_read(addr);
-        self.set_register_a(data | self.register_a);
+    fn dcp(&mut self, data: u8) -> u8 {
+        let result = self.decrement(data);
+        self.status.set(CpuFlags::CARRY, result <= self.register_a);
+        self.update_zero_and_negative_flag(self.register_a.wrapping_sub(result));
+        result
     }
 
-    fn lax(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let data = self.mem_read(addr);
-        self.set_register_a(data);
-        self.register_x = self.register_a;
+    fn anc(&mut self, data: u8) {
+        self.and(data);
+        self.status
+            .set(CpuFlags::CARRY, self.status.contains(CpuFlags::NEGATIVE));
     }
 
-    fn sub_from_register_a(&mut self, data: u8) {
-        self.add_to_register_a(((data as i8).wrapping_neg().wrapping_sub(1)) as u8);
+    fn alr(&mut self, data: u8) {
+        self.and(data);
+        self.status.set(CpuFlags::CARRY, self.register_a & 1 == 1);
+        self.set_register_a(self.register_a >> 1);
     }
 
-    fn isb(&mut self, mode: &AddressingMode) {
-        let data = self.inc(mode);
-        self.sub_from_register_a(data);
+    fn arr(&mut self, data: u8) {
+        self.and(data);
+        let carry_in = if self.status.contains(CpuFlags::CARRY) {
+            0b1000_0000
+        } else {
+            0
+        };
+        self.set_register_a((self.register_a >> 1) | carry_in);
+
+        let bit_6 = self.register_a >> 6 & 1;
+        let bit_5 = self.register_a >> 5 & 1;
+        self.status.set(CpuFlags::CARRY, bit_6 == 1);
+        self.status.set(CpuFlags::OVERFLOW, bit_6 ^ bit_5 == 1);
     }
 
-    fn dcp(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let mut data = self.mem_read(addr);
-        data = data.wrapping_sub(1);
-        self.mem_write(addr, data);
-        // self._update_zero_and_negative_flags(data);
-        if data <= self.register_a {
-            self.status.insert(CpuFlags::CARRY);
-        }
+    fn axs(&mut self, data: u8) {
+        let and = self.register_a & self.register_x;
 
-        self.update_zero_and_negative_flag(self.register_a.wrapping_sub(data));
+        self.status.set(CpuFlags::CARRY, and >= data);
+        self.register_x = and.wrapping_sub(data);
+        self.update_zero_and_negative_flag(self.register_x);
     }
 
-    fn aax(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        self.mem_write(addr, self.register_x & self.register_a);
+    fn lxa(&mut self, data: u8) {
+        self.set_register_a((self.register_a | UNSTABLE_MAGIC) & data);
+        self.register_x = self.register_a;
     }
 
-    fn or_with_register_a(&mut self, data: u8) {
-        self.set_register_a(data | self.register_a);
+    fn xaa(&mut self, data: u8) {
+        self.set_register_a((self.register_a | UNSTABLE_MAGIC) & self.register_x & data);
     }
 
-    fn slo(&mut self, mode: &AddressingMode) {
-        let data = self.asl(mode);
-        self.or_with_register_a(data);
+    fn las(&mut self, data: u8) {
+        let data = data & self.stack_pointer;
+        self.set_register_a(data);
+        self.register_x = data;
+        self.stack_pointer = data;
+    }
+
+    /// SHA/SHX/SHY/TAS store `value & (H + 1)`, H being the high byte of the
+    /// un-indexed base address. When indexing crosses a page, the stored
+    /// value also replaces the high byte of the target address.
+    fn store_and_high(&mut self, value: u8) -> u8 {
+        let index = match self.operation.mode {
+            AddressingMode::Absolute_X => self.register_x,
+            _ => self.register_y,
+        };
+        let base = self.address.wrapping_sub(index as u16);
+        let data = value & ((base >> 8) as u8).wrapping_add(1);
+
+        if page_cross(base, self.address) {
+            self.address = (data as u16) << 8 | (self.address & 0x00FF);
+        }
+        data
     }
 
-    pub fn get_absolute_address(&mut self, mode: &AddressingMode, addr: u16) -> u16 {
-        match mode {
-            AddressingMode::ZeroPage => self.mem_read(addr) as u16,
+    /// Instructions that only read their operand. Unofficial multi-byte
+    /// NOPs still read theirs, so the indexed variants pay the page-cross
+    /// penalty like any other read.
+    fn read_operand(&mut self, data: u8) {
+        match self.operation.mnemonic {
+            Mnemonic::ADC => self.add_with_carry(data),
+            Mnemonic::AND => self.and(data),
+            Mnemonic::BIT => self.bit(data),
+            Mnemonic::CMP => self.compare(data, self.register_a),
+            Mnemonic::CPX => self.compare(data, self.register_x),
+            Mnemonic::CPY => self.compare(data, self.register_y),
+            Mnemonic::EOR => self.xor_with_register_a(data),
+            Mnemonic::LDA => self.lda(data),
+            Mnemonic::LDX => self.ldx(data),
+            Mnemonic::LDY => self.ldy(data),
+            Mnemonic::ORA => self.or_with_register_a(data),
+            Mnemonic::SBC => self.subtract_with_carry(data),
+            Mnemonic::LAX => self.lax(data),
+            Mnemonic::ANC => self.anc(data),
+            Mnemonic::ALR => self.alr(data),
+            Mnemonic::ARR => self.arr(data),
+            Mnemonic::AXS => self.axs(data),
+            Mnemonic::LXA => self.lxa(data),
+            Mnemonic::XAA => self.xaa(data),
+            Mnemonic::LAS => self.las(data),
+            _ => (),
+        }
+    }
 
-            AddressingMode::Absolute => self.mem_read_u16(addr),
+    /// The value a store writes. The SHx family may move `address` too.
+    fn store_value(&mut self) -> u8 {
+        match self.operation.mnemonic {
+            Mnemonic::STX => self.register_x,
+            Mnemonic::STY => self.register_y,
+            Mnemonic::AAX => self.register_x & self.register_a,
+            Mnemonic::SHA => self.store_and_high(self.register_a & self.register_x),
+            Mnemonic::SHX => self.store_and_high(self.register_x),
+            Mnemonic::SHY => self.store_and_high(self.register_y),
+            Mnemonic::TAS => {
+                self.stack_pointer = self.register_a & self.register_x;
+                self.store_and_high(self.stack_pointer)
+            }
+            _ => self.register_a,
+        }
+    }
 
-            AddressingMode::ZeroPage_X => {
-                let pos = self.mem_read(addr);
-                let addr = pos.wrapping_add(self.register_x) as u16;
-                addr
+    /// The new value of a read-modify-write operand, along with whatever the
+    /// unofficial combined opcodes do to A
+    fn modify(&mut self, data: u8) -> u8 {
+        match self.operation.mnemonic {
+            Mnemonic::ASL => self.shift_left(data),
+            Mnemonic::LSR => self.shift_right(data),
+            Mnemonic::ROL => self.rotate_left(data),
+            Mnemonic::ROR => self.rotate_right(data),
+            Mnemonic::INC => self.increment(data),
+            Mnemonic::DEC => self.decrement(data),
+            Mnemonic::SLO => {
+                let result = self.shift_left(data);
+                self.or_with_register_a(result);
+                result
             }
-            AddressingMode::ZeroPage_Y => {
-                let pos = self.mem_read(addr);
-                let addr = pos.wrapping_add(self.register_y) as u16;
-                addr
+            Mnemonic::RLA => {
+                let result = self.rotate_left(data);
+                self.and_with_register_a(result);
+                result
             }
-
-            AddressingMode::Absolute_X => {
-                let base = self.mem_read_u16(addr);
-                let addr = base.wrapping_add(self.register_x as u16);
-                addr
+            Mnemonic::SRE => {
+                let result = self.shift_right(data);
+                self.xor_with_register_a(result);
+                result
             }
-            AddressingMode::Absolute_Y => {
-                let base = self.mem_read_u16(addr);
-                let addr = base.wrapping_add(self.register_y as u16);
-                addr
+            Mnemonic::RRA => {
+                let result = self.rotate_right(data);
+                self.add_with_carry(result);
+                result
             }
-
-            AddressingMode::Indirect_X => {
-                let base = self.mem_read(addr);
-
-                let ptr: u8 = (base as u8).wrapping_add(self.register_x);
-                let lo = self.mem_read(ptr as u16);
-                let hi = self.mem_read(ptr.wrapping_add(1) as u16);
-                (hi as u16) << 8 | (lo as u16)
+            Mnemonic::DCP => self.dcp(data),
+            Mnemonic::ISB => {
+                let result = self.increment(data);
+                self.subtract_with_carry(result);
+                result
             }
-            AddressingMode::Indirect_Y => {
-                let base = self.mem_read(addr);
+            _ => data,
+        }
+    }
 
-                let lo = self.mem_read(base as u16);
-                let hi = self.mem_read((base as u8).wrapping_add(1) as u16);
-                let deref_base = (hi as u16) << 8 | (lo as u16);
-                let deref = deref_base.wrapping_add(self.register_y as u16);
-                deref
+    /// Single byte instructions that work on registers alone
+    fn implied(&mut self) {
+        match self.operation.mnemonic {
+            Mnemonic::CLC => self.status.remove(CpuFlags::CARRY),
+            Mnemonic::CLD => self.status.remove(CpuFlags::DECIMAL_MODE),
+            Mnemonic::CLI => self.status.remove(CpuFlags::INTERRUPT_DISABLE),
+            Mnemonic::CLV => self.status.remove(CpuFlags::OVERFLOW),
+            Mnemonic::SEC => self.status.insert(CpuFlags::CARRY),
+            Mnemonic::SED => self.status.insert(CpuFlags::DECIMAL_MODE),
+            Mnemonic::SEI => self.status.insert(CpuFlags::INTERRUPT_DISABLE),
+            Mnemonic::DEX => self.dex(),
+            Mnemonic::DEY => self.dey(),
+            Mnemonic::INX => self.inx(),
+            Mnemonic::INY => self.iny(),
+            Mnemonic::TAX => self.tax(),
+            Mnemonic::TAY => self.tay(),
+            Mnemonic::TSX => self.tsx(),
+            Mnemonic::TXA => self.txa(),
+            Mnemonic::TXS => self.txs(),
+            Mnemonic::TYA => self.tya(),
+            // accumulator mode shifts and rotates
+            Mnemonic::ASL | Mnemonic::LSR | Mnemonic::ROL | Mnemonic::ROR => {
+                self.register_a = self.modify(self.register_a);
             }
+            _ => (),
+        }
+    }
 
-            _ => {
-                panic!("mode {:?} is not supported", mode);
+    /// Where an instruction goes once its operand address is known: reads
+    /// fetch and apply the operand, stores write, and read-modify-write
+    /// instructions do both.
+    fn operand_step(&mut self) -> Step {
+        let flags = match self.operation.mode {
+            AddressingMode::Indirect_X | AddressingMode::Indirect_Y => {
+                CoverageFlags::DATA | CoverageFlags::INDIRECT_DATA
+            }
+            _ => CoverageFlags::DATA,
+        };
+        self.bus.mark_coverage(self.address, flags);
+
+        match self.operation.mnemonic {
+            Mnemonic::STA
+            | Mnemonic::STX
+            | Mnemonic::STY
+            | Mnemonic::AAX
+            | Mnemonic::SHA
+            | Mnemonic::SHX
+            | Mnemonic::SHY
+            | Mnemonic::TAS => {
+                self.data = self.store_value();
+                Step::Write
             }
+            Mnemonic::ASL
+            | Mnemonic::LSR
+            | Mnemonic::ROL
+            | Mnemonic::ROR
+            | Mnemonic::INC
+            | Mnemonic::DEC
+            | Mnemonic::SLO
+            | Mnemonic::RLA
+            | Mnemonic::SRE
+            | Mnemonic::RRA
+            | Mnemonic::DCP
+            | Mnemonic::ISB => Step::ModifyRead,
+            _ => Step::Read,
         }
     }
 
-    pub fn run(&mut self) {
+    /// Adds an index register to `base`. Indexed modes spend a cycle reading
+    /// from the address before its high byte was fixed up. Opcodes with a
+    /// page-cross penalty only do so when a page was actually crossed; the
+    /// rest, which write their operand, always do.
+    fn index(&mut self, base: u16, index: u8) -> Step {
+        self.address = base.wrapping_add(index as u16);
+        self.page_crossed = page_cross(base, self.address);
+        if self.page_crossed || !self.operation.page_cross_penalty {
+            Step::Fixup
+        } else {
+            self.operand_step()
+        }
+    }
+
+    fn branch_condition(&self) -> bool {
+        match self.operation.mnemonic {
+            Mnemonic::BCC => !self.status.contains(CpuFlags::CARRY),
+            Mnemonic::BCS => self.status.contains(CpuFlags::CARRY),
+            Mnemonic::BEQ => self.status.contains(CpuFlags::ZERO),
+            Mnemonic::BMI => self.status.contains(CpuFlags::NEGATIVE),
+            Mnemonic::BNE => !self.status.contains(CpuFlags::ZERO),
+            Mnemonic::BPL => !self.status.contains(CpuFlags::NEGATIVE),
+            Mnemonic::BVC => !self.status.contains(CpuFlags::OVERFLOW),
+            _ => self.status.contains(CpuFlags::OVERFLOW),
+        }
+    }
+
+    pub fn run(&mut self) -> Result<(), CpuError> {
         self.run_with_callback(|_| {})
     }
 
-    pub fn run_with_callback<F>(&mut self, mut callback: F)
+    /// Runs instructions until `stop` is called, or until the CPU jams or
+    /// hits an error, which is returned. The callback sees the CPU before
+    /// each instruction executes.
+    pub fn run_with_callback<F>(&mut self, mut callback: F) -> Result<(), CpuError>
     where
         F: FnMut(&mut Cpu),
     {
-        // Fetch next execution instruction from the instruction memory
-        // Decode the instruction
-        // Execute the Instruction
-        // Repeat the cycle
+        while !self.halted {
+            callback(self);
+            if self.halted {
+                break;
+            }
 
-        loop {
-            if let Some(_nmi) = self.bus.poll_nmi_status() {
-                self.interrupt_nmi();
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Runs whole instructions for `cycles` CPU cycles, e.g. one frame's
+    /// worth, and returns the cycles actually spent. The last instruction is
+    /// finished, so the budget is usually overshot slightly; the overshoot is
+    /// taken out of the next call's budget so timing doesn't drift.
+    pub fn run_for_cycles(&mut self, cycles: u64) -> Result<u64, CpuError> {
+        let carried = self.cycle_overshoot;
+        let mut spent = carried;
+        while spent < cycles && !self.halted {
+            spent += self.step()? as u64;
+        }
+        self.cycle_overshoot = spent.saturating_sub(cycles);
+        Ok(spent - carried)
+    }
+
+    /// Runs until the PPU enters vertical blank, i.e. until a frame is ready
+    /// to present. Returns the CPU cycles spent.
+    pub fn run_until_vblank(&mut self) -> Result<u64, CpuError> {
+        let mut spent = 0;
+        let mut was_in_vblank = self.bus.ppu().in_vblank();
+        while !self.halted {
+            spent += self.step()? as u64;
+            let in_vblank = self.bus.ppu().in_vblank();
+            if in_vblank && !was_in_vblank {
+                break;
             }
+            was_in_vblank = in_vblank;
+        }
+        Ok(spent)
+    }
+
+    /// Clocks the CPU up to the next instruction boundary: a whole
+    /// instruction, or whatever is left of one that `clock` started, then
+    /// any OAM DMA it set off and any NMI or IRQ it polled. Returns the
+    /// number of CPU cycles spent, in a u16 rather than a u8 because a
+    /// write to $4014 adds the 513 or 514 cycles of its OAM DMA.
+    pub fn step(&mut self) -> Result<u16, CpuError> {
+        let start_cycles = self.bus.cycles();
+        self.clock()?;
+        while !self.at_instruction_boundary() {
+            self.clock()?;
+        }
+        Ok((self.bus.cycles() - start_cycles) as u16)
+    }
 
-            callback(self);
-            let opcode = self.mem_read(self.program_counter);
-            self.program_counter += 1;
-            let program_counter_state = self.program_counter;
-            let operation = OPCODES_MAP[&opcode];
-            self.bus.tick(operation.cycles);
-
-            match operation.mnemonic {
-                "ADC" => self.adc(&operation.mode),
-                "AND" => self.and(&operation.mode),
-                "ASL" => {
-                    self.asl(&operation.mode);
+    /// Whether the next clock fetches an opcode, so the registers hold the
+    /// result of every instruction so far and nothing of the next one
+    pub fn at_instruction_boundary(&self) -> bool {
+        self.step == Step::Opcode && !self.instruction_started && self.dmc_stall.is_none()
+    }
+
+    /// Runs exactly one CPU cycle: the rest of the system is ticked, then
+    /// the CPU makes one bus access and moves its state machine on. An
+    /// instruction, an interrupt sequence or an OAM DMA is spread over as
+    /// many calls as it has cycles, and a DMC fetch steals whole calls in
+    /// between. Errors come out of the call that finishes the instruction.
+    pub fn clock(&mut self) -> Result<(), CpuError> {
+        if let Some(stall) = self.dmc_stall {
+            self.dmc_stall_clock(stall);
+            return Ok(());
+        }
+
+        if self.at_instruction_boundary() {
+            self.begin_instruction();
+        }
+
+        let data = match self.access() {
+            Access::Read(addr) => {
+                if let Some(sample_addr) = self.bus.dmc_dma_request() {
+                    self.dmc_stall_clock(DmcStall {
+                        addr,
+                        sample_addr,
+                        halted_reads: 0,
+                    });
+                    return Ok(());
+                }
+                self.bus.tick(1);
+                self.bus.mem_read(addr)
+            }
+            Access::Write(addr, data) => {
+                self.bus.tick(1);
+                self.bus.mem_write(addr, data);
+                data
+            }
+        };
+        self.sample_interrupt_lines();
+        self.advance(data)
+    }
+
+    fn begin_instruction(&mut self) {
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer(&trace::trace(self));
+            self.tracer = Some(tracer);
+        }
+
+        self.opcode_addr = self.program_counter;
+        self.bus.begin_instruction(self.opcode_addr);
+        self.instruction_started = true;
+    }
+
+    /// The bus access the current step makes, worked out from the registers
+    /// and what the earlier steps latched
+    fn access(&self) -> Access {
+        let pc = self.program_counter;
+        let stack_top = STACK + self.stack_pointer as u16;
+        let stack_next = STACK + self.stack_pointer.wrapping_add(1) as u16;
+        match self.step {
+            Step::Opcode
+            | Step::Implied
+            | Step::Immediate
+            | Step::ZeroPage
+            | Step::ZeroPageBase
+            | Step::AbsoluteLow
+            | Step::AbsoluteHigh
+            | Step::PointerBase
+            | Step::BranchOffset
+            | Step::BranchTaken
+            | Step::JsrLow
+            | Step::JsrHigh
+            | Step::InterruptDummy(_)
+            | Step::OamHalt
+            | Step::OamAlign => Access::Read(pc),
+            Step::ZeroPageIndex | Step::PointerIndex | Step::PointerLow => {
+                Access::Read(self.pointer as u16)
+            }
+            Step::PointerHigh => Access::Read(self.pointer.wrapping_add(1) as u16),
+            Step::Fixup if self.page_crossed => Access::Read(self.address.wrapping_sub(0x100)),
+            Step::Fixup
+            | Step::IndirectLow
+            | Step::Read
+            | Step::ModifyRead
+            | Step::ReturnIncrement
+            | Step::VectorLow
+            | Step::OamRead => Access::Read(self.address),
+            // 6502 bug: the pointer's high byte is read without carrying
+            // into the page, so JMP ($30FF) reads $30FF and $3000
+            Step::IndirectHigh => {
+                Access::Read((self.address & 0xFF00) | (self.address.wrapping_add(1) & 0x00FF))
+            }
+            Step::VectorHigh => Access::Read(self.address.wrapping_add(1)),
+            Step::Write | Step::ModifyDummyWrite | Step::ModifyWrite => {
+                Access::Write(self.address, self.data)
+            }
+            Step::BranchFixup => Access::Read((pc & 0xFF00) | (self.address & 0x00FF)),
+            Step::StackDummy => Access::Read(stack_top),
+            Step::Pull | Step::PullLow | Step::PullHigh => Access::Read(stack_next),
+            Step::Push | Step::PushStatus => Access::Write(stack_top, self.data),
+            Step::PushReturnHigh | Step::PushPcHigh => Access::Write(stack_top, (pc >> 8) as u8),
+            Step::PushReturnLow | Step::PushPcLow => Access::Write(stack_top, pc as u8),
+            Step::Reset(cycle) => Access::Read(match cycle {
+                0 | 1 => pc,
+                2..=4 => stack_top,
+                5 => 0xFFFC,
+                _ => 0xFFFD,
+            }),
+            Step::OamWrite => Access::Write(0x2004, self.data),
+        }
+    }
+
+    /// Takes in what the current step's access read (or wrote) and picks
+    /// the next step. Returning to `Step::Opcode` ends the instruction.
+    fn advance(&mut self, data: u8) -> Result<(), CpuError> {
+        let pc = self.program_counter;
+        self.step = match self.step {
+            Step::Opcode => {
+                self.program_counter = pc.wrapping_add(1);
+                let operation = OPCODE_TABLE[data as usize];
+                self.operation = operation;
+                for i in 0..operation.len as u16 {
+                    self.bus
+                        .mark_coverage(pc.wrapping_add(i), CoverageFlags::CODE);
+                }
+
+                match operation.mode {
+                    // single byte instructions still fetch the byte after
+                    // the opcode
+                    _ if operation.len == 1 => Step::Implied,
+                    AddressingMode::Immediate => Step::Immediate,
+                    AddressingMode::Relative => Step::BranchOffset,
+                    AddressingMode::ZeroPage => Step::ZeroPage,
+                    AddressingMode::ZeroPage_X | AddressingMode::ZeroPage_Y => Step::ZeroPageBase,
+                    AddressingMode::Absolute if operation.mnemonic == Mnemonic::JSR => Step::JsrLow,
+                    AddressingMode::Absolute
+                    | AddressingMode::Absolute_X
+                    | AddressingMode::Absolute_Y
+                    | AddressingMode::Indirect => Step::AbsoluteLow,
+                    AddressingMode::Indirect_X | AddressingMode::Indirect_Y => Step::PointerBase,
+                    AddressingMode::Accumulator | AddressingMode::NoneAddressing => {
+                        self.next_instruction();
+                        return Err(CpuError::NoOperandAddress {
+                            mode: operation.mode,
+                            addr: self.program_counter,
+                        });
+                    }
+                }
+            }
+
+            Step::Implied => match self.operation.mnemonic {
+                Mnemonic::BRK if self.stop_on_brk => {
+                    self.halted = true;
+                    self.next_instruction();
+                    return Ok(());
+                }
+                Mnemonic::BRK => {
+                    // the padding byte has been fetched, so the pushed
+                    // return address is PC + 2
+                    self.program_counter = pc.wrapping_add(1);
+                    self.begin_interrupt(interrupt::BRK)
+                }
+                Mnemonic::KIL => {
+                    // the CPU locks up until it is reset: keep re-executing KIL
+                    self.program_counter = self.opcode_addr;
+                    self.next_instruction();
+                    return Err(CpuError::Jammed {
+                        pc: self.opcode_addr,
+                        opcode: self.operation.code,
+                    });
                 }
-                "BCC" => self.branch(!self.status.contains(CpuFlags::CARRY)),
-                "BCS" => self.branch(self.status.contains(CpuFlags::CARRY)),
-                "BEQ" => self.branch(self.status.contains(CpuFlags::ZERO)),
-                "BMI" => self.branch(self.status.contains(CpuFlags::NEGATIVE)),
-                "BNE" => self.branch(!self.status.contains(CpuFlags::ZERO)),
-                "BPL" => self.branch(!self.status.contains(CpuFlags::NEGATIVE)),
-                "BRK" => return,
-                "BVC" => self.branch(!self.status.contains(CpuFlags::OVERFLOW)),
-                "BVS" => self.branch(self.status.contains(CpuFlags::OVERFLOW)),
-                "CLC" => self.status.remove(CpuFlags::CARRY),
-                "CLD" => self.status.remove(CpuFlags::DECIMAL_MODE),
-                "CLI" => self.status.remove(CpuFlags::INTERRUPT_DISABLE),
-                "CLV" => self.status.remove(CpuFlags::OVERFLOW),
-                "CMP" => self.compare(&operation.mode, self.register_a),
-                "CPX" => self.compare(&operation.mode, self.register_x),
-                "CPY" => self.compare(&operation.mode, self.register_y),
-                "DEX" => self.dex(),
-                "INX" => self.inx(),
-                "INY" => self.iny(),
-                "JSR" => self.jsr(),
-                "LDA" => self.lda(&operation.mode),
-                "LDX" => self.ldx(&operation.mode),
-                "PHA" => self.stack_push(self.register_a),
-                "PHP" => self.php(),
-                "PLA" => self.pla(),
-                "PLP" => self.plp(),
-                "ROL" => {
-                    self.rol(&operation.mode);
+                Mnemonic::PHA => {
+                    self.data = self.register_a;
+                    Step::Push
+                }
+                Mnemonic::PHP => {
+                    self.data = self.php();
+                    Step::Push
+                }
+                Mnemonic::PLA | Mnemonic::PLP | Mnemonic::RTS | Mnemonic::RTI => Step::StackDummy,
+                _ => {
+                    self.implied();
+                    return self.end_instruction();
+                }
+            },
+
+            Step::Immediate => {
+                self.program_counter = pc.wrapping_add(1);
+                self.read_operand(data);
+                return self.end_instruction();
+            }
+
+            Step::ZeroPage => {
+                self.program_counter = pc.wrapping_add(1);
+                self.address = data as u16;
+                self.operand_step()
+            }
+            Step::ZeroPageBase => {
+                self.program_counter = pc.wrapping_add(1);
+                self.pointer = data;
+                Step::ZeroPageIndex
+            }
+            // the un-indexed address is read while the index is added
+            Step::ZeroPageIndex => {
+                let index = match self.operation.mode {
+                    AddressingMode::ZeroPage_X => self.register_x,
+                    _ => self.register_y,
+                };
+                self.address = self.pointer.wrapping_add(index) as u16;
+                self.operand_step()
+            }
+
+            Step::AbsoluteLow => {
+                self.program_counter = pc.wrapping_add(1);
+                self.address = data as u16;
+                Step::AbsoluteHigh
+            }
+            Step::AbsoluteHigh => {
+                self.program_counter = pc.wrapping_add(1);
+                let addr = (data as u16) << 8 | self.address;
+                match self.operation.mode {
+                    AddressingMode::Absolute_X => self.index(addr, self.register_x),
+                    AddressingMode::Absolute_Y => self.index(addr, self.register_y),
+                    AddressingMode::Indirect => {
+                        self.address = addr;
+                        self.bus.mark_coverage(addr, CoverageFlags::DATA);
+                        self.bus
+                            .mark_coverage(addr.wrapping_add(1), CoverageFlags::DATA);
+                        Step::IndirectLow
+                    }
+                    _ if self.operation.mnemonic == Mnemonic::JMP => {
+                        self.program_counter = addr;
+                        return self.end_instruction();
+                    }
+                    _ => {
+                        self.address = addr;
+                        self.operand_step()
+                    }
+                }
+            }
+            Step::Fixup => self.operand_step(),
+
+            Step::PointerBase => {
+                self.program_counter = pc.wrapping_add(1);
+                self.pointer = data;
+                match self.operation.mode {
+                    AddressingMode::Indirect_X => Step::PointerIndex,
+                    _ => Step::PointerLow,
+                }
+            }
+            Step::PointerIndex => {
+                self.pointer = self.pointer.wrapping_add(self.register_x);
+                Step::PointerLow
+            }
+            Step::PointerLow => {
+                self.address = data as u16;
+                Step::PointerHigh
+            }
+            Step::PointerHigh => {
+                let addr = (data as u16) << 8 | self.address;
+                match self.operation.mode {
+                    AddressingMode::Indirect_Y => self.index(addr, self.register_y),
+                    _ => {
+                        self.address = addr;
+                        self.operand_step()
+                    }
                 }
-                "ROR" => {
-                    self.ror(&operation.mode);
+            }
+
+            Step::IndirectLow => {
+                self.data = data;
+                Step::IndirectHigh
+            }
+            Step::IndirectHigh => {
+                self.program_counter = (data as u16) << 8 | self.data as u16;
+                self.bus
+                    .mark_coverage(self.program_counter, CoverageFlags::INDIRECT_CODE);
+                return self.end_instruction();
+            }
+
+            Step::Read => {
+                self.read_operand(data);
+                return self.end_instruction();
+            }
+            Step::Write => return self.end_instruction(),
+            // registers with write side effects (PPU, mappers) see both the
+            // unmodified value and the result
+            Step::ModifyRead => {
+                self.data = data;
+                Step::ModifyDummyWrite
+            }
+            Step::ModifyDummyWrite => {
+                self.data = self.modify(data);
+                Step::ModifyWrite
+            }
+            Step::ModifyWrite => return self.end_instruction(),
+
+            // the offset is fetched whether or not the branch is taken
+            Step::BranchOffset => {
+                self.program_counter = pc.wrapping_add(1);
+                if !self.branch_condition() {
+                    return self.end_instruction();
                 }
-                "RTS" => self.rts(),
-                "SBC" => self.sbc(&operation.mode),
-                "SEC" => self.status.insert(CpuFlags::CARRY),
-                "SED" => self.status.insert(CpuFlags::DECIMAL_MODE),
-                "SEI" => self.status.insert(CpuFlags::INTERRUPT_DISABLE),
-                "STA" => self.sta(&operation.mode),
-                "TAX" => self.tax(),
-                "TXA" => self.txa(),
-                "LSR" => {
-                    self.lsr(&operation.mode);
+                self.address = self.program_counter.wrapping_add(data as i8 as u16);
+                // Interrupts are polled after the offset fetch. A taken
+                // branch that stays on its page does not poll again on its
+                // last cycle, so an interrupt arriving then waits one more
+                // instruction.
+                self.branch_polls = (self.nmi_poll, self.irq_poll);
+                Step::BranchTaken
+            }
+            // +1 cycle for taking the branch, spent adding the offset to PCL
+            Step::BranchTaken if page_cross(pc, self.address) => Step::BranchFixup,
+            Step::BranchTaken => {
+                (self.nmi_poll, self.irq_poll) = self.branch_polls;
+                self.program_counter = self.address;
+                return self.end_instruction();
+            }
+            // +1 more to fix up PCH, reading from the wrong page first
+            Step::BranchFixup => {
+                self.program_counter = self.address;
+                return self.end_instruction();
+            }
+
+            // the high byte of the target is only fetched after the return
+            // address has been pushed
+            Step::JsrLow => {
+                self.program_counter = pc.wrapping_add(1);
+                self.address = data as u16;
+                Step::StackDummy
+            }
+            Step::JsrHigh => {
+                let return_addr = pc.wrapping_add(1);
+                self.program_counter = (data as u16) << 8 | self.address;
+                self.push_call_frame(CallKind::Subroutine, return_addr);
+                return self.end_instruction();
+            }
+
+            // the cycle spent incrementing S before a pull reads the stack
+            // top, or JSR's internal cycle before its pushes
+            Step::StackDummy => match self.operation.mnemonic {
+                Mnemonic::JSR => Step::PushReturnHigh,
+                Mnemonic::RTS => Step::PullLow,
+                _ => Step::Pull,
+            },
+            Step::Push => {
+                self.after_push();
+                return self.end_instruction();
+            }
+            Step::Pull => {
+                self.after_pull();
+                match self.operation.mnemonic {
+                    Mnemonic::PLA => self.set_register_a(data),
+                    _ => self.plp(data),
                 }
-                "INC" => {
-                    self.inc(&operation.mode);
+                // RTI restores the status first, early enough for its I flag
+                // to count from the next cycle's interrupt sample
+                if self.operation.mnemonic != Mnemonic::RTI {
+                    return self.end_instruction();
                 }
-                "BIT" => self.bit(&operation.mode),
-                "LDY" => self.ldy(&operation.mode),
-                "NOP" => (),
-                "JMP" => self.jmp(&operation.mode),
-                "DEC" => {
-                    self.dec(&operation.mode);
+                Step::PullLow
+            }
+            Step::PullLow => {
+                self.after_pull();
+                self.address = data as u16;
+                Step::PullHigh
+            }
+            Step::PullHigh => {
+                self.after_pull();
+                self.address |= (data as u16) << 8;
+                if self.operation.mnemonic == Mnemonic::RTS {
+                    Step::ReturnIncrement
+                } else {
+                    self.program_counter = self.address;
+                    return self.end_instruction();
                 }
-                "TXS" => self.txs(),
-                "TSX" => self.tsx(),
-                "STX" => self.stx(&operation.mode),
-                "STY" => self.sty(&operation.mode),
-                "ORA" => self.ora(&operation.mode),
-                "EOR" => self.eor(&operation.mode),
-                "DEY" => self.dey(),
-                "TAY" => self.tay(),
-                "TYA" => self.tya(),
-                "RTI" => self.rti(),
-                "DOP" => (),
-                "TOP" => (),
-                "LAX" => self.lax(&operation.mode),
-                "AAX" => self.aax(&operation.mode),
-                "DCP" => self.dcp(&operation.mode),
-                "ISB" => self.isb(&operation.mode),
-                "SLO" => self.slo(&operation.mode),
-                "RLA" => self.rla(&operation.mode),
-                "SRE" => self.sre(&operation.mode),
-                "RRA" => self.rra(&operation.mode),
-                _ => todo!(),
-            }
-
-            if program_counter_state == self.program_counter {
-                self.program_counter += (operation.len - 1) as u16;
-            }
-        }
-    }
-
-    fn interrupt_nmi(&mut self) {
-        self.stack_push_u16(self.program_counter);
+            }
+            // PC is incremented past the JSR's last byte in a cycle of its own
+            Step::ReturnIncrement => {
+                self.program_counter = self.address.wrapping_add(1);
+                return self.end_instruction();
+            }
+
+            Step::InterruptDummy(reads) if reads > 1 => Step::InterruptDummy(reads - 1),
+            Step::InterruptDummy(_) => Step::PushPcHigh,
+            Step::PushReturnHigh => {
+                self.after_push();
+                Step::PushReturnLow
+            }
+            Step::PushReturnLow => {
+                self.after_push();
+                Step::JsrHigh
+            }
+
+            Step::PushPcHigh => {
+                self.after_push();
+                Step::PushPcLow
+            }
+            Step::PushPcLow => {
+                self.after_push();
+                self.pick_vector()
+            }
+            Step::PushStatus => {
+                self.after_push();
+                self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+                self.bus.mark_coverage(self.address, CoverageFlags::DATA);
+                self.bus
+                    .mark_coverage(self.address.wrapping_add(1), CoverageFlags::DATA);
+                Step::VectorLow
+            }
+            Step::VectorLow => {
+                self.data = data;
+                Step::VectorHigh
+            }
+            Step::VectorHigh => return self.end_interrupt(data),
+
+            Step::Reset(cycle) => {
+                match cycle {
+                    2 | 3 => self.stack_pointer = self.stack_pointer.wrapping_sub(1),
+                    4 => {
+                        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+                        self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+                    }
+                    5 => self.address = data as u16,
+                    6 => {
+                        let reset_vector = (data as u16) << 8 | self.address;
+                        self.program_counter = self.entry_point.unwrap_or(reset_vector);
+                        self.next_instruction();
+                        return Ok(());
+                    }
+                    _ => (),
+                }
+                Step::Reset(cycle + 1)
+            }
+
+            Step::OamHalt if self.bus.cycles() % 2 == 1 => Step::OamAlign,
+            Step::OamHalt | Step::OamAlign => Step::OamRead,
+            Step::OamRead => {
+                self.data = data;
+                Step::OamWrite
+            }
+            Step::OamWrite if self.address & 0x00FF == 0x00FF => return self.poll_interrupts(),
+            Step::OamWrite => {
+                self.address += 1;
+                Step::OamRead
+            }
+        };
+        Ok(())
+    }
+
+    /// Wraps up after an instruction's last cycle: call tracking catches up
+    /// with S, then an OAM DMA the instruction started runs, then any
+    /// interrupt polled along the way is taken.
+    fn end_instruction(&mut self) -> Result<(), CpuError> {
+        if let Some(stack) = &mut self.call_stack {
+            stack.sync(self.stack_pointer);
+        }
+
+        if let Some(page) = self.bus.take_oam_dma() {
+            self.address = (page as u16) << 8;
+            self.step = Step::OamHalt;
+            return Ok(());
+        }
+        self.poll_interrupts()
+    }
+
+    fn poll_interrupts(&mut self) -> Result<(), CpuError> {
+        if self.nmi_poll {
+            self.nmi_pending = false;
+            self.step = self.begin_interrupt(interrupt::NMI);
+            Ok(())
+        } else if self.irq_poll {
+            self.step = self.begin_interrupt(interrupt::IRQ);
+            Ok(())
+        } else {
+            self.finish_instruction()
+        }
+    }
+
+    /// Reports stack faults and watchpoint hits for the instruction just
+    /// finished, and moves on to the next one.
+    fn finish_instruction(&mut self) -> Result<(), CpuError> {
+        self.next_instruction();
+
+        if let Some(fault) = self.stack_fault.take() {
+            if let Some(handler) = &mut self.stack_fault_handler {
+                handler(fault, self.opcode_addr);
+            }
+            if self.trap_stack_faults {
+                return Err(CpuError::StackFault {
+                    fault,
+                    pc: self.opcode_addr,
+                });
+            }
+        }
+
+        if self.bus.watchpoint_hit().is_some() {
+            self.halted = true;
+        }
+        Ok(())
+    }
+
+    fn next_instruction(&mut self) {
+        self.step = Step::Opcode;
+        self.instruction_started = false;
+    }
+
+    /// One cycle of a DMC sample fetch. The DMA can only halt the CPU on a
+    /// read, which the CPU then keeps repeating: once on the halt cycle, once
+    /// on a dummy cycle, and once more if needed to line up with a get
+    /// cycle, before the DMA reads the sample. That is 3 or 4 stolen cycles,
+    /// none of which sample the interrupt lines, and then the held-up read
+    /// goes ahead.
+    fn dmc_stall_clock(&mut self, mut stall: DmcStall) {
+        let halted =
+            stall.halted_reads < 2 || (stall.halted_reads == 2 && self.bus.cycles() % 2 == 1);
+        self.bus.tick(1);
+        if halted {
+            self.bus.dmc_halted_read(stall.addr);
+            stall.halted_reads += 1;
+            self.dmc_stall = Some(stall);
+        } else {
+            self.bus.dmc_dma(stall.sample_addr);
+            self.dmc_stall = None;
+        }
+    }
+
+    /// Runs at the end of every CPU cycle. NMI is edge-triggered, so an edge
+    /// stays latched until serviced; IRQ is a level and only its current
+    /// state matters.
+    ///
+    /// The I flag is sampled along with the IRQ line. CLI, SEI and PLP only
+    /// change I after their last cycle's sample, so their effect on IRQs is
+    /// delayed by one instruction, while RTI restores I early enough to take
+    /// effect right away.
+    fn sample_interrupt_lines(&mut self) {
+        self.nmi_poll = self.nmi_pending;
+        self.irq_poll = self.irq_pending;
+
+        if self.bus.poll_nmi_status().is_some() {
+            self.nmi_pending = true;
+        }
+        self.irq_pending =
+            self.bus.poll_irq_status() && !self.status.contains(CpuFlags::INTERRUPT_DISABLE);
+    }
+
+    /// Starts pushing PC and status (with the interrupt's B flag pattern)
+    /// and jumping through its vector. NMI and BRK ignore the I flag; IRQs
+    /// are only polled while it is clear.
+    fn begin_interrupt(&mut self, interrupt: interrupt::Interrupt) -> Step {
+        self.interrupt = interrupt;
+        match interrupt.dummy_reads {
+            0 => Step::PushPcHigh,
+            reads => Step::InterruptDummy(reads),
+        }
+    }
+
+    /// The vector is picked while the status is pushed: an NMI arriving by
+    /// then hijacks a BRK or IRQ, which then runs the NMI handler with its
+    /// own B flag on the stack.
+    fn pick_vector(&mut self) -> Step {
+        let interrupt = self.interrupt;
         let mut flag = self.status.clone();
-        flag.set(CpuFlags::BREAK, false);
-        flag.set(CpuFlags::BREAK2, true);
+        flag.set(CpuFlags::BREAK, interrupt.b_flag_mask & 0b010000 != 0);
+        flag.set(CpuFlags::BREAK2, interrupt.b_flag_mask & 0b100000 != 0);
+        self.data = flag.bits();
+
+        let is_nmi = interrupt.vector_addr == interrupt::NMI.vector_addr;
+        self.address = if !is_nmi && self.nmi_pending {
+            self.nmi_pending = false;
+            interrupt::NMI.vector_addr
+        } else {
+            interrupt.vector_addr
+        };
+        Step::PushStatus
+    }
+
+    /// The vector's high byte is in: jump to the handler. A BRK then ends
+    /// like any other instruction, while an NMI or IRQ hands straight over
+    /// to the first instruction of its handler.
+    fn end_interrupt(&mut self, data: u8) -> Result<(), CpuError> {
+        let is_brk = self.interrupt.b_flag_mask & 0b010000 != 0;
+        let return_addr = self.program_counter;
+        self.program_counter = (data as u16) << 8 | self.data as u16;
+
+        let kind = if self.address == interrupt::NMI.vector_addr {
+            CallKind::Nmi
+        } else if is_brk {
+            CallKind::Brk
+        } else {
+            CallKind::Irq
+        };
+        self.push_call_frame(kind, return_addr);
 
-        self.stack_push(flag.bits());
-        self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+        if is_brk {
+            self.end_instruction()
+        } else {
+            self.finish_instruction()
+        }
+    }
+}
 
-        self.bus.tick(2);
-        self.program_counter = self.mem_read_u16(0xfffA);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::joypad::Joypad;
+    use crate::ppu::NesPPU;
+
+    const NESTEST: &[u8] = include_bytes!("../roms/nestest.nes");
+
+    fn test_cpu<'a>(program: &[u8]) -> Cpu<'a> {
+        let rom = Rom::new(&NESTEST.to_vec()).unwrap();
+        let bus = Bus::new(rom, |_: &NesPPU, _: &mut Joypad, _: &mut Joypad| {}).unwrap();
+        let mut cpu = Cpu::new(bus);
+        cpu.power_on(RamInitPattern::Zeroed);
+        cpu.load_at(0x0400, program);
+        cpu.program_counter = 0x0400;
+        cpu
+    }
+
+    #[test]
+    fn clock_runs_one_cycle_at_a_time() {
+        // LDA $0300,X with X = 0: no page cross, so 4 cycles
+        let mut cpu = test_cpu(&[0xBD, 0x00, 0x03]);
+        cpu.load_at(0x0300, &[0x42]);
+        let start = cpu.bus().cycles();
+
+        for cycle in 1..4 {
+            cpu.clock().unwrap();
+            assert_eq!(cpu.bus().cycles() - start, cycle);
+            assert!(!cpu.at_instruction_boundary());
+            assert_eq!(cpu.register_a, 0);
+        }
+        cpu.clock().unwrap();
+        assert!(cpu.at_instruction_boundary());
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.program_counter, 0x0403);
+    }
+
+    #[test]
+    fn step_finishes_an_instruction_started_by_clock() {
+        // INC $10, then NOP
+        let mut cpu = test_cpu(&[0xE6, 0x10, 0xEA]);
+        cpu.clock().unwrap();
+        cpu.clock().unwrap();
+        assert_eq!(cpu.step().unwrap(), 3);
+        assert_eq!(cpu.bus().peek(0x0010), 1);
+        assert_eq!(cpu.step().unwrap(), 2);
+        assert_eq!(cpu.program_counter, 0x0403);
+    }
+
+    #[test]
+    fn taken_branch_across_a_page_takes_4_cycles() {
+        // BNE -3 from $0400 lands on $03FF
+        let mut cpu = test_cpu(&[0xD0, 0xFD]);
+        cpu.status.remove(CpuFlags::ZERO);
+        assert_eq!(cpu.step().unwrap(), 4);
+        assert_eq!(cpu.program_counter, 0x03FF);
     }
 }
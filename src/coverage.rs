@@ -0,0 +1,149 @@
+bitflags! {
+    /// How a PRG-ROM byte has been used. The bits line up with the PRG part
+    /// of an FCEUX code/data log.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct CoverageFlags: u8 {
+        /// Fetched as an opcode or operand of an executed instruction
+        const CODE = 0b0000_0001;
+        /// Read by an instruction as data, or as a vector/pointer
+        const DATA = 0b0000_0010;
+        /// Jumped to through JMP ($nnnn)
+        const INDIRECT_CODE = 0b0001_0000;
+        /// Read through a ($nn,X) or ($nn),Y pointer
+        const INDIRECT_DATA = 0b0010_0000;
+    }
+}
+
+bitflags! {
+    /// How a CHR-ROM byte has been used, as in the CHR part of an FCEUX
+    /// code/data log
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ChrCoverageFlags: u8 {
+        /// Part of a tile that was on screen
+        const RENDERED = 0b01;
+        /// Read by the CPU through $2007
+        const READ = 0b10;
+    }
+}
+
+/// Per-byte usage map of PRG-ROM. It is indexed by offset into the ROM
+/// rather than by CPU address, so bytes in different banks mapped at the
+/// same address are kept apart.
+pub struct Coverage {
+    flags: Vec<CoverageFlags>,
+    /// Which 8 KiB window of $8000-$FFFF each byte was last accessed through
+    windows: Vec<u8>,
+}
+
+impl Coverage {
+    pub fn new(prg_rom_len: usize) -> Self {
+        Coverage {
+            flags: vec![CoverageFlags::empty(); prg_rom_len],
+            windows: vec![0; prg_rom_len],
+        }
+    }
+
+    /// Records a use of the byte at `offset`, accessed at CPU address `addr`
+    pub fn mark(&mut self, offset: usize, addr: u16, flags: CoverageFlags) {
+        if let Some(entry) = self.flags.get_mut(offset) {
+            entry.insert(flags);
+            self.windows[offset] = ((addr >> 13) & 0b11) as u8;
+        }
+    }
+
+    pub fn get(&self, offset: usize) -> CoverageFlags {
+        self.flags
+            .get(offset)
+            .copied()
+            .unwrap_or(CoverageFlags::empty())
+    }
+
+    pub fn is_executed(&self, offset: usize) -> bool {
+        self.get(offset).contains(CoverageFlags::CODE)
+    }
+
+    /// One entry per PRG-ROM byte
+    pub fn flags(&self) -> &[CoverageFlags] {
+        &self.flags
+    }
+
+    /// Packs the executed bytes into a bitmap, bit `n % 8` of byte `n / 8`
+    /// standing for PRG-ROM offset `n`.
+    pub fn executed_bitmap(&self) -> Vec<u8> {
+        let mut bitmap = vec![0; self.flags.len().div_ceil(8)];
+        for (offset, flags) in self.flags.iter().enumerate() {
+            if flags.contains(CoverageFlags::CODE) {
+                bitmap[offset / 8] |= 1 << (offset % 8);
+            }
+        }
+        bitmap
+    }
+
+    pub fn executed_count(&self) -> usize {
+        self.flags
+            .iter()
+            .filter(|flags| flags.contains(CoverageFlags::CODE))
+            .count()
+    }
+
+    /// The PRG part of an FCEUX `.cdl` file: the flags of each byte, with
+    /// bits 2-3 holding the window it was last accessed through
+    pub fn to_cdl(&self) -> Vec<u8> {
+        self.flags
+            .iter()
+            .zip(&self.windows)
+            .map(|(flags, window)| flags.bits() | window << 2)
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.flags.fill(CoverageFlags::empty());
+        self.windows.fill(0);
+    }
+}
+
+/// Per-byte usage map of CHR-ROM
+pub struct ChrCoverage {
+    flags: Vec<ChrCoverageFlags>,
+}
+
+impl ChrCoverage {
+    pub fn new(chr_rom_len: usize) -> Self {
+        ChrCoverage {
+            flags: vec![ChrCoverageFlags::empty(); chr_rom_len],
+        }
+    }
+
+    pub fn mark(&mut self, offset: usize, flags: ChrCoverageFlags) {
+        if let Some(entry) = self.flags.get_mut(offset) {
+            entry.insert(flags);
+        }
+    }
+
+    /// Marks the 16 bytes of the tile at pattern table offset `tile_addr`
+    pub fn mark_tile(&mut self, tile_addr: usize, flags: ChrCoverageFlags) {
+        for offset in tile_addr..tile_addr + 16 {
+            self.mark(offset, flags);
+        }
+    }
+
+    pub fn get(&self, offset: usize) -> ChrCoverageFlags {
+        self.flags
+            .get(offset)
+            .copied()
+            .unwrap_or(ChrCoverageFlags::empty())
+    }
+
+    pub fn flags(&self) -> &[ChrCoverageFlags] {
+        &self.flags
+    }
+
+    /// The CHR part of an FCEUX `.cdl` file
+    pub fn to_cdl(&self) -> Vec<u8> {
+        self.flags.iter().map(|flags| flags.bits()).collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.flags.fill(ChrCoverageFlags::empty());
+    }
+}
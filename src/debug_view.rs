@@ -0,0 +1,271 @@
+// Pictures of the PPU's memory for debuggers. They are drawn from the
+// PPU's state as it is, without the fetches the renderer makes, so looking
+// doesn't change anything on cartridges that watch pattern reads.
+
+use crate::frame::Frame;
+use crate::ppu::{self, NesPPU};
+use crate::render;
+
+const GRID_COLOR: (u8, u8, u8) = (0x40, 0x40, 0x40);
+const HIGHLIGHT_COLOR: (u8, u8, u8) = (0xff, 0x00, 0xff);
+
+/// 3x5 pixel hex digits, a row of three bits at a time from the top
+const HEX_DIGITS: [u16; 16] = [
+    0b111_101_101_101_111,
+    0b010_110_010_010_111,
+    0b111_001_111_100_111,
+    0b111_001_111_001_111,
+    0b101_101_111_001_001,
+    0b111_100_111_001_111,
+    0b111_100_111_101_111,
+    0b111_001_001_001_001,
+    0b111_101_111_101_111,
+    0b111_101_111_001_111,
+    0b010_101_111_101_101,
+    0b110_101_110_101_110,
+    0b111_100_100_100_111,
+    0b110_101_101_101_110,
+    0b111_100_111_100_111,
+    0b111_100_111_100_100,
+];
+
+/// What `nametables` draws over the tiles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NametableOverlay {
+    /// Lines between the tiles
+    pub grid: bool,
+    /// The 256x240 part the next frame starts from, wrapping around the
+    /// edges the way scrolling does
+    pub viewport: bool,
+}
+
+/// The four nametables as the PPU sees them through the mirroring, $2000
+/// top left, $2400 top right, $2800 bottom left and $2C00 bottom right, in
+/// a 512x480 frame. Tiles come from the background pattern table.
+pub fn nametables(ppu: &NesPPU, overlay: NametableOverlay) -> Frame {
+    let mut frame = Frame::with_size(512, 480);
+    let bank = ppu.ctrl.bknd_pattern_addr();
+
+    for (n, addr) in [0x2000, 0x2400, 0x2800, 0x2C00].into_iter().enumerate() {
+        let nametable = ppu.nametable(addr);
+        let (left, top) = (n % 2 * 256, n / 2 * 240);
+        for i in 0..0x3c0 {
+            let (column, row) = (i % 32, i / 32);
+            let tile = ppu.chr_tile(bank + nametable[i] as u16 * 16);
+            // each attribute byte covers 4x4 tiles, two bits per 2x2
+            let attribute = nametable[0x3c0 + row / 4 * 8 + column / 4];
+            let shift = (row % 4 / 2 * 2 + column % 4 / 2) * 2;
+            let palette = (attribute >> shift) as usize & 0b11;
+            draw_tile(
+                ppu,
+                &mut frame,
+                &tile,
+                palette * 4,
+                left + column * 8,
+                top + row * 8,
+            );
+        }
+    }
+
+    if overlay.grid {
+        for y in 0..480 {
+            for x in 0..512 {
+                if x % 8 == 0 || y % 8 == 0 {
+                    frame.set_pixel(x, y, GRID_COLOR);
+                }
+            }
+        }
+    }
+    if overlay.viewport {
+        let nametable = ppu.scroll.base_nametable_addr();
+        let left = ppu.scroll.scroll_x() + (nametable as usize >> 10 & 1) * 256;
+        let top = ppu.scroll.scroll_y() + (nametable as usize >> 11 & 1) * 240;
+        for i in 0..256 {
+            frame.set_pixel((left + i) % 512, top % 480, HIGHLIGHT_COLOR);
+            frame.set_pixel((left + i) % 512, (top + 239) % 480, HIGHLIGHT_COLOR);
+        }
+        for i in 0..240 {
+            frame.set_pixel(left % 512, (top + i) % 480, HIGHLIGHT_COLOR);
+            frame.set_pixel((left + 255) % 512, (top + i) % 480, HIGHLIGHT_COLOR);
+        }
+    }
+    frame
+}
+
+/// Pattern table `bank` (0 for $0000, 1 for $1000) as a 128x128 sheet of
+/// 16x16 tiles, in palette `palette` (0-3 for the background, 4-7 for the
+/// sprites). CHR-RAM shows what has been written to it so far.
+pub fn pattern_table(ppu: &NesPPU, bank: usize, palette: usize) -> Frame {
+    let mut frame = Frame::with_size(128, 128);
+    draw_pattern_table(ppu, &mut frame, bank, palette, 0);
+    frame
+}
+
+/// Both pattern tables side by side, $0000 on the left, in a 256x128 frame
+pub fn pattern_tables(ppu: &NesPPU, palette: usize) -> Frame {
+    let mut frame = Frame::with_size(256, 128);
+    for bank in 0..2 {
+        draw_pattern_table(ppu, &mut frame, bank, palette, bank * 128);
+    }
+    frame
+}
+
+fn draw_pattern_table(ppu: &NesPPU, frame: &mut Frame, bank: usize, palette: usize, left: usize) {
+    for n in 0..256 {
+        let tile = ppu.chr_tile((bank * 0x1000 + n * 16) as u16);
+        let (x, y) = (left + n % 16 * 8, n / 16 * 8);
+        draw_tile(ppu, frame, &tile, palette % 8 * 4, x, y);
+    }
+}
+
+/// Palette RAM as 16x16 swatches in a 256x32 frame: the four background
+/// palettes along the top and the four sprite palettes below. Each swatch
+/// is labelled with the colour number the game stored there. The mirrored
+/// entries, $3F10/$3F14/$3F18/$3F1C, show the bytes they share.
+pub fn palettes(ppu: &NesPPU) -> Frame {
+    let mut frame = Frame::with_size(256, 32);
+    for i in 0..32 {
+        let color = ppu.palette_table[ppu::palette_index(0x3f00 + i as u16)] & 0x3f;
+        let rgb = render::rgb(ppu, color);
+        let (left, top) = (i % 16 * 16, i / 16 * 16);
+        for y in top..top + 16 {
+            for x in left..left + 16 {
+                frame.set_pixel(x, y, rgb);
+            }
+        }
+
+        let luma = rgb.0 as u32 * 77 + rgb.1 as u32 * 150 + rgb.2 as u32 * 29;
+        let ink = if luma < 128 * 256 {
+            (0xff, 0xff, 0xff)
+        } else {
+            (0x00, 0x00, 0x00)
+        };
+        draw_hex_digit(&mut frame, color >> 4, left + 8, top + 10, ink);
+        draw_hex_digit(&mut frame, color & 0x0f, left + 12, top + 10, ink);
+    }
+    frame
+}
+
+/// One OAM entry, decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpriteInfo {
+    pub index: usize,
+    pub x: u8,
+    /// The line above the sprite's top line, as stored in OAM
+    pub y: u8,
+    pub tile: u8,
+    /// 0-3, for palette RAM $3F10-$3F1F
+    pub palette: u8,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    pub behind_background: bool,
+}
+
+impl SpriteInfo {
+    /// Whether sprite evaluation puts the sprite on `line`, for sprites
+    /// `height` lines tall
+    pub fn on_line(&self, line: u16, height: usize) -> bool {
+        ppu::sprite_row(self.y, line as usize, height).is_some()
+    }
+}
+
+/// All 64 sprites in OAM, in OAM order
+pub fn sprites(ppu: &NesPPU) -> Vec<SpriteInfo> {
+    ppu.oam_data
+        .chunks_exact(4)
+        .enumerate()
+        .map(|(index, sprite)| SpriteInfo {
+            index,
+            x: sprite[3],
+            y: sprite[0],
+            tile: sprite[1],
+            palette: sprite[2] & 0b11,
+            flip_horizontal: sprite[2] & 0b0100_0000 != 0,
+            flip_vertical: sprite[2] & 0b1000_0000 != 0,
+            behind_background: sprite[2] & 0b0010_0000 != 0,
+        })
+        .collect()
+}
+
+/// The 64 sprites in a 128x192 frame, eight to a row in OAM order, each in
+/// a 16x24 cell with room for 8x16 sprites. They are drawn flipped and in
+/// their palettes, on grey where they are transparent. With `highlight`,
+/// the sprites on that line get a border.
+pub fn oam(ppu: &NesPPU, highlight: Option<u16>) -> Frame {
+    let mut frame = Frame::with_size(128, 192);
+    let height = ppu.ctrl.sprite_size() as usize;
+    for sprite in sprites(ppu) {
+        let (left, top) = (sprite.index % 8 * 16, sprite.index / 8 * 24);
+        let border = highlight.is_some_and(|line| sprite.on_line(line, height));
+        for y in top..top + 24 {
+            for x in left..left + 16 {
+                let edge = x == left || x == left + 15 || y == top || y == top + 23;
+                let color = if border && edge {
+                    HIGHLIGHT_COLOR
+                } else {
+                    GRID_COLOR
+                };
+                frame.set_pixel(x, y, color);
+            }
+        }
+
+        let entry = &ppu.oam_data[sprite.index * 4..sprite.index * 4 + 4];
+        for row in 0..height {
+            let source_row = if sprite.flip_vertical {
+                height - 1 - row
+            } else {
+                row
+            };
+            let tile = ppu.chr_tile(ppu.sprite_tile_addr(entry, source_row));
+            let (low, high) = (tile[source_row % 8], tile[source_row % 8 + 8]);
+            for column in 0..8 {
+                let bit = if sprite.flip_horizontal {
+                    column
+                } else {
+                    7 - column
+                };
+                let value = (low >> bit & 1) | (high >> bit & 1) << 1;
+                if value != 0 {
+                    let color =
+                        ppu.palette_color(0x10 + sprite.palette as usize * 4 + value as usize);
+                    frame.set_pixel(left + 4 + column, top + 4 + row, render::rgb(ppu, color));
+                }
+            }
+        }
+    }
+    frame
+}
+
+fn draw_hex_digit(frame: &mut Frame, digit: u8, x: usize, y: usize, ink: (u8, u8, u8)) {
+    let glyph = HEX_DIGITS[digit as usize];
+    for row in 0..5 {
+        for column in 0..3 {
+            if glyph >> (14 - row * 3 - column) & 1 == 1 {
+                frame.set_pixel(x + column, y + row, ink);
+            }
+        }
+    }
+}
+
+/// Draws an 8x8 tile with its top left corner at `x`, `y`, in the four
+/// colours of palette RAM from `palette_start`. Colour 0 is the backdrop.
+fn draw_tile(
+    ppu: &NesPPU,
+    frame: &mut Frame,
+    tile: &[u8; 16],
+    palette_start: usize,
+    x: usize,
+    y: usize,
+) {
+    for row in 0..8 {
+        for column in 0..8 {
+            let bit = 7 - column;
+            let value = (tile[row] >> bit & 1) | (tile[row + 8] >> bit & 1) << 1;
+            let color = match value {
+                0 => ppu.palette_color(0),
+                _ => ppu.palette_color(palette_start + value as usize),
+            };
+            frame.set_pixel(x + column, y + row, render::rgb(ppu, color));
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use crate::bus::Bus;
+use crate::opcodes::*;
+use crate::symbols::Symbols;
+use std::fmt;
+
+/// Bytes per PRG bank, matching `Bus::prg_bank`
+const PRG_BANK_SIZE: usize = 0x4000;
+
+/// One disassembled instruction
+pub struct Instruction {
+    pub addr: u16,
+    /// PRG bank the bytes came from; `None` outside $8000-$FFFF
+    pub bank: Option<u8>,
+    pub bytes: Vec<u8>,
+    /// Operand-and-mnemonic text, in the syntax `assembler` accepts.
+    /// Unofficial opcodes are marked with a `*`.
+    pub text: String,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = self
+            .bytes
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<String>>()
+            .join(" ");
+        match self.bank {
+            Some(bank) => write!(
+                f,
+                "{:02X}:{:04X}  {:8}  {}",
+                bank, self.addr, hex, self.text
+            ),
+            None => write!(f, "   {:04X}  {:8}  {}", self.addr, hex, self.text),
+        }
+    }
+}
+
+/// Disassembles `count` instructions from `addr` as the CPU currently sees
+/// memory, so PRG addresses decode from whichever bank is mapped there.
+pub fn disassemble(
+    bus: &Bus,
+    addr: u16,
+    count: usize,
+    symbols: Option<&Symbols>,
+) -> Vec<Instruction> {
+    let bank_of = |addr: u16| (addr >= 0x8000).then(|| bus.prg_bank(addr));
+    let mut result = Vec::with_capacity(count);
+    let mut addr = addr;
+    for _ in 0..count {
+        let bytes = (0..3)
+            .map(|i| bus.peek(addr.wrapping_add(i)))
+            .collect::<Vec<_>>();
+        let instruction = decode(addr, &bytes, bank_of(addr), &bank_of, symbols);
+        addr = addr.wrapping_add(instruction.bytes.len() as u16);
+        result.push(instruction);
+    }
+    result
+}
+
+/// Disassembles a whole PRG bank straight from the ROM image, as if it were
+/// mapped at `origin`. No cartridge or mapper state is involved, so any bank
+/// can be inspected offline.
+pub fn disassemble_bank(
+    prg_rom: &[u8],
+    bank: u8,
+    origin: u16,
+    symbols: Option<&Symbols>,
+) -> Result<Vec<Instruction>, String> {
+    let start = bank as usize * PRG_BANK_SIZE;
+    let data = prg_rom
+        .get(start..start + PRG_BANK_SIZE)
+        .ok_or_else(|| format!("PRG-ROM has no bank {}", bank))?;
+    let window = origin as usize..origin as usize + PRG_BANK_SIZE;
+    // operands outside the bank's window may point at any bank
+    let bank_of = |addr: u16| window.contains(&(addr as usize)).then_some(bank);
+
+    let mut result = vec![];
+    let mut offset = 0;
+    while offset < data.len() {
+        let addr = origin.wrapping_add(offset as u16);
+        let end = (offset + 3).min(data.len());
+        let instruction = decode(addr, &data[offset..end], Some(bank), &bank_of, symbols);
+        offset += instruction.bytes.len();
+        result.push(instruction);
+    }
+    Ok(result)
+}
+
+/// Decodes the instruction at the start of `bytes`. When the bytes run out
+/// before the operand does, the opcode is emitted as a `.byte` instead.
+fn decode(
+    addr: u16,
+    bytes: &[u8],
+    bank: Option<u8>,
+    bank_of: &dyn Fn(u16) -> Option<u8>,
+    symbols: Option<&Symbols>,
+) -> Instruction {
+    let ops = &OPCODE_TABLE[bytes[0] as usize];
+    let len = ops.len as usize;
+    if bytes.len() < len {
+        return Instruction {
+            addr,
+            bank,
+            bytes: bytes[..1].to_vec(),
+            text: format!(".byte ${:02X}", bytes[0]),
+        };
+    }
+
+    let label = |target: u16, hex: String| {
+        symbols
+            .and_then(|symbols| symbols.lookup(bank_of(target), target))
+            .map_or(hex, String::from)
+    };
+    let byte = || bytes[1];
+    let word = || u16::from_le_bytes([bytes[1], bytes[2]]);
+    let zero_page = || label(byte() as u16, format!("${:02X}", byte()));
+    let absolute = || label(word(), format!("${:04X}", word()));
+
+    let operand = match ops.mode {
+        AddressingMode::NoneAddressing => String::new(),
+        AddressingMode::Accumulator => String::from("A"),
+        AddressingMode::Immediate => format!("#${:02X}", byte()),
+        AddressingMode::ZeroPage => zero_page(),
+        AddressingMode::ZeroPage_X => format!("{},X", zero_page()),
+        AddressingMode::ZeroPage_Y => format!("{},Y", zero_page()),
+        AddressingMode::Absolute => absolute(),
+        AddressingMode::Absolute_X => format!("{},X", absolute()),
+        AddressingMode::Absolute_Y => format!("{},Y", absolute()),
+        AddressingMode::Indirect => format!("({})", absolute()),
+        AddressingMode::Indirect_X => format!("({},X)", zero_page()),
+        AddressingMode::Indirect_Y => format!("({}),Y", zero_page()),
+        AddressingMode::Relative => {
+            let target = addr.wrapping_add(2).wrapping_add(byte() as i8 as u16);
+            label(target, format!("${:04X}", target))
+        }
+    };
+
+    let mnemonic = format!("{}{}", if ops.unofficial { "*" } else { "" }, ops.mnemonic);
+    Instruction {
+        addr,
+        bank,
+        bytes: bytes[..len].to_vec(),
+        text: format!("{} {}", mnemonic, operand).trim_end().to_string(),
+    }
+}
@@ -0,0 +1,31 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Data lines D1-D4, which the controller ports leave to other devices
+pub const EXPANSION_DATA_MASK: u8 = 0b0001_1110;
+
+/// A peripheral wired to the extra data lines of $4016/$4017: a Zapper, an
+/// Arkanoid paddle, or something on the Famicom expansion port. D0 stays
+/// with the standard controllers.
+pub trait ExpansionDevice {
+    /// The bits this device drives on a read of `port` (0 for $4016, 1 for
+    /// $4017). Only D1-D4 are used.
+    fn read(&mut self, port: u8) -> u8;
+
+    /// A write to $4016. Bits 0-2 are the OUT0-OUT2 lines, bit 0 being the
+    /// controller strobe.
+    fn write(&mut self, data: u8) {
+        let _ = data;
+    }
+}
+
+/// Lets the host keep a handle to a device it attached, to feed it input
+impl<T: ExpansionDevice> ExpansionDevice for Rc<RefCell<T>> {
+    fn read(&mut self, port: u8) -> u8 {
+        self.borrow_mut().read(port)
+    }
+
+    fn write(&mut self, data: u8) {
+        self.borrow_mut().write(data)
+    }
+}
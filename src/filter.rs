@@ -0,0 +1,855 @@
+// Filters for the finished picture on its way to the screen. They work on
+// the RGB frame after the PPU is done with it, and most of them give back
+// a bigger picture than they get.
+
+use crate::frame::Frame;
+#[cfg(feature = "image")]
+use std::path::Path;
+
+/// An RGB picture of any size, in the same layout as `Frame::data`
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+#[cfg(feature = "image")]
+impl Image {
+    pub fn save_png(&self, path: impl AsRef<Path>) -> image::ImageResult<()> {
+        image::save_buffer_with_format(
+            path,
+            &self.data,
+            self.width as u32,
+            self.height as u32,
+            image::ColorType::Rgb8,
+            image::ImageFormat::Png,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Filter {
+    /// The frame as it is
+    #[default]
+    None,
+    /// Dark gaps between the lines and a red, green and blue stripe mask,
+    /// like a CRT up close. Bright lines bleed into the gaps.
+    Crt,
+    /// Scale2x, also known as AdvMAME2x: doubles the picture and rounds off
+    /// the steps of diagonal lines, without making any new colours
+    Scale2x,
+    /// Scale3x, the same idea at three times the size
+    Scale3x,
+    /// xBR at twice the size: finds edges by how different the colours
+    /// along and across them are, and blends along them, so curves come
+    /// out smooth
+    Xbr2x,
+    /// Doubles the picture, sorting the neighbours of each pixel into ones
+    /// like it and ones not by hqx's colour limits, and mixing each corner
+    /// with them by a few rules. It isn't hq2x: that looks the pattern up
+    /// in Maxim Stepin's hand-made table, which this doesn't have.
+    Smooth2x,
+    /// The same idea at three times the size
+    Smooth3x,
+    /// Doubles the picture, deciding each corner between four pixels once,
+    /// as xBRZ does, so the pixels around it agree on the edge, then
+    /// drawing one of a few fixed strokes across it. Far simpler than
+    /// xBRZ's scalers, and not meant to match them.
+    Corner2x,
+    /// The same idea at three times the size
+    Corner3x,
+}
+
+impl Filter {
+    pub const ALL: [Filter; 9] = [
+        Filter::None,
+        Filter::Crt,
+        Filter::Scale2x,
+        Filter::Scale3x,
+        Filter::Xbr2x,
+        Filter::Smooth2x,
+        Filter::Smooth3x,
+        Filter::Corner2x,
+        Filter::Corner3x,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Filter::None => "none",
+            Filter::Crt => "crt",
+            Filter::Scale2x => "scale2x",
+            Filter::Scale3x => "scale3x",
+            Filter::Xbr2x => "xbr2x",
+            Filter::Smooth2x => "smooth2x",
+            Filter::Smooth3x => "smooth3x",
+            Filter::Corner2x => "corner2x",
+            Filter::Corner3x => "corner3x",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Filter> {
+        Filter::ALL
+            .into_iter()
+            .find(|filter| filter.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The filter after this one in `ALL`, wrapping around
+    pub fn next(self) -> Filter {
+        let index = Filter::ALL.iter().position(|&filter| filter == self);
+        Filter::ALL[index.map_or(0, |i| (i + 1) % Filter::ALL.len())]
+    }
+
+    /// How many pixels across and down the filter makes of each one
+    pub fn scale(self) -> usize {
+        match self {
+            Filter::None => 1,
+            Filter::Crt | Filter::Scale3x | Filter::Smooth3x | Filter::Corner3x => 3,
+            Filter::Scale2x | Filter::Xbr2x | Filter::Smooth2x | Filter::Corner2x => 2,
+        }
+    }
+
+    pub fn apply(self, frame: &Frame) -> Image {
+        match self {
+            Filter::None => Image {
+                width: frame.width(),
+                height: frame.height(),
+                data: frame.to_rgb24().into_owned(),
+            },
+            Filter::Crt => crt(frame),
+            Filter::Scale2x => scale2x(frame),
+            Filter::Scale3x => scale3x(frame),
+            Filter::Xbr2x => xbr2x(frame),
+            Filter::Smooth2x => smooth2x(frame),
+            Filter::Smooth3x => smooth3x(frame),
+            Filter::Corner2x => corner_scale::<4>(frame, 2),
+            Filter::Corner3x => corner_scale::<9>(frame, 3),
+        }
+    }
+}
+
+/// Each pixel becomes three lines of three: two lit lines and a gap, and
+/// a column each for red, green and blue
+fn crt(frame: &Frame) -> Image {
+    const SCALE: usize = 3;
+    let width = frame.width() * SCALE;
+    let mut data = vec![0; width * frame.height() * SCALE * 3];
+
+    for (i, pixel) in frame.to_rgb24().chunks_exact(3).enumerate() {
+        let (x, y) = (i % frame.width(), i / frame.width());
+        let luma = (pixel[0] as u32 * 77 + pixel[1] as u32 * 150 + pixel[2] as u32 * 29) >> 8;
+        for row in 0..SCALE {
+            // out of 256
+            let line_weight = if row == SCALE - 1 {
+                96 + luma * 5 / 8
+            } else {
+                256
+            };
+            for column in 0..SCALE {
+                let base = ((y * SCALE + row) * width + x * SCALE + column) * 3;
+                for channel in 0..3 {
+                    let mask_weight = if channel == column { 288 } else { 200 };
+                    let value = (pixel[channel] as u32 * line_weight * mask_weight) >> 16;
+                    data[base + channel] = value.min(255) as u8;
+                }
+            }
+        }
+    }
+
+    Image {
+        width,
+        height: frame.height() * SCALE,
+        data,
+    }
+}
+
+/// Mixes each frame with the one before it, the way a slow TV phosphor
+/// does. Sprites a game flickers every other frame then show steadily at
+/// half strength, and flicker meant as transparency looks transparent.
+pub struct FrameBlend {
+    /// How much of the previous frame goes into the mix, out of 100
+    weight: u8,
+    previous: Option<Vec<u8>>,
+}
+
+impl FrameBlend {
+    pub fn new(weight: u8) -> Self {
+        FrameBlend {
+            weight: weight.min(100),
+            previous: None,
+        }
+    }
+
+    pub fn weight(&self) -> u8 {
+        self.weight
+    }
+
+    pub fn blend(&mut self, frame: &Frame) -> Frame {
+        let weight = self.weight as u16;
+        let rgb = frame.to_rgb24();
+        let mut blended = Frame::with_size(frame.width(), frame.height());
+        match &self.previous {
+            Some(previous) => {
+                let mixed = blended.data.iter_mut().zip(rgb.iter()).zip(previous);
+                for ((out, &now), &before) in mixed {
+                    *out = ((now as u16 * (100 - weight) + before as u16 * weight) / 100) as u8;
+                }
+            }
+            None => blended.data.copy_from_slice(&rgb),
+        }
+        self.previous = Some(rgb.into_owned());
+        blended
+    }
+}
+
+type Rgb = [u8; 3];
+type Yuv = (i32, i32, i32);
+
+/// The pixel at `x`, `y`, with the edge pixels repeated outside the frame
+fn pixel_at(frame: &Frame, x: isize, y: isize) -> Rgb {
+    let x = x.clamp(0, frame.width() as isize - 1) as usize;
+    let y = y.clamp(0, frame.height() as isize - 1) as usize;
+    let (r, g, b) = frame.pixel(x, y);
+    [r, g, b]
+}
+
+/// Builds a picture `scale` times the size of the frame, asking `block` for
+/// the `scale` x `scale` pixels, row by row, that each pixel turns into.
+/// `block` gets the pixel's neighbourhood as a function of the offset.
+fn upscale<const N: usize>(
+    frame: &Frame,
+    scale: usize,
+    block: impl Fn(&dyn Fn(isize, isize) -> Rgb) -> [Rgb; N],
+) -> Image {
+    let width = frame.width() * scale;
+    let mut data = vec![0; width * frame.height() * scale * 3];
+    for y in 0..frame.height() {
+        for x in 0..frame.width() {
+            let around = |dx: isize, dy: isize| pixel_at(frame, x as isize + dx, y as isize + dy);
+            for (i, rgb) in block(&around).iter().enumerate() {
+                let base = ((y * scale + i / scale) * width + x * scale + i % scale) * 3;
+                data[base..base + 3].copy_from_slice(rgb);
+            }
+        }
+    }
+    Image {
+        width,
+        height: frame.height() * scale,
+        data,
+    }
+}
+
+// The neighbours are named as in the Scale2x and xBR papers:
+//
+//   A B C
+//   D E F
+//   G H I
+
+/// Andrea Mazzoleni's Scale2x
+fn scale2x(frame: &Frame) -> Image {
+    upscale(frame, 2, |at| {
+        let (b, d, e, f, h) = (at(0, -1), at(-1, 0), at(0, 0), at(1, 0), at(0, 1));
+        if b == h || d == f {
+            return [e; 4];
+        }
+        [
+            if d == b { d } else { e },
+            if b == f { f } else { e },
+            if d == h { d } else { e },
+            if h == f { f } else { e },
+        ]
+    })
+}
+
+fn scale3x(frame: &Frame) -> Image {
+    upscale(frame, 3, |at| {
+        let (a, b, c) = (at(-1, -1), at(0, -1), at(1, -1));
+        let (d, e, f) = (at(-1, 0), at(0, 0), at(1, 0));
+        let (g, h, i) = (at(-1, 1), at(0, 1), at(1, 1));
+        if b == h || d == f {
+            return [e; 9];
+        }
+        [
+            if d == b { d } else { e },
+            if (d == b && e != c) || (b == f && e != a) {
+                b
+            } else {
+                e
+            },
+            if b == f { f } else { e },
+            if (d == b && e != g) || (d == h && e != a) {
+                d
+            } else {
+                e
+            },
+            e,
+            if (b == f && e != i) || (h == f && e != c) {
+                f
+            } else {
+                e
+            },
+            if d == h { d } else { e },
+            if (d == h && e != i) || (h == f && e != g) {
+                h
+            } else {
+                e
+            },
+            if h == f { f } else { e },
+        ]
+    })
+}
+
+/// A colour as brightness and two kinds of hue, scaled like RGB
+fn yuv([r, g, b]: Rgb) -> Yuv {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    (
+        (299 * r + 587 * g + 114 * b) / 1000,
+        (-169 * r - 331 * g + 500 * b) / 1000,
+        (500 * r - 419 * g - 81 * b) / 1000,
+    )
+}
+
+/// How different two colours look, weighing brightness well above hue
+/// the way xBR does
+fn distance(a: Rgb, b: Rgb) -> i32 {
+    let ((y1, u1, v1), (y2, u2, v2)) = (yuv(a), yuv(b));
+    48 * (y1 - y2).abs() + 7 * (u1 - u2).abs() + 6 * (v1 - v2).abs()
+}
+
+/// `share` parts out of `out_of` of the way from `from` to `to`
+fn blend(from: Rgb, to: Rgb, share: u16, out_of: u16) -> Rgb {
+    let mix = |a: u8, b: u8| ((a as u16 * (out_of - share) + b as u16 * share) / out_of) as u8;
+    [
+        mix(from[0], to[0]),
+        mix(from[1], to[1]),
+        mix(from[2], to[2]),
+    ]
+}
+
+/// Turns an offset by a quarter turn, `turns` times
+fn turn((mut dx, mut dy): (isize, isize), turns: usize) -> (isize, isize) {
+    for _ in 0..turns {
+        (dx, dy) = (-dy, dx);
+    }
+    (dx, dy)
+}
+
+/// Where the pixel at `column`, `row` of a `scale` x `scale` block ends
+/// up when the block is turned the same way as `turn` turns offsets
+fn turned_cell(column: usize, row: usize, turns: usize, scale: usize) -> usize {
+    // offsets from the middle of the block, in half pixels
+    let half = scale as isize - 1;
+    let (dx, dy) = turn((2 * column as isize - half, 2 * row as isize - half), turns);
+    ((dy + half) / 2) as usize * scale + ((dx + half) / 2) as usize
+}
+
+/// Hyllian's xBR, with the level 2 rules for shallow and steep edges.
+/// Each corner of the block is worked out the same way, turning the
+/// neighbourhood so the corner is at the bottom right.
+fn xbr2x(frame: &Frame) -> Image {
+    upscale(frame, 2, |at| {
+        let e = at(0, 0);
+        let mut block = [e; 4];
+
+        for turns in 0..4 {
+            let p = |dx: isize, dy: isize| {
+                let (dx, dy) = turn((dx, dy), turns);
+                at(dx, dy)
+            };
+            let (f, h) = (p(1, 0), p(0, 1));
+            if e == f || e == h {
+                continue;
+            }
+            let (b, c, d, g, i) = (p(0, -1), p(1, -1), p(-1, 0), p(-1, 1), p(1, 1));
+            let (f4, i4, h5, i5) = (p(2, 0), p(2, 1), p(0, 2), p(1, 2));
+            // an edge across the corner, from F to H, against one along it
+            let across = distance(e, c)
+                + distance(e, g)
+                + distance(i, f4)
+                + distance(i, h5)
+                + 4 * distance(h, f);
+            let along = distance(h, d)
+                + distance(h, i5)
+                + distance(f, i4)
+                + distance(f, b)
+                + 4 * distance(e, i);
+            if across >= along {
+                continue;
+            }
+
+            let fill = if distance(e, f) <= distance(e, h) {
+                f
+            } else {
+                h
+            };
+            let (ke, ki) = (distance(f, g), distance(h, c));
+            let shallow = 2 * ke <= ki && e != g && d != g;
+            let steep = ke >= 2 * ki && e != c && b != c;
+            let bottom_right = turned_cell(1, 1, turns, 2);
+            if shallow || steep {
+                block[bottom_right] = blend(block[bottom_right], fill, 3, 4);
+            } else {
+                block[bottom_right] = blend(block[bottom_right], fill, 2, 4);
+            }
+            if shallow {
+                let bottom_left = turned_cell(0, 1, turns, 2);
+                block[bottom_left] = blend(block[bottom_left], fill, 1, 4);
+            }
+            if steep {
+                let top_right = turned_cell(1, 0, turns, 2);
+                block[top_right] = blend(block[top_right], fill, 1, 4);
+            }
+        }
+        block
+    })
+}
+
+// The bits of a smoothing pattern, set for the neighbours that differ from E
+const SMOOTH_A: usize = 1;
+const SMOOTH_B: usize = 2;
+const SMOOTH_C: usize = 4;
+const SMOOTH_D: usize = 8;
+const SMOOTH_G: usize = 32;
+
+/// Where A, B, C, D, F, G, H and I are from E
+const SMOOTH_NEIGHBOURS: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// Whether two colours differ by the limits on brightness and hue hqx uses
+fn smooth_differ((y1, u1, v1): Yuv, (y2, u2, v2): Yuv) -> bool {
+    (y1 - y2).abs() > 48 || (u1 - u2).abs() > 7 || (v1 - v2).abs() > 6
+}
+
+/// Where the pixel at `offset` from E, with the neighbourhood turned,
+/// is in the three by three pixels around it, counting row by row
+fn smooth_index(offset: (isize, isize), turns: usize) -> usize {
+    let (dx, dy) = turn(offset, turns);
+    (dy * 3 + dx + 4) as usize
+}
+
+/// The neighbours of E that differ from it with the neighbourhood turned,
+/// a bit each for A, B, C, D, F, G, H and I in that order
+fn smooth_pattern(yuvs: &[Yuv; 9], turns: usize) -> usize {
+    SMOOTH_NEIGHBOURS
+        .into_iter()
+        .enumerate()
+        .filter(|&(_, offset)| smooth_differ(yuvs[4], yuvs[smooth_index(offset, turns)]))
+        .fold(0, |pattern, (bit, _)| pattern | 1 << bit)
+}
+
+/// Sixteenths of E, A, B and D for the top left of a `smooth2x` block, first
+/// for when B and D differ from each other and then for when they are
+/// alike. Only the second matters when both differ from E, when an edge
+/// could run between them.
+const fn smooth2x_rule(pattern: usize) -> [[u16; 4]; 2] {
+    let a = pattern & SMOOTH_A != 0;
+    let (b, c) = (pattern & SMOOTH_B != 0, pattern & SMOOTH_C != 0);
+    let (d, g) = (pattern & SMOOTH_D != 0, pattern & SMOOTH_G != 0);
+    let rule = match (b, d) {
+        (false, false) => [8, 0, 4, 4],
+        (true, false) if a => [12, 0, 0, 4],
+        (true, false) => [8, 4, 0, 4],
+        (false, true) if a => [12, 0, 4, 0],
+        (false, true) => [8, 4, 4, 0],
+        (true, true) if a => [16, 0, 0, 0],
+        (true, true) => [12, 4, 0, 0],
+    };
+    let edge = match (a, c, g) {
+        _ if !(b && d) => rule,
+        // E is part of a thin line through A
+        (false, true, true) => [12, 4, 0, 0],
+        (false, _, _) => [8, 0, 4, 4],
+        // the edge cuts the corner off at 45 degrees, or shallower or
+        // steeper, or goes round E
+        (true, false, false) => [8, 0, 4, 4],
+        (true, true, false) => [10, 0, 4, 2],
+        (true, false, true) => [10, 0, 2, 4],
+        // E is a single pixel in a sea of another colour
+        (true, true, true) if pattern == 0xFF => [14, 0, 1, 1],
+        (true, true, true) => [12, 0, 2, 2],
+    };
+    [rule, edge]
+}
+
+/// As `smooth2x_rule` for the top left of a `smooth3x` block, followed by
+/// sixteenths of B for the top middle and of D for the middle left
+const fn smooth3x_rule(pattern: usize) -> [[u16; 6]; 2] {
+    let a = pattern & SMOOTH_A != 0;
+    let (b, c) = (pattern & SMOOTH_B != 0, pattern & SMOOTH_C != 0);
+    let (d, g) = (pattern & SMOOTH_D != 0, pattern & SMOOTH_G != 0);
+    let top = if b { 0 } else { 4 };
+    let left = if d { 0 } else { 4 };
+    let rule = match (b, d) {
+        (false, false) => [8, 0, 4, 4, top, left],
+        (true, false) if a => [12, 0, 0, 4, top, left],
+        (false, true) if a => [12, 0, 4, 0, top, left],
+        (true, true) if a => [16, 0, 0, 0, top, left],
+        _ => [12, 4, 0, 0, top, left],
+    };
+    let edge = match (a, c, g) {
+        _ if !(b && d) => rule,
+        (false, true, true) => [12, 4, 0, 0, 0, 0],
+        (false, _, _) => [8, 0, 4, 4, 0, 0],
+        (true, false, false) => [2, 0, 7, 7, 2, 2],
+        (true, true, false) => [8, 0, 4, 4, 2, 0],
+        (true, false, true) => [8, 0, 4, 4, 0, 2],
+        (true, true, true) => [8, 0, 4, 4, 0, 0],
+    };
+    [rule, edge]
+}
+
+static SMOOTH2X_TABLE: [[[u16; 4]; 2]; 256] = {
+    let mut table = [[[0; 4]; 2]; 256];
+    let mut pattern = 0;
+    while pattern < 256 {
+        table[pattern] = smooth2x_rule(pattern);
+        pattern += 1;
+    }
+    table
+};
+
+static SMOOTH3X_TABLE: [[[u16; 6]; 2]; 256] = {
+    let mut table = [[[0; 6]; 2]; 256];
+    let mut pattern = 0;
+    while pattern < 256 {
+        table[pattern] = smooth3x_rule(pattern);
+        pattern += 1;
+    }
+    table
+};
+
+/// Mixes colours by sixteenths
+fn mix(colours: [Rgb; 4], sixteenths: &[u16]) -> Rgb {
+    let channel = |i: usize| {
+        let parts = colours.iter().zip(sixteenths);
+        (parts.map(|(c, &w)| c[i] as u16 * w).sum::<u16>() / 16) as u8
+    };
+    [channel(0), channel(1), channel(2)]
+}
+
+/// The smoothing rule for the top left of the block with the neighbourhood
+/// turned, and the colours it mixes: E, A, B and D
+fn smooth_corner<const N: usize>(
+    pixels: &[Rgb; 9],
+    yuvs: &[Yuv; 9],
+    turns: usize,
+    table: &[[[u16; N]; 2]; 256],
+) -> ([u16; N], [Rgb; 4]) {
+    let [e, a, b, d] =
+        [(0, 0), (-1, -1), (0, -1), (-1, 0)].map(|offset| smooth_index(offset, turns));
+    let alike = !smooth_differ(yuvs[b], yuvs[d]) as usize;
+    let rule = table[smooth_pattern(yuvs, turns)][alike];
+    (rule, [pixels[e], pixels[a], pixels[b], pixels[d]])
+}
+
+/// The three by three pixels around E, row by row
+fn neighbourhood(at: &dyn Fn(isize, isize) -> Rgb) -> [Rgb; 9] {
+    std::array::from_fn(|i| at(i as isize % 3 - 1, i as isize / 3 - 1))
+}
+
+/// Whether every pixel around E is the same colour as it, where the
+/// smoothing and corner filters leave it as it is
+fn flat(pixels: &[Rgb; 9]) -> bool {
+    pixels.iter().all(|&pixel| pixel == pixels[4])
+}
+
+fn smooth2x(frame: &Frame) -> Image {
+    upscale(frame, 2, |at| {
+        let pixels = neighbourhood(at);
+        if flat(&pixels) {
+            return [pixels[4]; 4];
+        }
+        let yuvs = pixels.map(yuv);
+        let mut block = [pixels[4]; 4];
+        for turns in 0..4 {
+            let (rule, colours) = smooth_corner(&pixels, &yuvs, turns, &SMOOTH2X_TABLE);
+            block[turned_cell(0, 0, turns, 2)] = mix(colours, &rule);
+        }
+        block
+    })
+}
+
+fn smooth3x(frame: &Frame) -> Image {
+    upscale(frame, 3, |at| {
+        let pixels = neighbourhood(at);
+        if flat(&pixels) {
+            return [pixels[4]; 9];
+        }
+        let yuvs = pixels.map(yuv);
+        let mut block = [pixels[4]; 9];
+        // the most of B or D any corner asks for in each middle edge
+        let mut edges = [0; 9];
+        for turns in 0..4 {
+            let (rule, colours) = smooth_corner(&pixels, &yuvs, turns, &SMOOTH3X_TABLE);
+            block[turned_cell(0, 0, turns, 3)] = mix(colours, &rule[..4]);
+            for (column, row, share) in [(1, 0, rule[4]), (0, 1, rule[5])] {
+                let cell = turned_cell(column, row, turns, 3);
+                edges[cell] = edges[cell].max(share);
+            }
+        }
+        // the blocks and the neighbourhood are both laid out row by row
+        for (cell, &share) in edges.iter().enumerate() {
+            if share > 0 {
+                block[cell] = blend(pixels[4], pixels[cell], share, 16);
+            }
+        }
+        block
+    })
+}
+
+/// How `corner_scale` blends one corner of a pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CornerBlend {
+    None,
+    Normal,
+    /// The edge is much clearer one way than the other
+    Dominant,
+}
+
+/// xBRZ's distance between colours: how far apart they are in YCbCr
+fn ycbcr_distance(a: Rgb, b: Rgb) -> f32 {
+    let [r, g, b] = [0, 1, 2].map(|i| a[i] as f32 - b[i] as f32);
+    let y = 0.2627 * r + 0.6780 * g + 0.0593 * b;
+    let cb = 0.5 / (1.0 - 0.0593) * (b - y);
+    let cr = 0.5 / (1.0 - 0.2627) * (r - y);
+    (y * y + cb * cb + cr * cr).sqrt()
+}
+
+fn corner_alike(a: Rgb, b: Rgb) -> bool {
+    ycbcr_distance(a, b) < 30.0
+}
+
+/// How to blend the bottom right corner of E, from the four by four
+/// pixels around the corner, by xBRZ's test. E is `f` here, as in the
+/// xBRZ source:
+///
+///   a b c d
+///   e f g h
+///   i j k l
+///   m n o p
+fn corner_kind(p: &dyn Fn(isize, isize) -> Rgb) -> CornerBlend {
+    let (f, g, j, k) = (p(0, 0), p(1, 0), p(0, 1), p(1, 1));
+    if (f == g && j == k) || (f == j && g == k) {
+        return CornerBlend::None;
+    }
+    let d = ycbcr_distance;
+    let jg = d(p(-1, 1), f) + d(f, p(1, -1)) + d(p(0, 2), k) + d(k, p(2, 0)) + 4.0 * d(j, g);
+    let fk = d(p(-1, 0), j) + d(j, p(1, 2)) + d(p(0, -1), g) + d(g, p(2, 1)) + 4.0 * d(f, k);
+    if jg >= fk || f == g || f == j {
+        CornerBlend::None
+    } else if 3.6 * jg < fk {
+        CornerBlend::Dominant
+    } else {
+        CornerBlend::Normal
+    }
+}
+
+/// The kinds of line `corner_scale` draws across a corner
+#[derive(Clone, Copy)]
+enum CornerLine {
+    Shallow,
+    Steep,
+    ShallowAndSteep,
+    Diagonal,
+    /// Only round off the corner
+    Corner,
+}
+
+/// The pixels of a block a line across its bottom right corner passes
+/// over, as their column, row and how much of the line's colour they take
+/// out of `CORNER_OUT_OF`
+fn corner_stroke(line: CornerLine, scale: usize) -> &'static [(usize, usize, u16)] {
+    match (scale, line) {
+        (2, CornerLine::Shallow) => &[(0, 1, 30), (1, 1, 90)],
+        (2, CornerLine::Steep) => &[(1, 0, 30), (1, 1, 90)],
+        (2, CornerLine::ShallowAndSteep) => &[(0, 1, 30), (1, 0, 30), (1, 1, 100)],
+        (2, CornerLine::Diagonal) => &[(1, 1, 60)],
+        (2, CornerLine::Corner) => &[(1, 1, 25)],
+        (_, CornerLine::Shallow) => &[(0, 2, 30), (2, 1, 30), (1, 2, 90), (2, 2, 120)],
+        (_, CornerLine::Steep) => &[(2, 0, 30), (1, 2, 30), (2, 1, 90), (2, 2, 120)],
+        (_, CornerLine::ShallowAndSteep) => {
+            &[(0, 2, 30), (2, 0, 30), (1, 2, 90), (2, 1, 90), (2, 2, 120)]
+        }
+        (_, CornerLine::Diagonal) => &[(2, 1, 15), (1, 2, 15), (2, 2, 105)],
+        (_, CornerLine::Corner) => &[(2, 2, 54)],
+    }
+}
+
+/// The shares in `corner_stroke` are out of this many
+const CORNER_OUT_OF: u16 = 120;
+
+/// Rounds off corners at 2x or 3x. As in Zenju's xBRZ, every corner of
+/// every pixel is decided once, from the four pixels that meet there, and
+/// each pixel then draws the lines through its corners, looking at its
+/// other corners so two lines don't cut through the same pixel. The lines
+/// are fixed strokes from `corner_stroke`, not xBRZ's scalers.
+fn corner_scale<const N: usize>(frame: &Frame, scale: usize) -> Image {
+    upscale(frame, scale, |at| {
+        let e = at(0, 0);
+        let mut block = [e; N];
+        if flat(&neighbourhood(at)) {
+            return block;
+        }
+        let turned = |turns: usize| {
+            move |dx: isize, dy: isize| {
+                let (dx, dy) = turn((dx, dy), turns);
+                at(dx, dy)
+            }
+        };
+        // the corner at the bottom right after each number of turns
+        let corners = [0, 1, 2, 3].map(|turns| corner_kind(&turned(turns)));
+
+        for turns in 0..4 {
+            let corner = corners[turns];
+            if corner == CornerBlend::None {
+                continue;
+            }
+            let p = turned(turns);
+            let (b, c, d, f) = (p(0, -1), p(1, -1), p(-1, 0), p(1, 0));
+            let (g, h, i) = (p(-1, 1), p(0, 1), p(1, 1));
+            let (top_right, bottom_left) = (corners[(turns + 3) % 4], corners[(turns + 1) % 4]);
+
+            let line = corner == CornerBlend::Dominant
+                || !((top_right != CornerBlend::None && !corner_alike(e, g))
+                    || (bottom_left != CornerBlend::None && !corner_alike(e, c))
+                    // an L of one colour around E: only the corner
+                    || (!corner_alike(e, i)
+                        && corner_alike(g, h)
+                        && corner_alike(h, i)
+                        && corner_alike(i, f)
+                        && corner_alike(f, c)));
+            let line = if line {
+                let (fg, hc) = (ycbcr_distance(f, g), ycbcr_distance(h, c));
+                let shallow = 2.2 * fg <= hc && e != g && d != g;
+                let steep = 2.2 * hc <= fg && e != c && b != c;
+                match (shallow, steep) {
+                    (true, true) => CornerLine::ShallowAndSteep,
+                    (true, false) => CornerLine::Shallow,
+                    (false, true) => CornerLine::Steep,
+                    (false, false) => CornerLine::Diagonal,
+                }
+            } else {
+                CornerLine::Corner
+            };
+
+            let fill = if ycbcr_distance(e, f) <= ycbcr_distance(e, h) {
+                f
+            } else {
+                h
+            };
+            for &(column, row, share) in corner_stroke(line, scale) {
+                let cell = turned_cell(column, row, turns, scale);
+                block[cell] = blend(block[cell], fill, share, CORNER_OUT_OF);
+            }
+        }
+        block
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Four by four pixels, black above a staircase and grey below it
+    fn edge_frame() -> Frame {
+        let mut frame = Frame::with_size(4, 4);
+        for y in 0..4 {
+            for x in 0..4 {
+                let grey = if x + y >= 3 { 240 } else { 0 };
+                frame.set_pixel(x, y, (grey, grey, grey));
+            }
+        }
+        frame
+    }
+
+    /// The picture as rows of grey levels, checking it stayed grey
+    fn greys(image: &Image) -> Vec<Vec<u8>> {
+        image
+            .data
+            .chunks_exact(image.width * 3)
+            .map(|row| {
+                row.chunks_exact(3)
+                    .map(|rgb| {
+                        assert!(rgb[0] == rgb[1] && rgb[1] == rgb[2]);
+                        rgb[0]
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn smooth2x_on_an_edge() {
+        let expected = [
+            [0, 0, 0, 0, 0, 0, 240, 240],
+            [0, 0, 0, 0, 0, 90, 240, 240],
+            [0, 0, 0, 0, 120, 240, 240, 240],
+            [0, 0, 0, 120, 240, 240, 240, 240],
+            [0, 0, 120, 240, 240, 240, 240, 240],
+            [0, 90, 240, 240, 240, 240, 240, 240],
+            [240; 8],
+            [240; 8],
+        ];
+        assert_eq!(greys(&Filter::Smooth2x.apply(&edge_frame())), expected);
+    }
+
+    #[test]
+    fn smooth3x_on_an_edge() {
+        let expected = [
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 240, 240, 240],
+            [0, 0, 0, 0, 0, 0, 0, 0, 30, 240, 240, 240],
+            [0, 0, 0, 0, 0, 0, 0, 0, 120, 240, 240, 240],
+            [0, 0, 0, 0, 0, 0, 30, 210, 240, 240, 240, 240],
+            [0, 0, 0, 0, 0, 30, 210, 240, 240, 240, 240, 240],
+            [0, 0, 0, 0, 30, 210, 240, 240, 240, 240, 240, 240],
+            [0, 0, 0, 30, 210, 240, 240, 240, 240, 240, 240, 240],
+            [0, 0, 0, 210, 240, 240, 240, 240, 240, 240, 240, 240],
+            [0, 30, 120, 240, 240, 240, 240, 240, 240, 240, 240, 240],
+            [240; 12],
+            [240; 12],
+            [240; 12],
+        ];
+        assert_eq!(greys(&Filter::Smooth3x.apply(&edge_frame())), expected);
+    }
+
+    #[test]
+    fn corner2x_on_an_edge() {
+        let expected = [
+            [0, 0, 0, 0, 0, 60, 240, 240],
+            [0, 0, 0, 0, 0, 180, 240, 240],
+            [0, 0, 0, 0, 120, 240, 240, 240],
+            [0, 0, 0, 120, 240, 240, 240, 240],
+            [0, 0, 120, 240, 240, 240, 240, 240],
+            [60, 180, 240, 240, 240, 240, 240, 240],
+            [240; 8],
+            [240; 8],
+        ];
+        assert_eq!(greys(&Filter::Corner2x.apply(&edge_frame())), expected);
+    }
+
+    #[test]
+    fn corner3x_on_an_edge() {
+        let expected = [
+            [0, 0, 0, 0, 0, 0, 0, 0, 60, 240, 240, 240],
+            [0, 0, 0, 0, 0, 0, 0, 0, 180, 240, 240, 240],
+            [0, 0, 0, 0, 0, 0, 0, 60, 240, 240, 240, 240],
+            [0, 0, 0, 0, 0, 0, 30, 210, 240, 240, 240, 240],
+            [0, 0, 0, 0, 0, 30, 210, 240, 240, 240, 240, 240],
+            [0, 0, 0, 0, 30, 210, 240, 240, 240, 240, 240, 240],
+            [0, 0, 0, 30, 210, 240, 240, 240, 240, 240, 240, 240],
+            [0, 0, 60, 210, 240, 240, 240, 240, 240, 240, 240, 240],
+            [60, 180, 240, 240, 240, 240, 240, 240, 240, 240, 240, 240],
+            [240; 12],
+            [240; 12],
+            [240; 12],
+        ];
+        assert_eq!(greys(&Filter::Corner3x.apply(&edge_frame())), expected);
+    }
+}
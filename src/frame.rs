@@ -1,23 +1,317 @@
+use std::borrow::Cow;
+#[cfg(feature = "image")]
+use std::path::Path;
+use std::str::FromStr;
+
+/// How `Frame::data` holds each pixel. Frontends can pick the one their
+/// textures take and upload the frame as it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PixelFormat {
+    /// Red, green and blue bytes
+    #[default]
+    Rgb24,
+    /// Red, green, blue and alpha bytes, with alpha always 0xff. That's
+    /// SDL's RGBA32, or RGBA with unsigned bytes in OpenGL.
+    Rgba8888,
+    /// 16 bits, little-endian: 5 bits of red at the top, 6 of green and 5
+    /// of blue
+    Rgb565,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgb24 => 3,
+            PixelFormat::Rgba8888 => 4,
+            PixelFormat::Rgb565 => 2,
+        }
+    }
+}
+
 pub struct Frame {
+    /// The pixels row by row, in `format`. Rows are `pitch` bytes apart.
     pub data: Vec<u8>,
+    /// The same picture as palette entries, once `set_indexed` turns it on.
+    /// The low 6 bits of each pixel are the colour and bits 6-8 the PPUMASK
+    /// emphasis bits, which is what NTSC filters and palette shaders want.
+    pub indexed: Option<Vec<u16>>,
+    width: usize,
+    height: usize,
+    format: PixelFormat,
 }
 
 impl Frame {
-    const WIDTH: usize = 256;
-    const HIGHT: usize = 240;
+    pub const WIDTH: usize = 256;
+    pub const HIGHT: usize = 240;
 
     pub fn new() -> Self {
+        Frame::with_size(Frame::WIDTH, Frame::HIGHT)
+    }
+
+    /// A frame of another size than the picture, for debug views
+    pub fn with_size(width: usize, height: usize) -> Self {
         Frame {
-            data: vec![0; (Frame::WIDTH) * (Frame::HIGHT) * 3],
+            data: vec![0; width * height * 3],
+            indexed: None,
+            width,
+            height,
+            format: PixelFormat::Rgb24,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Bytes from the start of one row of `data` to the next
+    pub fn pitch(&self) -> usize {
+        self.width * self.format.bytes_per_pixel()
+    }
+
+    /// Switches `data` to `format`, converting the picture so far
+    pub fn set_format(&mut self, format: PixelFormat) {
+        if format == self.format {
+            return;
+        }
+        let mut converted = Frame {
+            data: vec![0; self.width * self.height * format.bytes_per_pixel()],
+            indexed: None,
+            width: self.width,
+            height: self.height,
+            format,
+        };
+        for y in 0..self.height {
+            for x in 0..self.width {
+                converted.set_pixel(x, y, self.pixel(x, y));
+            }
+        }
+        self.data = converted.data;
+        self.format = format;
+    }
+
+    /// Starts or stops keeping the picture as palette entries too
+    pub fn set_indexed(&mut self, enabled: bool) {
+        self.indexed = enabled.then(|| vec![0; self.width * self.height]);
+    }
+
+    pub fn set_index(&mut self, x: usize, y: usize, index: u16) {
+        if let Some(pixel) = self
+            .indexed
+            .as_mut()
+            .and_then(|indexed| indexed.get_mut(y * self.width + x))
+        {
+            *pixel = index;
         }
     }
 
     pub fn set_pixel(&mut self, x: usize, y: usize, rgb: (u8, u8, u8)) {
-        let base = y * 3 * Frame::WIDTH + x * 3;
-        if base + 2 < self.data.len() {
-            self.data[base] = rgb.0;
-            self.data[base + 1] = rgb.1;
-            self.data[base + 2] = rgb.2;
+        let size = self.format.bytes_per_pixel();
+        let base = (y * self.width + x) * size;
+        let Some(pixel) = self.data.get_mut(base..base + size) else {
+            return;
+        };
+        match self.format {
+            PixelFormat::Rgb24 => pixel.copy_from_slice(&[rgb.0, rgb.1, rgb.2]),
+            PixelFormat::Rgba8888 => pixel.copy_from_slice(&[rgb.0, rgb.1, rgb.2, 0xff]),
+            PixelFormat::Rgb565 => {
+                let packed =
+                    (rgb.0 as u16 >> 3) << 11 | (rgb.1 as u16 >> 2) << 5 | rgb.2 as u16 >> 3;
+                pixel.copy_from_slice(&packed.to_le_bytes());
+            }
+        }
+    }
+
+    /// The colour of pixel (`x`, `y`). RGB565 loses the low bits, which
+    /// come back as copies of the high ones.
+    pub fn pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let size = self.format.bytes_per_pixel();
+        let base = (y * self.width + x) * size;
+        let pixel = &self.data[base..base + size];
+        match self.format {
+            PixelFormat::Rgb24 | PixelFormat::Rgba8888 => (pixel[0], pixel[1], pixel[2]),
+            PixelFormat::Rgb565 => {
+                let packed = u16::from_le_bytes([pixel[0], pixel[1]]);
+                let (r, g, b) = (packed >> 11, packed >> 5 & 0x3f, packed & 0x1f);
+                (
+                    (r << 3 | r >> 2) as u8,
+                    (g << 2 | g >> 4) as u8,
+                    (b << 3 | b >> 2) as u8,
+                )
+            }
+        }
+    }
+
+    /// The picture as red, green and blue bytes, converted if the frame is
+    /// in another format
+    pub fn to_rgb24(&self) -> Cow<'_, [u8]> {
+        if self.format == PixelFormat::Rgb24 {
+            return Cow::Borrowed(&self.data);
+        }
+        let mut rgb = Vec::with_capacity(self.width * self.height * 3);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (r, g, b) = self.pixel(x, y);
+                rgb.extend([r, g, b]);
+            }
+        }
+        Cow::Owned(rgb)
+    }
+}
+
+#[cfg(feature = "image")]
+impl Frame {
+    pub fn save_png(&self, path: impl AsRef<Path>) -> image::ImageResult<()> {
+        image::save_buffer_with_format(
+            path,
+            &self.to_rgb24(),
+            self.width as u32,
+            self.height as u32,
+            image::ColorType::Rgb8,
+            image::ImageFormat::Png,
+        )
+    }
+}
+
+/// How many pixels to hide at each edge when showing a frame. TVs cut the
+/// edges of the picture off, so games often leave garbage there: scroll
+/// seams at the top and bottom, or attribute glitches at the sides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Overscan {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Overscan {
+    /// About what a typical NTSC TV hid
+    pub const NTSC: Overscan = Overscan {
+        top: 8,
+        bottom: 8,
+        left: 0,
+        right: 0,
+    };
+
+    /// The part of a frame left showing, as x, y, width and height. Asking
+    /// for more than the whole frame leaves a single pixel.
+    pub fn visible(&self) -> (usize, usize, usize, usize) {
+        let left = self.left.min(Frame::WIDTH - 1);
+        let top = self.top.min(Frame::HIGHT - 1);
+        let width = (Frame::WIDTH - left).saturating_sub(self.right).max(1);
+        let height = (Frame::HIGHT - top).saturating_sub(self.bottom).max(1);
+        (left, top, width, height)
+    }
+}
+
+/// Either one number, for the top and the bottom, or four separated by
+/// commas: top, bottom, left and right
+impl FromStr for Overscan {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Overscan, String> {
+        let edges = text
+            .split(',')
+            .map(|edge| {
+                edge.trim()
+                    .parse::<usize>()
+                    .map_err(|_| format!("`{}` is not a number of pixels", edge))
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+        match edges[..] {
+            [lines] => Ok(Overscan {
+                top: lines,
+                bottom: lines,
+                left: 0,
+                right: 0,
+            }),
+            [top, bottom, left, right] => Ok(Overscan {
+                top,
+                bottom,
+                left,
+                right,
+            }),
+            _ => Err(format!(
+                "overscan `{}` needs one number or four: top, bottom, left and right",
+                text
+            )),
+        }
+    }
+}
+
+/// How a frame is sized to fill the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScaleMode {
+    /// Fills the whole window, whatever its shape
+    #[default]
+    Stretch,
+    /// As large as fits while keeping the picture's shape
+    Fit,
+    /// Like `Fit`, but only whole multiples of the picture's height, so
+    /// every line is drawn the same number of times
+    Integer,
+}
+
+impl FromStr for ScaleMode {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<ScaleMode, String> {
+        match text.to_ascii_lowercase().as_str() {
+            "stretch" => Ok(ScaleMode::Stretch),
+            "fit" => Ok(ScaleMode::Fit),
+            "integer" => Ok(ScaleMode::Integer),
+            _ => Err(format!(
+                "`{}` is not a scale mode, try stretch, fit or integer",
+                text
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Scaling {
+    pub mode: ScaleMode,
+    /// Widens the picture by 8:7. The NES draws pixels a little wider than
+    /// tall on an NTSC TV, so square pixels make everything look thin.
+    pub pixel_aspect: bool,
+}
+
+impl Scaling {
+    /// Where a `width` x `height` picture goes in a window, as x, y, width
+    /// and height, centred
+    pub fn place(
+        &self,
+        (width, height): (usize, usize),
+        (window_width, window_height): (usize, usize),
+    ) -> (usize, usize, usize, usize) {
+        if self.mode == ScaleMode::Stretch {
+            return (0, 0, window_width, window_height);
+        }
+        // the shape to keep, as the width per line of height
+        let aspect = if self.pixel_aspect {
+            width as f64 * 8.0 / 7.0 / height as f64
+        } else {
+            width as f64 / height as f64
+        };
+        let mut scale = (window_height as f64 / height as f64)
+            .min(window_width as f64 / aspect / height as f64);
+        if self.mode == ScaleMode::Integer {
+            scale = scale.floor().max(1.0);
         }
+        let placed_height = (height as f64 * scale).round() as usize;
+        let placed_width = (placed_height as f64 * aspect).round() as usize;
+        (
+            window_width.saturating_sub(placed_width) / 2,
+            window_height.saturating_sub(placed_height) / 2,
+            placed_width,
+            placed_height,
+        )
     }
 }
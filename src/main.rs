@@ -1,26 +1,57 @@
+pub mod apu;
+pub mod assembler;
 pub mod bus;
+pub mod call_stack;
+pub mod cheats;
+#[cfg(feature = "image")]
+pub mod clip;
+pub mod clock;
 pub mod core;
+pub mod coverage;
+pub mod debug_view;
+pub mod disasm;
+pub mod expansion;
+pub mod filter;
 pub mod frame;
 pub mod opcodes;
 pub mod ppu;
 pub mod ppu_registers;
 pub mod rom;
+pub mod romdb;
+pub mod symbols;
 pub mod trace;
 pub mod joypad;
+pub mod mapper;
 pub mod render;
+pub mod video;
 
 use bus::*;
-use ppu::NesPPU;
+use filter::{Filter, FrameBlend};
+use frame::{Overscan, ScaleMode, Scaling};
+use ppu::{Layer, NesPPU, RenderMode};
 use core::*;
 use std::collections::HashMap;
-use frame::*;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use render::{Palette, PalettePreset};
 use rom::*;
 use trace::*;
-use render::*;
+use video::{AudioDump, VideoDump, VideoFormat};
 
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
+#[cfg(feature = "image")]
+use clip::Recorder;
+#[cfg(feature = "image")]
+use sdl2::keyboard::Mod;
 use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::rc::Rc;
+#[cfg(feature = "image")]
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[macro_use]
 extern crate lazy_static;
@@ -28,7 +59,57 @@ extern crate lazy_static;
 #[macro_use]
 extern crate bitflags;
 
-fn main() {
+/// The ROM after `current` in its directory, in file name order, wrapping
+/// around to the first
+fn next_rom(current: &Path) -> Option<PathBuf> {
+    let mut roms: Vec<PathBuf> = std::fs::read_dir(current.parent()?)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|ext| {
+                ["nes", "unf", "fds"]
+                    .iter()
+                    .any(|known| ext.eq_ignore_ascii_case(known))
+            })
+        })
+        .collect();
+    roms.sort();
+    let index = roms
+        .iter()
+        .position(|path| path == current)
+        .map_or(0, |i| i + 1);
+    roms.get(index % roms.len().max(1)).cloned()
+}
+
+/// `<rom name>-<milliseconds since 1970>.<extension>`, next to the ROM
+#[cfg(feature = "image")]
+fn timestamped_path(rom_path: &Path, extension: &str) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis());
+    let stem = rom_path.file_stem().unwrap_or_default().to_string_lossy();
+    rom_path.with_file_name(format!("{}-{}.{}", stem, millis, extension))
+}
+
+fn load_save(bus: &mut Bus, rom_path: &Path) {
+    let save_path = rom_path.with_extension("sav");
+    if bus.has_battery() && save_path.exists() {
+        if let Err(err) = bus.load_prg_ram(&save_path) {
+            eprintln!("could not load {}: {}", save_path.display(), err);
+        }
+    }
+}
+
+fn write_save(bus: &Bus, rom_path: &Path) {
+    let save_path = rom_path.with_extension("sav");
+    if bus.has_battery() {
+        if let Err(err) = bus.save_prg_ram(&save_path) {
+            eprintln!("could not save {}: {}", save_path.display(), err);
+        }
+    }
+}
+
+fn main() -> ExitCode {
     let rom_name = "Pac-Man";
     // init sdl2
     let sdl_context = sdl2::init().unwrap();
@@ -36,24 +117,147 @@ fn main() {
     let window = video_subsystem
         .window(rom_name, (256.0 * 4.0) as u32, (240.0 * 4.0) as u32)
         .position_centered()
+        .resizable()
         .build()
         .unwrap();
 
+    // scaled up pixels stay sharp
+    sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "0");
     let mut canvas = window.into_canvas().present_vsync().build().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
-    canvas.set_scale(2.0, 2.0).unwrap();
 
     let creator = canvas.texture_creator();
+    // big enough for what the filter that scales the most gives back
+    let max_scale = Filter::ALL
+        .iter()
+        .map(|filter| filter.scale())
+        .max()
+        .unwrap_or(1);
     let mut texture = creator
-        .create_texture_target(PixelFormatEnum::RGB24, 256, 240)
+        .create_texture_target(
+            PixelFormatEnum::RGB24,
+            (256 * max_scale) as u32,
+            (240 * max_scale) as u32,
+        )
         .unwrap();
 
     //load the game
-    let bytes: Vec<u8> = std::fs::read(format!("{}{}.nes", "/home/adarsh/Adarsh_Data/Adarsh_Coding/nes_emulator/roms/", rom_name)).unwrap();
-    // let bytes: Vec<u8> = std::fs::read("pacman.nes").unwrap();
-    let rom = Rom::new(&bytes).unwrap();
+    let rom_path = format!("{}{}.nes", "/home/adarsh/Adarsh_Data/Adarsh_Coding/nes_emulator/roms/", rom_name);
+    let rom = match Rom::from_file(&rom_path) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("could not load {}: {}", rom_path, err);
+            std::process::exit(1);
+        }
+    };
+
+    // --palette <file.pal> draws in the colours of a palette dump, and
+    // --palette <name> in one of the built-in ones
+    let palette = std::env::args()
+        .skip_while(|arg| arg != "--palette")
+        .nth(1)
+        .map(|path| match PalettePreset::from_name(&path) {
+            Some(preset) => Palette::preset(preset),
+            None => Palette::from_file(&path).unwrap_or_else(|err| {
+                eprintln!("could not load {}: {}", path, err);
+                std::process::exit(1);
+            }),
+        });
+
+    // --overscan <lines> or <top,bottom,left,right> hides the edges of the
+    // picture the way a TV would
+    let overscan = std::env::args()
+        .skip_while(|arg| arg != "--overscan")
+        .nth(1)
+        .map_or(Ok(Overscan::default()), |edges| edges.parse::<Overscan>())
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        });
+    let (crop_x, crop_y, crop_width, crop_height) = overscan.visible();
+
+    // --scale stretch, fit or integer picks how the picture fills the
+    // window, and --aspect widens it to the 8:7 pixels a TV shows
+    let scaling = Scaling {
+        mode: std::env::args()
+            .skip_while(|arg| arg != "--scale")
+            .nth(1)
+            .map_or(Ok(ScaleMode::default()), |mode| mode.parse::<ScaleMode>())
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }),
+        pixel_aspect: std::env::args().any(|arg| arg == "--aspect"),
+    };
+
+    // --blend <percent> mixes that much of the previous frame into each
+    // one, which steadies sprites that flicker
+    let mut blend = std::env::args()
+        .skip_while(|arg| arg != "--blend")
+        .nth(1)
+        .map(|percent| match percent.parse::<u8>() {
+            Ok(weight) => FrameBlend::new(weight),
+            Err(_) => {
+                eprintln!("`{}` is not a percentage", percent);
+                std::process::exit(1);
+            }
+        });
+
+    // --dump-video <file> writes every frame to a .y4m file, as raw RGB to
+    // any other file, or as Y4M to standard output for `-`
+    let mut video_dump = std::env::args()
+        .skip_while(|arg| arg != "--dump-video")
+        .nth(1)
+        .map(|path| {
+            let out: Box<dyn Write> = if path == "-" {
+                Box::new(io::stdout())
+            } else {
+                match File::create(&path) {
+                    Ok(file) => Box::new(file),
+                    Err(err) => {
+                        eprintln!("could not create {}: {}", path, err);
+                        std::process::exit(1);
+                    }
+                }
+            };
+            let format = VideoFormat::for_path(&path);
+            let dump =
+                VideoDump::new(BufWriter::new(out), format, rom.region).unwrap_or_else(|err| {
+                    eprintln!("could not write to {}: {}", path, err);
+                    std::process::exit(1);
+                });
+            if format == VideoFormat::Rgb {
+                eprintln!(
+                    "encode with: ffmpeg {} -i {} ...",
+                    dump.ffmpeg_input(),
+                    path
+                );
+            }
+            dump
+        });
 
-    let mut frame = Frame::new();
+    // --dump-audio <file> writes the sound to go with --dump-video as a
+    // WAV file
+    if let Some(path) = std::env::args()
+        .skip_while(|arg| arg != "--dump-audio")
+        .nth(1)
+    {
+        let Some(dump) = video_dump.as_mut() else {
+            eprintln!("--dump-audio goes with --dump-video");
+            return ExitCode::FAILURE;
+        };
+        match AudioDump::create(&path) {
+            Ok(audio) => dump.set_audio(audio),
+            Err(err) => {
+                eprintln!("could not create {}: {}", path, err);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    // shared with the frame callback, so the dump can be finished once the
+    // emulation stops
+    let video_dump = Rc::new(RefCell::new(video_dump));
+    let dumping = video_dump.clone();
 
     let mut key_map = HashMap::new();
     key_map.insert(Keycode::Down, joypad::JoypadButton::DOWN);
@@ -65,13 +269,92 @@ fn main() {
     key_map.insert(Keycode::A, joypad::JoypadButton::BUTTON_A);
     key_map.insert(Keycode::S, joypad::JoypadButton::BUTTON_B);
 
+    let mut key_map2 = HashMap::new();
+    key_map2.insert(Keycode::K, joypad::JoypadButton::DOWN);
+    key_map2.insert(Keycode::I, joypad::JoypadButton::UP);
+    key_map2.insert(Keycode::L, joypad::JoypadButton::RIGHT);
+    key_map2.insert(Keycode::J, joypad::JoypadButton::LEFT);
+    key_map2.insert(Keycode::Y, joypad::JoypadButton::SELECT);
+    key_map2.insert(Keycode::U, joypad::JoypadButton::START);
+    key_map2.insert(Keycode::G, joypad::JoypadButton::BUTTON_A);
+    key_map2.insert(Keycode::H, joypad::JoypadButton::BUTTON_B);
+
 
     // run the game cycle
-    let bus = Bus::new(rom, move |ppu: &NesPPU, joypad: &mut joypad::Joypad| {
-        render(ppu, &mut frame);
-        texture.update(None, &frame.data, 256 * 3).unwrap();
+    let quit = Rc::new(Cell::new(false));
+    let quit_requested = quit.clone();
+    // F2 or dropping a ROM on the window swaps the cartridge
+    let current_rom = Rc::new(RefCell::new(PathBuf::from(&rom_path)));
+    let swap_to: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
+    let playing = current_rom.clone();
+    let swap_requested = swap_to.clone();
+    // F3 moves on to the next disk side on the Famicom Disk System
+    let change_disk = Rc::new(Cell::new(false));
+    let change_disk_requested = change_disk.clone();
+    // F4 switches between drawing line by line and the faster whole-frame
+    // renderer, which misses mid-frame effects
+    let toggle_render_mode = Rc::new(Cell::new(false));
+    let toggle_render_mode_requested = toggle_render_mode.clone();
+    // F5 lets more than eight sprites show on a line, which stops flicker
+    let toggle_sprite_limit = Rc::new(Cell::new(false));
+    let toggle_sprite_limit_requested = toggle_sprite_limit.clone();
+    // F6 and F7 hide and show the background and the sprites
+    let toggle_layer: Rc<Cell<Option<Layer>>> = Rc::new(Cell::new(None));
+    let toggle_layer_requested = toggle_layer.clone();
+    // F8 switches to the next filter for the picture, and --filter <name>
+    // picks the one to start with
+    let mut filter = match std::env::args().skip_while(|arg| arg != "--filter").nth(1) {
+        Some(name) => match Filter::from_name(&name) {
+            Some(filter) => filter,
+            None => {
+                eprintln!("`{}` is not a filter", name);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Filter::None,
+    };
+    // F10 starts and stops recording a GIF clip, and Shift+F10 an animated
+    // PNG one
+    #[cfg(feature = "image")]
+    let mut recording: Option<(Recorder, &str)> = None;
+    let mut bus = Bus::new(rom, move |ppu: &NesPPU, joypad1: &mut joypad::Joypad, joypad2: &mut joypad::Joypad| {
+        if let Some(title) = playing.borrow().file_stem().and_then(|stem| stem.to_str()) {
+            if canvas.window().title() != title {
+                canvas.window_mut().set_title(title).unwrap();
+            }
+        }
+
+        #[cfg(feature = "image")]
+        if let Some((recorder, _)) = recording.as_mut() {
+            recorder.push(ppu.frame());
+        }
 
-        canvas.copy(&texture, None, None).unwrap();
+        let mut dump = dumping.borrow_mut();
+        if let Some(Err(err)) = dump.as_mut().map(|dump| dump.write_frame(ppu.frame())) {
+            eprintln!("video dump stopped: {}", err);
+            *dump = None;
+        }
+
+        let blended = blend.as_mut().map(|blend| blend.blend(ppu.frame()));
+        let image = filter.apply(blended.as_ref().unwrap_or(ppu.frame()));
+        let filled = Rect::new(0, 0, image.width as u32, image.height as u32);
+        texture.update(filled, &image.data, image.width * 3).unwrap();
+
+        let scale = filter.scale();
+        let crop = Rect::new(
+            (crop_x * scale) as i32,
+            (crop_y * scale) as i32,
+            (crop_width * scale) as u32,
+            (crop_height * scale) as u32,
+        );
+        let (window_width, window_height) = canvas.output_size().unwrap();
+        let (x, y, width, height) = scaling.place(
+            (crop_width, crop_height),
+            (window_width as usize, window_height as usize),
+        );
+        let placed = Rect::new(x as i32, y as i32, width as u32, height as u32);
+        canvas.clear();
+        canvas.copy(&texture, crop, placed).unwrap();
 
         canvas.present();
         for event in event_pump.poll_iter() {
@@ -80,29 +363,175 @@ fn main() {
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
-                } => std::process::exit(0),
+                } => quit_requested.set(true),
 
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => *swap_requested.borrow_mut() = next_rom(&playing.borrow()),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    ..
+                } => change_disk_requested.set(true),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F4),
+                    ..
+                } => toggle_render_mode_requested.set(true),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => toggle_sprite_limit_requested.set(true),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    ..
+                } => toggle_layer_requested.set(Some(Layer::Background)),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    ..
+                } => toggle_layer_requested.set(Some(Layer::Sprites)),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    ..
+                } => {
+                    filter = filter.next();
+                    eprintln!("filter: {}", filter.name());
+                }
+                // F9 saves a screenshot of the frame as the NES drew it,
+                // and Shift+F9 of the picture as shown, filter and all
+                #[cfg(feature = "image")]
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    keymod,
+                    ..
+                } => {
+                    let path = timestamped_path(&playing.borrow(), "png");
+                    let saved = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        image.save_png(&path)
+                    } else {
+                        ppu.frame().save_png(&path)
+                    };
+                    match saved {
+                        Ok(()) => eprintln!("saved {}", path.display()),
+                        Err(err) => eprintln!("could not save {}: {}", path.display(), err),
+                    }
+                }
+                #[cfg(feature = "image")]
+                Event::KeyDown {
+                    keycode: Some(Keycode::F10),
+                    keymod,
+                    ..
+                } => match recording.take() {
+                    Some((recorder, extension)) => {
+                        let path = timestamped_path(&playing.borrow(), extension);
+                        match recorder.save(&path) {
+                            Ok(()) => eprintln!(
+                                "saved {:.1}s clip to {}",
+                                recorder.duration(),
+                                path.display()
+                            ),
+                            Err(err) => {
+                                eprintln!("could not save {}: {}", path.display(), err)
+                            }
+                        }
+                    }
+                    None => {
+                        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                        let extension = if shift { "png" } else { "gif" };
+                        recording = Some((Recorder::new(ppu.region()), extension));
+                        eprintln!("recording");
+                    }
+                },
+                Event::DropFile { filename, .. } => {
+                    *swap_requested.borrow_mut() = Some(PathBuf::from(filename))
+                }
 
                 Event::KeyDown { keycode, .. } => {
-                    if let Some(key) = key_map.get(&keycode.unwrap_or(Keycode::Ampersand)) {
-                        joypad.set_button_pressed_status(*key, true);
+                    let keycode = keycode.unwrap_or(Keycode::Ampersand);
+                    if let Some(key) = key_map.get(&keycode) {
+                        joypad1.set_button_pressed_status(*key, true);
+                    }
+                    if let Some(key) = key_map2.get(&keycode) {
+                        joypad2.set_button_pressed_status(*key, true);
                     }
                 }
                 Event::KeyUp { keycode, .. } => {
-                    if let Some(key) = key_map.get(&keycode.unwrap_or(Keycode::Ampersand)) {
-                        joypad.set_button_pressed_status(*key, false);
+                    let keycode = keycode.unwrap_or(Keycode::Ampersand);
+                    if let Some(key) = key_map.get(&keycode) {
+                        joypad1.set_button_pressed_status(*key, false);
+                    }
+                    if let Some(key) = key_map2.get(&keycode) {
+                        joypad2.set_button_pressed_status(*key, false);
                     }
                 }
 
                 _ => { /* do nothing */ }
             }
         }
-    });
+    }).unwrap();
+
+    if let Some(palette) = palette {
+        bus.set_palette(palette);
+    }
+    load_save(&mut bus, &current_rom.borrow());
 
     let mut cpu = Cpu::new(bus);
-    cpu.reset();
+    cpu.power_on(RamInitPattern::Striped);
     // cpu.run();
-    cpu.run_with_callback(|cpu| {
+    let result = cpu.run_with_callback(|cpu| {
         // println!("{}", trace(cpu));
+        if quit.get() {
+            cpu.stop();
+        }
+        if toggle_render_mode.take() {
+            let mode = match cpu.bus().ppu().render_mode() {
+                RenderMode::Scanline => RenderMode::Frame,
+                RenderMode::Frame => RenderMode::Scanline,
+            };
+            cpu.bus_mut().set_render_mode(mode);
+        }
+        if toggle_sprite_limit.take() {
+            let limit = cpu.bus().ppu().sprite_limit();
+            cpu.bus_mut().set_sprite_limit(!limit);
+        }
+        if let Some(layer) = toggle_layer.take() {
+            let visible = cpu.bus().ppu().layer_visible(layer);
+            cpu.bus_mut().set_layer_visible(layer, !visible);
+        }
+        let sides = cpu.bus().disk_sides();
+        if change_disk.take() && sides > 0 {
+            let side = cpu.bus().inserted_disk().map_or(0, |side| (side + 1) % sides);
+            cpu.bus_mut().insert_disk(Some(side));
+            eprintln!("disk {} side {}", side / 2 + 1, if side % 2 == 0 { 'A' } else { 'B' });
+        }
+        let Some(path) = swap_to.borrow_mut().take() else {
+            return;
+        };
+        let rom = match Rom::from_file(&path) {
+            Ok(rom) => rom,
+            Err(err) => {
+                eprintln!("could not load {}: {}", path.display(), err);
+                return;
+            }
+        };
+        write_save(cpu.bus(), &current_rom.borrow());
+        match cpu.insert_cartridge(rom, RamInitPattern::Striped) {
+            Ok(()) => {
+                load_save(cpu.bus_mut(), &path);
+                *current_rom.borrow_mut() = path;
+            }
+            Err(err) => eprintln!("could not load {}: {}", path.display(), err),
+        }
     });
+
+    write_save(cpu.bus(), &current_rom.borrow());
+    if let Some(mut dump) = video_dump.borrow_mut().take() {
+        if let Err(err) = dump.flush() {
+            eprintln!("could not finish the video dump: {}", err);
+        }
+    }
+    if let Err(err) = result {
+        eprintln!("emulation stopped: {}", err);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
 }
\ No newline at end of file
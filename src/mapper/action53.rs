@@ -0,0 +1,185 @@
+use super::Mapper;
+use crate::rom::{Mirroring, Rom, PRG_ROM_PAGE_SIZE};
+
+const CHR_RAM_SIZE: usize = 0x8000;
+const CHR_BANK_SIZE: usize = 0x2000;
+
+/// Mapper 28, Action 53 (homebrew multicarts): $5000-$5FFF selects one
+/// of four registers and $8000-$FFFF writes it.
+///
+/// - $00: CHR-RAM bank (one of four 8 KiB banks)
+/// - $01: inner PRG bank
+/// - $80: mirroring (bits 0-1), PRG bank mode (bits 2-3) and game size
+///   (bits 4-5), which decides how many inner bank bits count
+/// - $81: outer PRG bank, in units of 32 KiB
+///
+/// With one-screen mirroring, bit 4 of $00 and $01 writes also picks the
+/// screen, so games written for AxROM run unchanged.
+pub struct Action53 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    register_select: u8,
+    chr_bank: usize,
+    inner_bank: usize,
+    mode: u8,
+    outer_bank: usize,
+}
+
+impl Action53 {
+    pub fn new(rom: Rom) -> Self {
+        Action53 {
+            prg_rom: rom.prg_rom,
+            chr: vec![0; CHR_RAM_SIZE],
+            register_select: 0,
+            chr_bank: 0,
+            inner_bank: 0,
+            mode: 0,
+            // the menu lives in the last bank
+            outer_bank: 0xFF,
+        }
+    }
+
+    fn set_one_screen(&mut self, data: u8) {
+        if self.mode & 0b10 == 0 {
+            self.mode = (self.mode & !1) | ((data >> 4) & 1);
+        }
+    }
+
+    /// The 16 KiB PRG bank mapped at `addr`
+    fn prg_bank(&self, addr: u16) -> usize {
+        let a14 = (addr as usize >> 14) & 1;
+        // 32, 64, 128 or 256 KiB of the outer bank follow the inner bank,
+        // i.e. the low 1, 2, 3 or 4 bits of the 16 KiB bank number
+        let mask = (2 << ((self.mode as usize >> 4) & 0b11)) - 1;
+        let switchable = match (self.mode >> 2) & 0b11 {
+            0 | 1 => Some((self.inner_bank << 1) | a14),
+            // fixed at $8000, switched at $C000
+            2 => (a14 == 1).then_some(self.inner_bank),
+            // switched at $8000, fixed at $C000, like UNROM
+            _ => (a14 == 0).then_some(self.inner_bank),
+        };
+        match switchable {
+            Some(bank) => ((self.outer_bank << 1) & !mask) | (bank & mask),
+            None => (self.outer_bank << 1) | a14,
+        }
+    }
+}
+
+impl Mapper for Action53 {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        self.prg_offset(addr).map(|offset| self.prg_rom[offset])
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x5000..=0x5FFF => self.register_select = data & 0x81,
+            0x8000..=0xFFFF => match self.register_select {
+                0x00 => {
+                    self.chr_bank = (data & 0b11) as usize;
+                    self.set_one_screen(data);
+                }
+                0x01 => {
+                    self.inner_bank = (data & 0x0F) as usize;
+                    self.set_one_screen(data);
+                }
+                0x80 => self.mode = data & 0x3F,
+                _ => self.outer_bank = data as usize,
+            },
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr[self.chr_bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.chr[self.chr_bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE] = data;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.mode & 0b11 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => Some(
+                (self.prg_bank(addr) * PRG_ROM_PAGE_SIZE + addr as usize % PRG_ROM_PAGE_SIZE)
+                    % self.prg_rom.len(),
+            ),
+            _ => None,
+        }
+    }
+
+    fn chr_offset(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.prg_rom.len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        0
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        if let Some(offset) = self.prg_offset(addr) {
+            self.prg_rom[offset] = data;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Region;
+
+    /// 512 KiB of PRG with each 16 KiB bank starting with its own number
+    fn test_mapper() -> Action53 {
+        let mut prg_rom = vec![0; 32 * PRG_ROM_PAGE_SIZE];
+        for bank in 0..32 {
+            prg_rom[bank * PRG_ROM_PAGE_SIZE] = bank as u8;
+        }
+        Action53::new(Rom {
+            prg_rom,
+            chr_rom: vec![],
+            mapper: 28,
+            screen_mirroring: Mirroring::Horizontal,
+            prg_ram_size: 0,
+            battery: false,
+            region: Region::Ntsc,
+            trainer: None,
+            disk_sides: vec![],
+        })
+    }
+
+    fn write_register(mapper: &mut Action53, register: u8, data: u8) {
+        mapper.cpu_write(0x5000, register);
+        mapper.cpu_write(0x8000, data);
+    }
+
+    #[test]
+    fn game_size_picks_how_many_inner_bank_bits_count() {
+        // outer bank 4 (16 KiB banks 8-9), inner bank 3, 32 KiB mode: the
+        // game size decides how many bits of 3 << 1 replace those of 8
+        for (size, bank) in [(0, 8), (1, 10), (2, 14), (3, 6)] {
+            let mut mapper = test_mapper();
+            write_register(&mut mapper, 0x81, 4);
+            write_register(&mut mapper, 0x80, size << 4);
+            write_register(&mut mapper, 0x01, 3);
+            assert_eq!(mapper.cpu_read(0x8000), Some(bank), "game size {}", size);
+            assert_eq!(
+                mapper.cpu_read(0xC000),
+                Some(bank + 1),
+                "game size {}",
+                size
+            );
+        }
+    }
+}
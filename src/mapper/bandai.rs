@@ -0,0 +1,263 @@
+use super::eeprom::Eeprom;
+use super::Mapper;
+use crate::rom::{Mirroring, Rom, CHR_ROM_PAGE_SIZE, PRG_ROM_PAGE_SIZE};
+
+const CHR_BANK_SIZE: usize = 0x400;
+/// PRG-ROM each outer bank of mapper 153 covers
+const OUTER_PRG_BANK_SIZE: usize = 0x40000;
+
+/// Mappers 16, 153 and 159, Bandai's FCG-1/2 and LZ93D50: a 16 KiB PRG
+/// bank at $8000 with the last bank fixed at $C000, eight 1 KiB CHR banks
+/// and a 16-bit IRQ counter that counts CPU cycles down.
+///
+/// Mapper 16 boards keep saves in a 24C02 EEPROM and mapper 159 boards in
+/// a 24C01, both read back through bit 4 of $6000-$7FFF. Mapper 153 has
+/// battery-backed PRG-RAM and CHR-RAM instead, and uses bit 0 of the CHR
+/// registers to pick a 256 KiB half of PRG-ROM.
+///
+/// FCG boards have their registers at $6000-$7FFF and LZ93D50 ones at
+/// $8000-$FFFF. Mapper 16 covers both, so both ranges work, and the IRQ
+/// registers act like the chip the game's writes point at.
+pub struct BandaiFcg {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    prg_ram_enabled: bool,
+    battery: bool,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    eeprom: Option<Eeprom>,
+    /// Mapper 153 only
+    outer_prg_banks: bool,
+    prg_bank: usize,
+    chr_banks: [usize; 8],
+    mirroring: Mirroring,
+    irq_counter: u16,
+    irq_latch: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+}
+
+impl BandaiFcg {
+    pub fn new(rom: Rom) -> Self {
+        let chr_is_ram = rom.chr_rom.is_empty();
+        let chr = if chr_is_ram {
+            vec![0; CHR_ROM_PAGE_SIZE]
+        } else {
+            rom.chr_rom
+        };
+        let eeprom = match rom.mapper {
+            16 => Some(Eeprom::c02()),
+            159 => Some(Eeprom::c01()),
+            _ => None,
+        };
+        let outer_prg_banks = rom.mapper == 153;
+        BandaiFcg {
+            prg_rom: rom.prg_rom,
+            prg_ram: if outer_prg_banks {
+                vec![0; rom.prg_ram_size]
+            } else {
+                Vec::new()
+            },
+            prg_ram_enabled: false,
+            battery: rom.battery,
+            chr,
+            chr_is_ram,
+            eeprom,
+            outer_prg_banks,
+            prg_bank: 0,
+            chr_banks: [0; 8],
+            mirroring: rom.screen_mirroring,
+            irq_counter: 0,
+            irq_latch: 0,
+            irq_enabled: false,
+            irq_pending: false,
+        }
+    }
+
+    /// `lz93d50` tells the IRQ registers which chip to act as: the
+    /// LZ93D50 loads a latch and copies it to the counter when enabled,
+    /// where the FCG writes the counter directly
+    fn write_register(&mut self, register: u16, data: u8, lz93d50: bool) {
+        match register {
+            0x0..=0x7 => self.chr_banks[register as usize] = data as usize,
+            0x8 => self.prg_bank = (data & 0x0F) as usize,
+            0x9 => {
+                self.mirroring = match data & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0xA => {
+                self.irq_enabled = data & 1 != 0;
+                if lz93d50 {
+                    self.irq_counter = self.irq_latch;
+                }
+                self.irq_pending = false;
+            }
+            0xB | 0xC => {
+                let shift = if register == 0xB { 0 } else { 8 };
+                let target = if lz93d50 {
+                    &mut self.irq_latch
+                } else {
+                    &mut self.irq_counter
+                };
+                *target = (*target & !(0xFF << shift)) | (data as u16) << shift;
+            }
+            0xD => {
+                self.prg_ram_enabled = data & 0b0010_0000 != 0;
+                if let Some(eeprom) = &mut self.eeprom {
+                    eeprom.write(data & 0b0010_0000 != 0, data & 0b0100_0000 != 0);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Which 256 KiB half of PRG-ROM is mapped, on mapper 153
+    fn outer_prg_bank(&self) -> usize {
+        if self.outer_prg_banks {
+            self.chr_banks.iter().fold(0, |bank, &chr| bank | (chr & 1))
+        } else {
+            0
+        }
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        // mapper 153 has CHR-RAM, which isn't banked
+        let bank = if self.outer_prg_banks {
+            addr as usize / CHR_BANK_SIZE
+        } else {
+            self.chr_banks[(addr as usize / CHR_BANK_SIZE) % 8]
+        };
+        (bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE) % self.chr.len()
+    }
+}
+
+impl Mapper for BandaiFcg {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF => match &self.eeprom {
+                Some(eeprom) => Some((eeprom.output() as u8) << 4),
+                None if self.prg_ram_enabled && !self.prg_ram.is_empty() => {
+                    Some(self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()])
+                }
+                None => None,
+            },
+            _ => self.prg_offset(addr).map(|offset| self.prg_rom[offset]),
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if !self.outer_prg_banks => {
+                self.write_register(addr & 0x0F, data, false)
+            }
+            0x6000..=0x7FFF if self.prg_ram_enabled && !self.prg_ram.is_empty() => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) as usize % len] = data;
+            }
+            0x8000..=0xFFFF => self.write_register(addr & 0x0F, data, true),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery || self.eeprom.is_some()
+    }
+
+    fn save_data(&self) -> &[u8] {
+        match &self.eeprom {
+            Some(eeprom) => eeprom.data(),
+            None => &self.prg_ram,
+        }
+    }
+
+    fn save_data_mut(&mut self) -> &mut [u8] {
+        match &mut self.eeprom {
+            Some(eeprom) => eeprom.data_mut(),
+            None => &mut self.prg_ram,
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn irq_ack(&mut self) {
+        self.irq_pending = false;
+    }
+
+    fn cpu_tick(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+        if self.irq_counter == 0 {
+            self.irq_pending = true;
+        }
+        self.irq_counter = self.irq_counter.wrapping_sub(1);
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if self.prg_rom.is_empty() {
+            return None;
+        }
+        let outer = self.outer_prg_bank() * OUTER_PRG_BANK_SIZE;
+        let offset = match addr {
+            0x8000..=0xBFFF => outer + self.prg_bank * PRG_ROM_PAGE_SIZE + (addr as usize - 0x8000),
+            // the last bank of the 256 KiB half
+            0xC000..=0xFFFF => {
+                let half = OUTER_PRG_BANK_SIZE.min(self.prg_rom.len());
+                outer + half - PRG_ROM_PAGE_SIZE + (addr as usize - 0xC000)
+            }
+            _ => return None,
+        };
+        Some(offset % self.prg_rom.len())
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        (!self.chr_is_ram).then(|| self.chr_index(addr))
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.prg_rom.len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        if self.chr_is_ram {
+            0
+        } else {
+            self.chr.len()
+        }
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        if let Some(offset) = self.prg_offset(addr) {
+            self.prg_rom[offset] = data;
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use super::Mapper;
+use crate::rom::{Mirroring, Rom, CHR_ROM_PAGE_SIZE};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+const NINA_CHR_BANK_SIZE: usize = 0x1000;
+
+/// Mapper 34, which covers two unrelated boards that both switch 32 KiB
+/// of PRG-ROM at once:
+///
+/// - BNROM (Deadly Towers): writes to $8000-$FFFF pick the bank, with bus
+///   conflicts, and there is 8 KiB of CHR-RAM.
+/// - NINA-001 (Impossible Mission II): $7FFD picks the PRG bank and
+///   $7FFE/$7FFF two 4 KiB CHR-ROM banks. Those writes also land in the
+///   PRG-RAM underneath.
+///
+/// iNES headers can't say which, and NES 2.0 ones, whose submapper could,
+/// are turned down by `Rom::new`. So the choice is made by CHR size alone:
+/// boards with more than 8 KiB of CHR-ROM are taken to be NINA-001.
+pub struct Bnrom {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    battery: bool,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    nina: bool,
+    prg_bank: usize,
+    chr_banks: [usize; 2],
+    mirroring: Mirroring,
+}
+
+impl Bnrom {
+    pub fn new(rom: Rom) -> Self {
+        let nina = rom.chr_rom.len() > CHR_ROM_PAGE_SIZE;
+        let chr_is_ram = rom.chr_rom.is_empty();
+        let chr = if chr_is_ram {
+            vec![0; CHR_ROM_PAGE_SIZE]
+        } else {
+            rom.chr_rom
+        };
+        Bnrom {
+            prg_rom: rom.prg_rom,
+            prg_ram: if nina {
+                vec![0; rom.prg_ram_size]
+            } else {
+                Vec::new()
+            },
+            battery: rom.battery,
+            chr,
+            chr_is_ram,
+            nina,
+            prg_bank: 0,
+            chr_banks: [0, 1],
+            mirroring: rom.screen_mirroring,
+        }
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let index = if self.nina {
+            let bank = self.chr_banks[(addr as usize / NINA_CHR_BANK_SIZE) % 2];
+            bank * NINA_CHR_BANK_SIZE + addr as usize % NINA_CHR_BANK_SIZE
+        } else {
+            addr as usize
+        };
+        index % self.chr.len()
+    }
+}
+
+impl Mapper for Bnrom {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                Some(self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()])
+            }
+            _ => self.prg_offset(addr).map(|offset| self.prg_rom[offset]),
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.nina => {
+                match addr {
+                    0x7FFD => self.prg_bank = (data & 1) as usize,
+                    0x7FFE => self.chr_banks[0] = (data & 0x0F) as usize,
+                    0x7FFF => self.chr_banks[1] = (data & 0x0F) as usize,
+                    _ => {}
+                }
+                if !self.prg_ram.is_empty() {
+                    let len = self.prg_ram.len();
+                    self.prg_ram[(addr - 0x6000) as usize % len] = data;
+                }
+            }
+            0x8000..=0xFFFF if !self.nina => {
+                if let Some(rom_byte) = self.cpu_read(addr) {
+                    self.prg_bank = (data & rom_byte) as usize;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => Some(
+                (self.prg_bank * PRG_BANK_SIZE + (addr - 0x8000) as usize) % self.prg_rom.len(),
+            ),
+            _ => None,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        (!self.chr_is_ram).then(|| self.chr_index(addr))
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.prg_rom.len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        if self.chr_is_ram {
+            0
+        } else {
+            self.chr.len()
+        }
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        if let Some(offset) = self.prg_offset(addr) {
+            self.prg_rom[offset] = data;
+        }
+    }
+}
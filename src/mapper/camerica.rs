@@ -0,0 +1,107 @@
+use super::Mapper;
+use crate::rom::{Mirroring, Rom, CHR_ROM_PAGE_SIZE, PRG_ROM_PAGE_SIZE};
+
+/// Mapper 71, Camerica/Codemasters (BF909x): a 16 KiB PRG bank switched
+/// by writes to $C000-$FFFF with the last bank fixed at $C000, and 8 KiB
+/// of CHR-RAM. The Fire Hawk board (BF9097) also picks single-screen
+/// mirroring through bit 4 of writes to $9000-$9FFF. iNES headers can't
+/// tell the boards apart, so the first such write switches mirroring
+/// control over to the register.
+pub struct Camerica {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    prg_bank: usize,
+    mirroring: Mirroring,
+}
+
+impl Camerica {
+    pub fn new(rom: Rom) -> Self {
+        let chr_is_ram = rom.chr_rom.is_empty();
+        let chr = if chr_is_ram {
+            vec![0; CHR_ROM_PAGE_SIZE]
+        } else {
+            rom.chr_rom
+        };
+        Camerica {
+            prg_rom: rom.prg_rom,
+            chr,
+            chr_is_ram,
+            prg_bank: 0,
+            mirroring: rom.screen_mirroring,
+        }
+    }
+
+    fn last_bank(&self) -> usize {
+        (self.prg_rom.len() / PRG_ROM_PAGE_SIZE).saturating_sub(1)
+    }
+}
+
+impl Mapper for Camerica {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        self.prg_offset(addr).map(|offset| self.prg_rom[offset])
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x9000..=0x9FFF => {
+                self.mirroring = if data & 0b1_0000 == 0 {
+                    Mirroring::SingleScreenLower
+                } else {
+                    Mirroring::SingleScreenUpper
+                };
+            }
+            0xC000..=0xFFFF => self.prg_bank = data as usize,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr[addr as usize % self.chr.len()]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let len = self.chr.len();
+            self.chr[addr as usize % len] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let bank = match addr {
+            0x8000..=0xBFFF => self.prg_bank,
+            0xC000..=0xFFFF => self.last_bank(),
+            _ => return None,
+        };
+        (!self.prg_rom.is_empty()).then(|| {
+            (bank * PRG_ROM_PAGE_SIZE + (addr as usize & (PRG_ROM_PAGE_SIZE - 1)))
+                % self.prg_rom.len()
+        })
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        (!self.chr_is_ram).then(|| addr as usize % self.chr.len())
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.prg_rom.len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        if self.chr_is_ram {
+            0
+        } else {
+            self.chr.len()
+        }
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        if let Some(offset) = self.prg_offset(addr) {
+            self.prg_rom[offset] = data;
+        }
+    }
+}
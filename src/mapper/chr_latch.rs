@@ -0,0 +1,66 @@
+const CHR_BANK_SIZE: usize = 0x1000;
+
+/// The CHR banking of MMC2 and MMC4. Each 4 KiB pattern table has two
+/// banks, and a latch picks one of them whenever the PPU fetches tile $FD
+/// or $FE, so the banks change partway through a frame on their own.
+pub struct ChrLatch {
+    /// For each pattern table, the banks used while its latch is $FD and $FE
+    banks: [[usize; 2]; 2],
+    /// For each pattern table, 0 while its latch is $FD and 1 while $FE
+    latches: [usize; 2],
+    /// MMC2 only watches $0FD8 and $0FE8 in the left table, where MMC4
+    /// watches the whole high plane of both tiles as in the right one
+    exact_left: bool,
+}
+
+impl ChrLatch {
+    pub fn mmc2() -> Self {
+        ChrLatch {
+            banks: [[0; 2]; 2],
+            latches: [1, 1],
+            exact_left: true,
+        }
+    }
+
+    pub fn mmc4() -> Self {
+        ChrLatch {
+            exact_left: false,
+            ..ChrLatch::mmc2()
+        }
+    }
+
+    /// Sets the bank `table` uses while its latch is $FD (`latch` 0) or $FE
+    /// (`latch` 1)
+    pub fn set_bank(&mut self, table: usize, latch: usize, bank: u8) {
+        self.banks[table][latch] = (bank & 0x1F) as usize;
+    }
+
+    /// The latches flip on the high plane of tile $FD or $FE, so the tile
+    /// itself is still drawn from the old bank
+    pub fn fetch(&mut self, addr: u16) {
+        let table = (addr as usize / CHR_BANK_SIZE) & 1;
+        let in_tile = addr & 0x0FFF;
+        let matches = |first: u16| {
+            if table == 0 && self.exact_left {
+                in_tile == first
+            } else {
+                (first..first + 8).contains(&in_tile)
+            }
+        };
+        if matches(0x0FD8) {
+            self.latches[table] = 0;
+        } else if matches(0x0FE8) {
+            self.latches[table] = 1;
+        }
+    }
+
+    /// Offset into CHR-ROM of `chr_len` bytes for PPU address `addr`
+    pub fn offset(&self, addr: u16, chr_len: usize) -> Option<usize> {
+        if chr_len == 0 {
+            return None;
+        }
+        let table = (addr as usize / CHR_BANK_SIZE) & 1;
+        let bank = self.banks[table][self.latches[table]];
+        Some((bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE) % chr_len)
+    }
+}
@@ -0,0 +1,84 @@
+use super::Mapper;
+use crate::rom::{Mirroring, Rom};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+const CHR_BANK_SIZE: usize = 0x2000;
+
+/// Mapper 11, Color Dreams: one register anywhere in $8000-$FFFF picks a
+/// 32 KiB PRG bank (bits 0-1) and an 8 KiB CHR bank (bits 4-7). The board
+/// doesn't stop the ROM from driving the bus during the write, so the
+/// register gets the written value ANDed with the ROM byte at that address.
+pub struct ColorDreams {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    prg_bank: usize,
+    chr_bank: usize,
+    mirroring: Mirroring,
+}
+
+impl ColorDreams {
+    pub fn new(rom: Rom) -> Self {
+        ColorDreams {
+            prg_rom: rom.prg_rom,
+            chr_rom: rom.chr_rom,
+            prg_bank: 0,
+            chr_bank: 0,
+            mirroring: rom.screen_mirroring,
+        }
+    }
+}
+
+impl Mapper for ColorDreams {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        self.prg_offset(addr).map(|offset| self.prg_rom[offset])
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let Some(rom_byte) = self.cpu_read(addr) {
+            let data = data & rom_byte;
+            self.prg_bank = (data & 0b11) as usize;
+            self.chr_bank = (data >> 4) as usize;
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr_offset(addr)
+            .map_or(0, |offset| self.chr_rom[offset])
+    }
+
+    fn ppu_write(&mut self, _addr: u16, _data: u8) {
+        // CHR-ROM only
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => Some(
+                (self.prg_bank * PRG_BANK_SIZE + (addr - 0x8000) as usize) % self.prg_rom.len(),
+            ),
+            _ => None,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        (!self.chr_rom.is_empty())
+            .then(|| (self.chr_bank * CHR_BANK_SIZE + addr as usize) % self.chr_rom.len())
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.prg_rom.len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        self.chr_rom.len()
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        if let Some(offset) = self.prg_offset(addr) {
+            self.prg_rom[offset] = data;
+        }
+    }
+}
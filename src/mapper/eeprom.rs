@@ -0,0 +1,202 @@
+/// What the chip is doing with the clock pulses it gets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Idle,
+    /// Receiving the device address byte (24C02 only)
+    Device,
+    /// Receiving the word address, with the R/W bit after it on the 24C01
+    Address,
+    Read,
+    Write,
+    /// Pulling SDA low to acknowledge a byte
+    SendAck,
+    /// Waiting for the host to acknowledge a byte it read
+    WaitAck,
+}
+
+/// The I²C serial EEPROM Bandai boards keep save data in: a 24C01 (128
+/// bytes) or a 24C02 (256 bytes). The host bit-bangs SCL and SDA and reads
+/// SDA back. The 24C02 speaks standard I²C with a device address byte; the
+/// 24C01 takes a 7-bit address and the R/W bit straight after the start
+/// condition, and sends every byte LSB first.
+pub struct Eeprom {
+    data: Vec<u8>,
+    c01: bool,
+    mode: Mode,
+    /// The mode to go to once the acknowledge bit is done
+    next_mode: Mode,
+    address: u8,
+    device: u8,
+    shift: u8,
+    /// Bits shifted in or out of the current byte
+    bits: u8,
+    /// Level the chip drives SDA to
+    output: bool,
+    scl: bool,
+    sda: bool,
+}
+
+impl Eeprom {
+    pub fn c01() -> Self {
+        Eeprom {
+            data: vec![0; 128],
+            c01: true,
+            mode: Mode::Idle,
+            next_mode: Mode::Idle,
+            address: 0,
+            device: 0,
+            shift: 0,
+            bits: 0,
+            output: true,
+            scl: false,
+            sda: false,
+        }
+    }
+
+    pub fn c02() -> Self {
+        Eeprom {
+            data: vec![0; 256],
+            c01: false,
+            ..Eeprom::c01()
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// The level of SDA as the host reads it back
+    pub fn output(&self) -> bool {
+        self.output
+    }
+
+    /// Drives SCL and SDA to new levels
+    pub fn write(&mut self, scl: bool, sda: bool) {
+        if self.scl && scl && self.sda && !sda {
+            self.start();
+        } else if self.scl && scl && !self.sda && sda {
+            self.mode = Mode::Idle;
+            self.output = true;
+        } else if !self.scl && scl {
+            self.clock_rise(sda);
+        } else if self.scl && !scl {
+            self.clock_fall();
+        }
+        self.scl = scl;
+        self.sda = sda;
+    }
+
+    fn start(&mut self) {
+        self.mode = if self.c01 {
+            Mode::Address
+        } else {
+            Mode::Device
+        };
+        self.bits = 0;
+        self.shift = 0;
+        self.output = true;
+    }
+
+    /// Bit `n` of a byte in the order it goes over the wire
+    fn bit_mask(&self, n: u8) -> u8 {
+        if self.c01 {
+            1 << n
+        } else {
+            0x80 >> n
+        }
+    }
+
+    fn shift_in(&mut self, sda: bool) {
+        if self.bits < 8 {
+            if sda {
+                self.shift |= self.bit_mask(self.bits);
+            }
+            self.bits += 1;
+        }
+    }
+
+    fn shift_out(&mut self) {
+        if self.bits < 8 {
+            self.output = self.shift & self.bit_mask(self.bits) != 0;
+            self.bits += 1;
+        }
+    }
+
+    fn mask(&self) -> u8 {
+        (self.data.len() - 1) as u8
+    }
+
+    fn clock_rise(&mut self, sda: bool) {
+        match self.mode {
+            // the eighth bit after a 24C01 address is R/W
+            Mode::Address if self.c01 && self.bits == 7 => {
+                self.address = self.shift;
+                self.bits = 8;
+                self.next_mode = if sda { Mode::Read } else { Mode::Write };
+            }
+            Mode::Device | Mode::Address | Mode::Write => self.shift_in(sda),
+            Mode::Read => self.shift_out(),
+            Mode::SendAck => self.output = false,
+            Mode::WaitAck => {
+                // the host only acknowledges when it wants another byte
+                self.next_mode = if sda { Mode::Idle } else { Mode::Read };
+            }
+            Mode::Idle => {}
+        }
+    }
+
+    fn clock_fall(&mut self) {
+        if self.bits < 8 && !matches!(self.mode, Mode::SendAck | Mode::WaitAck) {
+            return;
+        }
+        match self.mode {
+            Mode::Device => {
+                self.device = self.shift;
+                if self.device & 0xF0 != 0xA0 {
+                    self.mode = Mode::Idle;
+                    return;
+                }
+                self.next_mode = if self.device & 1 != 0 {
+                    Mode::Read
+                } else {
+                    Mode::Address
+                };
+                self.mode = Mode::SendAck;
+            }
+            Mode::Address => {
+                if !self.c01 {
+                    self.address = self.shift;
+                    self.next_mode = Mode::Write;
+                }
+                self.mode = Mode::SendAck;
+            }
+            Mode::Write => {
+                let address = (self.address & self.mask()) as usize;
+                self.data[address] = self.shift;
+                self.address = self.address.wrapping_add(1) & self.mask();
+                self.next_mode = Mode::Write;
+                self.mode = Mode::SendAck;
+            }
+            Mode::Read => {
+                self.address = self.address.wrapping_add(1) & self.mask();
+                self.mode = Mode::WaitAck;
+                self.output = true;
+            }
+            Mode::SendAck | Mode::WaitAck => {
+                self.mode = self.next_mode;
+                self.bits = 0;
+                self.shift = if self.mode == Mode::Read {
+                    self.data[(self.address & self.mask()) as usize]
+                } else {
+                    0
+                };
+                self.output = true;
+            }
+            Mode::Idle => {}
+        }
+    }
+}
@@ -0,0 +1,454 @@
+use super::Mapper;
+use crate::rom::{Mirroring, Rom, CHR_ROM_PAGE_SIZE};
+
+/// The BIOS sits at $E000-$FFFF and RAM fills $6000-$DFFF
+const BIOS_START: u16 = 0xE000;
+const RAM_SIZE: usize = 0x8000;
+
+/// Blank disk before the first block, 28300 bits
+const LEADING_GAP: usize = 28300 / 8;
+/// Blank disk after each block, 976 bits
+const BLOCK_GAP: usize = 976 / 8;
+/// The bit that ends a gap and starts a block
+const BLOCK_START_MARK: u8 = 0x80;
+/// Stands in for each block's CRC, which the drive never reports as wrong
+const FAKE_CRC: [u8; 2] = [0x4D, 0x62];
+
+/// CPU cycles per byte passing under the head, at about 96.4 kbit/s
+const BYTE_DELAY: u32 = 150;
+/// CPU cycles the head takes to get back to the start of the disk
+const HEAD_RETURN_DELAY: u32 = 50000;
+/// CPU cycles a newly inserted disk takes to show up, so the BIOS sees the
+/// drive empty in between when the disk is changed
+const INSERT_DELAY: u32 = 1_000_000;
+
+/// Mapper 20, the Famicom Disk System: the RAM adapter with its 8 KiB BIOS,
+/// 32 KiB of RAM, 8 KiB of CHR-RAM, the disk drive and a 16-bit timer IRQ.
+///
+/// The drive hands the disk over one byte at a time through $4024/$4031,
+/// raising the disk IRQ for each. .fds images leave out the gaps between
+/// blocks, so they are put back when the disk is loaded. CRCs are never
+/// checked. The expansion audio registers at $4040-$4092 are kept, and the
+/// wavetable reads back, but nothing plays them yet.
+pub struct Fds {
+    bios: Vec<u8>,
+    ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+    mirroring: Mirroring,
+
+    /// Every disk side with its gaps put back, each `side_len` bytes long
+    disk: Vec<u8>,
+    side_len: usize,
+    /// The side in the drive, if any
+    side: Option<usize>,
+    /// CPU cycles the drive still reports empty after a disk change
+    insert_delay: u32,
+
+    /// $4023
+    disk_registers_enabled: bool,
+    sound_registers_enabled: bool,
+
+    irq_reload: u16,
+    irq_counter: u16,
+    irq_repeat: bool,
+    irq_enabled: bool,
+    timer_irq: bool,
+
+    /// $4025
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    crc_control: bool,
+    /// Cleared while the drive is meant to skip over a gap
+    transfer_enabled: bool,
+    disk_irq_enabled: bool,
+
+    disk_irq: bool,
+    /// A byte has been read from, or written to, the disk
+    transfer_complete: bool,
+    read_data: u8,
+    write_data: u8,
+    /// $4026, driving the expansion port
+    ext_connector: u8,
+
+    /// Byte offset of the head into the side
+    head: usize,
+    /// The head reached the end of the disk and has to go back to the start
+    end_of_head: bool,
+    /// CPU cycles until the next byte is under the head
+    delay: u32,
+    /// The head is moving across the disk
+    scanning: bool,
+    /// A block start mark has gone past since `transfer_enabled` was set
+    gap_ended: bool,
+
+    /// $4040-$4092
+    audio_registers: [u8; 0x53],
+}
+
+impl Fds {
+    pub fn new(rom: Rom) -> Self {
+        let sides: Vec<Vec<u8>> = rom.disk_sides.iter().map(|side| add_gaps(side)).collect();
+        let side_len = sides.iter().map(|side| side.len()).max().unwrap_or(0);
+        let mut disk = Vec::with_capacity(side_len * sides.len());
+        for side in sides {
+            let start = disk.len();
+            disk.extend(side);
+            disk.resize(start + side_len, 0);
+        }
+
+        Fds {
+            bios: rom.prg_rom,
+            ram: vec![0; RAM_SIZE],
+            chr_ram: vec![0; CHR_ROM_PAGE_SIZE],
+            mirroring: rom.screen_mirroring,
+            side: (!disk.is_empty()).then_some(0),
+            disk,
+            side_len,
+            insert_delay: 0,
+            disk_registers_enabled: false,
+            sound_registers_enabled: false,
+            irq_reload: 0,
+            irq_counter: 0,
+            irq_repeat: false,
+            irq_enabled: false,
+            timer_irq: false,
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: true,
+            crc_control: false,
+            transfer_enabled: false,
+            disk_irq_enabled: false,
+            disk_irq: false,
+            transfer_complete: false,
+            read_data: 0,
+            write_data: 0,
+            ext_connector: 0,
+            head: 0,
+            end_of_head: true,
+            delay: 0,
+            scanning: false,
+            gap_ended: false,
+            audio_registers: [0; 0x53],
+        }
+    }
+
+    /// Last value written to an expansion audio register in $4040-$4092
+    pub fn audio_register(&self, addr: u16) -> u8 {
+        self.audio_registers
+            .get((addr as usize).wrapping_sub(0x4040))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn disk_inserted(&self) -> bool {
+        self.side.is_some() && self.insert_delay == 0
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+        if self.irq_counter == 0 {
+            self.timer_irq = true;
+            self.irq_counter = self.irq_reload;
+            if !self.irq_repeat {
+                self.irq_enabled = false;
+            }
+        } else {
+            self.irq_counter -= 1;
+        }
+    }
+
+    /// Moves the disk along by a CPU cycle, transferring the byte under the
+    /// head when one comes up
+    fn clock_drive(&mut self) {
+        let Some(side) = self.side.filter(|_| self.disk_inserted() && self.motor_on) else {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        };
+        if self.reset_transfer && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.end_of_head = false;
+            self.delay = HEAD_RETURN_DELAY;
+            self.head = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let offset = side * self.side_len + self.head;
+        if self.read_mode {
+            let data = self.disk[offset];
+            let mut raise_irq = self.disk_irq_enabled;
+            if !self.transfer_enabled {
+                self.gap_ended = false;
+            } else if data != 0 && !self.gap_ended {
+                // the start mark is handed over, but without an IRQ
+                self.gap_ended = true;
+                raise_irq = false;
+            }
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = data;
+                self.disk_irq |= raise_irq;
+            }
+        } else {
+            if !self.crc_control {
+                self.transfer_complete = true;
+                self.disk_irq |= self.disk_irq_enabled;
+                // while transfers are off the drive writes the gap
+                self.disk[offset] = if self.transfer_enabled {
+                    self.write_data
+                } else {
+                    0
+                };
+            }
+            self.gap_ended = false;
+        }
+
+        self.head += 1;
+        if self.head >= self.side_len {
+            self.motor_on = false;
+        } else {
+            self.delay = BYTE_DELAY;
+        }
+    }
+}
+
+/// Turns a side as .fds images store it into what passes under the head:
+/// each block behind a gap and a start mark, and followed by its CRC
+fn add_gaps(side: &[u8]) -> Vec<u8> {
+    let mut disk = vec![0; LEADING_GAP];
+    let mut pos = 0;
+    let mut file_size = 0;
+    while pos < side.len() {
+        let len = match side[pos] {
+            // disk info, file count, file header, file data
+            1 => 56,
+            2 => 2,
+            3 => 16,
+            4 => 1 + file_size,
+            _ => break,
+        };
+        let Some(block) = side.get(pos..pos + len) else {
+            break;
+        };
+        if block[0] == 3 {
+            file_size = u16::from_le_bytes([block[13], block[14]]) as usize;
+        }
+
+        disk.push(BLOCK_START_MARK);
+        disk.extend_from_slice(block);
+        disk.extend_from_slice(&FAKE_CRC);
+        disk.resize(disk.len() + BLOCK_GAP, 0);
+        pos += len;
+    }
+    disk
+}
+
+impl Mapper for Fds {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4030 if self.disk_registers_enabled => {
+                let mut status = 0;
+                if self.timer_irq {
+                    status |= 0b0000_0001;
+                }
+                if self.transfer_complete {
+                    status |= 0b0000_0010;
+                }
+                if self.end_of_head {
+                    status |= 0b0100_0000;
+                }
+                Some(status)
+            }
+            0x4031 if self.disk_registers_enabled => Some(self.read_data),
+            0x4032 if self.disk_registers_enabled => {
+                let inserted = self.disk_inserted();
+                let mut status = 0;
+                if !inserted {
+                    status |= 0b0000_0001;
+                }
+                if !inserted || !self.scanning {
+                    status |= 0b0000_0010;
+                }
+                if !inserted {
+                    // write protected
+                    status |= 0b0000_0100;
+                }
+                Some(status)
+            }
+            // the battery is good, and the expansion port reads back what
+            // $4026 drives
+            0x4033 if self.disk_registers_enabled => Some(self.ext_connector | 0x80),
+            0x4040..=0x407F if self.sound_registers_enabled => {
+                Some(self.audio_registers[(addr - 0x4040) as usize] & 0x3F)
+            }
+            0x6000..=0xDFFF => Some(self.ram[(addr - 0x6000) as usize]),
+            _ => self.prg_offset(addr).map(|offset| self.bios[offset]),
+        }
+    }
+
+    fn after_cpu_read(&mut self, addr: u16) {
+        if !self.disk_registers_enabled {
+            return;
+        }
+        match addr {
+            0x4030 => {
+                self.transfer_complete = false;
+                self.timer_irq = false;
+                self.disk_irq = false;
+            }
+            0x4031 => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4020 => self.irq_reload = (self.irq_reload & 0xFF00) | data as u16,
+            0x4021 => self.irq_reload = (self.irq_reload & 0x00FF) | ((data as u16) << 8),
+            0x4022 => {
+                self.irq_repeat = data & 0b01 != 0;
+                self.irq_enabled = data & 0b10 != 0 && self.disk_registers_enabled;
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4023 => {
+                self.disk_registers_enabled = data & 0b01 != 0;
+                self.sound_registers_enabled = data & 0b10 != 0;
+                if !self.disk_registers_enabled {
+                    self.irq_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024 if self.disk_registers_enabled => {
+                self.write_data = data;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 if self.disk_registers_enabled => {
+                self.disk_irq = false;
+                self.motor_on = data & 0b0000_0001 != 0;
+                self.reset_transfer = data & 0b0000_0010 != 0;
+                self.read_mode = data & 0b0000_0100 != 0;
+                self.mirroring = if data & 0b0000_1000 != 0 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+                self.crc_control = data & 0b0001_0000 != 0;
+                self.transfer_enabled = data & 0b0100_0000 != 0;
+                self.disk_irq_enabled = data & 0b1000_0000 != 0;
+            }
+            0x4026 if self.disk_registers_enabled => self.ext_connector = data,
+            0x4040..=0x4092 if self.sound_registers_enabled => {
+                self.audio_registers[(addr - 0x4040) as usize] = data;
+            }
+            0x6000..=0xDFFF => self.ram[(addr - 0x6000) as usize] = data,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr_ram[addr as usize % self.chr_ram.len()]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        let len = self.chr_ram.len();
+        self.chr_ram[addr as usize % len] = data;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn has_battery(&self) -> bool {
+        // the disks are written to, so they're what gets saved
+        !self.disk.is_empty()
+    }
+
+    fn save_data(&self) -> &[u8] {
+        &self.disk
+    }
+
+    fn save_data_mut(&mut self) -> &mut [u8] {
+        &mut self.disk
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn irq_ack(&mut self) {
+        self.timer_irq = false;
+        self.disk_irq = false;
+    }
+
+    fn cpu_tick(&mut self) {
+        self.clock_timer();
+        self.insert_delay = self.insert_delay.saturating_sub(1);
+        self.clock_drive();
+    }
+
+    fn disk_sides(&self) -> usize {
+        self.disk.len().checked_div(self.side_len).unwrap_or(0)
+    }
+
+    fn inserted_disk(&self) -> Option<usize> {
+        self.side
+    }
+
+    fn insert_disk(&mut self, side: Option<usize>) {
+        self.side = side.filter(|&side| side < self.disk_sides());
+        self.insert_delay = if self.side.is_some() { INSERT_DELAY } else { 0 };
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            BIOS_START..=0xFFFF => Some((addr - BIOS_START) as usize % self.bios.len()),
+            _ => None,
+        }
+    }
+
+    fn chr_offset(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.bios.len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        0
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        if let Some(offset) = self.prg_offset(addr) {
+            self.bios[offset] = data;
+        }
+    }
+}
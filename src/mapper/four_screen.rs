@@ -0,0 +1,145 @@
+use super::{Mapper, Nametable};
+use crate::rom::Mirroring;
+
+/// A board with the iNES four-screen flag: 2 KiB of RAM on the cartridge
+/// gives each of the four nametable slots its own memory. The console's
+/// VRAM answers for $2000 and $2400 and the cartridge RAM for $2800 and
+/// $2C00. Everything else is the wrapped board's.
+pub struct FourScreen<M> {
+    board: M,
+    ram: [u8; 0x800],
+}
+
+impl<M: Mapper> FourScreen<M> {
+    pub fn new(board: M) -> Self {
+        FourScreen {
+            board,
+            ram: [0; 0x800],
+        }
+    }
+
+    fn ram_index(addr: u16) -> usize {
+        (addr as usize & 0x0FFF) - 0x800
+    }
+}
+
+impl<M: Mapper> Mapper for FourScreen<M> {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        self.board.cpu_read(addr)
+    }
+
+    fn after_cpu_read(&mut self, addr: u16) {
+        self.board.after_cpu_read(addr)
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        self.board.cpu_write(addr, data)
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.board.ppu_read(addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.board.ppu_write(addr, data)
+    }
+
+    fn ppu_fetch(&mut self, addr: u16) {
+        self.board.ppu_fetch(addr)
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::FourScreen
+    }
+
+    fn nametable(&self, table: usize) -> Option<Nametable> {
+        match table {
+            0 | 1 => Some(Nametable::Vram(table)),
+            _ => Some(Nametable::Cartridge),
+        }
+    }
+
+    fn nametable_read(&self, addr: u16) -> u8 {
+        self.ram[Self::ram_index(addr)]
+    }
+
+    fn nametable_write(&mut self, addr: u16, data: u8) {
+        self.ram[Self::ram_index(addr)] = data;
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        self.board.prg_ram()
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        self.board.prg_ram_mut()
+    }
+
+    fn has_battery(&self) -> bool {
+        self.board.has_battery()
+    }
+
+    fn save_data(&self) -> &[u8] {
+        self.board.save_data()
+    }
+
+    fn save_data_mut(&mut self) -> &mut [u8] {
+        self.board.save_data_mut()
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.board.irq_pending()
+    }
+
+    fn irq_ack(&mut self) {
+        self.board.irq_ack()
+    }
+
+    fn cpu_tick(&mut self) {
+        self.board.cpu_tick()
+    }
+
+    fn scanline_tick(&mut self) {
+        self.board.scanline_tick()
+    }
+
+    fn counts_a12(&self) -> bool {
+        self.board.counts_a12()
+    }
+
+    fn a12_rising_edge(&mut self) {
+        self.board.a12_rising_edge()
+    }
+
+    fn disk_sides(&self) -> usize {
+        self.board.disk_sides()
+    }
+
+    fn inserted_disk(&self) -> Option<usize> {
+        self.board.inserted_disk()
+    }
+
+    fn insert_disk(&mut self, side: Option<usize>) {
+        self.board.insert_disk(side)
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        self.board.prg_offset(addr)
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        self.board.chr_offset(addr)
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.board.prg_rom_len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        self.board.chr_rom_len()
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        self.board.poke_prg(addr, data)
+    }
+}
@@ -0,0 +1,163 @@
+use super::chr_latch::ChrLatch;
+use super::Mapper;
+use crate::rom::{Mirroring, Rom};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+
+/// Mapper 9, MMC2 (Punch-Out!!): an 8 KiB PRG bank switched at $8000 and
+/// the last three banks fixed after it, with latch-switched CHR banks.
+pub struct Mmc2 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    battery: bool,
+    chr_rom: Vec<u8>,
+    prg_bank: usize,
+    chr_latch: ChrLatch,
+    mirroring: Mirroring,
+}
+
+impl Mmc2 {
+    pub fn new(rom: Rom) -> Self {
+        Mmc2 {
+            prg_rom: rom.prg_rom,
+            prg_ram: vec![0; rom.prg_ram_size],
+            battery: rom.battery,
+            chr_rom: rom.chr_rom,
+            prg_bank: 0,
+            chr_latch: ChrLatch::mmc2(),
+            mirroring: rom.screen_mirroring,
+        }
+    }
+}
+
+impl Mapper for Mmc2 {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                Some(self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()])
+            }
+            _ => self.prg_offset(addr).map(|offset| self.prg_rom[offset]),
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) as usize % len] = data;
+            }
+            0xA000..=0xAFFF => self.prg_bank = (data & 0x0F) as usize,
+            0xB000..=0xBFFF => self.chr_latch.set_bank(0, 0, data),
+            0xC000..=0xCFFF => self.chr_latch.set_bank(0, 1, data),
+            0xD000..=0xDFFF => self.chr_latch.set_bank(1, 0, data),
+            0xE000..=0xEFFF => self.chr_latch.set_bank(1, 1, data),
+            0xF000..=0xFFFF => {
+                self.mirroring = if data & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr_offset(addr)
+            .map_or(0, |offset| self.chr_rom[offset])
+    }
+
+    fn ppu_write(&mut self, _addr: u16, _data: u8) {
+        // CHR-ROM only
+    }
+
+    fn ppu_fetch(&mut self, addr: u16) {
+        self.chr_latch.fetch(addr);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let banks = self.prg_rom.len() / PRG_BANK_SIZE;
+        if banks == 0 {
+            return None;
+        }
+        let bank = match addr {
+            0x8000..=0x9FFF => self.prg_bank,
+            // $A000, $C000 and $E000 hold the last three banks, wrapping
+            // round on boards with fewer than four
+            0xA000..=0xFFFF => {
+                let from_end = 4 - (addr as usize - 0x8000) / PRG_BANK_SIZE;
+                banks - from_end % banks
+            }
+            _ => return None,
+        };
+        Some((bank % banks) * PRG_BANK_SIZE + (addr as usize % PRG_BANK_SIZE))
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        self.chr_latch.offset(addr, self.chr_rom.len())
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.prg_rom.len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        self.chr_rom.len()
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        if let Some(offset) = self.prg_offset(addr) {
+            self.prg_rom[offset] = data;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Region;
+
+    /// `banks` 8 KiB PRG banks, each starting with its own number
+    fn test_mapper(banks: usize) -> Mmc2 {
+        let mut prg_rom = vec![0; banks * PRG_BANK_SIZE];
+        for bank in 0..banks {
+            prg_rom[bank * PRG_BANK_SIZE] = bank as u8;
+        }
+        Mmc2::new(Rom {
+            prg_rom,
+            chr_rom: vec![0; 0x2000],
+            mapper: 9,
+            screen_mirroring: Mirroring::Vertical,
+            prg_ram_size: 0,
+            battery: false,
+            region: Region::Ntsc,
+            trainer: None,
+            disk_sides: vec![],
+        })
+    }
+
+    #[test]
+    fn fixed_banks_wrap_on_small_prg() {
+        for (banks, fixed) in [(16, [13, 14, 15]), (2, [1, 0, 1]), (1, [0, 0, 0])] {
+            let mapper = test_mapper(banks);
+            let read = [0xA000, 0xC000, 0xE000].map(|addr| mapper.cpu_read(addr).unwrap());
+            assert_eq!(read, fixed, "{} banks", banks);
+        }
+    }
+}
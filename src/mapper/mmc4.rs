@@ -0,0 +1,125 @@
+use super::chr_latch::ChrLatch;
+use super::Mapper;
+use crate::rom::{Mirroring, Rom};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+/// Mapper 10, MMC4 (Fire Emblem, Famicom Wars): MMC2 with a 16 KiB PRG bank
+/// switched at $8000 and the last bank fixed at $C000. The CHR latches
+/// work the same, except that the left table's reacts to the whole tile.
+pub struct Mmc4 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    battery: bool,
+    chr_rom: Vec<u8>,
+    prg_bank: usize,
+    chr_latch: ChrLatch,
+    mirroring: Mirroring,
+}
+
+impl Mmc4 {
+    pub fn new(rom: Rom) -> Self {
+        Mmc4 {
+            prg_rom: rom.prg_rom,
+            prg_ram: vec![0; rom.prg_ram_size],
+            battery: rom.battery,
+            chr_rom: rom.chr_rom,
+            prg_bank: 0,
+            chr_latch: ChrLatch::mmc4(),
+            mirroring: rom.screen_mirroring,
+        }
+    }
+}
+
+impl Mapper for Mmc4 {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                Some(self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()])
+            }
+            _ => self.prg_offset(addr).map(|offset| self.prg_rom[offset]),
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) as usize % len] = data;
+            }
+            0xA000..=0xAFFF => self.prg_bank = (data & 0x0F) as usize,
+            0xB000..=0xBFFF => self.chr_latch.set_bank(0, 0, data),
+            0xC000..=0xCFFF => self.chr_latch.set_bank(0, 1, data),
+            0xD000..=0xDFFF => self.chr_latch.set_bank(1, 0, data),
+            0xE000..=0xEFFF => self.chr_latch.set_bank(1, 1, data),
+            0xF000..=0xFFFF => {
+                self.mirroring = if data & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr_offset(addr)
+            .map_or(0, |offset| self.chr_rom[offset])
+    }
+
+    fn ppu_write(&mut self, _addr: u16, _data: u8) {
+        // CHR-ROM only
+    }
+
+    fn ppu_fetch(&mut self, addr: u16) {
+        self.chr_latch.fetch(addr);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let banks = self.prg_rom.len() / PRG_BANK_SIZE;
+        if banks == 0 {
+            return None;
+        }
+        let bank = match addr {
+            0x8000..=0xBFFF => self.prg_bank,
+            0xC000..=0xFFFF => banks - 1,
+            _ => return None,
+        };
+        Some((bank % banks) * PRG_BANK_SIZE + (addr as usize % PRG_BANK_SIZE))
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        self.chr_latch.offset(addr, self.chr_rom.len())
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.prg_rom.len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        self.chr_rom.len()
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        if let Some(offset) = self.prg_offset(addr) {
+            self.prg_rom[offset] = data;
+        }
+    }
+}
@@ -0,0 +1,238 @@
+mod action53;
+mod bandai;
+mod bnrom;
+mod camerica;
+mod chr_latch;
+mod color_dreams;
+mod eeprom;
+mod fds;
+mod four_screen;
+mod mmc2;
+mod mmc4;
+mod namco118;
+mod namco163;
+mod nrom;
+mod unrom512;
+mod uxrom;
+mod vrc4;
+mod vrc6;
+mod vrc7;
+mod vrc_irq;
+
+pub use action53::Action53;
+pub use bandai::BandaiFcg;
+pub use bnrom::Bnrom;
+pub use camerica::Camerica;
+pub use color_dreams::ColorDreams;
+pub use fds::Fds;
+pub use four_screen::FourScreen;
+pub use mmc2::Mmc2;
+pub use mmc4::Mmc4;
+pub use namco118::Namco118;
+pub use namco163::Namco163;
+pub use nrom::Nrom;
+pub use unrom512::Unrom512;
+pub use uxrom::Uxrom;
+pub use vrc4::Vrc4;
+pub use vrc6::Vrc6;
+pub use vrc7::Vrc7;
+
+use crate::rom::{Mirroring, Rom, RomError, FDS_BIOS_SIZE};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// What a nametable slot at $2000/$2400/$2800/$2C00 shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nametable {
+    /// One of the console's two 1 KiB VRAM pages
+    Vram(usize),
+    /// Cartridge memory, read through `Mapper::nametable_read`
+    Cartridge,
+}
+
+/// The cartridge hardware. The CPU sees it at $4020-$FFFF and the PPU at
+/// $0000-$1FFF, and each board decides what answers there: which PRG/CHR
+/// banks are mapped, where writes go, how the nametables are mirrored.
+pub trait Mapper {
+    /// A CPU read from $4020-$FFFF, or `None` when the cartridge leaves the
+    /// bus undriven. Must not have side effects, so debuggers can peek.
+    fn cpu_read(&self, addr: u16) -> Option<u8>;
+
+    /// Called after the CPU read `addr`, for registers whose reads change
+    /// state. Debugger peeks don't call it.
+    fn after_cpu_read(&mut self, addr: u16) {
+        let _ = addr;
+    }
+
+    /// A CPU write to $4020-$FFFF, usually a bank switch
+    fn cpu_write(&mut self, addr: u16, data: u8);
+
+    /// A PPU read from the pattern tables at $0000-$1FFF
+    fn ppu_read(&self, addr: u16) -> u8;
+
+    /// A PPU write to the pattern tables, which only CHR-RAM keeps
+    fn ppu_write(&mut self, addr: u16, data: u8);
+
+    /// Called after the PPU fetched pattern data at `addr`, while rendering
+    /// or through $2007, for boards that watch those fetches (MMC2, MMC4)
+    fn ppu_fetch(&mut self, addr: u16) {
+        let _ = addr;
+    }
+
+    fn mirroring(&self) -> Mirroring;
+
+    /// The nametable in slot `table` (0-3), for boards that map each slot
+    /// on their own. `None` leaves it to `mirroring`.
+    fn nametable(&self, table: usize) -> Option<Nametable> {
+        let _ = table;
+        None
+    }
+
+    /// A PPU read from a nametable slot mapped to `Nametable::Cartridge`
+    fn nametable_read(&self, addr: u16) -> u8 {
+        let _ = addr;
+        0
+    }
+
+    /// A PPU write to a nametable slot mapped to `Nametable::Cartridge`
+    fn nametable_write(&mut self, addr: u16, data: u8) {
+        let _ = (addr, data);
+    }
+
+    /// Work RAM at $6000-$7FFF; empty on boards without any
+    fn prg_ram(&self) -> &[u8] {
+        &[]
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+
+    /// Whether PRG-RAM is battery backed and should be saved between runs
+    fn has_battery(&self) -> bool {
+        false
+    }
+
+    /// What gets saved between runs: PRG-RAM, unless the board keeps its
+    /// saves somewhere else, such as an EEPROM
+    fn save_data(&self) -> &[u8] {
+        self.prg_ram()
+    }
+
+    fn save_data_mut(&mut self) -> &mut [u8] {
+        self.prg_ram_mut()
+    }
+
+    /// Whether the board is pulling the IRQ line low
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    /// Releases the IRQ line. Games acknowledge through a register write,
+    /// which `cpu_write` already handles; this is for the host.
+    fn irq_ack(&mut self) {}
+
+    /// Called once per CPU cycle, for boards whose IRQ counts CPU cycles
+    /// (VRC, FME-7)
+    fn cpu_tick(&mut self) {}
+
+    /// Called at the end of every scanline the PPU renders, for boards
+    /// whose IRQ counts scanlines. Not called on boards that count A12
+    /// rising edges instead, see `counts_a12`.
+    fn scanline_tick(&mut self) {}
+
+    /// Whether the scanline counter is clocked by `a12_rising_edge`. The
+    /// bus then leaves out `scanline_tick`, which would clock it twice a
+    /// line.
+    fn counts_a12(&self) -> bool {
+        false
+    }
+
+    /// Called when PPU address line A12 goes high after staying low for a
+    /// while, which normally happens once per rendered line. MMC3 clocks
+    /// its scanline counter on it; the PPU already leaves out the short
+    /// dips the board filters away.
+    fn a12_rising_edge(&mut self) {}
+
+    /// Disk sides the drive can take, on the Famicom Disk System; 0 on
+    /// cartridges
+    fn disk_sides(&self) -> usize {
+        0
+    }
+
+    /// The disk side in the drive, if any
+    fn inserted_disk(&self) -> Option<usize> {
+        None
+    }
+
+    /// Puts disk side `side` in the drive, or takes the disk out with `None`
+    fn insert_disk(&mut self, side: Option<usize>) {
+        let _ = side;
+    }
+
+    /// Offset into PRG-ROM of the byte mapped at CPU address `addr`
+    fn prg_offset(&self, addr: u16) -> Option<usize>;
+
+    /// Offset into CHR-ROM of the byte mapped at PPU address `addr`. `None`
+    /// when CHR-RAM is mapped there instead.
+    fn chr_offset(&self, addr: u16) -> Option<usize>;
+
+    fn prg_rom_len(&self) -> usize;
+
+    /// 0 on boards with CHR-RAM
+    fn chr_rom_len(&self) -> usize;
+
+    /// Overwrites the PRG-ROM byte mapped at `addr`, for loaders and
+    /// debuggers. Bank registers are left alone.
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        let _ = (addr, data);
+    }
+}
+
+/// Shared between the bus and the PPU, which both talk to the cartridge
+pub type SharedMapper = Rc<RefCell<dyn Mapper>>;
+
+/// iNES mapper numbers `from_rom` can build. Keep in step with it.
+const SUPPORTED_MAPPERS: &[u8] = &[
+    0, 2, 9, 10, 11, 16, 19, 20, 21, 22, 23, 24, 25, 26, 28, 30, 34, 71, 85, 153, 159, 206,
+];
+
+pub fn is_supported(mapper: u8) -> bool {
+    SUPPORTED_MAPPERS.contains(&mapper)
+}
+
+/// Builds the board the iNES header asks for
+pub fn from_rom(rom: Rom) -> Result<SharedMapper, RomError> {
+    let four_screen = rom.screen_mirroring == Mirroring::FourScreen;
+    match rom.mapper {
+        0 => Ok(share(Nrom::new(rom), four_screen)),
+        2 => Ok(share(Uxrom::new(rom), four_screen)),
+        9 => Ok(share(Mmc2::new(rom), four_screen)),
+        10 => Ok(share(Mmc4::new(rom), four_screen)),
+        11 => Ok(share(ColorDreams::new(rom), four_screen)),
+        16 | 153 | 159 => Ok(share(BandaiFcg::new(rom), four_screen)),
+        19 => Ok(share(Namco163::new(rom), four_screen)),
+        20 if rom.prg_rom.len() != FDS_BIOS_SIZE => Err(RomError::MissingBios),
+        20 => Ok(share(Fds::new(rom), four_screen)),
+        21 | 22 | 23 | 25 => Ok(share(Vrc4::new(rom), four_screen)),
+        24 | 26 => Ok(share(Vrc6::new(rom), four_screen)),
+        28 => Ok(share(Action53::new(rom), four_screen)),
+        // keeps its four-screen nametables in CHR-RAM
+        30 => Ok(share(Unrom512::new(rom), false)),
+        34 => Ok(share(Bnrom::new(rom), four_screen)),
+        71 => Ok(share(Camerica::new(rom), four_screen)),
+        85 => Ok(share(Vrc7::new(rom), four_screen)),
+        206 => Ok(share(Namco118::new(rom), four_screen)),
+        mapper => Err(RomError::UnsupportedMapper(mapper)),
+    }
+}
+
+/// Puts `board` where the bus and the PPU can both reach it, with its own
+/// nametable RAM when the header asks for four screens
+fn share<M: Mapper + 'static>(board: M, four_screen: bool) -> SharedMapper {
+    if four_screen {
+        Rc::new(RefCell::new(FourScreen::new(board)))
+    } else {
+        Rc::new(RefCell::new(board))
+    }
+}
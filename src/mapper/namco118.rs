@@ -0,0 +1,106 @@
+use super::Mapper;
+use crate::rom::{Mirroring, Rom};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+
+/// Mapper 206, Namco 118 / DxROM: the banking MMC3 grew out of, without
+/// its mode bits, mirroring control or IRQ. $8000 selects one of eight
+/// bank registers and $8001 writes it: R0 and R1 map 2 KiB CHR banks at
+/// $0000 and $0800, R2-R5 1 KiB banks at $1000-$1FFF, and R6 and R7 8 KiB
+/// PRG banks at $8000 and $A000. The last two PRG banks are fixed.
+pub struct Namco118 {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    bank_select: usize,
+    registers: [usize; 8],
+    mirroring: Mirroring,
+}
+
+impl Namco118 {
+    pub fn new(rom: Rom) -> Self {
+        Namco118 {
+            prg_rom: rom.prg_rom,
+            chr_rom: rom.chr_rom,
+            bank_select: 0,
+            registers: [0; 8],
+            mirroring: rom.screen_mirroring,
+        }
+    }
+
+    /// The 1 KiB CHR bank mapped at `addr`
+    fn chr_bank(&self, addr: u16) -> usize {
+        let slot = (addr as usize / CHR_BANK_SIZE) % 8;
+        match slot {
+            // 2 KiB banks ignore their low bit
+            0..=3 => (self.registers[slot / 2] & !1) | (slot & 1),
+            _ => self.registers[slot - 2],
+        }
+    }
+}
+
+impl Mapper for Namco118 {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        self.prg_offset(addr).map(|offset| self.prg_rom[offset])
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match (addr, addr & 1) {
+            (0x8000..=0x9FFF, 0) => self.bank_select = (data & 0b111) as usize,
+            (0x8000..=0x9FFF, _) => {
+                let mask = if self.bank_select >= 6 { 0x0F } else { 0x3F };
+                self.registers[self.bank_select] = (data & mask) as usize;
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr_offset(addr)
+            .map_or(0, |offset| self.chr_rom[offset])
+    }
+
+    fn ppu_write(&mut self, _addr: u16, _data: u8) {
+        // CHR-ROM only
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let banks = self.prg_rom.len() / PRG_BANK_SIZE;
+        if banks == 0 {
+            return None;
+        }
+        let bank = match addr {
+            0x8000..=0x9FFF => self.registers[6],
+            0xA000..=0xBFFF => self.registers[7],
+            0xC000..=0xDFFF => banks.saturating_sub(2),
+            0xE000..=0xFFFF => banks - 1,
+            _ => return None,
+        };
+        Some((bank % banks) * PRG_BANK_SIZE + (addr as usize % PRG_BANK_SIZE))
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        (!self.chr_rom.is_empty()).then(|| {
+            (self.chr_bank(addr) * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE)
+                % self.chr_rom.len()
+        })
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.prg_rom.len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        self.chr_rom.len()
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        if let Some(offset) = self.prg_offset(addr) {
+            self.prg_rom[offset] = data;
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use super::{Mapper, Nametable};
+use crate::rom::{Mirroring, Rom, CHR_ROM_PAGE_SIZE};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+/// Bank numbers from here up map a page of the console's VRAM instead
+const VRAM_BANKS: u8 = 0xE0;
+const IRQ_COUNTER_MAX: u16 = 0x7FFF;
+
+/// Mapper 19, Namco 163: three switchable 8 KiB PRG banks with the last
+/// one fixed at $E000, eight 1 KiB CHR banks and four nametable banks that
+/// can each show a VRAM page or a CHR-ROM page. 128 bytes of internal RAM,
+/// which also holds the sound registers, sit behind a port at $4800, and a
+/// 15-bit counter at $5000/$5800 raises the IRQ after counting CPU cycles.
+///
+/// CHR banks $E0-$FF in the pattern tables are read as CHR-ROM like any
+/// other bank, rather than as VRAM.
+pub struct Namco163 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    battery: bool,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    /// Banks at $8000, $A000 and $C000
+    prg_banks: [usize; 3],
+    chr_banks: [usize; 8],
+    nametable_banks: [u8; 4],
+    /// $F800: bits 4-7 must be 0100 for PRG-RAM to take writes, and bits
+    /// 0-3 each protect a 2 KiB window of it
+    ram_protect: u8,
+    internal_ram: [u8; 0x80],
+    /// Address for the $4800 port, in bits 0-6, and whether it counts up
+    /// after each access, in bit 7
+    internal_ram_addr: u8,
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+    mirroring: Mirroring,
+}
+
+impl Namco163 {
+    pub fn new(rom: Rom) -> Self {
+        let chr_is_ram = rom.chr_rom.is_empty();
+        let chr = if chr_is_ram {
+            vec![0; CHR_ROM_PAGE_SIZE]
+        } else {
+            rom.chr_rom
+        };
+        Namco163 {
+            prg_rom: rom.prg_rom,
+            prg_ram: vec![0; rom.prg_ram_size],
+            battery: rom.battery,
+            chr,
+            chr_is_ram,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            nametable_banks: [VRAM_BANKS; 4],
+            ram_protect: 0,
+            internal_ram: [0; 0x80],
+            internal_ram_addr: 0,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            mirroring: rom.screen_mirroring,
+        }
+    }
+
+    /// The internal RAM, for the sound channels to read their registers from
+    pub fn internal_ram(&self) -> &[u8] {
+        &self.internal_ram
+    }
+
+    fn advance_internal_ram_addr(&mut self) {
+        if self.internal_ram_addr & 0x80 != 0 {
+            self.internal_ram_addr = 0x80 | (self.internal_ram_addr + 1) & 0x7F;
+        }
+    }
+
+    fn prg_ram_writable(&self, addr: u16) -> bool {
+        let window = (addr - 0x6000) / 0x800;
+        self.ram_protect & 0xF0 == 0x40 && self.ram_protect & (1 << window) == 0
+    }
+
+    fn chr_index(&self, bank: usize, addr: u16) -> usize {
+        (bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE) % self.chr.len()
+    }
+}
+
+impl Mapper for Namco163 {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4800..=0x4FFF => Some(self.internal_ram[(self.internal_ram_addr & 0x7F) as usize]),
+            0x5000..=0x57FF => Some(self.irq_counter as u8),
+            0x5800..=0x5FFF => {
+                Some((self.irq_counter >> 8) as u8 | if self.irq_enabled { 0x80 } else { 0 })
+            }
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                Some(self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()])
+            }
+            _ => self.prg_offset(addr).map(|offset| self.prg_rom[offset]),
+        }
+    }
+
+    fn after_cpu_read(&mut self, addr: u16) {
+        if let 0x4800..=0x4FFF = addr {
+            self.advance_internal_ram_addr();
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4800..=0x4FFF => {
+                self.internal_ram[(self.internal_ram_addr & 0x7F) as usize] = data;
+                self.advance_internal_ram_addr();
+            }
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7F00) | data as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | ((data & 0x7F) as u16) << 8;
+                self.irq_enabled = data & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() && self.prg_ram_writable(addr) => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) as usize % len] = data;
+            }
+            0x8000..=0xBFFF => {
+                self.chr_banks[(addr as usize - 0x8000) / 0x800] = data as usize;
+            }
+            0xC000..=0xDFFF => {
+                self.nametable_banks[(addr as usize - 0xC000) / 0x800] = data;
+            }
+            0xE000..=0xE7FF => self.prg_banks[0] = (data & 0x3F) as usize,
+            0xE800..=0xEFFF => self.prg_banks[1] = (data & 0x3F) as usize,
+            0xF000..=0xF7FF => self.prg_banks[2] = (data & 0x3F) as usize,
+            0xF800..=0xFFFF => {
+                self.ram_protect = data;
+                self.internal_ram_addr = data;
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        let bank = self.chr_banks[(addr as usize / CHR_BANK_SIZE) % 8];
+        self.chr[self.chr_index(bank, addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let bank = self.chr_banks[(addr as usize / CHR_BANK_SIZE) % 8];
+            let index = self.chr_index(bank, addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn nametable(&self, table: usize) -> Option<Nametable> {
+        Some(match self.nametable_banks[table % 4] {
+            bank if bank >= VRAM_BANKS => Nametable::Vram((bank & 1) as usize),
+            _ => Nametable::Cartridge,
+        })
+    }
+
+    fn nametable_read(&self, addr: u16) -> u8 {
+        let bank = self.nametable_banks[(addr as usize - 0x2000) / 0x400 % 4];
+        self.chr[self.chr_index(bank as usize, addr)]
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        // only the layouts that `Mirroring` can describe; used by renderers
+        // that don't go through `nametable`
+        match self.nametable_banks.map(|bank| bank & 1) {
+            [0, 1, 0, 1] => Mirroring::Vertical,
+            [0, 0, 1, 1] => Mirroring::Horizontal,
+            [0, 0, 0, 0] => Mirroring::SingleScreenLower,
+            [1, 1, 1, 1] => Mirroring::SingleScreenUpper,
+            _ => self.mirroring,
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn irq_ack(&mut self) {
+        self.irq_pending = false;
+    }
+
+    fn cpu_tick(&mut self) {
+        if self.irq_enabled && self.irq_counter < IRQ_COUNTER_MAX {
+            self.irq_counter += 1;
+            if self.irq_counter == IRQ_COUNTER_MAX {
+                self.irq_pending = true;
+            }
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let banks = self.prg_rom.len() / PRG_BANK_SIZE;
+        if banks == 0 {
+            return None;
+        }
+        let bank = match addr {
+            0x8000..=0xDFFF => self.prg_banks[(addr as usize - 0x8000) / PRG_BANK_SIZE],
+            0xE000..=0xFFFF => banks - 1,
+            _ => return None,
+        };
+        Some((bank % banks) * PRG_BANK_SIZE + (addr as usize % PRG_BANK_SIZE))
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        let bank = self.chr_banks[(addr as usize / CHR_BANK_SIZE) % 8];
+        (!self.chr_is_ram).then(|| self.chr_index(bank, addr))
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.prg_rom.len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        if self.chr_is_ram {
+            0
+        } else {
+            self.chr.len()
+        }
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        if let Some(offset) = self.prg_offset(addr) {
+            self.prg_rom[offset] = data;
+        }
+    }
+}
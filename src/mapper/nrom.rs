@@ -0,0 +1,111 @@
+use super::Mapper;
+use crate::rom::{Mirroring, Rom, CHR_ROM_PAGE_SIZE};
+
+/// Mapper 0: no bank switching. 16 KiB of PRG-ROM is mirrored to fill
+/// $8000-$FFFF, and boards without CHR-ROM carry 8 KiB of CHR-RAM.
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    battery: bool,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(rom: Rom) -> Self {
+        let chr_is_ram = rom.chr_rom.is_empty();
+        let chr = if chr_is_ram {
+            vec![0; CHR_ROM_PAGE_SIZE]
+        } else {
+            rom.chr_rom
+        };
+        Nrom {
+            prg_rom: rom.prg_rom,
+            prg_ram: vec![0; rom.prg_ram_size],
+            battery: rom.battery,
+            chr,
+            chr_is_ram,
+            mirroring: rom.screen_mirroring,
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                Some(self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()])
+            }
+            _ => self.prg_offset(addr).map(|offset| self.prg_rom[offset]),
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        // there are no registers, so only PRG-RAM takes writes
+        if let 0x6000..=0x7FFF = addr {
+            if !self.prg_ram.is_empty() {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) as usize % len] = data;
+            }
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr[addr as usize % self.chr.len()]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let len = self.chr.len();
+            self.chr[addr as usize % len] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => {
+                Some((addr - 0x8000) as usize % self.prg_rom.len())
+            }
+            _ => None,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        (!self.chr_is_ram).then(|| addr as usize % self.chr.len())
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.prg_rom.len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        if self.chr_is_ram {
+            0
+        } else {
+            self.chr.len()
+        }
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        if let Some(offset) = self.prg_offset(addr) {
+            self.prg_rom[offset] = data;
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use super::{Mapper, Nametable};
+use crate::rom::{Mirroring, Rom, PRG_ROM_PAGE_SIZE};
+
+const CHR_RAM_SIZE: usize = 0x8000;
+const CHR_BANK_SIZE: usize = 0x2000;
+/// Four-screen boards keep the nametables in the last 8 KiB of CHR-RAM
+const NAMETABLE_RAM: usize = 0x6000;
+const FLASH_SECTOR_SIZE: usize = 0x1000;
+/// What the flash answers in software ID mode: SST, then the SST39SF040
+const FLASH_ID: [u8; 2] = [0xBF, 0xB7];
+
+/// Progress through a flash command, which is a fixed sequence of writes
+/// to $5555 and $2AAA before the one that does something
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FlashCommand {
+    Ready,
+    /// $AA written to $5555
+    Unlocked,
+    /// $55 written to $2AAA
+    Command,
+    /// $A0: the next write programs a byte
+    Program,
+    /// $80, waiting for the second unlock
+    Erase,
+    EraseUnlocked,
+    EraseCommand,
+}
+
+/// Mapper 30, UNROM 512 (homebrew): a 16 KiB PRG bank at $8000 with the
+/// last bank fixed at $C000, four 8 KiB CHR-RAM banks and header-selected
+/// mirroring, including one-screen mirroring switched by the register.
+///
+/// Boards with the battery bit set are self-flashable: the register moves
+/// to $C000-$FFFF and writes to $8000-$BFFF talk to the SST39SF040 flash
+/// holding PRG-ROM, which is how games save. The whole flash image is
+/// the save data.
+pub struct Unrom512 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    flashable: bool,
+    prg_bank: usize,
+    chr_bank: usize,
+    mirroring: Mirroring,
+    four_screen: bool,
+    /// Mirroring comes from bit 7 of the register
+    one_screen: bool,
+    flash_command: FlashCommand,
+    flash_id_mode: bool,
+}
+
+impl Unrom512 {
+    pub fn new(rom: Rom) -> Self {
+        Unrom512 {
+            prg_rom: rom.prg_rom,
+            chr: vec![0; CHR_RAM_SIZE],
+            flashable: rom.battery,
+            prg_bank: 0,
+            chr_bank: 0,
+            mirroring: rom.screen_mirroring,
+            four_screen: rom.screen_mirroring == Mirroring::FourScreen,
+            one_screen: matches!(
+                rom.screen_mirroring,
+                Mirroring::SingleScreenLower | Mirroring::SingleScreenUpper
+            ),
+            flash_command: FlashCommand::Ready,
+            flash_id_mode: false,
+        }
+    }
+
+    fn write_register(&mut self, data: u8) {
+        self.prg_bank = (data & 0x1F) as usize;
+        self.chr_bank = ((data >> 5) & 0b11) as usize;
+        if self.one_screen {
+            self.mirroring = if data & 0x80 == 0 {
+                Mirroring::SingleScreenLower
+            } else {
+                Mirroring::SingleScreenUpper
+            };
+        }
+    }
+
+    /// A write to the flash at offset `offset` of PRG-ROM
+    fn write_flash(&mut self, offset: usize, data: u8) {
+        // commands only decode the low 15 address lines
+        let command_addr = offset & 0x7FFF;
+        self.flash_command = match (self.flash_command, command_addr, data) {
+            // before the reset arm: programming takes any byte, $F0 too
+            (FlashCommand::Program, _, _) => {
+                // programming can only clear bits; erasing sets them again
+                self.prg_rom[offset] &= data;
+                FlashCommand::Ready
+            }
+            (_, _, 0xF0) => {
+                self.flash_id_mode = false;
+                FlashCommand::Ready
+            }
+            (FlashCommand::Ready, 0x5555, 0xAA) => FlashCommand::Unlocked,
+            (FlashCommand::Unlocked, 0x2AAA, 0x55) => FlashCommand::Command,
+            (FlashCommand::Command, 0x5555, 0xA0) => FlashCommand::Program,
+            (FlashCommand::Command, 0x5555, 0x80) => FlashCommand::Erase,
+            (FlashCommand::Command, 0x5555, 0x90) => {
+                self.flash_id_mode = true;
+                FlashCommand::Ready
+            }
+            (FlashCommand::Erase, 0x5555, 0xAA) => FlashCommand::EraseUnlocked,
+            (FlashCommand::EraseUnlocked, 0x2AAA, 0x55) => FlashCommand::EraseCommand,
+            (FlashCommand::EraseCommand, 0x5555, 0x10) => {
+                self.prg_rom.fill(0xFF);
+                FlashCommand::Ready
+            }
+            (FlashCommand::EraseCommand, _, 0x30) => {
+                let sector = offset / FLASH_SECTOR_SIZE * FLASH_SECTOR_SIZE;
+                self.prg_rom[sector..sector + FLASH_SECTOR_SIZE].fill(0xFF);
+                FlashCommand::Ready
+            }
+            _ => FlashCommand::Ready,
+        };
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        self.chr_bank * CHR_BANK_SIZE + (addr as usize % CHR_BANK_SIZE)
+    }
+}
+
+impl Mapper for Unrom512 {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        if self.flash_id_mode {
+            if let 0x8000..=0xBFFF = addr {
+                return Some(FLASH_ID[addr as usize & 1]);
+            }
+        }
+        self.prg_offset(addr).map(|offset| self.prg_rom[offset])
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x8000..=0xBFFF if self.flashable => {
+                if let Some(offset) = self.prg_offset(addr) {
+                    self.write_flash(offset, data);
+                }
+            }
+            0x8000..=0xFFFF => self.write_register(data),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.chr[index] = data;
+    }
+
+    fn nametable(&self, _table: usize) -> Option<Nametable> {
+        self.four_screen.then_some(Nametable::Cartridge)
+    }
+
+    fn nametable_read(&self, addr: u16) -> u8 {
+        self.chr[NAMETABLE_RAM + (addr as usize & 0x0FFF)]
+    }
+
+    fn nametable_write(&mut self, addr: u16, data: u8) {
+        self.chr[NAMETABLE_RAM + (addr as usize & 0x0FFF)] = data;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn has_battery(&self) -> bool {
+        self.flashable
+    }
+
+    fn save_data(&self) -> &[u8] {
+        &self.prg_rom
+    }
+
+    fn save_data_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_rom
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if self.prg_rom.is_empty() {
+            return None;
+        }
+        let bank = match addr {
+            0x8000..=0xBFFF => self.prg_bank,
+            0xC000..=0xFFFF => self.prg_rom.len() / PRG_ROM_PAGE_SIZE - 1,
+            _ => return None,
+        };
+        Some((bank * PRG_ROM_PAGE_SIZE + (addr as usize % PRG_ROM_PAGE_SIZE)) % self.prg_rom.len())
+    }
+
+    fn chr_offset(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.prg_rom.len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        0
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        if let Some(offset) = self.prg_offset(addr) {
+            self.prg_rom[offset] = data;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Region;
+
+    /// A self-flashable board with 512 KiB of erased flash
+    fn test_mapper() -> Unrom512 {
+        Unrom512::new(Rom {
+            prg_rom: vec![0xFF; 32 * PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![],
+            mapper: 30,
+            screen_mirroring: Mirroring::Vertical,
+            prg_ram_size: 0,
+            battery: true,
+            region: Region::Ntsc,
+            trainer: None,
+            disk_sides: vec![],
+        })
+    }
+
+    /// Sends a flash command, switching to the banks that put $5555 and
+    /// $2AAA in the $8000 window
+    fn flash_command(mapper: &mut Unrom512, command: u8) {
+        mapper.cpu_write(0xC000, 1);
+        mapper.cpu_write(0x9555, 0xAA);
+        mapper.cpu_write(0xC000, 0);
+        mapper.cpu_write(0xAAAA, 0x55);
+        mapper.cpu_write(0xC000, 1);
+        mapper.cpu_write(0x9555, command);
+    }
+
+    #[test]
+    fn programs_any_byte_including_the_reset_command() {
+        let mut mapper = test_mapper();
+        for (addr, data) in [(0x8123, 0xF0), (0x8124, 0x5A)] {
+            flash_command(&mut mapper, 0xA0);
+            mapper.cpu_write(addr, data);
+            assert_eq!(mapper.cpu_read(addr), Some(data));
+        }
+        // and the state machine is back to ready, so this is ignored
+        mapper.cpu_write(0x8125, 0x00);
+        assert_eq!(mapper.cpu_read(0x8125), Some(0xFF));
+    }
+}
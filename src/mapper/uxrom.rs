@@ -0,0 +1,98 @@
+use super::Mapper;
+use crate::rom::{Mirroring, Rom, CHR_ROM_PAGE_SIZE};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+/// Mapper 2, UxROM: one register anywhere in $8000-$FFFF picks the 16 KiB
+/// PRG bank at $8000, and the last bank is fixed at $C000. Nearly every
+/// board carries 8 KiB of CHR-RAM for the game to fill through $2007. As
+/// on Color Dreams, the ROM drives the bus during the write, so the
+/// register gets the written value ANDed with the ROM byte.
+pub struct Uxrom {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    prg_bank: usize,
+    mirroring: Mirroring,
+}
+
+impl Uxrom {
+    pub fn new(rom: Rom) -> Self {
+        let chr_is_ram = rom.chr_rom.is_empty();
+        let chr = if chr_is_ram {
+            vec![0; CHR_ROM_PAGE_SIZE]
+        } else {
+            rom.chr_rom
+        };
+        Uxrom {
+            prg_rom: rom.prg_rom,
+            chr,
+            chr_is_ram,
+            prg_bank: 0,
+            mirroring: rom.screen_mirroring,
+        }
+    }
+
+    fn last_bank(&self) -> usize {
+        (self.prg_rom.len() / PRG_BANK_SIZE).saturating_sub(1)
+    }
+}
+
+impl Mapper for Uxrom {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        self.prg_offset(addr).map(|offset| self.prg_rom[offset])
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let Some(rom_byte) = self.cpu_read(addr) {
+            self.prg_bank = (data & rom_byte) as usize;
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr[addr as usize % self.chr.len()]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let len = self.chr.len();
+            self.chr[addr as usize % len] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let bank = match addr {
+            0x8000..=0xBFFF => self.prg_bank,
+            0xC000..=0xFFFF => self.last_bank(),
+            _ => return None,
+        };
+        (!self.prg_rom.is_empty())
+            .then(|| (bank * PRG_BANK_SIZE + (addr as usize & 0x3FFF)) % self.prg_rom.len())
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        (!self.chr_is_ram).then(|| addr as usize % self.chr.len())
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.prg_rom.len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        if self.chr_is_ram {
+            0
+        } else {
+            self.chr.len()
+        }
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        if let Some(offset) = self.prg_offset(addr) {
+            self.prg_rom[offset] = data;
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use super::vrc_irq::VrcIrq;
+use super::Mapper;
+use crate::rom::{Mirroring, Rom, CHR_ROM_PAGE_SIZE};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+
+/// Mappers 21, 22, 23 and 25: Konami's VRC2 and VRC4. Two switchable
+/// 8 KiB PRG banks, eight 1 KiB CHR banks written a nibble at a time and,
+/// on VRC4, the VRC IRQ counter.
+///
+/// Each board wires two CPU address lines to the register select bits,
+/// and a mapper number covers several boards, so the lines of all of them
+/// are ORed together. Games only ever use the lines of their own board.
+pub struct Vrc4 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    battery: bool,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    /// Address lines that drive register select bits 0 and 1
+    select_lines: [u16; 2],
+    /// VRC2a (mapper 22) ignores the low bit of CHR banks and has no IRQ
+    vrc2a: bool,
+    prg_banks: [usize; 2],
+    /// Swaps the $8000 bank with the fixed one at $C000
+    prg_swap: bool,
+    chr_banks: [usize; 8],
+    mirroring: Mirroring,
+    irq: VrcIrq,
+}
+
+impl Vrc4 {
+    pub fn new(rom: Rom) -> Self {
+        let select_lines = match rom.mapper {
+            // VRC4a: A1, A2; VRC4c: A6, A7
+            21 => [0x02 | 0x40, 0x04 | 0x80],
+            // VRC2a: A1, A0
+            22 => [0x02, 0x01],
+            // VRC2b, VRC4f: A0, A1; VRC4e: A2, A3
+            23 => [0x01 | 0x04, 0x02 | 0x08],
+            // VRC2c, VRC4b: A1, A0; VRC4d: A3, A2
+            _ => [0x02 | 0x08, 0x01 | 0x04],
+        };
+        let chr_is_ram = rom.chr_rom.is_empty();
+        let chr = if chr_is_ram {
+            vec![0; CHR_ROM_PAGE_SIZE]
+        } else {
+            rom.chr_rom
+        };
+        Vrc4 {
+            prg_rom: rom.prg_rom,
+            prg_ram: vec![0; rom.prg_ram_size],
+            battery: rom.battery,
+            chr,
+            chr_is_ram,
+            select_lines,
+            vrc2a: rom.mapper == 22,
+            prg_banks: [0, 0],
+            prg_swap: false,
+            chr_banks: [0; 8],
+            mirroring: rom.screen_mirroring,
+            irq: VrcIrq::new(),
+        }
+    }
+
+    /// The register `addr` selects, as $x000-$x003
+    fn register(&self, addr: u16) -> u16 {
+        let bit0 = (addr & self.select_lines[0] != 0) as u16;
+        let bit1 = (addr & self.select_lines[1] != 0) as u16;
+        (addr & 0xF000) | (bit1 << 1) | bit0
+    }
+
+    /// Writes one nibble of a CHR bank: $B000/$B001 set the low and high
+    /// nibble of bank 0, $B002/$B003 those of bank 1, and so on up to $E003
+    fn write_chr_bank(&mut self, register: u16, data: u8) {
+        let index = ((register >> 12) as usize - 0xB) * 2 + ((register & 0b10) >> 1) as usize;
+        let bank = &mut self.chr_banks[index];
+        *bank = if register & 1 == 0 {
+            (*bank & !0x0F) | (data & 0x0F) as usize
+        } else {
+            (*bank & 0x0F) | ((data & 0x1F) as usize) << 4
+        };
+    }
+
+    /// Index into CHR of the byte mapped at PPU address `addr`
+    fn chr_index(&self, addr: u16) -> usize {
+        let mut bank = self.chr_banks[(addr as usize / CHR_BANK_SIZE) % 8];
+        if self.vrc2a {
+            bank >>= 1;
+        }
+        (bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE) % self.chr.len()
+    }
+}
+
+impl Mapper for Vrc4 {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                Some(self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()])
+            }
+            _ => self.prg_offset(addr).map(|offset| self.prg_rom[offset]),
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            if !self.prg_ram.is_empty() {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) as usize % len] = data;
+            }
+            return;
+        }
+
+        let register = self.register(addr);
+        match register {
+            0x8000..=0x8003 => self.prg_banks[0] = (data & 0x1F) as usize,
+            0x9000..=0x9003 if self.vrc2a => {
+                self.mirroring = if data & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            0x9000..=0x9001 => {
+                self.mirroring = match data & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0x9002..=0x9003 => self.prg_swap = data & 0b10 != 0,
+            0xA000..=0xA003 => self.prg_banks[1] = (data & 0x1F) as usize,
+            0xB000..=0xEFFF => self.write_chr_bank(register, data),
+            _ if self.vrc2a => {}
+            0xF000 => self.irq.write_latch_low(data),
+            0xF001 => self.irq.write_latch_high(data),
+            0xF002 => self.irq.write_control(data),
+            0xF003 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn irq_ack(&mut self) {
+        self.irq.acknowledge();
+    }
+
+    fn cpu_tick(&mut self) {
+        self.irq.tick();
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let banks = self.prg_rom.len() / PRG_BANK_SIZE;
+        if banks == 0 {
+            return None;
+        }
+        let second_last = banks.saturating_sub(2);
+        let bank = match (addr, self.prg_swap) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => self.prg_banks[0],
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => second_last,
+            (0xA000..=0xBFFF, _) => self.prg_banks[1],
+            (0xE000..=0xFFFF, _) => banks - 1,
+            _ => return None,
+        };
+        Some((bank % banks) * PRG_BANK_SIZE + (addr as usize % PRG_BANK_SIZE))
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        (!self.chr_is_ram).then(|| self.chr_index(addr))
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.prg_rom.len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        if self.chr_is_ram {
+            0
+        } else {
+            self.chr.len()
+        }
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        if let Some(offset) = self.prg_offset(addr) {
+            self.prg_rom[offset] = data;
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use super::vrc_irq::VrcIrq;
+use super::Mapper;
+use crate::rom::{Mirroring, Rom, CHR_ROM_PAGE_SIZE, PRG_ROM_PAGE_SIZE};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+
+/// Mappers 24 and 26, Konami's VRC6: a 16 KiB PRG bank at $8000, an 8 KiB
+/// one at $C000 and the last 8 KiB fixed at $E000, eight 1 KiB CHR banks
+/// and the VRC IRQ counter. Mapper 26 has A0 and A1 swapped.
+///
+/// The expansion audio registers at $9000-$B002 are kept for the sound
+/// channels to read, but nothing plays them yet.
+pub struct Vrc6 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    prg_ram_enabled: bool,
+    battery: bool,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    swap_a0_a1: bool,
+    prg_16k_bank: usize,
+    prg_8k_bank: usize,
+    chr_banks: [usize; 8],
+    mirroring: Mirroring,
+    irq: VrcIrq,
+    /// $9000-$9003, $A000-$A002 and $B000-$B002: pulse 1, pulse 2, sawtooth
+    audio_registers: [[u8; 4]; 3],
+}
+
+impl Vrc6 {
+    pub fn new(rom: Rom) -> Self {
+        let chr_is_ram = rom.chr_rom.is_empty();
+        let chr = if chr_is_ram {
+            vec![0; CHR_ROM_PAGE_SIZE]
+        } else {
+            rom.chr_rom
+        };
+        Vrc6 {
+            prg_rom: rom.prg_rom,
+            prg_ram: vec![0; rom.prg_ram_size],
+            prg_ram_enabled: false,
+            battery: rom.battery,
+            chr,
+            chr_is_ram,
+            swap_a0_a1: rom.mapper == 26,
+            prg_16k_bank: 0,
+            prg_8k_bank: 0,
+            chr_banks: [0; 8],
+            mirroring: rom.screen_mirroring,
+            irq: VrcIrq::new(),
+            audio_registers: [[0; 4]; 3],
+        }
+    }
+
+    /// Last value written to an expansion audio register in $9000-$B003
+    pub fn audio_register(&self, addr: u16) -> u8 {
+        let channel = ((addr >> 12) as usize).wrapping_sub(0x9);
+        self.audio_registers
+            .get(channel)
+            .map_or(0, |registers| registers[addr as usize & 0b11])
+    }
+
+    /// The register `addr` selects, as $x000-$x003
+    fn register(&self, addr: u16) -> u16 {
+        if self.swap_a0_a1 {
+            (addr & 0xF000) | ((addr & 1) << 1) | ((addr & 2) >> 1)
+        } else {
+            addr & 0xF003
+        }
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[(addr as usize / CHR_BANK_SIZE) % 8];
+        (bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE) % self.chr.len()
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled && !self.prg_ram.is_empty() => {
+                Some(self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()])
+            }
+            _ => self.prg_offset(addr).map(|offset| self.prg_rom[offset]),
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            if self.prg_ram_enabled && !self.prg_ram.is_empty() {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) as usize % len] = data;
+            }
+            return;
+        }
+
+        let register = self.register(addr);
+        match register {
+            0x8000..=0x8003 => self.prg_16k_bank = (data & 0x0F) as usize,
+            // only the default PPU banking mode is supported, where bits 2-3
+            // pick the mirroring
+            0xB003 => {
+                self.mirroring = match (data >> 2) & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+                self.prg_ram_enabled = data & 0b1000_0000 != 0;
+            }
+            0x9000..=0xB002 => {
+                let channel = (register >> 12) as usize - 0x9;
+                self.audio_registers[channel][register as usize & 0b11] = data;
+            }
+            0xC000..=0xC003 => self.prg_8k_bank = (data & 0x1F) as usize,
+            0xD000..=0xE003 => {
+                let index = ((register >> 12) as usize - 0xD) * 4 + (register & 0b11) as usize;
+                self.chr_banks[index] = data as usize;
+            }
+            0xF000 => self.irq.write_latch(data),
+            0xF001 => self.irq.write_control(data),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn irq_ack(&mut self) {
+        self.irq.acknowledge();
+    }
+
+    fn cpu_tick(&mut self) {
+        self.irq.tick();
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if self.prg_rom.is_empty() {
+            return None;
+        }
+        let offset = match addr {
+            0x8000..=0xBFFF => self.prg_16k_bank * PRG_ROM_PAGE_SIZE + (addr as usize - 0x8000),
+            0xC000..=0xDFFF => self.prg_8k_bank * PRG_BANK_SIZE + (addr as usize - 0xC000),
+            0xE000..=0xFFFF => self.prg_rom.len() - PRG_BANK_SIZE + (addr as usize - 0xE000),
+            _ => return None,
+        };
+        Some(offset % self.prg_rom.len())
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        (!self.chr_is_ram).then(|| self.chr_index(addr))
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.prg_rom.len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        if self.chr_is_ram {
+            0
+        } else {
+            self.chr.len()
+        }
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        if let Some(offset) = self.prg_offset(addr) {
+            self.prg_rom[offset] = data;
+        }
+    }
+}
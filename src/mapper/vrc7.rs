@@ -0,0 +1,209 @@
+use super::vrc_irq::VrcIrq;
+use super::Mapper;
+use crate::rom::{Mirroring, Rom, CHR_ROM_PAGE_SIZE};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+
+/// Mapper 85, Konami's VRC7: three switchable 8 KiB PRG banks with the
+/// last one fixed at $E000, eight 1 KiB CHR banks and the VRC IRQ counter.
+/// VRC7a (Lagrange Point) tells the registers of a pair apart by A4 and
+/// VRC7b by A3, so either line does.
+///
+/// The FM synth's registers are written through $9010 (select) and $9030
+/// (data). They're kept for the sound side to read, but nothing plays
+/// them yet.
+pub struct Vrc7 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    prg_ram_enabled: bool,
+    battery: bool,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    /// Banks at $8000, $A000 and $C000
+    prg_banks: [usize; 3],
+    chr_banks: [usize; 8],
+    mirroring: Mirroring,
+    irq: VrcIrq,
+    fm_select: u8,
+    fm_registers: [u8; 0x40],
+    /// Bit 6 of $E000 holds the synth in reset, silencing it
+    fm_reset: bool,
+}
+
+impl Vrc7 {
+    pub fn new(rom: Rom) -> Self {
+        let chr_is_ram = rom.chr_rom.is_empty();
+        let chr = if chr_is_ram {
+            vec![0; CHR_ROM_PAGE_SIZE]
+        } else {
+            rom.chr_rom
+        };
+        Vrc7 {
+            prg_rom: rom.prg_rom,
+            prg_ram: vec![0; rom.prg_ram_size],
+            prg_ram_enabled: false,
+            battery: rom.battery,
+            chr,
+            chr_is_ram,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            mirroring: rom.screen_mirroring,
+            irq: VrcIrq::new(),
+            fm_select: 0,
+            fm_registers: [0; 0x40],
+            fm_reset: false,
+        }
+    }
+
+    /// Last value written to FM synth register `index`
+    pub fn fm_register(&self, index: u8) -> u8 {
+        self.fm_registers[index as usize % self.fm_registers.len()]
+    }
+
+    pub fn fm_reset(&self) -> bool {
+        self.fm_reset
+    }
+
+    /// The register `addr` selects, as $x000 or $x010
+    fn register(&self, addr: u16) -> u16 {
+        let second = addr & 0x18 != 0;
+        (addr & 0xF000) | if second { 0x10 } else { 0 }
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[(addr as usize / CHR_BANK_SIZE) % 8];
+        (bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE) % self.chr.len()
+    }
+}
+
+impl Mapper for Vrc7 {
+    fn cpu_read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled && !self.prg_ram.is_empty() => {
+                Some(self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()])
+            }
+            _ => self.prg_offset(addr).map(|offset| self.prg_rom[offset]),
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF => {
+                if self.prg_ram_enabled && !self.prg_ram.is_empty() {
+                    let len = self.prg_ram.len();
+                    self.prg_ram[(addr - 0x6000) as usize % len] = data;
+                }
+                return;
+            }
+            _ if addr & 0xF030 == 0x9010 => {
+                self.fm_select = data;
+                return;
+            }
+            _ if addr & 0xF030 == 0x9030 => {
+                let index = self.fm_select as usize % self.fm_registers.len();
+                self.fm_registers[index] = data;
+                return;
+            }
+            _ => {}
+        }
+
+        match self.register(addr) {
+            0x8000 => self.prg_banks[0] = (data & 0x3F) as usize,
+            0x8010 => self.prg_banks[1] = (data & 0x3F) as usize,
+            0x9000 => self.prg_banks[2] = (data & 0x3F) as usize,
+            register @ 0xA000..=0xD010 => {
+                let index = ((register >> 12) as usize - 0xA) * 2 + (register >> 4) as usize % 2;
+                self.chr_banks[index] = data as usize;
+            }
+            0xE000 => {
+                self.mirroring = match data & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+                self.fm_reset = data & 0b0100_0000 != 0;
+                self.prg_ram_enabled = data & 0b1000_0000 != 0;
+            }
+            0xE010 => self.irq.write_latch(data),
+            0xF000 => self.irq.write_control(data),
+            0xF010 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn has_battery(&self) -> bool {
+        self.battery
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn irq_ack(&mut self) {
+        self.irq.acknowledge();
+    }
+
+    fn cpu_tick(&mut self) {
+        self.irq.tick();
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let banks = self.prg_rom.len() / PRG_BANK_SIZE;
+        if banks == 0 {
+            return None;
+        }
+        let bank = match addr {
+            0x8000..=0xDFFF => self.prg_banks[(addr as usize - 0x8000) / PRG_BANK_SIZE],
+            0xE000..=0xFFFF => banks - 1,
+            _ => return None,
+        };
+        Some((bank % banks) * PRG_BANK_SIZE + (addr as usize % PRG_BANK_SIZE))
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        (!self.chr_is_ram).then(|| self.chr_index(addr))
+    }
+
+    fn prg_rom_len(&self) -> usize {
+        self.prg_rom.len()
+    }
+
+    fn chr_rom_len(&self) -> usize {
+        if self.chr_is_ram {
+            0
+        } else {
+            self.chr.len()
+        }
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        if let Some(offset) = self.prg_offset(addr) {
+            self.prg_rom[offset] = data;
+        }
+    }
+}
@@ -0,0 +1,88 @@
+/// CPU cycles per scanline, in thirds: the prescaler drops by 3 each cycle
+/// so that it runs out every 113⅔ cycles
+const PRESCALER_PERIOD: i16 = 341;
+
+/// The IRQ counter of Konami's VRC4, VRC6 and VRC7. An 8-bit counter
+/// counts up to $FF and reloads from the latch, raising the IRQ as it
+/// does. It is clocked once per scanline by a prescaler running off the
+/// CPU clock, or on every CPU cycle in cycle mode.
+#[derive(Default)]
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    /// Whether acknowledging the IRQ enables the counter again
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    pending: bool,
+}
+
+impl VrcIrq {
+    pub fn new() -> Self {
+        VrcIrq::default()
+    }
+
+    /// Low four bits of the latch, as VRC4 writes it
+    pub fn write_latch_low(&mut self, data: u8) {
+        self.latch = (self.latch & 0xF0) | (data & 0x0F);
+    }
+
+    /// High four bits of the latch, as VRC4 writes it
+    pub fn write_latch_high(&mut self, data: u8) {
+        self.latch = (self.latch & 0x0F) | (data << 4);
+    }
+
+    /// The whole latch, as VRC6 and VRC7 write it
+    pub fn write_latch(&mut self, data: u8) {
+        self.latch = data;
+    }
+
+    /// Bit 0 enables the counter again on acknowledge, bit 1 enables it,
+    /// reloading it from the latch, and bit 2 selects cycle mode. Also
+    /// acknowledges the IRQ.
+    pub fn write_control(&mut self, data: u8) {
+        self.enable_after_ack = data & 0b001 != 0;
+        self.enabled = data & 0b010 != 0;
+        self.cycle_mode = data & 0b100 != 0;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = PRESCALER_PERIOD;
+        }
+        self.pending = false;
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    pub fn pending(&self) -> bool {
+        self.pending
+    }
+
+    /// Runs the counter for one CPU cycle
+    pub fn tick(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.cycle_mode {
+            self.clock_counter();
+            return;
+        }
+        self.prescaler -= 3;
+        if self.prescaler <= 0 {
+            self.prescaler += PRESCALER_PERIOD;
+            self.clock_counter();
+        }
+    }
+
+    fn clock_counter(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use bitflags::bitflags;
-use lazy_static::lazy_static;
-use std::{collections::HashMap, fmt::Debug};
+use std::fmt::{self, Debug};
+use std::str::FromStr;
 
 bitflags! {
     /// # Status Register (P) http://wiki.nesdev.com/w/index.php/Status_flags
@@ -28,306 +28,496 @@ bitflags! {
     }
 }
 
+/// Defines `Mnemonic` along with `Mnemonic::ALL` and `Mnemonic::name`
+/// from one list, so they can't get out of step
+macro_rules! mnemonics {
+    ($($(#[$attr:meta])* $name:ident,)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[allow(clippy::upper_case_acronyms)]
+        pub enum Mnemonic {
+            $($(#[$attr])* $name,)*
+        }
+
+        impl Mnemonic {
+            pub const ALL: &'static [Mnemonic] = &[$(Mnemonic::$name,)*];
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Mnemonic::$name => stringify!($name),)*
+                }
+            }
+        }
+    };
+}
+
+mnemonics! {
+    AAX,
+    ADC,
+    ALR,
+    ANC,
+    AND,
+    ARR,
+    ASL,
+    AXS,
+    BCC,
+    BCS,
+    BEQ,
+    BIT,
+    BMI,
+    BNE,
+    BPL,
+    BRK,
+    BVC,
+    BVS,
+    CLC,
+    CLD,
+    CLI,
+    CLV,
+    CMP,
+    CPX,
+    CPY,
+    DCP,
+    DEC,
+    DEX,
+    DEY,
+    DOP,
+    EOR,
+    INC,
+    INX,
+    INY,
+    ISB,
+    JMP,
+    JSR,
+    LAS,
+    LAX,
+    LDA,
+    LDX,
+    LDY,
+    LSR,
+    LXA,
+    NOP,
+    ORA,
+    PHA,
+    PHP,
+    PLA,
+    PLP,
+    RLA,
+    ROL,
+    ROR,
+    RRA,
+    RTI,
+    RTS,
+    SBC,
+    SEC,
+    SED,
+    SEI,
+    SHA,
+    SHX,
+    SHY,
+    SLO,
+    SRE,
+    STA,
+    STX,
+    STY,
+    TAS,
+    TAX,
+    TAY,
+    TOP,
+    TSX,
+    TXA,
+    TXS,
+    TYA,
+    XAA,
+    /// Jams the CPU; only a reset recovers it
+    KIL,
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+impl FromStr for Mnemonic {
+    type Err = String;
+
+    /// Case-insensitive, so `lda` and `LDA` both parse
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Mnemonic::ALL
+            .iter()
+            .copied()
+            .find(|mnemonic| mnemonic.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown mnemonic `{}`", name))
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct Opcode {
     pub code: u8,
-    pub mnemonic: &'static str,
+    pub mnemonic: Mnemonic,
     pub len: u8,
     pub cycles: u8,
     pub mode: AddressingMode,
+    /// Takes one more cycle when indexing crosses a page. Instructions that
+    /// write their operand spend that cycle every time instead, and it is
+    /// already counted in `cycles`.
+    pub page_cross_penalty: bool,
+    /// Undocumented opcode, including the extra NOPs and the $EB copy of SBC
+    pub unofficial: bool,
 }
 
 impl Opcode {
-    fn new(code: u8, mnemonic: &'static str, len: u8, cycles: u8, mode: AddressingMode) -> Self {
+    const fn new(code: u8, mnemonic: Mnemonic, len: u8, cycles: u8, mode: AddressingMode) -> Self {
         Opcode {
             code,
             mnemonic,
             len,
             cycles,
             mode,
+            page_cross_penalty: false,
+            unofficial: false,
         }
     }
-}
 
-lazy_static! {
-    pub static ref CPU_OPS_CODES: Vec<Opcode> = vec![
-        Opcode::new(0x00, "BRK", 1, 7, AddressingMode::NoneAddressing),
-        Opcode::new(0x08, "PHP", 1, 3, AddressingMode::NoneAddressing),
-        Opcode::new(0x18, "CLC", 1, 0, AddressingMode::NoneAddressing),
-        Opcode::new(0x20, "JSR", 3, 6, AddressingMode::Absolute),
-        Opcode::new(0x28, "PLP", 1, 4, AddressingMode::NoneAddressing),
-        Opcode::new(0x38, "SEC", 1, 0, AddressingMode::NoneAddressing),
-        Opcode::new(0x40, "RTI", 1, 6, AddressingMode::NoneAddressing),
-        Opcode::new(0x48, "PHA", 1, 0, AddressingMode::NoneAddressing),
-        Opcode::new(0x58, "CLI", 1, 0, AddressingMode::NoneAddressing),
-        Opcode::new(0x60, "RTS", 1, 6, AddressingMode::NoneAddressing),
-        Opcode::new(0x68, "PLA", 1, 0, AddressingMode::NoneAddressing),
-        Opcode::new(0x78, "SEI", 1, 0, AddressingMode::NoneAddressing),
-        Opcode::new(0x88, "DEY", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0x8A, "TXA", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0x98, "TYA", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0x9A, "TXS", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0xA8, "TAY", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0xAA, "TAX", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0xB8, "CLV", 1, 0, AddressingMode::NoneAddressing),
-        Opcode::new(0xBA, "TSX", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0xC8, "INY", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0xCA, "DEX", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0xD8, "CLD", 1, 0, AddressingMode::NoneAddressing),
-        Opcode::new(0xE8, "INX", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0xF8, "SED", 1, 0, AddressingMode::NoneAddressing),
-
-        Opcode::new(0x61, "ADC", 2, 6, AddressingMode::Indirect_X),
-        Opcode::new(0x65, "ADC", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0x69, "ADC", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0x6D, "ADC", 3, 4, AddressingMode::Absolute),
-        Opcode::new(0x71, "ADC", 2, 5,/*+1 if page crossed*/ AddressingMode::Indirect_Y),
-        Opcode::new(0x75, "ADC", 2, 4, AddressingMode::ZeroPage_X),
-        Opcode::new(0x79, "ADC", 3, 4,/*+1 if page crossed*/ AddressingMode::Absolute_Y),
-        Opcode::new(0x7D, "ADC", 3, 4,/*+1 if page crossed*/ AddressingMode::Absolute_X),
-
-        Opcode::new(0x81, "STA", 2, 6, AddressingMode::Indirect_X),
-        Opcode::new(0x85, "STA", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0x8D, "STA", 3, 4, AddressingMode::Absolute),
-        Opcode::new(0x91, "STA", 2, 6, AddressingMode::Indirect_Y),
-        Opcode::new(0x95, "STA", 2, 4, AddressingMode::ZeroPage_X),
-        Opcode::new(0x99, "STA", 3, 5, AddressingMode::Absolute_Y),
-        Opcode::new(0x9D, "STA", 3, 5, AddressingMode::Absolute_X),
-
-        Opcode::new(0xA1, "LDA", 2, 6, AddressingMode::Indirect_X),
-        Opcode::new(0xA5, "LDA", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0xA9, "LDA", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0xAD, "LDA", 3, 4, AddressingMode::Absolute),
-        Opcode::new(0xB1, "LDA", 2, 5/*+1 if page crossed*/, AddressingMode::Indirect_Y),
-        Opcode::new(0xB5, "LDA", 2, 4, AddressingMode::ZeroPage_X),
-        Opcode::new(0xB9, "LDA", 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_Y),
-        Opcode::new(0xBD, "LDA", 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_X),
-
-        Opcode::new(0xA2, "LDX", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0xA6, "LDX", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0xAE, "LDX", 3, 4, AddressingMode::Absolute),
-        Opcode::new(0xB6, "LDX", 2, 4, AddressingMode::ZeroPage_Y),
-        Opcode::new(0xBE, "LDX", 3, 4,/*+1 if page crossed*/ AddressingMode::Absolute_Y),
-
-        Opcode::new(0xA0, "LDY", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0xA4, "LDY", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0xB4, "LDY", 2, 4, AddressingMode::ZeroPage_X),
-        Opcode::new(0xAC, "LDY", 3, 4, AddressingMode::Absolute),
-        Opcode::new(0xBC, "LDY", 3, 4, AddressingMode::Absolute_X),
-
-        Opcode::new(0xE1, "SBC", 2, 6, AddressingMode::Indirect_X),
-        Opcode::new(0xE5, "SBC", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0xE9, "SBC", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0xEB, "SBC", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0xED, "SBC", 3, 4, AddressingMode::Absolute),
-        Opcode::new(0xF1, "SBC", 2, 5/*+1 if page crossed*/, AddressingMode::Indirect_Y),
-        Opcode::new(0xF5, "SBC", 2, 4, AddressingMode::ZeroPage_X),
-        Opcode::new(0xF9, "SBC", 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_Y),
-        Opcode::new(0xFD, "SBC", 3, 4/*+1 if page crossed*/, AddressingMode::Absolute_X),
-
-        Opcode::new(0x29, "AND", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0x25, "AND", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0x35, "AND", 2, 4, AddressingMode::ZeroPage_X),
-        Opcode::new(0x2D, "AND", 3, 4, AddressingMode::Absolute),
-        Opcode::new(0x3D, "AND", 3, 4,/*+1 if page crossed*/ AddressingMode::Absolute_X),
-        Opcode::new(0x39, "AND", 3, 4,/*+1 if page crossed*/ AddressingMode::Absolute_Y),
-        Opcode::new(0x21, "AND", 2, 6, AddressingMode::Indirect_X),
-        Opcode::new(0x31, "AND", 2, 5,/*+1 if page crossed*/ AddressingMode::Indirect_Y),
-
-        Opcode::new(0x0A, "ASL", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0x06, "ASL", 2, 5, AddressingMode::ZeroPage),
-        Opcode::new(0x16, "ASL", 2, 6, AddressingMode::ZeroPage_X),
-        Opcode::new(0x0E, "ASL", 3, 6, AddressingMode::Absolute),
-        Opcode::new(0x1E, "ASL", 3, 7, AddressingMode::Absolute_X),
-
-        Opcode::new(0x2A, "ROL", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0x26, "ROL", 2, 5, AddressingMode::ZeroPage),
-        Opcode::new(0x36, "ROL", 2, 6, AddressingMode::ZeroPage_X),
-        Opcode::new(0x2E, "ROL", 3, 6, AddressingMode::Absolute),
-        Opcode::new(0x3E, "ROL", 3, 7, AddressingMode::Absolute_X),
-
-        Opcode::new(0x6A, "ROR", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0x66, "ROR", 2, 5, AddressingMode::ZeroPage),
-        Opcode::new(0x76, "ROR", 2, 6, AddressingMode::ZeroPage_X),
-        Opcode::new(0x6E, "ROR", 3, 6, AddressingMode::Absolute),
-        Opcode::new(0x7E, "ROR", 3, 7, AddressingMode::Absolute_X),
-
-        Opcode::new(0xC1, "CMP", 2, 6, AddressingMode::Indirect_X),
-        Opcode::new(0xC5, "CMP", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0xC9, "CMP", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0xCD, "CMP", 3, 4, AddressingMode::Absolute),
-        Opcode::new(0xD1, "CMP", 2, 5, AddressingMode::Indirect_Y),
-        Opcode::new(0xD5, "CMP", 2, 4, AddressingMode::ZeroPage_X),
-        Opcode::new(0xD9, "CMP", 3, 4, AddressingMode::Absolute_Y),
-        Opcode::new(0xDD, "CMP", 3, 4, AddressingMode::Absolute_X),
-
-        Opcode::new(0xE0, "CPX", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0xE4, "CPX", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0xEC, "CPX", 3, 4, AddressingMode::Absolute),
-
-        Opcode::new(0xC0, "CPY", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0xC4, "CPY", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0xCC, "CPY", 3, 4, AddressingMode::Absolute),
-
-        Opcode::new(0x10, "BPL", 2, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0x30, "BMI", 2, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0x50, "BVC", 2, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0x70, "BVS", 2, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0x90, "BCC", 2, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0xB0, "BCS", 2, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0xD0, "BNE", 2, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0xF0, "BEQ", 2, 2, AddressingMode::NoneAddressing),
-
-        Opcode::new(0x4A, "LSR", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0x46, "LSR", 2, 5, AddressingMode::ZeroPage),
-        Opcode::new(0x56, "LSR", 2, 6, AddressingMode::ZeroPage_X),
-        Opcode::new(0x4E, "LSR", 3, 6, AddressingMode::Absolute),
-        Opcode::new(0x5E, "LSR", 3, 7, AddressingMode::Absolute_X),
-
-        Opcode::new(0xE6, "INC", 2, 5, AddressingMode::ZeroPage),
-        Opcode::new(0xF6, "INC", 2, 6, AddressingMode::ZeroPage_X),
-        Opcode::new(0xEE, "INC", 3, 6, AddressingMode::Absolute),
-        Opcode::new(0xFE, "INC", 3, 7, AddressingMode::Absolute_X),
-
-        Opcode::new(0x24, "BIT", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0x2C, "BIT", 3, 4, AddressingMode::Absolute),
-
-        Opcode::new(0x1A, "NOP", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0x3A, "NOP", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0x5A, "NOP", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0x7A, "NOP", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0xDA, "NOP", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0xEA, "NOP", 1, 2, AddressingMode::NoneAddressing),
-        Opcode::new(0xFA, "NOP", 1, 2, AddressingMode::NoneAddressing),
-
-        Opcode::new(0x04, "DOP", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0x14, "DOP", 2, 4, AddressingMode::ZeroPage_X),
-        Opcode::new(0x34, "DOP", 2, 4, AddressingMode::ZeroPage_X),
-        Opcode::new(0x44, "DOP", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0x54, "DOP", 2, 4, AddressingMode::ZeroPage_X),
-        Opcode::new(0x64, "DOP", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0x74, "DOP", 2, 4, AddressingMode::ZeroPage_X),
-        Opcode::new(0x80, "DOP", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0x82, "DOP", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0x89, "DOP", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0xC2, "DOP", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0xD4, "DOP", 2, 4, AddressingMode::ZeroPage_X),
-        Opcode::new(0xE2, "DOP", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0xF4, "DOP", 2, 4, AddressingMode::ZeroPage_X),
-
-        Opcode::new(0x0C, "TOP", 3, 4, AddressingMode::Absolute),
-        Opcode::new(0x1C, "TOP", 3, 4, AddressingMode::Absolute_X),
-        Opcode::new(0x3C, "TOP", 3, 4, AddressingMode::Absolute_X),
-        Opcode::new(0x5C, "TOP", 3, 4, AddressingMode::Absolute_X),
-        Opcode::new(0x7C, "TOP", 3, 4, AddressingMode::Absolute_X),
-        Opcode::new(0xDC, "TOP", 3, 4, AddressingMode::Absolute_X),
-        Opcode::new(0xFC, "TOP", 3, 4, AddressingMode::Absolute_X),
-
-        Opcode::new(0x4C, "JMP", 3, 3, AddressingMode::Absolute),
-        Opcode::new(0x6C, "JMP", 3, 5, AddressingMode::NoneAddressing),
-
-        Opcode::new(0xC6, "DEC", 2, 5, AddressingMode::ZeroPage),
-        Opcode::new(0xD6, "DEC", 2, 6, AddressingMode::ZeroPage_X),
-        Opcode::new(0xCE, "DEC", 3, 6, AddressingMode::Absolute),
-        Opcode::new(0xDE, "DEC", 3, 7, AddressingMode::Absolute_X),
-
-        Opcode::new(0x86, "STX", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0x96, "STX", 2, 4, AddressingMode::ZeroPage_Y),
-        Opcode::new(0x8E, "STX", 3, 4, AddressingMode::Absolute),
-
-        Opcode::new(0x84, "STY", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0x94, "STY", 2, 4, AddressingMode::ZeroPage_X),
-        Opcode::new(0x8C, "STY", 3, 4, AddressingMode::Absolute),
-
-        Opcode::new(0x01, "ORA", 2, 6, AddressingMode::Indirect_X),
-        Opcode::new(0x05, "ORA", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0x09, "ORA", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0x0D, "ORA", 3, 4, AddressingMode::Absolute),
-        Opcode::new(0x11, "ORA", 2, 5, AddressingMode::Indirect_Y),
-        Opcode::new(0x15, "ORA", 2, 4, AddressingMode::ZeroPage_X),
-        Opcode::new(0x19, "ORA", 3, 4, AddressingMode::Absolute_Y),
-        Opcode::new(0x1D, "ORA", 3, 4, AddressingMode::Absolute_X),
-
-        Opcode::new(0x41, "EOR", 2, 6, AddressingMode::Indirect_X),
-        Opcode::new(0x45, "EOR", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0x49, "EOR", 2, 2, AddressingMode::Immediate),
-        Opcode::new(0x4D, "EOR", 3, 4, AddressingMode::Absolute),
-        Opcode::new(0x51, "EOR", 2, 5, AddressingMode::Indirect_Y),
-        Opcode::new(0x55, "EOR", 2, 4, AddressingMode::ZeroPage_X),
-        Opcode::new(0x59, "EOR", 3, 4, AddressingMode::Absolute_Y),
-        Opcode::new(0x5D, "EOR", 3, 4, AddressingMode::Absolute_X),
-
-        Opcode::new(0xA3, "LAX", 2, 6, AddressingMode::Indirect_X),
-        Opcode::new(0xA7, "LAX", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0xAF, "LAX", 3, 4, AddressingMode::Absolute),
-        Opcode::new(0xB3, "LAX", 2, 5, AddressingMode::Indirect_Y),
-        Opcode::new(0xB7, "LAX", 2, 4, AddressingMode::ZeroPage_Y),
-        Opcode::new(0xBF, "LAX", 3, 4, AddressingMode::Absolute_Y),
-
-        Opcode::new(0x83, "AAX", 2, 6, AddressingMode::Indirect_X),
-        Opcode::new(0x87, "AAX", 2, 3, AddressingMode::ZeroPage),
-        Opcode::new(0x8F, "AAX", 3, 4, AddressingMode::Absolute),
-        Opcode::new(0x97, "AAX", 2, 4, AddressingMode::ZeroPage_Y),
-
-        Opcode::new(0xC3, "DCP", 2, 8, AddressingMode::Indirect_X),
-        Opcode::new(0xC7, "DCP", 2, 5, AddressingMode::ZeroPage),
-        Opcode::new(0xCF, "DCP", 3, 6, AddressingMode::Absolute),
-        Opcode::new(0xD3, "DCP", 2, 8, AddressingMode::Indirect_Y),
-        Opcode::new(0xD7, "DCP", 2, 6, AddressingMode::ZeroPage_X),
-        Opcode::new(0xDB, "DCP", 3, 7, AddressingMode::Absolute_Y),
-        Opcode::new(0xDF, "DCP", 3, 7, AddressingMode::Absolute_X),
-
-        Opcode::new(0xE3, "ISB", 2, 8, AddressingMode::Indirect_X),
-        Opcode::new(0xE7, "ISB", 2, 5, AddressingMode::ZeroPage),
-        Opcode::new(0xEF, "ISB", 3, 6, AddressingMode::Absolute),
-        Opcode::new(0xF3, "ISB", 2, 8, AddressingMode::Indirect_Y),
-        Opcode::new(0xF7, "ISB", 2, 6, AddressingMode::ZeroPage_X),
-        Opcode::new(0xFB, "ISB", 3, 7, AddressingMode::Absolute_Y),
-        Opcode::new(0xFF, "ISB", 3, 7, AddressingMode::Absolute_X),
-
-        Opcode::new(0x03, "SLO", 2, 8, AddressingMode::Indirect_X),
-        Opcode::new(0x07, "SLO", 2, 5, AddressingMode::ZeroPage),
-        Opcode::new(0x0F, "SLO", 3, 6, AddressingMode::Absolute),
-        Opcode::new(0x13, "SLO", 2, 8, AddressingMode::Indirect_Y),
-        Opcode::new(0x17, "SLO", 2, 6, AddressingMode::ZeroPage_X),
-        Opcode::new(0x1B, "SLO", 3, 7, AddressingMode::Absolute_Y),
-        Opcode::new(0x1F, "SLO", 3, 7, AddressingMode::Absolute_X),
-
-        Opcode::new(0x23, "RLA", 2, 8, AddressingMode::Indirect_X),
-        Opcode::new(0x27, "RLA", 2, 5, AddressingMode::ZeroPage),
-        Opcode::new(0x2F, "RLA", 3, 6, AddressingMode::Absolute),
-        Opcode::new(0x33, "RLA", 2, 8, AddressingMode::Indirect_Y),
-        Opcode::new(0x37, "RLA", 2, 6, AddressingMode::ZeroPage_X),
-        Opcode::new(0x3B, "RLA", 3, 7, AddressingMode::Absolute_Y),
-        Opcode::new(0x3F, "RLA", 3, 7, AddressingMode::Absolute_X),
-
-        Opcode::new(0x43, "SRE", 2, 8, AddressingMode::Indirect_X),
-        Opcode::new(0x47, "SRE", 2, 5, AddressingMode::ZeroPage),
-        Opcode::new(0x4F, "SRE", 3, 6, AddressingMode::Absolute),
-        Opcode::new(0x53, "SRE", 2, 8, AddressingMode::Indirect_Y),
-        Opcode::new(0x57, "SRE", 2, 6, AddressingMode::ZeroPage_X),
-        Opcode::new(0x5B, "SRE", 3, 7, AddressingMode::Absolute_Y),
-        Opcode::new(0x5F, "SRE", 3, 7, AddressingMode::Absolute_X),
-
-        Opcode::new(0x63, "RRA", 2, 8, AddressingMode::Indirect_X),
-        Opcode::new(0x67, "RRA", 2, 5, AddressingMode::ZeroPage),
-        Opcode::new(0x6F, "RRA", 3, 6, AddressingMode::Absolute),
-        Opcode::new(0x73, "RRA", 2, 8, AddressingMode::Indirect_Y),
-        Opcode::new(0x77, "RRA", 2, 6, AddressingMode::ZeroPage_X),
-        Opcode::new(0x7B, "RRA", 3, 7, AddressingMode::Absolute_Y),
-        Opcode::new(0x7F, "RRA", 3, 7, AddressingMode::Absolute_X),
-
-    ];
-
-    pub static ref OPCODES_MAP: HashMap<u8, &'static Opcode> = {
-        let mut map = HashMap::new();
-        for cpuop in &*CPU_OPS_CODES {
-            map.insert(cpuop.code, cpuop);
+    const fn illegal(
+        code: u8,
+        mnemonic: Mnemonic,
+        len: u8,
+        cycles: u8,
+        mode: AddressingMode,
+    ) -> Self {
+        Opcode {
+            unofficial: true,
+            ..Opcode::new(code, mnemonic, len, cycles, mode)
         }
-        map
-    };
+    }
+
+    const fn with_page_cross_penalty(self) -> Self {
+        Opcode {
+            page_cross_penalty: true,
+            ..self
+        }
+    }
+}
+
+#[rustfmt::skip]
+pub static CPU_OPS_CODES: &[Opcode] = &[
+    Opcode::new(0x00, Mnemonic::BRK, 1, 7, AddressingMode::NoneAddressing),
+    Opcode::new(0x08, Mnemonic::PHP, 1, 3, AddressingMode::NoneAddressing),
+    Opcode::new(0x18, Mnemonic::CLC, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0x20, Mnemonic::JSR, 3, 6, AddressingMode::Absolute),
+    Opcode::new(0x28, Mnemonic::PLP, 1, 4, AddressingMode::NoneAddressing),
+    Opcode::new(0x38, Mnemonic::SEC, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0x40, Mnemonic::RTI, 1, 6, AddressingMode::NoneAddressing),
+    Opcode::new(0x48, Mnemonic::PHA, 1, 3, AddressingMode::NoneAddressing),
+    Opcode::new(0x58, Mnemonic::CLI, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0x60, Mnemonic::RTS, 1, 6, AddressingMode::NoneAddressing),
+    Opcode::new(0x68, Mnemonic::PLA, 1, 4, AddressingMode::NoneAddressing),
+    Opcode::new(0x78, Mnemonic::SEI, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0x88, Mnemonic::DEY, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0x8A, Mnemonic::TXA, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0x98, Mnemonic::TYA, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0x9A, Mnemonic::TXS, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0xA8, Mnemonic::TAY, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0xAA, Mnemonic::TAX, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0xB8, Mnemonic::CLV, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0xBA, Mnemonic::TSX, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0xC8, Mnemonic::INY, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0xCA, Mnemonic::DEX, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0xD8, Mnemonic::CLD, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0xE8, Mnemonic::INX, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0xF8, Mnemonic::SED, 1, 2, AddressingMode::NoneAddressing),
+
+    Opcode::new(0x61, Mnemonic::ADC, 2, 6, AddressingMode::Indirect_X),
+    Opcode::new(0x65, Mnemonic::ADC, 2, 3, AddressingMode::ZeroPage),
+    Opcode::new(0x69, Mnemonic::ADC, 2, 2, AddressingMode::Immediate),
+    Opcode::new(0x6D, Mnemonic::ADC, 3, 4, AddressingMode::Absolute),
+    Opcode::new(0x71, Mnemonic::ADC, 2, 5, AddressingMode::Indirect_Y).with_page_cross_penalty(),
+    Opcode::new(0x75, Mnemonic::ADC, 2, 4, AddressingMode::ZeroPage_X),
+    Opcode::new(0x79, Mnemonic::ADC, 3, 4, AddressingMode::Absolute_Y).with_page_cross_penalty(),
+    Opcode::new(0x7D, Mnemonic::ADC, 3, 4, AddressingMode::Absolute_X).with_page_cross_penalty(),
+
+    Opcode::new(0x81, Mnemonic::STA, 2, 6, AddressingMode::Indirect_X),
+    Opcode::new(0x85, Mnemonic::STA, 2, 3, AddressingMode::ZeroPage),
+    Opcode::new(0x8D, Mnemonic::STA, 3, 4, AddressingMode::Absolute),
+    Opcode::new(0x91, Mnemonic::STA, 2, 6, AddressingMode::Indirect_Y),
+    Opcode::new(0x95, Mnemonic::STA, 2, 4, AddressingMode::ZeroPage_X),
+    Opcode::new(0x99, Mnemonic::STA, 3, 5, AddressingMode::Absolute_Y),
+    Opcode::new(0x9D, Mnemonic::STA, 3, 5, AddressingMode::Absolute_X),
+
+    Opcode::new(0xA1, Mnemonic::LDA, 2, 6, AddressingMode::Indirect_X),
+    Opcode::new(0xA5, Mnemonic::LDA, 2, 3, AddressingMode::ZeroPage),
+    Opcode::new(0xA9, Mnemonic::LDA, 2, 2, AddressingMode::Immediate),
+    Opcode::new(0xAD, Mnemonic::LDA, 3, 4, AddressingMode::Absolute),
+    Opcode::new(0xB1, Mnemonic::LDA, 2, 5, AddressingMode::Indirect_Y).with_page_cross_penalty(),
+    Opcode::new(0xB5, Mnemonic::LDA, 2, 4, AddressingMode::ZeroPage_X),
+    Opcode::new(0xB9, Mnemonic::LDA, 3, 4, AddressingMode::Absolute_Y).with_page_cross_penalty(),
+    Opcode::new(0xBD, Mnemonic::LDA, 3, 4, AddressingMode::Absolute_X).with_page_cross_penalty(),
+
+    Opcode::new(0xA2, Mnemonic::LDX, 2, 2, AddressingMode::Immediate),
+    Opcode::new(0xA6, Mnemonic::LDX, 2, 3, AddressingMode::ZeroPage),
+    Opcode::new(0xAE, Mnemonic::LDX, 3, 4, AddressingMode::Absolute),
+    Opcode::new(0xB6, Mnemonic::LDX, 2, 4, AddressingMode::ZeroPage_Y),
+    Opcode::new(0xBE, Mnemonic::LDX, 3, 4, AddressingMode::Absolute_Y).with_page_cross_penalty(),
+
+    Opcode::new(0xA0, Mnemonic::LDY, 2, 2, AddressingMode::Immediate),
+    Opcode::new(0xA4, Mnemonic::LDY, 2, 3, AddressingMode::ZeroPage),
+    Opcode::new(0xB4, Mnemonic::LDY, 2, 4, AddressingMode::ZeroPage_X),
+    Opcode::new(0xAC, Mnemonic::LDY, 3, 4, AddressingMode::Absolute),
+    Opcode::new(0xBC, Mnemonic::LDY, 3, 4, AddressingMode::Absolute_X).with_page_cross_penalty(),
+
+    Opcode::new(0xE1, Mnemonic::SBC, 2, 6, AddressingMode::Indirect_X),
+    Opcode::new(0xE5, Mnemonic::SBC, 2, 3, AddressingMode::ZeroPage),
+    Opcode::new(0xE9, Mnemonic::SBC, 2, 2, AddressingMode::Immediate),
+    Opcode::illegal(0xEB, Mnemonic::SBC, 2, 2, AddressingMode::Immediate),
+    Opcode::new(0xED, Mnemonic::SBC, 3, 4, AddressingMode::Absolute),
+    Opcode::new(0xF1, Mnemonic::SBC, 2, 5, AddressingMode::Indirect_Y).with_page_cross_penalty(),
+    Opcode::new(0xF5, Mnemonic::SBC, 2, 4, AddressingMode::ZeroPage_X),
+    Opcode::new(0xF9, Mnemonic::SBC, 3, 4, AddressingMode::Absolute_Y).with_page_cross_penalty(),
+    Opcode::new(0xFD, Mnemonic::SBC, 3, 4, AddressingMode::Absolute_X).with_page_cross_penalty(),
+
+    Opcode::new(0x29, Mnemonic::AND, 2, 2, AddressingMode::Immediate),
+    Opcode::new(0x25, Mnemonic::AND, 2, 3, AddressingMode::ZeroPage),
+    Opcode::new(0x35, Mnemonic::AND, 2, 4, AddressingMode::ZeroPage_X),
+    Opcode::new(0x2D, Mnemonic::AND, 3, 4, AddressingMode::Absolute),
+    Opcode::new(0x3D, Mnemonic::AND, 3, 4, AddressingMode::Absolute_X).with_page_cross_penalty(),
+    Opcode::new(0x39, Mnemonic::AND, 3, 4, AddressingMode::Absolute_Y).with_page_cross_penalty(),
+    Opcode::new(0x21, Mnemonic::AND, 2, 6, AddressingMode::Indirect_X),
+    Opcode::new(0x31, Mnemonic::AND, 2, 5, AddressingMode::Indirect_Y).with_page_cross_penalty(),
+
+    Opcode::new(0x0A, Mnemonic::ASL, 1, 2, AddressingMode::Accumulator),
+    Opcode::new(0x06, Mnemonic::ASL, 2, 5, AddressingMode::ZeroPage),
+    Opcode::new(0x16, Mnemonic::ASL, 2, 6, AddressingMode::ZeroPage_X),
+    Opcode::new(0x0E, Mnemonic::ASL, 3, 6, AddressingMode::Absolute),
+    Opcode::new(0x1E, Mnemonic::ASL, 3, 7, AddressingMode::Absolute_X),
+
+    Opcode::new(0x2A, Mnemonic::ROL, 1, 2, AddressingMode::Accumulator),
+    Opcode::new(0x26, Mnemonic::ROL, 2, 5, AddressingMode::ZeroPage),
+    Opcode::new(0x36, Mnemonic::ROL, 2, 6, AddressingMode::ZeroPage_X),
+    Opcode::new(0x2E, Mnemonic::ROL, 3, 6, AddressingMode::Absolute),
+    Opcode::new(0x3E, Mnemonic::ROL, 3, 7, AddressingMode::Absolute_X),
+
+    Opcode::new(0x6A, Mnemonic::ROR, 1, 2, AddressingMode::Accumulator),
+    Opcode::new(0x66, Mnemonic::ROR, 2, 5, AddressingMode::ZeroPage),
+    Opcode::new(0x76, Mnemonic::ROR, 2, 6, AddressingMode::ZeroPage_X),
+    Opcode::new(0x6E, Mnemonic::ROR, 3, 6, AddressingMode::Absolute),
+    Opcode::new(0x7E, Mnemonic::ROR, 3, 7, AddressingMode::Absolute_X),
+
+    Opcode::new(0xC1, Mnemonic::CMP, 2, 6, AddressingMode::Indirect_X),
+    Opcode::new(0xC5, Mnemonic::CMP, 2, 3, AddressingMode::ZeroPage),
+    Opcode::new(0xC9, Mnemonic::CMP, 2, 2, AddressingMode::Immediate),
+    Opcode::new(0xCD, Mnemonic::CMP, 3, 4, AddressingMode::Absolute),
+    Opcode::new(0xD1, Mnemonic::CMP, 2, 5, AddressingMode::Indirect_Y).with_page_cross_penalty(),
+    Opcode::new(0xD5, Mnemonic::CMP, 2, 4, AddressingMode::ZeroPage_X),
+    Opcode::new(0xD9, Mnemonic::CMP, 3, 4, AddressingMode::Absolute_Y).with_page_cross_penalty(),
+    Opcode::new(0xDD, Mnemonic::CMP, 3, 4, AddressingMode::Absolute_X).with_page_cross_penalty(),
+
+    Opcode::new(0xE0, Mnemonic::CPX, 2, 2, AddressingMode::Immediate),
+    Opcode::new(0xE4, Mnemonic::CPX, 2, 3, AddressingMode::ZeroPage),
+    Opcode::new(0xEC, Mnemonic::CPX, 3, 4, AddressingMode::Absolute),
+
+    Opcode::new(0xC0, Mnemonic::CPY, 2, 2, AddressingMode::Immediate),
+    Opcode::new(0xC4, Mnemonic::CPY, 2, 3, AddressingMode::ZeroPage),
+    Opcode::new(0xCC, Mnemonic::CPY, 3, 4, AddressingMode::Absolute),
+
+    Opcode::new(0x10, Mnemonic::BPL, 2, 2/*+1 if taken, +2 if to a new page*/, AddressingMode::Relative),
+    Opcode::new(0x30, Mnemonic::BMI, 2, 2/*+1 if taken, +2 if to a new page*/, AddressingMode::Relative),
+    Opcode::new(0x50, Mnemonic::BVC, 2, 2/*+1 if taken, +2 if to a new page*/, AddressingMode::Relative),
+    Opcode::new(0x70, Mnemonic::BVS, 2, 2/*+1 if taken, +2 if to a new page*/, AddressingMode::Relative),
+    Opcode::new(0x90, Mnemonic::BCC, 2, 2/*+1 if taken, +2 if to a new page*/, AddressingMode::Relative),
+    Opcode::new(0xB0, Mnemonic::BCS, 2, 2/*+1 if taken, +2 if to a new page*/, AddressingMode::Relative),
+    Opcode::new(0xD0, Mnemonic::BNE, 2, 2/*+1 if taken, +2 if to a new page*/, AddressingMode::Relative),
+    Opcode::new(0xF0, Mnemonic::BEQ, 2, 2/*+1 if taken, +2 if to a new page*/, AddressingMode::Relative),
+
+    Opcode::new(0x4A, Mnemonic::LSR, 1, 2, AddressingMode::Accumulator),
+    Opcode::new(0x46, Mnemonic::LSR, 2, 5, AddressingMode::ZeroPage),
+    Opcode::new(0x56, Mnemonic::LSR, 2, 6, AddressingMode::ZeroPage_X),
+    Opcode::new(0x4E, Mnemonic::LSR, 3, 6, AddressingMode::Absolute),
+    Opcode::new(0x5E, Mnemonic::LSR, 3, 7, AddressingMode::Absolute_X),
+
+    Opcode::new(0xE6, Mnemonic::INC, 2, 5, AddressingMode::ZeroPage),
+    Opcode::new(0xF6, Mnemonic::INC, 2, 6, AddressingMode::ZeroPage_X),
+    Opcode::new(0xEE, Mnemonic::INC, 3, 6, AddressingMode::Absolute),
+    Opcode::new(0xFE, Mnemonic::INC, 3, 7, AddressingMode::Absolute_X),
+
+    Opcode::new(0x24, Mnemonic::BIT, 2, 3, AddressingMode::ZeroPage),
+    Opcode::new(0x2C, Mnemonic::BIT, 3, 4, AddressingMode::Absolute),
+
+    Opcode::illegal(0x1A, Mnemonic::NOP, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::illegal(0x3A, Mnemonic::NOP, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::illegal(0x5A, Mnemonic::NOP, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::illegal(0x7A, Mnemonic::NOP, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::illegal(0xDA, Mnemonic::NOP, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::new(0xEA, Mnemonic::NOP, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::illegal(0xFA, Mnemonic::NOP, 1, 2, AddressingMode::NoneAddressing),
+
+    Opcode::illegal(0x04, Mnemonic::DOP, 2, 3, AddressingMode::ZeroPage),
+    Opcode::illegal(0x14, Mnemonic::DOP, 2, 4, AddressingMode::ZeroPage_X),
+    Opcode::illegal(0x34, Mnemonic::DOP, 2, 4, AddressingMode::ZeroPage_X),
+    Opcode::illegal(0x44, Mnemonic::DOP, 2, 3, AddressingMode::ZeroPage),
+    Opcode::illegal(0x54, Mnemonic::DOP, 2, 4, AddressingMode::ZeroPage_X),
+    Opcode::illegal(0x64, Mnemonic::DOP, 2, 3, AddressingMode::ZeroPage),
+    Opcode::illegal(0x74, Mnemonic::DOP, 2, 4, AddressingMode::ZeroPage_X),
+    Opcode::illegal(0x80, Mnemonic::DOP, 2, 2, AddressingMode::Immediate),
+    Opcode::illegal(0x82, Mnemonic::DOP, 2, 2, AddressingMode::Immediate),
+    Opcode::illegal(0x89, Mnemonic::DOP, 2, 2, AddressingMode::Immediate),
+    Opcode::illegal(0xC2, Mnemonic::DOP, 2, 2, AddressingMode::Immediate),
+    Opcode::illegal(0xD4, Mnemonic::DOP, 2, 4, AddressingMode::ZeroPage_X),
+    Opcode::illegal(0xE2, Mnemonic::DOP, 2, 2, AddressingMode::Immediate),
+    Opcode::illegal(0xF4, Mnemonic::DOP, 2, 4, AddressingMode::ZeroPage_X),
+
+    Opcode::illegal(0x0C, Mnemonic::TOP, 3, 4, AddressingMode::Absolute),
+    Opcode::illegal(0x1C, Mnemonic::TOP, 3, 4, AddressingMode::Absolute_X).with_page_cross_penalty(),
+    Opcode::illegal(0x3C, Mnemonic::TOP, 3, 4, AddressingMode::Absolute_X).with_page_cross_penalty(),
+    Opcode::illegal(0x5C, Mnemonic::TOP, 3, 4, AddressingMode::Absolute_X).with_page_cross_penalty(),
+    Opcode::illegal(0x7C, Mnemonic::TOP, 3, 4, AddressingMode::Absolute_X).with_page_cross_penalty(),
+    Opcode::illegal(0xDC, Mnemonic::TOP, 3, 4, AddressingMode::Absolute_X).with_page_cross_penalty(),
+    Opcode::illegal(0xFC, Mnemonic::TOP, 3, 4, AddressingMode::Absolute_X).with_page_cross_penalty(),
+
+    Opcode::new(0x4C, Mnemonic::JMP, 3, 3, AddressingMode::Absolute),
+    Opcode::new(0x6C, Mnemonic::JMP, 3, 5, AddressingMode::Indirect),
+
+    Opcode::new(0xC6, Mnemonic::DEC, 2, 5, AddressingMode::ZeroPage),
+    Opcode::new(0xD6, Mnemonic::DEC, 2, 6, AddressingMode::ZeroPage_X),
+    Opcode::new(0xCE, Mnemonic::DEC, 3, 6, AddressingMode::Absolute),
+    Opcode::new(0xDE, Mnemonic::DEC, 3, 7, AddressingMode::Absolute_X),
+
+    Opcode::new(0x86, Mnemonic::STX, 2, 3, AddressingMode::ZeroPage),
+    Opcode::new(0x96, Mnemonic::STX, 2, 4, AddressingMode::ZeroPage_Y),
+    Opcode::new(0x8E, Mnemonic::STX, 3, 4, AddressingMode::Absolute),
+
+    Opcode::new(0x84, Mnemonic::STY, 2, 3, AddressingMode::ZeroPage),
+    Opcode::new(0x94, Mnemonic::STY, 2, 4, AddressingMode::ZeroPage_X),
+    Opcode::new(0x8C, Mnemonic::STY, 3, 4, AddressingMode::Absolute),
+
+    Opcode::new(0x01, Mnemonic::ORA, 2, 6, AddressingMode::Indirect_X),
+    Opcode::new(0x05, Mnemonic::ORA, 2, 3, AddressingMode::ZeroPage),
+    Opcode::new(0x09, Mnemonic::ORA, 2, 2, AddressingMode::Immediate),
+    Opcode::new(0x0D, Mnemonic::ORA, 3, 4, AddressingMode::Absolute),
+    Opcode::new(0x11, Mnemonic::ORA, 2, 5, AddressingMode::Indirect_Y).with_page_cross_penalty(),
+    Opcode::new(0x15, Mnemonic::ORA, 2, 4, AddressingMode::ZeroPage_X),
+    Opcode::new(0x19, Mnemonic::ORA, 3, 4, AddressingMode::Absolute_Y).with_page_cross_penalty(),
+    Opcode::new(0x1D, Mnemonic::ORA, 3, 4, AddressingMode::Absolute_X).with_page_cross_penalty(),
+
+    Opcode::new(0x41, Mnemonic::EOR, 2, 6, AddressingMode::Indirect_X),
+    Opcode::new(0x45, Mnemonic::EOR, 2, 3, AddressingMode::ZeroPage),
+    Opcode::new(0x49, Mnemonic::EOR, 2, 2, AddressingMode::Immediate),
+    Opcode::new(0x4D, Mnemonic::EOR, 3, 4, AddressingMode::Absolute),
+    Opcode::new(0x51, Mnemonic::EOR, 2, 5, AddressingMode::Indirect_Y).with_page_cross_penalty(),
+    Opcode::new(0x55, Mnemonic::EOR, 2, 4, AddressingMode::ZeroPage_X),
+    Opcode::new(0x59, Mnemonic::EOR, 3, 4, AddressingMode::Absolute_Y).with_page_cross_penalty(),
+    Opcode::new(0x5D, Mnemonic::EOR, 3, 4, AddressingMode::Absolute_X).with_page_cross_penalty(),
+
+    Opcode::illegal(0xA3, Mnemonic::LAX, 2, 6, AddressingMode::Indirect_X),
+    Opcode::illegal(0xA7, Mnemonic::LAX, 2, 3, AddressingMode::ZeroPage),
+    Opcode::illegal(0xAF, Mnemonic::LAX, 3, 4, AddressingMode::Absolute),
+    Opcode::illegal(0xB3, Mnemonic::LAX, 2, 5, AddressingMode::Indirect_Y).with_page_cross_penalty(),
+    Opcode::illegal(0xB7, Mnemonic::LAX, 2, 4, AddressingMode::ZeroPage_Y),
+    Opcode::illegal(0xBF, Mnemonic::LAX, 3, 4, AddressingMode::Absolute_Y).with_page_cross_penalty(),
+
+    Opcode::illegal(0x83, Mnemonic::AAX, 2, 6, AddressingMode::Indirect_X),
+    Opcode::illegal(0x87, Mnemonic::AAX, 2, 3, AddressingMode::ZeroPage),
+    Opcode::illegal(0x8F, Mnemonic::AAX, 3, 4, AddressingMode::Absolute),
+    Opcode::illegal(0x97, Mnemonic::AAX, 2, 4, AddressingMode::ZeroPage_Y),
+
+    Opcode::illegal(0xC3, Mnemonic::DCP, 2, 8, AddressingMode::Indirect_X),
+    Opcode::illegal(0xC7, Mnemonic::DCP, 2, 5, AddressingMode::ZeroPage),
+    Opcode::illegal(0xCF, Mnemonic::DCP, 3, 6, AddressingMode::Absolute),
+    Opcode::illegal(0xD3, Mnemonic::DCP, 2, 8, AddressingMode::Indirect_Y),
+    Opcode::illegal(0xD7, Mnemonic::DCP, 2, 6, AddressingMode::ZeroPage_X),
+    Opcode::illegal(0xDB, Mnemonic::DCP, 3, 7, AddressingMode::Absolute_Y),
+    Opcode::illegal(0xDF, Mnemonic::DCP, 3, 7, AddressingMode::Absolute_X),
+
+    Opcode::illegal(0xE3, Mnemonic::ISB, 2, 8, AddressingMode::Indirect_X),
+    Opcode::illegal(0xE7, Mnemonic::ISB, 2, 5, AddressingMode::ZeroPage),
+    Opcode::illegal(0xEF, Mnemonic::ISB, 3, 6, AddressingMode::Absolute),
+    Opcode::illegal(0xF3, Mnemonic::ISB, 2, 8, AddressingMode::Indirect_Y),
+    Opcode::illegal(0xF7, Mnemonic::ISB, 2, 6, AddressingMode::ZeroPage_X),
+    Opcode::illegal(0xFB, Mnemonic::ISB, 3, 7, AddressingMode::Absolute_Y),
+    Opcode::illegal(0xFF, Mnemonic::ISB, 3, 7, AddressingMode::Absolute_X),
+
+    Opcode::illegal(0x03, Mnemonic::SLO, 2, 8, AddressingMode::Indirect_X),
+    Opcode::illegal(0x07, Mnemonic::SLO, 2, 5, AddressingMode::ZeroPage),
+    Opcode::illegal(0x0F, Mnemonic::SLO, 3, 6, AddressingMode::Absolute),
+    Opcode::illegal(0x13, Mnemonic::SLO, 2, 8, AddressingMode::Indirect_Y),
+    Opcode::illegal(0x17, Mnemonic::SLO, 2, 6, AddressingMode::ZeroPage_X),
+    Opcode::illegal(0x1B, Mnemonic::SLO, 3, 7, AddressingMode::Absolute_Y),
+    Opcode::illegal(0x1F, Mnemonic::SLO, 3, 7, AddressingMode::Absolute_X),
+
+    Opcode::illegal(0x23, Mnemonic::RLA, 2, 8, AddressingMode::Indirect_X),
+    Opcode::illegal(0x27, Mnemonic::RLA, 2, 5, AddressingMode::ZeroPage),
+    Opcode::illegal(0x2F, Mnemonic::RLA, 3, 6, AddressingMode::Absolute),
+    Opcode::illegal(0x33, Mnemonic::RLA, 2, 8, AddressingMode::Indirect_Y),
+    Opcode::illegal(0x37, Mnemonic::RLA, 2, 6, AddressingMode::ZeroPage_X),
+    Opcode::illegal(0x3B, Mnemonic::RLA, 3, 7, AddressingMode::Absolute_Y),
+    Opcode::illegal(0x3F, Mnemonic::RLA, 3, 7, AddressingMode::Absolute_X),
+
+    Opcode::illegal(0x43, Mnemonic::SRE, 2, 8, AddressingMode::Indirect_X),
+    Opcode::illegal(0x47, Mnemonic::SRE, 2, 5, AddressingMode::ZeroPage),
+    Opcode::illegal(0x4F, Mnemonic::SRE, 3, 6, AddressingMode::Absolute),
+    Opcode::illegal(0x53, Mnemonic::SRE, 2, 8, AddressingMode::Indirect_Y),
+    Opcode::illegal(0x57, Mnemonic::SRE, 2, 6, AddressingMode::ZeroPage_X),
+    Opcode::illegal(0x5B, Mnemonic::SRE, 3, 7, AddressingMode::Absolute_Y),
+    Opcode::illegal(0x5F, Mnemonic::SRE, 3, 7, AddressingMode::Absolute_X),
+
+    Opcode::illegal(0x63, Mnemonic::RRA, 2, 8, AddressingMode::Indirect_X),
+    Opcode::illegal(0x67, Mnemonic::RRA, 2, 5, AddressingMode::ZeroPage),
+    Opcode::illegal(0x6F, Mnemonic::RRA, 3, 6, AddressingMode::Absolute),
+    Opcode::illegal(0x73, Mnemonic::RRA, 2, 8, AddressingMode::Indirect_Y),
+    Opcode::illegal(0x77, Mnemonic::RRA, 2, 6, AddressingMode::ZeroPage_X),
+    Opcode::illegal(0x7B, Mnemonic::RRA, 3, 7, AddressingMode::Absolute_Y),
+    Opcode::illegal(0x7F, Mnemonic::RRA, 3, 7, AddressingMode::Absolute_X),
+
+    Opcode::illegal(0x0B, Mnemonic::ANC, 2, 2, AddressingMode::Immediate),
+    Opcode::illegal(0x2B, Mnemonic::ANC, 2, 2, AddressingMode::Immediate),
+    Opcode::illegal(0x4B, Mnemonic::ALR, 2, 2, AddressingMode::Immediate),
+    Opcode::illegal(0x6B, Mnemonic::ARR, 2, 2, AddressingMode::Immediate),
+    Opcode::illegal(0x8B, Mnemonic::XAA, 2, 2, AddressingMode::Immediate),
+    Opcode::illegal(0xAB, Mnemonic::LXA, 2, 2, AddressingMode::Immediate),
+    Opcode::illegal(0xCB, Mnemonic::AXS, 2, 2, AddressingMode::Immediate),
+
+    Opcode::illegal(0xBB, Mnemonic::LAS, 3, 4, AddressingMode::Absolute_Y).with_page_cross_penalty(),
+    Opcode::illegal(0x9B, Mnemonic::TAS, 3, 5, AddressingMode::Absolute_Y),
+    Opcode::illegal(0x93, Mnemonic::SHA, 2, 6, AddressingMode::Indirect_Y),
+    Opcode::illegal(0x9F, Mnemonic::SHA, 3, 5, AddressingMode::Absolute_Y),
+    Opcode::illegal(0x9E, Mnemonic::SHX, 3, 5, AddressingMode::Absolute_Y),
+    Opcode::illegal(0x9C, Mnemonic::SHY, 3, 5, AddressingMode::Absolute_X),
+
+    Opcode::illegal(0x02, Mnemonic::KIL, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::illegal(0x12, Mnemonic::KIL, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::illegal(0x22, Mnemonic::KIL, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::illegal(0x32, Mnemonic::KIL, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::illegal(0x42, Mnemonic::KIL, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::illegal(0x52, Mnemonic::KIL, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::illegal(0x62, Mnemonic::KIL, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::illegal(0x72, Mnemonic::KIL, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::illegal(0x92, Mnemonic::KIL, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::illegal(0xB2, Mnemonic::KIL, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::illegal(0xD2, Mnemonic::KIL, 1, 2, AddressingMode::NoneAddressing),
+    Opcode::illegal(0xF2, Mnemonic::KIL, 1, 2, AddressingMode::NoneAddressing),
+];
+
+/// Decode table indexed directly by the opcode byte.
+pub static OPCODE_TABLE: [Opcode; 256] = build_opcode_table();
+
+const fn build_opcode_table() -> [Opcode; 256] {
+    let mut table = [Opcode::new(0, Mnemonic::KIL, 1, 2, AddressingMode::NoneAddressing); 256];
+    let mut code = 0;
+    while code < 256 {
+        table[code].code = code as u8;
+        code += 1;
+    }
+
+    let mut i = 0;
+    while i < CPU_OPS_CODES.len() {
+        let op = CPU_OPS_CODES[i];
+        table[op.code as usize] = op;
+        i += 1;
+    }
+    table
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(non_camel_case_types)]
 pub enum AddressingMode {
     Immediate,
@@ -339,5 +529,11 @@ pub enum AddressingMode {
     Absolute_Y,
     Indirect_X,
     Indirect_Y,
+    /// ASL, LSR, ROL and ROR on A
+    Accumulator,
+    /// Branches, a signed offset from the next instruction
+    Relative,
+    /// JMP ($nnnn)
+    Indirect,
     NoneAddressing,
 }
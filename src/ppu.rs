@@ -1,14 +1,72 @@
+use crate::coverage::{ChrCoverage, ChrCoverageFlags};
+use crate::frame::{Frame, PixelFormat};
+use crate::mapper::{Nametable, Nrom, SharedMapper};
 use crate::ppu_registers::*;
+use crate::render::{self, Palette};
 use crate::rom::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// PPU dots A12 has to stay low before going high counts as an edge. MMC3
+/// ignores the dips while the nametables are fetched between tiles, by
+/// waiting for a few M2 cycles of it being low.
+const A12_FILTER_DOTS: u64 = 10;
+
+/// How the PPU turns its state into pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Each visible line is drawn as the PPU goes through it, from the
+    /// registers as they are then, so changes the game makes mid-frame
+    /// show up
+    Scanline,
+    /// The whole frame is drawn at once when vblank starts. Faster, but
+    /// only the state at the end of the frame shows.
+    Frame,
+}
+
+/// A layer of the picture, for hiding it from the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    Background,
+    Sprites,
+}
+
+/// A copy of the PPU's registers and memories at one moment, for debuggers
+/// to show and tests to compare against. Changing it changes nothing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PpuState {
+    pub ctrl: u8,
+    pub mask: u8,
+    pub status: u8,
+    /// The current VRAM address
+    pub v: u16,
+    /// The VRAM address being set up through $2005/$2006
+    pub t: u16,
+    pub fine_x: u8,
+    /// Fine Y scroll, from v
+    pub fine_y: u8,
+    /// The $2005/$2006 write toggle; set once the first write is in
+    pub w: bool,
+    pub scanline: u16,
+    pub dot: usize,
+    /// Whether an NMI is waiting for the CPU
+    pub nmi_pending: bool,
+    pub oam_addr: u8,
+    pub oam: [u8; 256],
+    /// The four nametables as the PPU sees them at $2000, $2400, $2800 and
+    /// $2C00, after mirroring
+    pub nametables: [[u8; 0x400]; 4],
+    pub palette: [u8; 32],
+}
 
 pub struct NesPPU {
-    pub chr_rom: Vec<u8>,
-    pub mirroring: Mirroring,
+    mapper: SharedMapper,
     pub ctrl: ControlRegister,
     pub mask: MaskRegister,
     pub status: StatusRegister,
     pub scroll: ScrollRegister,
-    pub addr: AddrRegister,
+    /// The console's 2 KiB of VRAM. Four-screen boards bring their own
+    /// for the other two nametables.
     pub vram: [u8; 2048],
 
     pub oam_addr: u8,
@@ -16,10 +74,48 @@ pub struct NesPPU {
     pub palette_table: [u8; 32],
 
     internal_data_buf: u8,
+    /// Last value driven on the CPU-PPU data bus. Reads of write-only
+    /// registers and of unused status and palette bits see it.
+    io_latch: u8,
 
     pub scanline: u16,
     cycles: usize,
     pub nmi_interrupt: Option<u8>,
+    chr_coverage: Option<ChrCoverage>,
+    /// The sprites on the current line, as sprite evaluation copied them
+    /// from OAM
+    secondary_oam: Vec<[u8; 4]>,
+    /// Whether the first entry evaluation looked at, normally sprite 0, is
+    /// on the line. That one is first in secondary OAM and can hit.
+    sprite_zero_on_line: bool,
+    /// Whether a line shows at most eight sprites, as on hardware. Games
+    /// flicker their sprites to work around it, so lifting it can help.
+    sprite_limit: bool,
+    /// Layers left out of the picture for debugging, on top of whatever
+    /// PPUMASK hides. The game can't tell.
+    hide_background: bool,
+    hide_sprites: bool,
+    /// Dot on the current line at which sprite 0 hits the background
+    sprite_zero_hit_dot: Option<usize>,
+    /// Dot on the current line at which sprite evaluation sets the overflow
+    /// flag
+    sprite_overflow_dot: Option<usize>,
+    render_mode: RenderMode,
+    frame: Frame,
+    /// Pixels of the current line drawn so far, in scanline mode
+    drawn_x: usize,
+    /// Tile columns between the start of the line and where v points. 0
+    /// while v is where the line started; after a $2006 write partway
+    /// through the line, the tile the next fetch reaches.
+    column_offset: usize,
+    /// Dots since power on
+    dots: u64,
+    /// The dot the PPU address bus A12 line went low on, while it's low
+    a12_low_since: Option<u64>,
+    /// The TV system, which decides what the colour emphasis bits do
+    region: Region,
+    /// The RGB colours the output uses
+    palette: Palette,
 }
 
 pub trait PPU {
@@ -38,27 +134,201 @@ pub trait PPU {
 
 impl NesPPU {
     pub fn new_empty_rom() -> Self {
-        NesPPU::new(vec![0; 2048], Mirroring::Horizontal)
+        let mapper = Nrom::new(Rom {
+            prg_rom: vec![],
+            chr_rom: vec![0; 2048],
+            mapper: 0,
+            screen_mirroring: Mirroring::Horizontal,
+            prg_ram_size: 0,
+            battery: false,
+            region: Region::Ntsc,
+            trainer: None,
+            disk_sides: vec![],
+        });
+        NesPPU::new(Rc::new(RefCell::new(mapper)))
     }
 
-    pub fn new(chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+    pub fn new(mapper: SharedMapper) -> Self {
         NesPPU {
-            chr_rom: chr_rom,
-            mirroring: mirroring,
+            mapper,
             ctrl: ControlRegister::new(),
             mask: MaskRegister::new(),
             status: StatusRegister::new(),
             oam_addr: 0,
             scroll: ScrollRegister::new(),
-            addr: AddrRegister::new(),
             vram: [0; 2048],
             oam_data: [0; 64 * 4],
             palette_table: [0; 32],
             internal_data_buf: 0,
+            io_latch: 0,
 
             cycles: 0,
             scanline: 0,
             nmi_interrupt: None,
+            chr_coverage: None,
+            secondary_oam: Vec::with_capacity(64),
+            sprite_zero_on_line: false,
+            sprite_limit: true,
+            hide_background: false,
+            hide_sprites: false,
+            sprite_zero_hit_dot: None,
+            sprite_overflow_dot: None,
+            render_mode: RenderMode::Scanline,
+            frame: Frame::new(),
+            drawn_x: 0,
+            column_offset: 0,
+            dots: 0,
+            a12_low_since: Some(0),
+            region: Region::Ntsc,
+            palette: Palette::default(),
+        }
+    }
+
+    pub fn io_latch(&self) -> u8 {
+        self.io_latch
+    }
+
+    /// Records a value driven on the PPU data bus by a register access
+    pub fn latch_io(&mut self, value: u8) {
+        self.io_latch = value;
+    }
+
+    pub fn render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.render_mode = mode;
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    pub fn sprite_limit(&self) -> bool {
+        self.sprite_limit
+    }
+
+    /// Turns the eight sprites per line limit on or off
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.sprite_limit = enabled;
+    }
+
+    pub fn layer_visible(&self, layer: Layer) -> bool {
+        match layer {
+            Layer::Background => !self.hide_background,
+            Layer::Sprites => !self.hide_sprites,
+        }
+    }
+
+    /// Shows or hides `layer` in the picture. Only the output changes:
+    /// sprite 0 hits and everything else the game sees stay the same.
+    pub fn set_layer_visible(&mut self, layer: Layer, visible: bool) {
+        match layer {
+            Layer::Background => self.hide_background = !visible,
+            Layer::Sprites => self.hide_sprites = !visible,
+        }
+    }
+
+    /// The OAM entries of the sprites on the line being drawn, front to
+    /// back
+    pub fn line_sprites(&self) -> &[[u8; 4]] {
+        &self.secondary_oam
+    }
+
+    pub fn debug_state(&self) -> PpuState {
+        PpuState {
+            ctrl: self.ctrl.bits(),
+            mask: self.mask.bits(),
+            status: self.status.snapshot(),
+            v: self.scroll.v,
+            t: self.scroll.t,
+            fine_x: self.scroll.x,
+            fine_y: self.scroll.fine_y() as u8,
+            w: self.scroll.w,
+            scanline: self.scanline,
+            dot: self.cycles,
+            nmi_pending: self.nmi_interrupt.is_some(),
+            oam_addr: self.oam_addr,
+            oam: self.oam_data,
+            nametables: [0x2000, 0x2400, 0x2800, 0x2C00].map(|addr| self.nametable(addr)),
+            palette: self.palette_table,
+        }
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Changes the colours the picture is drawn in, from the next pixel on
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// Starts or stops drawing the picture as palette entries as well as
+    /// RGB, see `Frame::indexed`
+    pub fn set_indexed_output(&mut self, enabled: bool) {
+        self.frame.set_indexed(enabled);
+    }
+
+    /// Changes how the picture is stored, see `Frame::format`
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.frame.set_format(format);
+    }
+
+    /// The picture drawn so far. Complete once vblank starts, which is
+    /// when the gameloop callback runs.
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+
+    /// Starts or stops recording which CHR-ROM bytes get drawn or read
+    pub fn set_chr_coverage_tracking(&mut self, enabled: bool) {
+        let chr_rom_len = self.mapper.borrow().chr_rom_len();
+        self.chr_coverage = enabled.then(|| ChrCoverage::new(chr_rom_len));
+    }
+
+    pub fn chr_coverage(&self) -> Option<&ChrCoverage> {
+        self.chr_coverage.as_ref()
+    }
+
+    /// Marks the tiles the finished frame drew from. Frames are drawn whole
+    /// from the nametables and OAM, so every tile they reference counts, even
+    /// where it was scrolled out of view.
+    fn mark_rendered_chr(&mut self) {
+        if self.chr_coverage.is_none() {
+            return;
+        }
+        let name_tables = [0x2000, 0x2400, 0x2800, 0x2C00].map(|addr| self.nametable(addr));
+        let Some(coverage) = &mut self.chr_coverage else {
+            return;
+        };
+
+        let mapper = self.mapper.borrow();
+        let mut mark = |tile_addr: u16| {
+            if let Some(offset) = mapper.chr_offset(tile_addr) {
+                coverage.mark_tile(offset, ChrCoverageFlags::RENDERED);
+            }
+        };
+
+        if self.mask.show_background() {
+            let bank = self.ctrl.bknd_pattern_addr();
+            for name_table in &name_tables {
+                for &tile_idx in &name_table[..0x3c0] {
+                    mark(bank + tile_idx as u16 * 16);
+                }
+            }
+        }
+
+        if self.mask.show_sprites() {
+            let bank = self.ctrl.sprt_pattern_addr();
+            for sprite in self.oam_data.chunks(4) {
+                mark(bank + sprite[1] as u16 * 16);
+            }
         }
     }
 
@@ -69,80 +339,510 @@ impl NesPPU {
     // Vertical:
     //   [ A ] [ B ]
     //   [ a ] [ b ]
+    /// Nametable mirroring, which some mappers switch at run time
+    pub fn mirroring(&self) -> Mirroring {
+        self.mapper.borrow().mirroring()
+    }
+
+    /// The 16 bytes of the pattern table tile at `addr`
+    pub fn chr_tile(&self, addr: u16) -> [u8; 16] {
+        let mapper = self.mapper.borrow();
+        std::array::from_fn(|i| mapper.ppu_read(addr + i as u16))
+    }
+
+    /// The tile at `addr` as the renderer fetches it. Boards that watch
+    /// pattern fetches see all 16 once the tile has been read.
+    pub fn fetch_chr_tile(&self, addr: u16) -> [u8; 16] {
+        let tile = self.chr_tile(addr);
+        let mut mapper = self.mapper.borrow_mut();
+        for i in 0..16 {
+            mapper.ppu_fetch(addr + i);
+        }
+        tile
+    }
+
+    /// The two bit planes of row `row` of the tile at `addr`, as the
+    /// renderer fetches them. Boards that watch pattern fetches see both.
+    pub fn fetch_chr_row(&self, addr: u16, row: u16) -> (u8, u8) {
+        let mut mapper = self.mapper.borrow_mut();
+        let low = mapper.ppu_read(addr + row);
+        let high = mapper.ppu_read(addr + 8 + row);
+        mapper.ppu_fetch(addr + row);
+        mapper.ppu_fetch(addr + 8 + row);
+        (low, high)
+    }
+
+    /// Palette entry `index` as the PPU puts it out. In greyscale mode only
+    /// the brightness column is kept, so every colour comes out grey.
+    pub fn palette_color(&self, index: usize) -> u8 {
+        let color = self.palette_table[index];
+        if self.mask.is_grayscale() {
+            color & 0x30
+        } else {
+            color
+        }
+    }
+
+    /// The colour drawn where nothing else is. That's palette entry 0,
+    /// except when rendering is off and v points into palette RAM: then
+    /// the PPU puts out the entry v points at, which demos use to show
+    /// every colour.
+    pub fn backdrop_color(&self) -> u8 {
+        let addr = self.scroll.vram_addr();
+        if !self.rendering_enabled() && addr >= 0x3f00 {
+            self.palette_color(palette_index(addr))
+        } else {
+            self.palette_color(0)
+        }
+    }
+
+    /// Pattern table address of the tile holding row `row` of the sprite
+    /// with OAM entry `sprite`, counting rows after any vertical flip
+    pub fn sprite_tile_addr(&self, sprite: &[u8], row: usize) -> u16 {
+        if self.ctrl.sprite_size() == 16 {
+            // bit 0 picks the pattern table, and the tile below is the next one
+            let bank = (sprite[1] as u16 & 1) * 0x1000;
+            bank + ((sprite[1] & 0xfe) as u16 + (row / 8) as u16) * 16
+        } else {
+            self.ctrl.sprt_pattern_addr() + sprite[1] as u16 * 16
+        }
+    }
+
+    /// The background pixel (0-3) at `x` on the line being drawn, read from
+    /// where v points without the side effects of a fetch
+    fn background_pixel(&self, x: usize) -> u8 {
+        let pos = x + self.scroll.x as usize;
+        let column = self.scroll.coarse_x() + pos / 8;
+        let (table, tile_column) = if column >= 32 {
+            (self.scroll.nametable_addr() ^ 0x400, column - 32)
+        } else {
+            (self.scroll.nametable_addr(), column)
+        };
+        let tile_idx =
+            self.read_nametable(table + (self.scroll.coarse_y() * 32 + tile_column) as u16);
+        let addr =
+            self.ctrl.bknd_pattern_addr() + tile_idx as u16 * 16 + self.scroll.fine_y() as u16;
+        let mapper = self.mapper.borrow();
+        let shift = 7 - pos % 8;
+        ((mapper.ppu_read(addr) >> shift) & 1) | (((mapper.ppu_read(addr + 8) >> shift) & 1) << 1)
+    }
+
+    /// The dot on the line just starting at which an opaque pixel of sprite
+    /// 0 first lands on an opaque background pixel. There's no hit on the
+    /// last pixel, nor on the first eight while either layer is masked off
+    /// there.
+    fn find_sprite_zero_hit(&self) -> Option<usize> {
+        if !self.mask.show_background() || !self.mask.show_sprites() {
+            return None;
+        }
+        if !self.sprite_zero_on_line {
+            return None;
+        }
+        let sprite = &self.secondary_oam[0];
+        let height = self.ctrl.sprite_size() as usize;
+        let mut row = sprite_row(sprite[0], self.scanline as usize, height)?;
+        if sprite[2] >> 7 & 1 == 1 {
+            row = height - 1 - row;
+        }
+        let flip_horizontal = sprite[2] >> 6 & 1 == 1;
+        let tile_addr = self.sprite_tile_addr(sprite, row) + (row % 8) as u16;
+        let (upper, lower) = {
+            let mapper = self.mapper.borrow();
+            (mapper.ppu_read(tile_addr), mapper.ppu_read(tile_addr + 8))
+        };
+        let left_masked =
+            !self.mask.leftmost_8pxl_background() || !self.mask.leftmost_8pxl_sprite();
+
+        (0..8).find_map(|bit| {
+            let x = sprite[3] as usize + bit;
+            let shift = if flip_horizontal { bit } else { 7 - bit };
+            let opaque = ((upper | lower) >> shift) & 1 == 1;
+            let hit = opaque && x < 255 && !(left_masked && x < 8) && self.background_pixel(x) != 0;
+            // pixel x comes out on dot x + 1
+            hit.then_some(x + 1)
+        })
+    }
+
+    /// Copies the sprites that cover the line just starting to secondary
+    /// OAM, the first eight in OAM order unless the limit is off.
+    /// Evaluation starts at OAMADDR, which is normally 0 by then. A game
+    /// that leaves it elsewhere gets entries read from there on, out of
+    /// step with the real ones if it isn't a multiple of four.
+    fn evaluate_sprites(&mut self) {
+        let y = self.scanline as usize;
+        let height = self.ctrl.sprite_size() as usize;
+        let limit = if self.sprite_limit { 8 } else { 64 };
+        let start = self.oam_addr as usize;
+        let mut sprites = std::mem::take(&mut self.secondary_oam);
+        sprites.clear();
+        sprites.extend(
+            (start..256)
+                .step_by(4)
+                .map(|addr| std::array::from_fn(|i| self.oam_data[(addr + i) & 0xff]))
+                .filter(|sprite: &[u8; 4]| sprite_row(sprite[0], y, height).is_some())
+                .take(limit),
+        );
+        self.sprite_zero_on_line = sprite_row(self.oam_data[start], y, height).is_some();
+        self.secondary_oam = sprites;
+    }
+
+    /// The OAM byte at `addr` as $2004 reads it. The attribute bytes have
+    /// no bits 2-4, so those read back as 0.
+    fn oam_byte(&self, addr: u8) -> u8 {
+        let value = self.oam_data[addr as usize];
+        if addr & 3 == 2 {
+            value & 0xe3
+        } else {
+            value
+        }
+    }
+
+    /// The dot on the line just starting at which sprite evaluation finds a
+    /// ninth sprite for the next line, if it does. Each OAM entry takes two
+    /// dots to check from dot 65 on, and eight if it's copied to secondary
+    /// OAM.
+    ///
+    /// Once eight are found the hardware keeps going with a bug: for every
+    /// entry out of range it steps to the next byte as well as to the next
+    /// entry, so it compares tile numbers, attributes and X positions against
+    /// the line. That both misses real overflows and reports false ones.
+    fn find_sprite_overflow(&self) -> Option<usize> {
+        if !self.rendering_enabled() {
+            return None;
+        }
+        let next_line = self.scanline as usize + 1;
+        let height = self.ctrl.sprite_size() as usize;
+        let in_range = |value: u8| sprite_row(value, next_line, height).is_some();
+
+        let mut dot = 65;
+        let mut found = 0;
+        let mut n = 0;
+        while n < 64 && found < 8 {
+            if in_range(self.oam_data[n * 4]) {
+                found += 1;
+                dot += 8;
+            } else {
+                dot += 2;
+            }
+            n += 1;
+        }
+
+        let mut m = 0;
+        while n < 64 {
+            if in_range(self.oam_data[n * 4 + m]) {
+                return Some(dot);
+            }
+            n += 1;
+            m = (m + 1) % 4;
+            dot += 2;
+        }
+        None
+    }
+
     pub fn mirror_vram_addr(&self, addr: u16) -> u16 {
         let mirrored_vram = addr & 0b10111111111111; // mirror down 0x3000-0x3eff to 0x2000 - 0x2eff
         let vram_index = mirrored_vram - 0x2000; // to vram vector
         let name_table = vram_index / 0x400;
-        match (&self.mirroring, name_table) {
+        if let Some(Nametable::Vram(page)) = self.mapper.borrow().nametable(name_table as usize) {
+            return page as u16 * 0x400 + vram_index % 0x400;
+        }
+        match (&self.mirroring(), name_table) {
             (Mirroring::Vertical, 2) | (Mirroring::Vertical, 3) => vram_index - 0x800,
             (Mirroring::Horizontal, 2) => vram_index - 0x400,
             (Mirroring::Horizontal, 1) => vram_index - 0x400,
             (Mirroring::Horizontal, 3) => vram_index - 0x800,
+            (Mirroring::SingleScreenLower, _) => vram_index % 0x400,
+            (Mirroring::SingleScreenUpper, _) => 0x400 + vram_index % 0x400,
+            // the cartridge maps four-screen nametables, see `FourScreen`
+            (Mirroring::FourScreen, _) => vram_index % 0x800,
             _ => vram_index,
         }
     }
 
+    /// Reads the nametable byte at `addr` in $2000-$2FFF, from VRAM or from
+    /// wherever the cartridge maps that slot
+    pub fn read_nametable(&self, addr: u16) -> u8 {
+        let table = (addr as usize & 0x0FFF) / 0x400;
+        let mapper = self.mapper.borrow();
+        match mapper.nametable(table) {
+            Some(Nametable::Cartridge) => mapper.nametable_read(addr),
+            _ => self.vram[self.mirror_vram_addr(addr) as usize],
+        }
+    }
+
+    /// The nametable, attribute table included, that the PPU sees at `addr`
+    /// ($2000, $2400, $2800 or $2C00)
+    pub fn nametable(&self, addr: u16) -> [u8; 0x400] {
+        std::array::from_fn(|i| self.read_nametable(addr + i as u16))
+    }
+
+    fn write_nametable(&mut self, addr: u16, value: u8) {
+        let table = (addr as usize & 0x0FFF) / 0x400;
+        let source = self.mapper.borrow().nametable(table);
+        match source {
+            Some(Nametable::Cartridge) => self.mapper.borrow_mut().nametable_write(addr, value),
+            _ => self.vram[self.mirror_vram_addr(addr) as usize] = value,
+        }
+    }
+
+    /// While rendering, a $2007 access bumps v the way the tile fetches
+    /// do, across and down at once, instead of by the set increment
     fn increment_vram_addr(&mut self) {
-        self.addr.increment(self.ctrl.vram_addr_increment());
+        if self.rendering_line() {
+            self.scroll.increment_x();
+            self.scroll.increment_y();
+        } else {
+            self.scroll.increment(self.ctrl.vram_addr_increment());
+        }
+    }
+
+    /// Whether the PPU is fetching tiles, and so moving v, right now
+    fn rendering_line(&self) -> bool {
+        self.rendering_enabled() && (self.scanline < 240 || self.scanline == 261)
     }
 
     pub fn tick(&mut self, cycles: u8) -> bool {
+        let dot_before = self.cycles;
         self.cycles += cycles as usize;
-        if self.cycles >= 341 {
-            if self.is_sprite_0_hit(self.cycles) {
-                self.status.set_sprite_zero_hit(true);
+        self.dots += cycles as u64;
+        if self.rendering_line() {
+            self.drive_pattern_fetches(dot_before);
+        }
+        if self.cycles >= 256 {
+            self.catch_up();
+        }
+        if self.rendering_line() {
+            // lines are drawn from v as it was when they started, so v only
+            // needs to end up where the fetches for the next one leave it
+            if dot_before < 256 && self.cycles >= 256 {
+                self.scroll.increment_y();
             }
-
+            if dot_before < 257 && self.cycles >= 257 {
+                self.scroll.copy_x();
+            }
+            // the sprite tile fetches leave OAMADDR at 0
+            if dot_before <= 320 && self.cycles >= 257 {
+                self.oam_addr = 0;
+            }
+            if self.scanline == 261 && dot_before <= 304 && self.cycles >= 280 {
+                self.scroll.copy_y();
+            }
+        }
+        if self
+            .sprite_zero_hit_dot
+            .is_some_and(|dot| self.cycles >= dot)
+        {
+            self.status.set_sprite_zero_hit(true);
+            self.sprite_zero_hit_dot = None;
+        }
+        if self
+            .sprite_overflow_dot
+            .is_some_and(|dot| self.cycles >= dot)
+        {
+            self.status.set_sprite_overflow(true);
+            self.sprite_overflow_dot = None;
+        }
+        if self.cycles >= 341 {
             self.cycles = self.cycles - 341;
             self.scanline += 1;
 
             if self.scanline == 241 {
+                if self.render_mode == RenderMode::Frame {
+                    self.draw(render::render);
+                }
+                self.mark_rendered_chr();
                 self.status.set_vblank_status(true);
-                self.status.set_sprite_zero_hit(false);
                 if self.ctrl.generate_vblank_nmi() {
                     self.nmi_interrupt = Some(1);
                 }
             }
 
-            if self.scanline >= 262 {
+            if self.scanline == 261 {
+                self.status.set_sprite_zero_hit(false);
+                self.status.set_sprite_overflow(false);
+                // a 2C02 bug: with OAMADDR at 8 or more as rendering starts,
+                // the eight bytes from there get copied over the first eight
+                if self.rendering_enabled() && self.oam_addr >= 8 {
+                    let from = (self.oam_addr & 0xf8) as usize;
+                    self.oam_data.copy_within(from..from + 8, 0);
+                }
+            }
+
+            let frame_done = self.scanline >= 262;
+            if frame_done {
                 self.scanline = 0;
                 self.nmi_interrupt = None;
-                self.status.set_sprite_zero_hit(false);
                 self.status.reset_vblank_status();
-                return true;
             }
+
+            if self.scanline < 240 {
+                self.evaluate_sprites();
+                self.sprite_zero_hit_dot = self.find_sprite_zero_hit();
+                self.sprite_overflow_dot = self.find_sprite_overflow();
+                self.drawn_x = 0;
+                self.column_offset = 0;
+            }
+            // the dots that spilled over into the new line
+            if self.rendering_line() {
+                self.drive_pattern_fetches(0);
+            }
+            return frame_done;
         }
         return false;
     }
 
+    /// Puts `addr` on the PPU address bus on dot `at`, telling the mapper
+    /// when that raises A12 after a long enough low stretch
+    fn drive_ppu_bus(&mut self, addr: u16, at: u64) {
+        if addr & 0x1000 == 0 {
+            self.a12_low_since.get_or_insert(at);
+            return;
+        }
+        if let Some(since) = self.a12_low_since.take() {
+            if at.saturating_sub(since) >= A12_FILTER_DOTS {
+                self.mapper.borrow_mut().a12_rising_edge();
+            }
+        }
+    }
+
+    /// Drives the bus as the fetches of a rendered line would between
+    /// `dot_before` and now. Only the pattern table each group of fetches
+    /// goes to matters for A12: the background from dot 1, eight sprite
+    /// slots from dot 257 and the first two tiles of the next line from
+    /// dot 321. The nametable fetches at the end of the line pull it low.
+    fn drive_pattern_fetches(&mut self, dot_before: usize) {
+        let now = self.cycles;
+        let crossed = move |dot: usize| dot_before < dot && now >= dot;
+        let line_start = self.dots - self.cycles as u64;
+
+        if crossed(1) {
+            self.drive_ppu_bus(self.ctrl.bknd_pattern_addr(), line_start + 1);
+        }
+        for slot in 0..8 {
+            // each slot fetches its pattern on its fifth dot
+            let dot = 261 + slot * 8;
+            if crossed(dot) {
+                let addr = self.sprite_slot_addr(slot);
+                self.drive_ppu_bus(addr, line_start + dot as u64);
+            }
+        }
+        if crossed(325) {
+            self.drive_ppu_bus(self.ctrl.bknd_pattern_addr(), line_start + 325);
+        }
+        if crossed(337) {
+            self.drive_ppu_bus(0x2000, line_start + 337);
+        }
+    }
+
+    /// Pattern table the sprite fetches of slot `slot` (0-7) go to on this
+    /// line. 8x16 sprites pick theirs per tile, and empty slots fetch tile
+    /// $FF.
+    fn sprite_slot_addr(&self, slot: usize) -> u16 {
+        if self.ctrl.sprite_size() != 16 {
+            return self.ctrl.sprt_pattern_addr();
+        }
+        let tile = match self.secondary_oam.get(slot) {
+            Some(sprite) if self.scanline < 240 => sprite[1],
+            _ => 0xff,
+        };
+        (tile as u16 & 1) * 0x1000
+    }
+
+    /// Draws the current line up to the dot the PPU has reached, with the
+    /// registers as they are now. Register writes call this first, so a
+    /// change partway through a line shows from there on, and one made
+    /// between lines, like a status bar split, from the next line. The bus
+    /// calls it before cartridge writes too, which can switch CHR banks or
+    /// mirroring.
+    pub fn catch_up(&mut self) {
+        if self.render_mode != RenderMode::Scanline || self.scanline >= 240 {
+            return;
+        }
+        let end = self.cycles.min(256);
+        if end <= self.drawn_x {
+            return;
+        }
+        let (line, start) = (self.scanline as usize, self.drawn_x);
+        self.draw(|ppu, frame| render::render_scanline(ppu, line, start..end, frame));
+        self.drawn_x = end;
+    }
+
+    /// Runs `render` on the PPU's own frame
+    fn draw(&mut self, render: impl FnOnce(&NesPPU, &mut Frame)) {
+        let mut frame = std::mem::replace(&mut self.frame, Frame::with_size(0, 0));
+        render(self, &mut frame);
+        self.frame = frame;
+    }
+
+    /// Whether the PPU fetches tiles at all, which is what drives scanline
+    /// counters on the cartridge
+    pub fn rendering_enabled(&self) -> bool {
+        self.mask.show_background() || self.mask.show_sprites()
+    }
+
+    /// Scanlines 241-260, between the end of the visible frame and the
+    /// pre-render line
+    pub fn in_vblank(&self) -> bool {
+        (241..261).contains(&self.scanline)
+    }
+
+    /// Current dot (PPU cycle) within the scanline
+    pub fn dot(&self) -> usize {
+        self.cycles
+    }
+
+    /// How many tiles into the line the background fetches were when v
+    /// was last set, see `render::render_scanline`
+    pub fn column_offset(&self) -> usize {
+        self.column_offset
+    }
+
     pub fn poll_nmi_interrupt(&mut self) -> Option<u8> {
         self.nmi_interrupt.take()
     }
+}
 
-    fn is_sprite_0_hit(&self, cycle: usize) -> bool {
-        let y = self.oam_data[0] as usize;
-        let x = self.oam_data[3] as usize;
-        (y == self.scanline as usize) && x <= cycle && self.mask.show_sprites()
+/// The row of a sprite that shows on `line`, if the sprite covers it, for
+/// sprites `height` lines tall. OAM holds the line above the sprite's top
+/// row: evaluation on one line picks the sprites for the next, comparing
+/// against the line it runs on, so a sprite shows from Y + 1 to Y + height.
+pub fn sprite_row(y: u8, line: usize, height: usize) -> Option<usize> {
+    let top = y as usize + 1;
+    (top..top + height).contains(&line).then(|| line - top)
+}
+
+/// Index into the palette table for `addr` in $3F00-$3FFF. The 32 bytes
+/// repeat every $20, and $3F10/$3F14/$3F18/$3F1C are the same bytes as
+/// $3F00/$3F04/$3F08/$3F0C: the sprite palettes share the backdrop.
+pub fn palette_index(addr: u16) -> usize {
+    let index = addr as usize & 0x1f;
+    if index & 0x13 == 0x10 {
+        index & 0x0f
+    } else {
+        index
     }
 }
 
 impl PPU for NesPPU {
     fn write_to_ctrl(&mut self, value: u8) {
+        self.catch_up();
         let before_nmi_status = self.ctrl.generate_vblank_nmi();
         self.ctrl.update(value);
+        self.scroll.write_ctrl(value);
         if !before_nmi_status && self.ctrl.generate_vblank_nmi() && self.status.is_in_vblank() {
             self.nmi_interrupt = Some(1);
         }
     }
 
     fn write_to_mask(&mut self, value: u8) {
+        self.catch_up();
         self.mask.update(value);
     }
 
     fn read_status(&mut self) -> u8 {
-        let data = self.status.snapshot();
+        // only the top three bits are driven
+        let data = (self.status.snapshot() & 0b1110_0000) | (self.io_latch & 0b0001_1111);
         self.status.reset_vblank_status();
-        self.addr.reset_latch();
         self.scroll.reset_latch();
         data
     }
@@ -152,69 +852,91 @@ impl PPU for NesPPU {
     }
 
     fn write_to_oam_data(&mut self, value: u8) {
+        if self.rendering_line() {
+            // OAM is busy with sprite evaluation, so the write is lost and
+            // only bumps the sprite part of the address
+            self.oam_addr = self.oam_addr.wrapping_add(4);
+            return;
+        }
         self.oam_data[self.oam_addr as usize] = value;
         self.oam_addr = self.oam_addr.wrapping_add(1);
     }
 
     fn read_oam_data(&self) -> u8 {
-        self.oam_data[self.oam_addr as usize]
+        // secondary OAM is being cleared to $FF over the first 64 dots of
+        // a visible line, and that's what reads see
+        if self.rendering_line() && self.scanline < 240 && (1..=64).contains(&self.cycles) {
+            return 0xff;
+        }
+        self.oam_byte(self.oam_addr)
     }
 
     fn write_to_scroll(&mut self, value: u8) {
-        self.scroll.write(value);
+        self.catch_up();
+        self.scroll.write_scroll(value);
     }
 
     fn write_to_ppu_addr(&mut self, value: u8) {
-        self.addr.update(value);
+        self.catch_up();
+        self.scroll.write_addr(value);
+        // the second write puts the new address on the bus
+        if !self.scroll.w {
+            self.drive_ppu_bus(self.scroll.vram_addr(), self.dots);
+            if self.rendering_line() && self.cycles < 256 {
+                // the fetches are two tiles ahead of the dot, and go on
+                // from the new address with the next one
+                self.column_offset = self.cycles / 8 + 2;
+            }
+        }
     }
 
     fn write_to_data(&mut self, value: u8) {
-        let addr = self.addr.get();
+        self.catch_up();
+        let addr = self.scroll.vram_addr();
         match addr {
-            0..=0x1fff => println!("attempt to write to chr rom space {}", addr),
-            0x2000..=0x2fff => {
-                self.vram[self.mirror_vram_addr(addr) as usize] = value;
-            }
-            0x3000..=0x3eff => unimplemented!("addr {} shouldn't be used in reallity", addr),
-
-            //Addresses $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C
-            0x3f10 | 0x3f14 | 0x3f18 | 0x3f1c => {
-                let add_mirror = addr - 0x10;
-                self.palette_table[(add_mirror - 0x3f00) as usize] = value;
-            }
-            0x3f00..=0x3fff => {
-                self.palette_table[(addr - 0x3f00) as usize] = value;
-            }
+            0..=0x1fff => self.mapper.borrow_mut().ppu_write(addr, value),
+            // $3000-$3EFF mirrors the nametables, four-screen RAM included
+            0x2000..=0x3eff => self.write_nametable(addr & 0x2fff, value),
+            0x3f00..=0x3fff => self.palette_table[palette_index(addr)] = value,
             _ => panic!("unexpected access to mirrored space {}", addr),
         }
         self.increment_vram_addr();
+        self.drive_ppu_bus(addr, self.dots);
     }
 
     fn read_data(&mut self) -> u8 {
-        let addr = self.addr.get();
+        self.catch_up();
+        let addr = self.scroll.vram_addr();
 
         self.increment_vram_addr();
+        self.drive_ppu_bus(addr, self.dots);
 
         match addr {
             0..=0x1fff => {
                 let result = self.internal_data_buf;
-                self.internal_data_buf = self.chr_rom[addr as usize];
+                let mut mapper = self.mapper.borrow_mut();
+                self.internal_data_buf = mapper.ppu_read(addr);
+                if let (Some(coverage), Some(offset)) =
+                    (&mut self.chr_coverage, mapper.chr_offset(addr))
+                {
+                    coverage.mark(offset, ChrCoverageFlags::READ);
+                }
+                mapper.ppu_fetch(addr);
                 result
             }
-            0x2000..=0x2fff => {
+            0x2000..=0x3eff => {
                 let result = self.internal_data_buf;
-                self.internal_data_buf = self.vram[self.mirror_vram_addr(addr) as usize];
+                self.internal_data_buf = self.read_nametable(addr & 0x2fff);
                 result
             }
-            0x3000..=0x3eff => unimplemented!("addr {} shouldn't be used in reallity", addr),
 
-            //Addresses $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C
-            0x3f10 | 0x3f14 | 0x3f18 | 0x3f1c => {
-                let add_mirror = addr - 0x10;
-                self.palette_table[(add_mirror - 0x3f00) as usize]
+            // palette reads don't go through the buffer. It gets the nametable
+            // byte the palette sits on top of instead.
+            // palette entries are six bits wide, the rest comes from the bus
+            0x3f00..=0x3fff => {
+                self.internal_data_buf = self.read_nametable(addr & 0x2fff);
+                (self.io_latch & 0b1100_0000) | self.palette_color(palette_index(addr))
             }
-
-            0x3f00..=0x3fff => self.palette_table[(addr - 0x3f00) as usize],
             _ => panic!("unexpected access to mirrored space {}", addr),
         }
     }
@@ -227,54 +949,192 @@ impl PPU for NesPPU {
     }
 }
 
-pub struct AddrRegister {
-    value: (u8, u8),
-    hi_ptr: bool,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl AddrRegister {
-    pub fn new() -> Self {
-        AddrRegister {
-            value: (0, 0), // high byte first, lo byte second
-            hi_ptr: true,
+    /// Background palette 0, by pixel value
+    const COLORS: [u8; 4] = [0x0f, 0x16, 0x2a, 0x12];
+
+    /// A PPU on an NROM board whose tile n is solid pixel value n, for
+    /// n up to 3
+    fn test_ppu(mirroring: Mirroring) -> NesPPU {
+        let mut chr_rom = vec![0; 0x2000];
+        for tile in 1..4 {
+            for row in 0..8 {
+                chr_rom[tile * 16 + row] = if tile & 1 != 0 { 0xff } else { 0 };
+                chr_rom[tile * 16 + 8 + row] = if tile & 2 != 0 { 0xff } else { 0 };
+            }
         }
+        let mapper = Nrom::new(Rom {
+            prg_rom: vec![0; 0x4000],
+            chr_rom,
+            mapper: 0,
+            screen_mirroring: mirroring,
+            prg_ram_size: 0,
+            battery: false,
+            region: Region::Ntsc,
+            trainer: None,
+            disk_sides: vec![],
+        });
+        let mut ppu = NesPPU::new(Rc::new(RefCell::new(mapper)));
+        ppu.set_indexed_output(true);
+        ppu.palette_table[..4].copy_from_slice(&COLORS);
+        ppu
     }
 
-    fn set(&mut self, data: u16) {
-        self.value.0 = (data >> 8) as u8;
-        self.value.1 = (data & 0xff) as u8;
+    /// Fills the nametable at `addr` with `tile`, and its first column
+    /// with `first_column`
+    fn fill_nametable(ppu: &mut NesPPU, addr: u16, tile: u8, first_column: u8) {
+        for i in 0..0x3c0 {
+            let tile = if i % 32 == 0 { first_column } else { tile };
+            ppu.write_nametable(addr + i, tile);
+        }
     }
 
-    pub fn update(&mut self, data: u8) {
-        if self.hi_ptr {
-            self.value.0 = data;
-        } else {
-            self.value.1 = data;
+    /// Runs the PPU one dot at a time until it gets to `dot` on `line`
+    fn run_to(ppu: &mut NesPPU, line: u16, dot: usize) {
+        while ppu.scanline != line || ppu.cycles < dot {
+            ppu.tick(1);
         }
+    }
 
-        if self.get() > 0x3fff {
-            //mirror down addr above 0x3fff
-            self.set(self.get() & 0b11111111111111);
-        }
-        self.hi_ptr = !self.hi_ptr;
+    /// Turns the background on and runs through the pre-render line, so
+    /// the next frame starts from the scroll set by `ctrl` and `$2005`
+    fn start_frame(ppu: &mut NesPPU, ctrl: u8, scroll_x: u8, scroll_y: u8) {
+        ppu.write_to_ctrl(ctrl);
+        ppu.write_to_scroll(scroll_x);
+        ppu.write_to_scroll(scroll_y);
+        ppu.write_to_mask(0b0000_1010);
+        run_to(ppu, 261, 0);
+        run_to(ppu, 0, 0);
     }
 
-    pub fn increment(&mut self, inc: u8) {
-        let lo = self.value.1;
-        self.value.1 = self.value.1.wrapping_add(inc);
-        if lo > self.value.1 {
-            self.value.0 = self.value.0.wrapping_add(1);
-        }
-        if self.get() > 0x3fff {
-            self.set(self.get() & 0b11111111111111); //mirror down addr above 0x3fff
+    /// The pixel values across line `y`
+    fn line(ppu: &NesPPU, y: usize) -> Vec<usize> {
+        let indexed = ppu.frame().indexed.as_ref().unwrap();
+        indexed[y * 256..(y + 1) * 256]
+            .iter()
+            .map(|&index| {
+                COLORS
+                    .iter()
+                    .position(|&c| c as u16 == index & 0x3f)
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    /// `count` pixels of value `value`
+    fn run(value: usize, count: usize) -> Vec<usize> {
+        vec![value; count]
+    }
+
+    #[test]
+    fn status_bar_split_with_ppuctrl_and_ppuscroll() {
+        let mut ppu = test_ppu(Mirroring::Vertical);
+        fill_nametable(&mut ppu, 0x2000, 1, 1);
+        fill_nametable(&mut ppu, 0x2400, 2, 2);
+        start_frame(&mut ppu, 0, 0, 0);
+
+        // just after the horizontal position is copied at dot 257, so the
+        // new one waits for the end of the next line
+        run_to(&mut ppu, 31, 260);
+        ppu.write_to_ctrl(0b01);
+        ppu.write_to_scroll(20);
+        ppu.write_to_scroll(0);
+        run_to(&mut ppu, 241, 0);
+
+        assert_eq!(line(&ppu, 0), run(1, 256));
+        assert_eq!(line(&ppu, 31), run(1, 256));
+        // fine X takes effect at once, coarse X and the nametable don't, so
+        // the line moves 4 pixels into the next nametable
+        assert_eq!(line(&ppu, 32), [run(1, 252), run(2, 4)].concat());
+        // 20 pixels into $2400, then into $2000 for the last 20
+        let split = [run(2, 236), run(1, 20)].concat();
+        assert_eq!(line(&ppu, 33), split);
+        assert_eq!(line(&ppu, 239), split);
+    }
+
+    #[test]
+    fn split_with_ppuaddr_and_ppuscroll() {
+        let mut ppu = test_ppu(Mirroring::Horizontal);
+        fill_nametable(&mut ppu, 0x2000, 1, 1);
+        fill_nametable(&mut ppu, 0x2800, 2, 3);
+        // the first two tile rows of $2800 shouldn't show
+        for i in 0..64 {
+            ppu.write_nametable(0x2800 + i, 0);
         }
+        start_frame(&mut ppu, 0, 0, 0);
+
+        // $2800 from X = 8, Y = 16, in hblank: nametable and Y through
+        // $2006, Y again and X through $2005, then the low byte of $2006
+        let (x, y) = (8u8, 16u8);
+        run_to(&mut ppu, 99, 270);
+        ppu.write_to_ppu_addr(0b10 << 2 | (y & 0xc0) >> 6);
+        ppu.write_to_scroll(y);
+        ppu.write_to_scroll(x);
+        ppu.write_to_ppu_addr((y & 0x38) << 2 | x >> 3);
+        run_to(&mut ppu, 241, 0);
+
+        assert_eq!(line(&ppu, 99), run(1, 256));
+        // $2C00 mirrors $2800, so its first column comes in on the right
+        let split = [run(2, 248), run(3, 8)].concat();
+        assert_eq!(line(&ppu, 100), split);
+        assert_eq!(line(&ppu, 239), split);
     }
 
-    pub fn reset_latch(&mut self) {
-        self.hi_ptr = true;
+    #[test]
+    fn ppuaddr_write_partway_through_a_line() {
+        let mut ppu = test_ppu(Mirroring::Vertical);
+        fill_nametable(&mut ppu, 0x2000, 1, 1);
+        fill_nametable(&mut ppu, 0x2400, 2, 3);
+        start_frame(&mut ppu, 0, 0, 0);
+
+        run_to(&mut ppu, 50, 128);
+        ppu.write_to_ppu_addr(0x04);
+        ppu.write_to_ppu_addr(0x00);
+        run_to(&mut ppu, 241, 0);
+
+        // $2400 starts with the first tile fetched after the write, two
+        // tiles on from the dot it was made on
+        let split = [run(1, 144), run(3, 8), run(2, 104)].concat();
+        assert_eq!(line(&ppu, 50), split);
+        // and the next line starts there, from t
+        assert_eq!(line(&ppu, 51), [run(3, 8), run(2, 248)].concat());
+    }
+
+    #[test]
+    fn sprite_zero_hits_on_the_line_after_its_y() {
+        let mut ppu = test_ppu(Mirroring::Horizontal);
+        fill_nametable(&mut ppu, 0x2000, 1, 1);
+        ppu.oam_data[..4].copy_from_slice(&[29, 1, 0, 40]);
+        start_frame(&mut ppu, 0, 0, 0);
+        ppu.write_to_mask(0b0001_1110);
+
+        run_to(&mut ppu, 30, 0);
+        assert_eq!(ppu.status.snapshot() & 0x40, 0);
+        // the sprite's first row is on line 30, and its first pixel, at
+        // X = 40, is drawn on dot 41
+        run_to(&mut ppu, 30, 40);
+        assert_eq!(ppu.status.snapshot() & 0x40, 0);
+        run_to(&mut ppu, 30, 41);
+        assert_eq!(ppu.status.snapshot() & 0x40, 0x40);
     }
 
-    pub fn get(&self) -> u16 {
-        ((self.value.0 as u16) << 8) | (self.value.1 as u16)
+    #[test]
+    fn overflow_is_set_on_the_line_above_the_ninth_sprite() {
+        let mut ppu = test_ppu(Mirroring::Horizontal);
+        ppu.oam_data.fill(0xff);
+        for sprite in ppu.oam_data[..9 * 4].chunks_mut(4) {
+            sprite.copy_from_slice(&[49, 1, 0, 0]);
+        }
+        start_frame(&mut ppu, 0, 0, 0);
+        ppu.write_to_mask(0b0001_1110);
+
+        run_to(&mut ppu, 49, 0);
+        assert_eq!(ppu.status.snapshot() & 0x20, 0);
+        // evaluation on line 49 finds the sprites for line 50
+        run_to(&mut ppu, 50, 0);
+        assert_eq!(ppu.status.snapshot() & 0x20, 0x20);
     }
 }
@@ -233,31 +233,154 @@ impl StatusRegister {
     }
 }
 
+// The PPU's internal scroll registers, shared by $2000, $2005 and $2006:
+// v is the current VRAM address, t the one being set up, x the fine X
+// scroll and w the toggle that picks between first and second writes.
+// While rendering, v walks across and down the nametables.
+//
+// yyy NN YYYYY XXXXX
+// ||| || ||||| +++++-- coarse X scroll
+// ||| || +++++-------- coarse Y scroll
+// ||| ++-------------- nametable select
+// +++----------------- fine Y scroll
+const COARSE_X: u16 = 0x001F;
+const COARSE_Y: u16 = 0x03E0;
+const NAMETABLE_X: u16 = 0x0400;
+const NAMETABLE_Y: u16 = 0x0800;
+const FINE_Y: u16 = 0x7000;
+
 pub struct ScrollRegister {
-    pub scroll_x: u8,
-    pub scroll_y: u8,
-    pub latch: bool,
+    pub v: u16,
+    pub t: u16,
+    pub x: u8,
+    pub w: bool,
 }
 
 impl ScrollRegister {
     pub fn new() -> Self {
         ScrollRegister {
-            scroll_x: 0,
-            scroll_y: 0,
-            latch: false,
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+        }
+    }
+
+    /// $2000 picks the nametable
+    pub fn write_ctrl(&mut self, data: u8) {
+        self.t = (self.t & !(NAMETABLE_X | NAMETABLE_Y)) | ((data as u16 & 0b11) << 10);
+    }
+
+    /// $2005: X scroll, then Y scroll
+    pub fn write_scroll(&mut self, data: u8) {
+        if !self.w {
+            self.t = (self.t & !COARSE_X) | (data as u16 >> 3);
+            self.x = data & 0b111;
+        } else {
+            self.t = (self.t & !(COARSE_Y | FINE_Y))
+                | ((data as u16 & 0b1111_1000) << 2)
+                | ((data as u16 & 0b111) << 12);
         }
+        self.w = !self.w;
     }
 
-    pub fn write(&mut self, data: u8) {
-        if !self.latch {
-            self.scroll_x = data;
+    /// $2006: high byte, then low byte, which also moves t into v. Bit 14
+    /// of t is cleared by the first write.
+    pub fn write_addr(&mut self, data: u8) {
+        if !self.w {
+            self.t = (self.t & 0x00FF) | ((data as u16 & 0b0011_1111) << 8);
         } else {
-            self.scroll_y = data;
+            self.t = (self.t & 0xFF00) | data as u16;
+            self.v = self.t;
         }
-        self.latch = !self.latch;
+        self.w = !self.w;
     }
 
+    /// Reading $2002 resets the write toggle
     pub fn reset_latch(&mut self) {
-        self.latch = false;
+        self.w = false;
+    }
+
+    /// The address $2007 accesses
+    pub fn vram_addr(&self) -> u16 {
+        self.v & 0x3FFF
+    }
+
+    /// Moves v on after a $2007 access outside rendering
+    pub fn increment(&mut self, inc: u8) {
+        self.v = self.v.wrapping_add(inc as u16) & 0x7FFF;
+    }
+
+    /// Moves v to the next tile to the right, into the next nametable
+    /// across after the last column
+    pub fn increment_x(&mut self) {
+        if self.v & COARSE_X == 31 {
+            self.v = (self.v & !COARSE_X) ^ NAMETABLE_X;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    /// Moves v down a pixel row. Row 29 is the last one of tiles, so after
+    /// it v goes on to the nametable below; rows 30 and 31 are the
+    /// attribute table, and wrap without switching nametables.
+    pub fn increment_y(&mut self) {
+        if self.v & FINE_Y != FINE_Y {
+            self.v += 1 << 12;
+            return;
+        }
+        self.v &= !FINE_Y;
+        let coarse_y = match (self.v & COARSE_Y) >> 5 {
+            29 => {
+                self.v ^= NAMETABLE_Y;
+                0
+            }
+            31 => 0,
+            row => row + 1,
+        };
+        self.v = (self.v & !COARSE_Y) | (coarse_y << 5);
+    }
+
+    /// Dot 257 of each rendered line: back to the left edge
+    pub fn copy_x(&mut self) {
+        let mask = COARSE_X | NAMETABLE_X;
+        self.v = (self.v & !mask) | (self.t & mask);
+    }
+
+    /// Dots 280-304 of the pre-render line: back to the top
+    pub fn copy_y(&mut self) {
+        let mask = COARSE_Y | NAMETABLE_Y | FINE_Y;
+        self.v = (self.v & !mask) | (self.t & mask);
+    }
+
+    pub fn coarse_x(&self) -> usize {
+        (self.v & COARSE_X) as usize
+    }
+
+    pub fn coarse_y(&self) -> usize {
+        ((self.v & COARSE_Y) >> 5) as usize
+    }
+
+    pub fn fine_y(&self) -> usize {
+        ((self.v & FINE_Y) >> 12) as usize
+    }
+
+    /// The nametable v is in, as $2000, $2400, $2800 or $2C00
+    pub fn nametable_addr(&self) -> u16 {
+        0x2000 | (self.v & (NAMETABLE_X | NAMETABLE_Y))
+    }
+
+    /// The scroll set up for the next frame, in pixels, from t
+    pub fn scroll_x(&self) -> usize {
+        (self.t & COARSE_X) as usize * 8 + self.x as usize
+    }
+
+    pub fn scroll_y(&self) -> usize {
+        ((self.t & COARSE_Y) >> 5) as usize * 8 + ((self.t & FINE_Y) >> 12) as usize
+    }
+
+    /// The nametable set up for the next frame, from t
+    pub fn base_nametable_addr(&self) -> u16 {
+        0x2000 | (self.t & (NAMETABLE_X | NAMETABLE_Y))
     }
 }
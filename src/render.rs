@@ -1,6 +1,13 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
 use crate::frame::Frame;
-use crate::ppu::NesPPU;
-use crate::rom::Mirroring;
+use crate::ppu::{sprite_row, Layer, NesPPU};
+use crate::ppu_registers::Color;
+use crate::rom::Region;
 
 #[rustfmt::skip]
 pub static SYSTEM_PALLETE: [(u8,u8,u8); 64] = [
@@ -19,6 +26,244 @@ pub static SYSTEM_PALLETE: [(u8,u8,u8); 64] = [
    (0x99, 0xFF, 0xFC), (0xDD, 0xDD, 0xDD), (0x11, 0x11, 0x11), (0x11, 0x11, 0x11)
 ];
 
+/// The table a lot of early emulators shipped with: brighter and more
+/// saturated than a real TV shows
+#[rustfmt::skip]
+static CLASSIC_PALETTE: [(u8,u8,u8); 64] = [
+   (0x7C, 0x7C, 0x7C), (0x00, 0x00, 0xFC), (0x00, 0x00, 0xBC), (0x44, 0x28, 0xBC), (0x94, 0x00, 0x84),
+   (0xA8, 0x00, 0x20), (0xA8, 0x10, 0x00), (0x88, 0x14, 0x00), (0x50, 0x30, 0x00), (0x00, 0x78, 0x00),
+   (0x00, 0x68, 0x00), (0x00, 0x58, 0x00), (0x00, 0x40, 0x58), (0x00, 0x00, 0x00), (0x00, 0x00, 0x00),
+   (0x00, 0x00, 0x00), (0xBC, 0xBC, 0xBC), (0x00, 0x78, 0xF8), (0x00, 0x58, 0xF8), (0x68, 0x44, 0xFC),
+   (0xD8, 0x00, 0xCC), (0xE4, 0x00, 0x58), (0xF8, 0x38, 0x00), (0xE4, 0x5C, 0x10), (0xAC, 0x7C, 0x00),
+   (0x00, 0xB8, 0x00), (0x00, 0xA8, 0x00), (0x00, 0xA8, 0x44), (0x00, 0x88, 0x88), (0x00, 0x00, 0x00),
+   (0x00, 0x00, 0x00), (0x00, 0x00, 0x00), (0xF8, 0xF8, 0xF8), (0x3C, 0xBC, 0xFC), (0x68, 0x88, 0xFC),
+   (0x98, 0x78, 0xF8), (0xF8, 0x78, 0xF8), (0xF8, 0x58, 0x98), (0xF8, 0x78, 0x58), (0xFC, 0xA0, 0x44),
+   (0xF8, 0xB8, 0x00), (0xB8, 0xF8, 0x18), (0x58, 0xD8, 0x54), (0x58, 0xF8, 0x98), (0x00, 0xE8, 0xD8),
+   (0x78, 0x78, 0x78), (0x00, 0x00, 0x00), (0x00, 0x00, 0x00), (0xFC, 0xFC, 0xFC), (0xA4, 0xE4, 0xFC),
+   (0xB8, 0xB8, 0xF8), (0xD8, 0xB8, 0xF8), (0xF8, 0xB8, 0xF8), (0xF8, 0xA4, 0xC0), (0xF0, 0xD0, 0xB0),
+   (0xFC, 0xE0, 0xA8), (0xF8, 0xD8, 0x78), (0xD8, 0xF8, 0x78), (0xB8, 0xF8, 0xB8), (0xB8, 0xF8, 0xD8),
+   (0x00, 0xFC, 0xFC), (0xF8, 0xD8, 0xF8), (0x00, 0x00, 0x00), (0x00, 0x00, 0x00)
+];
+
+/// Composite output levels of the 2C02 in volts, from the nesdev wiki: the
+/// low and high level of each of the four brightness rows, then black and
+/// white
+const SIGNAL_LOW: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+const SIGNAL_HIGH: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+const SIGNAL_BLACK: f32 = 0.518;
+const SIGNAL_WHITE: f32 = 1.962;
+/// How much an emphasis bit weakens the signal while it's active
+const SIGNAL_EMPHASIS: f32 = 0.746;
+
+/// The colour an NTSC TV decodes for palette entry `entry` (0-511, with
+/// the emphasis bits above the six colour bits). The PPU puts out a square
+/// wave for each colour: its phase is the hue and its two levels give the
+/// brightness and saturation. Each emphasis bit weakens the wave over the
+/// half of the colour cycle furthest from the colour it emphasises.
+fn composite_color(entry: usize) -> (u8, u8, u8) {
+    let hue = entry & 0x0f;
+    let emphasis = entry >> 6;
+    // the black columns come out at the second level
+    let level = if hue > 13 { 1 } else { (entry >> 4) & 3 };
+    let (mut low, mut high) = (SIGNAL_LOW[level], SIGNAL_HIGH[level]);
+    if hue == 0 {
+        low = high;
+    } else if hue > 12 {
+        high = low;
+    }
+    let in_phase = |hue: usize, phase: usize| (hue + phase) % 12 < 6;
+
+    // demodulate one colour cycle of 12 samples. Hue 2 lies on the U axis.
+    let (mut y, mut u, mut v) = (0.0, 0.0, 0.0);
+    for phase in 0..12 {
+        let mut signal = if in_phase(hue, phase) { high } else { low };
+        let weakened = [0xc, 0x4, 0x8]
+            .iter()
+            .enumerate()
+            .any(|(bit, &against)| emphasis >> bit & 1 == 1 && in_phase(against, phase));
+        if weakened && hue < 0x0e {
+            signal *= SIGNAL_EMPHASIS;
+        }
+        let level = (signal - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK) / 12.0;
+        let angle = std::f32::consts::PI * (phase as f32 - 0.5) / 6.0;
+        y += level;
+        u += 2.0 * level * angle.cos();
+        v -= 2.0 * level * angle.sin();
+    }
+
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    (
+        channel(y + 1.140 * v),
+        channel(y - 0.395 * u - 0.581 * v),
+        channel(y + 2.032 * u),
+    )
+}
+
+/// The palettes that come built in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PalettePreset {
+    /// `SYSTEM_PALLETE`, which the emulator has always used
+    Default,
+    /// See `CLASSIC_PALETTE`
+    Classic,
+    /// Worked out from the PPU's composite signal the way a TV decodes it,
+    /// emphasis included
+    Composite,
+}
+
+impl PalettePreset {
+    pub const ALL: [PalettePreset; 3] = [
+        PalettePreset::Default,
+        PalettePreset::Classic,
+        PalettePreset::Composite,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PalettePreset::Default => "default",
+            PalettePreset::Classic => "classic",
+            PalettePreset::Composite => "composite",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<PalettePreset> {
+        PalettePreset::ALL
+            .into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Why a .pal file couldn't be loaded
+#[derive(Debug)]
+pub enum PaletteError {
+    /// Neither 64 nor 512 RGB triplets
+    BadSize(usize),
+    Io(io::Error),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteError::BadSize(size) => write!(
+                f,
+                "palette files hold 64 or 512 RGB colours ({} or {} bytes), not {} bytes",
+                64 * 3,
+                512 * 3,
+                size
+            ),
+            PaletteError::Io(err) => write!(f, "could not read palette: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PaletteError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PaletteError {
+    fn from(err: io::Error) -> Self {
+        PaletteError::Io(err)
+    }
+}
+
+/// The RGB colours the 64 palette entries show as. Dumps with 512 colours
+/// have the 64 again for each combination of emphasis bits, in PPUMASK bit
+/// order; with just 64 the emphasis is worked out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    colors: Vec<(u8, u8, u8)>,
+}
+
+impl Palette {
+    /// Reads a .pal file: RGB triplets, nothing else
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Palette, PaletteError> {
+        Palette::from_pal(&fs::read(path)?)
+    }
+
+    pub fn from_pal(bytes: &[u8]) -> Result<Palette, PaletteError> {
+        if bytes.len() != 64 * 3 && bytes.len() != 512 * 3 {
+            return Err(PaletteError::BadSize(bytes.len()));
+        }
+        let colors = bytes
+            .chunks_exact(3)
+            .map(|rgb| (rgb[0], rgb[1], rgb[2]))
+            .collect();
+        Ok(Palette { colors })
+    }
+
+    pub fn preset(preset: PalettePreset) -> Palette {
+        let colors = match preset {
+            PalettePreset::Default => SYSTEM_PALLETE.to_vec(),
+            PalettePreset::Classic => CLASSIC_PALETTE.to_vec(),
+            PalettePreset::Composite => (0..512).map(composite_color).collect(),
+        };
+        Palette { colors }
+    }
+
+    /// Whether the palette has its own colours for every emphasis setting
+    pub fn has_emphasis(&self) -> bool {
+        self.colors.len() == 512
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::preset(PalettePreset::Default)
+    }
+}
+
+/// How much each emphasis bit dims the channels it doesn't name, in 256ths
+const EMPHASIS_ATTENUATION: u16 = 209;
+
+/// The colour the TV shows for palette entry `color`, from the PPU's
+/// palette. Each emphasis bit in PPUMASK darkens the other two channels,
+/// and PAL consoles have the red and green bits the other way round. The
+/// black columns $xE and $xF are output without emphasis.
+pub fn rgb(ppu: &NesPPU, color: u8) -> (u8, u8, u8) {
+    let palette = ppu.palette();
+    if palette.has_emphasis() {
+        let emphasis = (ppu.mask.bits() >> 5) as usize;
+        return palette.colors[emphasis * 64 + color as usize];
+    }
+    let (r, g, b) = palette.colors[color as usize];
+    if color & 0x0e == 0x0e {
+        return (r, g, b);
+    }
+
+    // how many bits dim red, green and blue
+    let mut dimmed = [0; 3];
+    for emphasis in ppu.mask.emphasise() {
+        let channel = match (emphasis, ppu.region()) {
+            (Color::Red, Region::Ntsc) | (Color::Green, Region::Pal) => 0,
+            (Color::Green, Region::Ntsc) | (Color::Red, Region::Pal) => 1,
+            (Color::Blue, _) => 2,
+        };
+        for (other, count) in dimmed.iter_mut().enumerate() {
+            if other != channel {
+                *count += 1;
+            }
+        }
+    }
+    let dim = |value: u8, count: u32| {
+        (0..count).fold(value as u16, |value, _| value * EMPHASIS_ATTENUATION / 256) as u8
+    };
+    (dim(r, dimmed[0]), dim(g, dimmed[1]), dim(b, dimmed[2]))
+}
+
+/// Sets pixel (`x`, `y`) to palette entry `color` as the PPU puts it out
+/// right now, in RGB and, when the frame keeps them, as an index with the
+/// emphasis bits
+fn put_pixel(ppu: &NesPPU, frame: &mut Frame, x: usize, y: usize, color: u8) {
+    frame.set_pixel(x, y, rgb(ppu, color));
+    let emphasis = (ppu.mask.bits() >> 5) as u16;
+    frame.set_index(x, y, color as u16 | emphasis << 6);
+}
+
 fn bg_pallette(
     ppu: &NesPPU,
     attribute_table: &[u8],
@@ -38,10 +283,10 @@ fn bg_pallette(
 
     let pallete_start: usize = 1 + (pallet_idx as usize) * 4;
     [
-        ppu.palette_table[0],
-        ppu.palette_table[pallete_start],
-        ppu.palette_table[pallete_start + 1],
-        ppu.palette_table[pallete_start + 2],
+        ppu.palette_color(0),
+        ppu.palette_color(pallete_start),
+        ppu.palette_color(pallete_start + 1),
+        ppu.palette_color(pallete_start + 2),
     ]
 }
 
@@ -49,9 +294,9 @@ fn sprite_palette(ppu: &NesPPU, pallete_idx: u8) -> [u8; 4] {
     let start = 0x11 + (pallete_idx * 4) as usize;
     [
         0,
-        ppu.palette_table[start],
-        ppu.palette_table[start + 1],
-        ppu.palette_table[start + 2],
+        ppu.palette_color(start),
+        ppu.palette_color(start + 1),
+        ppu.palette_color(start + 2),
     ]
 }
 
@@ -84,13 +329,14 @@ fn render_name_table(
     let bank = ppu.ctrl.bknd_pattern_addr();
 
     let attribute_table = &name_table[0x3c0..0x400];
+    // a hidden background leaves just the backdrop
+    let hidden = !ppu.layer_visible(Layer::Background);
 
     for i in 0..0x3c0 {
         let tile_column = i % 32;
         let tile_row = i / 32;
         let tile_idx = name_table[i] as u16;
-        let tile =
-            &ppu.chr_rom[(bank + tile_idx * 16) as usize..=(bank + tile_idx * 16 + 15) as usize];
+        let tile = ppu.fetch_chr_tile(bank + tile_idx * 16);
         let palette = bg_pallette(ppu, attribute_table, tile_column, tile_row);
 
         for y in 0..=7 {
@@ -98,14 +344,18 @@ fn render_name_table(
             let mut lower = tile[y + 8];
 
             for x in (0..=7).rev() {
-                let value = (1 & lower) << 1 | (1 & upper);
+                let value = if hidden {
+                    0
+                } else {
+                    (1 & lower) << 1 | (1 & upper)
+                };
                 upper = upper >> 1;
                 lower = lower >> 1;
-                let rgb = match value {
-                    0 => SYSTEM_PALLETE[ppu.palette_table[0] as usize],
-                    1 => SYSTEM_PALLETE[palette[1] as usize],
-                    2 => SYSTEM_PALLETE[palette[2] as usize],
-                    3 => SYSTEM_PALLETE[palette[3] as usize],
+                let color = match value {
+                    0 => ppu.palette_color(0),
+                    1 => palette[1],
+                    2 => palette[2],
+                    3 => palette[3],
                     _ => panic!("can't be"),
                 };
                 let pixel_x = tile_column * 8 + x;
@@ -116,10 +366,12 @@ fn render_name_table(
                     && pixel_y >= view_port.y1
                     && pixel_y < view_port.y2
                 {
-                    frame.set_pixel(
+                    put_pixel(
+                        ppu,
+                        frame,
                         (shift_x + pixel_x as isize) as usize,
                         (shift_y + pixel_y as isize) as usize,
-                        rgb,
+                        color,
                     );
                 }
             }
@@ -128,22 +380,19 @@ fn render_name_table(
 }
 
 pub fn render(ppu: &NesPPU, frame: &mut Frame) {
-    let scroll_x = (ppu.scroll.scroll_x) as usize;
-    let scroll_y = (ppu.scroll.scroll_y) as usize;
-
-    let (main_nametable, second_nametable) = match (&ppu.mirroring, ppu.ctrl.nametable_addr()) {
-        (Mirroring::Vertical, 0x2000)
-        | (Mirroring::Vertical, 0x2800)
-        | (Mirroring::Horizontal, 0x2000)
-        | (Mirroring::Horizontal, 0x2400) => (&ppu.vram[0..0x400], &ppu.vram[0x400..0x800]),
-        (Mirroring::Vertical, 0x2400)
-        | (Mirroring::Vertical, 0x2C00)
-        | (Mirroring::Horizontal, 0x2800)
-        | (Mirroring::Horizontal, 0x2C00) => (&ppu.vram[0x400..0x800], &ppu.vram[0..0x400]),
-        (_, _) => {
-            panic!("Not supported mirroring type {:?}", ppu.mirroring);
-        }
+    let scroll_x = ppu.scroll.scroll_x();
+    let scroll_y = ppu.scroll.scroll_y();
+
+    // the nametable to the right shows when scrolled sideways, the one
+    // below when scrolled down; mirroring decides which ones those are
+    let main_addr = ppu.scroll.base_nametable_addr();
+    let second_addr = if scroll_x > 0 {
+        main_addr ^ 0x400
+    } else {
+        main_addr ^ 0x800
     };
+    let main_nametable = &ppu.nametable(main_addr);
+    let second_nametable = &ppu.nametable(second_addr);
 
     render_name_table(
         ppu,
@@ -173,10 +422,14 @@ pub fn render(ppu: &NesPPU, frame: &mut Frame) {
         );
     }
 
+    if !ppu.layer_visible(Layer::Sprites) {
+        return;
+    }
     for i in (0..ppu.oam_data.len()).step_by(4).rev() {
         let tile_idx = ppu.oam_data[i + 1] as u16;
         let tile_x = ppu.oam_data[i + 3] as usize;
-        let tile_y = ppu.oam_data[i] as usize;
+        // sprites start on the line after their OAM Y
+        let tile_y = ppu.oam_data[i] as usize + 1;
 
         let flip_vertical = if ppu.oam_data[i + 2] >> 7 & 1 == 1 {
             true
@@ -192,8 +445,7 @@ pub fn render(ppu: &NesPPU, frame: &mut Frame) {
         let sprite_palette = sprite_palette(ppu, pallette_idx);
         let bank: u16 = ppu.ctrl.sprt_pattern_addr();
 
-        let tile =
-            &ppu.chr_rom[(bank + tile_idx * 16) as usize..=(bank + tile_idx * 16 + 15) as usize];
+        let tile = ppu.fetch_chr_tile(bank + tile_idx * 16);
 
         for y in 0..=7 {
             let mut upper = tile[y];
@@ -202,28 +454,28 @@ pub fn render(ppu: &NesPPU, frame: &mut Frame) {
                 let value = (1 & lower) << 1 | (1 & upper);
                 upper = upper >> 1;
                 lower = lower >> 1;
-                let rgb = match value {
+                let color = match value {
                     0 => continue 'ololo, // skip coloring the pixel
-                    1 => SYSTEM_PALLETE[sprite_palette[1] as usize],
-                    2 => SYSTEM_PALLETE[sprite_palette[2] as usize],
-                    3 => SYSTEM_PALLETE[sprite_palette[3] as usize],
+                    1 => sprite_palette[1],
+                    2 => sprite_palette[2],
+                    3 => sprite_palette[3],
                     _ => panic!("can't be"),
                 };
                 match (flip_horizontal, flip_vertical) {
                     (false, false) => {
-                        frame.set_pixel(tile_x + x, tile_y + y, rgb);
+                        put_pixel(ppu, frame, tile_x + x, tile_y + y, color);
                         // frame.set_pixel(tile_x + x, tile_y + y +250, rgb);
                     }
                     (true, false) => {
-                        frame.set_pixel(tile_x + 7 - x, tile_y + y, rgb);
+                        put_pixel(ppu, frame, tile_x + 7 - x, tile_y + y, color);
                         // frame.set_pixel(tile_x + 7 - x , tile_y + y + 250, rgb);
                     }
                     (false, true) => {
-                        frame.set_pixel(tile_x + x, tile_y + 7 - y, rgb);
+                        put_pixel(ppu, frame, tile_x + x, tile_y + 7 - y, color);
                         // frame.set_pixel(tile_x + x, tile_y + 7 - y + 250, rgb);
                     }
                     (true, true) => {
-                        frame.set_pixel(tile_x + 7 - x, tile_y + 7 - y, rgb);
+                        put_pixel(ppu, frame, tile_x + 7 - x, tile_y + 7 - y, color);
                         // frame.set_pixel(tile_x + 7 - x, tile_y + 7 - y+250, rgb);
                     }
                 }
@@ -231,3 +483,135 @@ pub fn render(ppu: &NesPPU, frame: &mut Frame) {
         }
     }
 }
+
+/// Draws the pixels `xs` of line `y` of `frame` from the PPU's state as it
+/// is now: the backdrop, the background as currently scrolled, then the
+/// sprites that cover the line. A line is drawn in pieces when the game
+/// changes the registers partway through it.
+pub fn render_scanline(ppu: &NesPPU, y: usize, xs: Range<usize>, frame: &mut Frame) {
+    let backdrop = ppu.backdrop_color();
+    for x in xs.clone() {
+        put_pixel(ppu, frame, x, y, backdrop);
+    }
+    let mut background_opaque = [false; 256];
+    if ppu.mask.show_background() && ppu.layer_visible(Layer::Background) {
+        render_background_line(ppu, y, &xs, frame, &mut background_opaque);
+    }
+    if ppu.mask.show_sprites() && ppu.layer_visible(Layer::Sprites) {
+        render_sprite_line(ppu, y, &xs, frame, &background_opaque);
+    }
+}
+
+/// Draws the tiles from where v points, which is where the fetches for
+/// the line start, and marks the pixels they don't leave transparent.
+///
+/// After a $2006 write partway through the line, v points at the tile the
+/// next fetch gets, about two tiles past the dot of the write. From that
+/// tile on the line matches hardware. The pixels between the write and it
+/// show the two tiles before it in the new nametable, where the PPU still
+/// shows the two it had fetched already.
+fn render_background_line(
+    ppu: &NesPPU,
+    y: usize,
+    xs: &Range<usize>,
+    frame: &mut Frame,
+    opaque: &mut [bool; 256],
+) {
+    let bank = ppu.ctrl.bknd_pattern_addr();
+    let fine_x = ppu.scroll.x as usize;
+    let nametable = ppu.scroll.nametable_addr();
+    let tile_row = ppu.scroll.coarse_y();
+    let fine_y = ppu.scroll.fine_y() as u16;
+    // PPUMASK can blank the left column, which hides scrolling seams
+    let first_x = if ppu.mask.leftmost_8pxl_background() {
+        0
+    } else {
+        8
+    };
+    let xs = xs.start.max(first_x) as isize..xs.end as isize;
+
+    // fine X scroll can leave part of a 33rd tile showing
+    for i in 0..=32 {
+        let column = (ppu.scroll.coarse_x() + 64 + i - ppu.column_offset()) % 64;
+        // past the last column the fetches go on into the next nametable,
+        // and before the first they come from the one before
+        let (table, tile_column) = if column >= 32 {
+            (nametable ^ 0x400, column - 32)
+        } else {
+            (nametable, column)
+        };
+        let tile_x = (i * 8) as isize - fine_x as isize;
+        if tile_x + 8 <= xs.start || tile_x >= xs.end {
+            continue;
+        }
+        let tile_idx = ppu.read_nametable(table + (tile_row * 32 + tile_column) as u16);
+        let attr_byte =
+            ppu.read_nametable(table + 0x3c0 + (tile_row / 4 * 8 + tile_column / 4) as u16);
+        let pallet_idx = (attr_byte >> ((tile_row % 4 / 2) * 4 + (tile_column % 4 / 2) * 2)) & 0b11;
+        let (upper, lower) = ppu.fetch_chr_row(bank + tile_idx as u16 * 16, fine_y);
+
+        for bit in 0..8 {
+            let value = ((lower >> (7 - bit)) & 1) << 1 | ((upper >> (7 - bit)) & 1);
+            let pixel_x = tile_x + bit as isize;
+            if value == 0 || !xs.contains(&pixel_x) {
+                continue;
+            }
+            let color = ppu.palette_color((pallet_idx * 4 + value) as usize);
+            put_pixel(ppu, frame, pixel_x as usize, y, color);
+            opaque[pixel_x as usize] = true;
+        }
+    }
+}
+
+/// Draws the sprites on the line. At each pixel the opaque sprite first in
+/// OAM wins, and then its attribute bit 5 decides whether it shows or the
+/// background does. So a sprite behind the background still hides the
+/// sprites after it, which games use for masking.
+fn render_sprite_line(
+    ppu: &NesPPU,
+    y: usize,
+    xs: &Range<usize>,
+    frame: &mut Frame,
+    background_opaque: &[bool; 256],
+) {
+    let height = ppu.ctrl.sprite_size() as usize;
+    let first_x = if ppu.mask.leftmost_8pxl_sprite() {
+        0
+    } else {
+        8
+    };
+    let xs = xs.start.max(first_x)..xs.end;
+
+    let mut claimed = [false; 256];
+    for sprite in ppu.line_sprites() {
+        // OAM or the sprite size can change after evaluation
+        let Some(mut row) = sprite_row(sprite[0], y, height) else {
+            continue;
+        };
+        let flip_vertical = sprite[2] >> 7 & 1 == 1;
+        let flip_horizontal = sprite[2] >> 6 & 1 == 1;
+        let pallette_idx = (sprite[2] & 0b11) as usize;
+        let behind_background = sprite[2] >> 5 & 1 == 1;
+
+        if flip_vertical {
+            row = height - 1 - row;
+        }
+        let tile_addr = ppu.sprite_tile_addr(sprite, row);
+        let (upper, lower) = ppu.fetch_chr_row(tile_addr, (row % 8) as u16);
+
+        for bit in 0..8 {
+            let shift = if flip_horizontal { bit } else { 7 - bit };
+            let value = ((lower >> shift) & 1) << 1 | ((upper >> shift) & 1);
+            let pixel_x = sprite[3] as usize + bit;
+            if value == 0 || !xs.contains(&pixel_x) || claimed[pixel_x] {
+                continue;
+            }
+            claimed[pixel_x] = true;
+            if behind_background && background_opaque[pixel_x] {
+                continue;
+            }
+            let color = ppu.palette_color(0x10 + pallette_idx * 4 + value as usize);
+            put_pixel(ppu, frame, pixel_x, y, color);
+        }
+    }
+}
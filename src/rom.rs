@@ -1,12 +1,162 @@
+use crate::mapper;
+use crate::romdb;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
 const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
+const UNIF_TAG: [u8; 4] = *b"UNIF";
+const UNIF_HEADER_SIZE: usize = 32;
+const FDS_TAG: [u8; 4] = *b"FDS\x1A";
+const FDS_HEADER_SIZE: usize = 16;
+/// The disk info block every disk side starts with. Headerless .fds images
+/// start with it too.
+const FDS_DISK_INFO: &[u8] = b"\x01*NINTENDO-HVC*";
+/// What the disk system BIOS is expected to be called, next to the image
+const FDS_BIOS_NAME: &str = "disksys.rom";
 pub const PRG_ROM_PAGE_SIZE: usize = 16384;
 pub const CHR_ROM_PAGE_SIZE: usize = 8192;
+pub const PRG_RAM_PAGE_SIZE: usize = 8192;
+pub const TRAINER_SIZE: usize = 512;
+/// Bytes per disk side in .fds images, which leave out the gaps and CRCs
+pub const FDS_SIDE_SIZE: usize = 65500;
+pub const FDS_BIOS_SIZE: usize = 8192;
+/// iNES mapper number the Famicom Disk System goes by
+pub const FDS_MAPPER: u8 = 20;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mirroring {
     Vertical,
     Horizontal,
     FourScreen,
+    /// Every nametable shows the first 1 KiB of VRAM
+    SingleScreenLower,
+    /// Every nametable shows the second 1 KiB of VRAM
+    SingleScreenUpper,
+}
+
+/// The TV system a game was made for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+}
+
+impl Region {
+    /// Frames per second, as a numerator and a denominator. NTSC consoles
+    /// skip a dot on every other frame, which this averages in.
+    pub fn frame_rate(self) -> (u32, u32) {
+        match self {
+            // 236.25 / 11 MHz over 357366 master cycles a frame
+            Region::Ntsc => (39375000, 655171),
+            // 26.6017125 MHz over 531960 master cycles a frame
+            Region::Pal => (322445, 6448),
+        }
+    }
+
+    /// How wide a pixel shows on a TV for its height, as a numerator and a
+    /// denominator
+    pub fn pixel_aspect(self) -> (u32, u32) {
+        match self {
+            Region::Ntsc => (8, 7),
+            // about 1.386: 7.375 MHz square pixel sampling over PAL's
+            // 5.3203 MHz dot clock
+            Region::Pal => (2950000, 2128137),
+        }
+    }
+}
+
+/// UNIF board names with a mapper here, and the iNES mapper number it goes
+/// by. HVC- boards are looked up under their NES- names.
+const UNIF_BOARDS: &[(&str, u8)] = &[
+    ("NES-NROM", 0),
+    ("NES-NROM-128", 0),
+    ("NES-NROM-256", 0),
+    ("NES-RROM", 0),
+    ("NES-RROM-128", 0),
+    ("NES-UNROM", 2),
+    ("NES-UOROM", 2),
+    ("NES-PNROM", 9),
+    ("NES-PEEOROM", 9),
+    ("NES-FJROM", 10),
+    ("NES-FKROM", 10),
+    ("BANDAI-FCG-1", 16),
+    ("BANDAI-FCG-2", 16),
+    ("BANDAI-LZ93D50+24C02", 16),
+    ("BANDAI-LZ93D50+24C01", 159),
+    ("UNROM-512-8", 30),
+    ("UNROM-512-16", 30),
+    ("UNROM-512-32", 30),
+    ("NES-BNROM", 34),
+    ("IREM-BNROM", 34),
+    ("AVE-NINA-01", 34),
+    ("AVE-NINA-02", 34),
+    ("NES-DEROM", 206),
+    ("NES-DE1ROM", 206),
+    ("NES-DRROM", 206),
+];
+
+/// Why a ROM image couldn't be loaded
+#[derive(Debug)]
+pub enum RomError {
+    /// Neither an iNES, UNIF nor FDS image
+    BadMagic,
+    /// An iNES image with a NES 2.0 header
+    Nes2Header,
+    UnsupportedMapper(u8),
+    /// A UNIF image for a board there's no mapper for. Empty when the file
+    /// doesn't name one.
+    UnsupportedBoard(String),
+    /// The file ends before all the data its header promises
+    Truncated {
+        expected: usize,
+        actual: usize,
+    },
+    /// A Famicom Disk System image without the disk system BIOS to run it
+    MissingBios,
+    Io(io::Error),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::BadMagic => write!(f, "not an iNES, UNIF or FDS image"),
+            RomError::Nes2Header => write!(f, "NES 2.0 headers are not supported"),
+            RomError::UnsupportedMapper(mapper) => write!(f, "mapper {} is not supported", mapper),
+            RomError::UnsupportedBoard(board) if board.is_empty() => {
+                write!(f, "UNIF image doesn't name its board")
+            }
+            RomError::UnsupportedBoard(board) => write!(f, "UNIF board {} is not supported", board),
+            RomError::Truncated { expected, actual } => write!(
+                f,
+                "ROM image is truncated: expected {} bytes, found {}",
+                expected, actual
+            ),
+            RomError::MissingBios => write!(
+                f,
+                "disk images need the {} KiB disk system BIOS, as {} next to the image",
+                FDS_BIOS_SIZE / 1024,
+                FDS_BIOS_NAME
+            ),
+            RomError::Io(err) => write!(f, "could not read ROM image: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for RomError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RomError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RomError {
+    fn from(err: io::Error) -> Self {
+        RomError::Io(err)
+    }
 }
 
 #[derive(Debug)]
@@ -15,24 +165,81 @@ pub struct Rom {
     pub chr_rom: Vec<u8>,
     pub mapper: u8,
     pub screen_mirroring: Mirroring,
+    /// Bytes of work RAM at $6000-$7FFF
+    pub prg_ram_size: usize,
+    /// PRG-RAM is battery backed, so its contents outlive power-off
+    pub battery: bool,
+    pub region: Region,
+    /// 512 bytes some dumps carry for $7000-$71FF, left over from the
+    /// copier hardware they came from
+    pub trainer: Option<Vec<u8>>,
+    /// The disk sides of a Famicom Disk System image, in .fds layout. Empty
+    /// for cartridges. The disk system BIOS goes in `prg_rom`.
+    pub disk_sides: Vec<Vec<u8>>,
 }
 
 impl Rom {
-    pub fn new(raw: &Vec<u8>) -> Result<Rom, String> {
-        if &raw[0..4] != NES_TAG {
-            return Err("File is not in iNES file format".to_string());
+    /// Loads an iNES, UNIF or FDS image from disk. Disk images get the BIOS
+    /// from `disksys.rom` in the same directory, when there is one.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Rom, RomError> {
+        let path = path.as_ref();
+        let mut rom = Rom::new(&fs::read(path)?)?;
+        if rom.is_disk() {
+            if let Ok(bios) = fs::read(path.with_file_name(FDS_BIOS_NAME)) {
+                rom.prg_rom = bios;
+            }
         }
+        Ok(rom)
+    }
+
+    /// Parses an image. FDS images come without the BIOS, which has to be
+    /// put in `prg_rom` before the Bus can run them.
+    pub fn new(raw: &Vec<u8>) -> Result<Rom, RomError> {
+        let rom = if raw.starts_with(&UNIF_TAG) {
+            Rom::from_unif(raw)?
+        } else if raw.starts_with(&NES_TAG) {
+            Rom::from_ines(raw)?
+        } else if raw.starts_with(&FDS_TAG) || raw.starts_with(FDS_DISK_INFO) {
+            Rom::from_fds(raw)?
+        } else {
+            return Err(RomError::BadMagic);
+        };
+        if !mapper::is_supported(rom.mapper) {
+            return Err(RomError::UnsupportedMapper(rom.mapper));
+        }
+        Ok(rom)
+    }
+
+    /// Whether this is a Famicom Disk System image rather than a cartridge
+    pub fn is_disk(&self) -> bool {
+        !self.disk_sides.is_empty()
+    }
 
-        let mapper = (raw[7] & 0b1111_0000) | (raw[6] >> 4);
+    fn from_ines(raw: &[u8]) -> Result<Rom, RomError> {
+        if raw.len() < 0x10 {
+            return Err(RomError::Truncated {
+                expected: 0x10,
+                actual: raw.len(),
+            });
+        }
 
         let ines_ver = (raw[7] >> 2) & 0b11;
-        if ines_ver != 0 {
-            return Err("NES2.0 format is not supported".to_string());
+        if ines_ver == 2 {
+            return Err(RomError::Nes2Header);
         }
+        // Anything else there, or in the padding at 12-15, is text an old
+        // tool wrote over the header, like "DiskDude!". Only bytes 4-6 mean
+        // anything then.
+        let archaic = ines_ver != 0 || raw[12..16].iter().any(|&byte| byte != 0);
+        let header = if archaic { &[0; 16][..] } else { &raw[..16] };
+
+        let mapper = (header[7] & 0b1111_0000) | (raw[6] >> 4);
 
         let four_screen = raw[6] & 0b1000 != 0;
         let vertical_mirroring = raw[6] & 0b1 != 0;
         let screen_mirroring = match (four_screen, vertical_mirroring) {
+            // UNROM 512 uses both bits for one-screen mirroring
+            (true, false) if mapper == 30 => Mirroring::SingleScreenLower,
             (true, _) => Mirroring::FourScreen,
             (false, true) => Mirroring::Vertical,
             (false, false) => Mirroring::Horizontal,
@@ -41,22 +248,224 @@ impl Rom {
         let prg_rom_size = raw[4] as usize * PRG_ROM_PAGE_SIZE;
         let chr_rom_size = raw[5] as usize * CHR_ROM_PAGE_SIZE;
 
-        let skip_trainer = raw[6] & 0b100 != 0;
+        let has_trainer = raw[6] & 0b100 != 0;
+        let battery = raw[6] & 0b10 != 0;
+        // 0 means 8 KiB, for compatibility with older dumps
+        let prg_ram_size = header[8].max(1) as usize * PRG_RAM_PAGE_SIZE;
+        let region = if header[9] & 1 != 0 {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        };
 
-        let prg_rom_start = 0x10 + if skip_trainer { 512 } else { 0 };
+        let prg_rom_start = 0x10 + if has_trainer { TRAINER_SIZE } else { 0 };
         let chr_rom_start = prg_rom_start + prg_rom_size;
+        let expected = chr_rom_start + chr_rom_size;
+        if raw.len() < expected {
+            return Err(RomError::Truncated {
+                expected,
+                actual: raw.len(),
+            });
+        }
+        let trainer = has_trainer.then(|| raw[0x10..prg_rom_start].to_vec());
 
-        // println!("{} {} {} {} {}, {:?}", prg_rom_size, chr_rom_size, skip_trainer, prg_rom_start, chr_rom_start, raw);
+        // println!("{} {} {} {} {}, {:?}", prg_rom_size, chr_rom_size, has_trainer, prg_rom_start, chr_rom_start, raw);
 
-        let output = Rom {
+        let mut output = Rom {
             prg_rom: raw[prg_rom_start..(prg_rom_start + prg_rom_size)].to_vec(),
             chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
             mapper: mapper,
             screen_mirroring: screen_mirroring,
+            prg_ram_size,
+            battery,
+            region,
+            trainer,
+            disk_sides: vec![],
         };
+        output.apply_database();
 
         // println!("{:?}", output);
 
         Ok(output)
     }
+
+    /// Reads a UNIF image: a 32-byte header, then chunks of a four-letter
+    /// ID, a little-endian length and the data. The board name in MAPR
+    /// picks the mapper, and PRG0-PRGF and CHR0-CHRF are joined in order.
+    fn from_unif(raw: &[u8]) -> Result<Rom, RomError> {
+        let mut board = None;
+        let mut prg_chunks: [&[u8]; 16] = [&[]; 16];
+        let mut chr_chunks: [&[u8]; 16] = [&[]; 16];
+        let mut screen_mirroring = Mirroring::Horizontal;
+        let mut battery = false;
+
+        let mut pos = UNIF_HEADER_SIZE;
+        while pos + 8 <= raw.len() {
+            let id = &raw[pos..pos + 4];
+            let len = u32::from_le_bytes([raw[pos + 4], raw[pos + 5], raw[pos + 6], raw[pos + 7]])
+                as usize;
+            let data = raw.get(pos + 8..pos + 8 + len).ok_or(RomError::Truncated {
+                expected: pos + 8 + len,
+                actual: raw.len(),
+            })?;
+            pos += 8 + len;
+
+            // the last character of PRGn/CHRn chunks is a hex digit
+            let index = || (id[3] as char).to_digit(16).map(|n| n as usize);
+            match &id[..3] {
+                b"MAP" if id[3] == b'R' => {
+                    let name = data.split(|&b| b == 0).next().unwrap_or_default();
+                    board = Some(String::from_utf8_lossy(name).trim().to_string());
+                }
+                b"PRG" => {
+                    if let Some(n) = index() {
+                        prg_chunks[n] = data;
+                    }
+                }
+                b"CHR" => {
+                    if let Some(n) = index() {
+                        chr_chunks[n] = data;
+                    }
+                }
+                b"MIR" if id[3] == b'R' => {
+                    screen_mirroring = match data.first() {
+                        Some(1) => Mirroring::Vertical,
+                        Some(2) => Mirroring::SingleScreenLower,
+                        Some(3) => Mirroring::SingleScreenUpper,
+                        Some(4) => Mirroring::FourScreen,
+                        // 5 leaves it to the mapper, which starts out horizontal
+                        _ => Mirroring::Horizontal,
+                    };
+                }
+                b"BAT" if id[3] == b'R' => battery = true,
+                _ => {}
+            }
+        }
+
+        let board = board.unwrap_or_default();
+        let lookup = board.replace("HVC-", "NES-");
+        let mapper = UNIF_BOARDS
+            .iter()
+            .find(|(name, _)| *name == lookup)
+            .map(|&(_, mapper)| mapper)
+            .ok_or(RomError::UnsupportedBoard(board))?;
+
+        Ok(Rom {
+            prg_rom: prg_chunks.concat(),
+            chr_rom: chr_chunks.concat(),
+            mapper,
+            screen_mirroring,
+            prg_ram_size: PRG_RAM_PAGE_SIZE,
+            battery,
+            region: Region::Ntsc,
+            trainer: None,
+            disk_sides: vec![],
+        })
+    }
+
+    /// Reads an FDS image: an optional 16-byte header, then one 65500-byte
+    /// dump per disk side. The disk drive gets 32 KiB of RAM and 8 KiB of
+    /// CHR-RAM, and the disk itself is what's saved.
+    fn from_fds(raw: &[u8]) -> Result<Rom, RomError> {
+        let start = if raw.starts_with(&FDS_TAG) {
+            FDS_HEADER_SIZE
+        } else {
+            0
+        };
+        let disk_sides: Vec<Vec<u8>> = raw
+            .get(start..)
+            .unwrap_or_default()
+            .chunks_exact(FDS_SIDE_SIZE)
+            .map(|side| side.to_vec())
+            .collect();
+        if disk_sides.is_empty() {
+            return Err(RomError::Truncated {
+                expected: start + FDS_SIDE_SIZE,
+                actual: raw.len(),
+            });
+        }
+
+        Ok(Rom {
+            prg_rom: vec![],
+            chr_rom: vec![],
+            mapper: FDS_MAPPER,
+            screen_mirroring: Mirroring::Horizontal,
+            prg_ram_size: 0x8000,
+            battery: true,
+            region: Region::Ntsc,
+            trainer: None,
+            disk_sides,
+        })
+    }
+
+    /// Corrects the header fields the ROM database knows better, saying so
+    /// on stderr
+    fn apply_database(&mut self) {
+        let crc = romdb::crc32([&self.prg_rom[..], &self.chr_rom[..]]);
+        let Some(entry) = romdb::lookup(crc) else {
+            return;
+        };
+
+        let mut fixes = vec![];
+        if let Some(mapper) = entry.mapper.filter(|&mapper| mapper != self.mapper) {
+            fixes.push(format!("mapper {} -> {}", self.mapper, mapper));
+            self.mapper = mapper;
+        }
+        if let Some(mirroring) = entry.mirroring.filter(|&m| m != self.screen_mirroring) {
+            fixes.push(format!(
+                "mirroring {:?} -> {:?}",
+                self.screen_mirroring, mirroring
+            ));
+            self.screen_mirroring = mirroring;
+        }
+        if let Some(region) = entry.region.filter(|&region| region != self.region) {
+            fixes.push(format!("region {:?} -> {:?}", self.region, region));
+            self.region = region;
+        }
+        if !fixes.is_empty() {
+            eprintln!(
+                "{} (CRC32 {:08X}) has a bad header, using {}",
+                entry.name,
+                crc,
+                fixes.join(", ")
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NESTEST: &[u8] = include_bytes!("../roms/nestest.nes");
+
+    #[test]
+    fn database_corrects_a_bad_header() {
+        let mut raw = NESTEST.to_vec();
+        // mapper 1, vertical mirroring and PAL
+        raw[6] |= 0b0001_0001;
+        raw[9] = 1;
+        let rom = Rom::new(&raw).unwrap();
+        assert_eq!(rom.mapper, 0);
+        assert_eq!(rom.screen_mirroring, Mirroring::Horizontal);
+        assert_eq!(rom.region, Region::Ntsc);
+    }
+
+    #[test]
+    fn text_over_the_header_is_ignored() {
+        let mut raw = NESTEST.to_vec();
+        raw[7..16].copy_from_slice(b"DiskDude!");
+        let rom = Rom::new(&raw).unwrap();
+        assert_eq!(rom.mapper, 0);
+        assert_eq!(rom.region, Region::Ntsc);
+    }
+
+    #[test]
+    fn database_leaves_unknown_dumps_alone() {
+        let mut raw = NESTEST.to_vec();
+        raw[6] |= 0b1;
+        raw[0x10] ^= 0xFF;
+        let rom = Rom::new(&raw).unwrap();
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+    }
 }
@@ -0,0 +1,99 @@
+use crate::rom::{Mirroring, Region};
+
+/// What the database knows about one dump. Fields left `None` are trusted
+/// to the header.
+pub struct RomDbEntry {
+    /// CRC32 of PRG-ROM followed by CHR-ROM, without header or trainer
+    pub crc: u32,
+    pub name: &'static str,
+    pub mapper: Option<u8>,
+    pub mirroring: Option<Mirroring>,
+    pub region: Option<Region>,
+}
+
+/// Dumps whose header fields are known. Only entries that disagree with
+/// a header change anything, so listing good dumps is harmless.
+const ROM_DB: &[RomDbEntry] = &[
+    RomDbEntry {
+        crc: 0x3337_EC46,
+        name: "Super Mario Bros.",
+        mapper: Some(0),
+        mirroring: Some(Mirroring::Vertical),
+        region: Some(Region::Ntsc),
+    },
+    RomDbEntry {
+        crc: 0x3FE2_72FB,
+        name: "The Legend of Zelda",
+        mapper: Some(1),
+        // MMC1 switches the mirroring itself
+        mirroring: None,
+        region: Some(Region::Ntsc),
+    },
+    RomDbEntry {
+        crc: 0xA9BB_F44F,
+        name: "Pac-Man",
+        mapper: Some(0),
+        mirroring: Some(Mirroring::Horizontal),
+        region: Some(Region::Ntsc),
+    },
+    RomDbEntry {
+        crc: 0x158B_0388,
+        name: "nestest",
+        mapper: Some(0),
+        mirroring: Some(Mirroring::Horizontal),
+        region: Some(Region::Ntsc),
+    },
+    RomDbEntry {
+        crc: 0xB840_35A7,
+        name: "Alter Ego",
+        mapper: Some(0),
+        mirroring: Some(Mirroring::Vertical),
+        region: Some(Region::Ntsc),
+    },
+];
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+/// The table for the reflected CRC-32 polynomial used by zip and PNG
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = n as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[n] = crc;
+        n += 1;
+    }
+    table
+}
+
+/// CRC32 of `chunks` one after the other
+pub fn crc32<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> u32 {
+    let crc = chunks.into_iter().flatten().fold(!0u32, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    });
+    !crc
+}
+
+pub fn lookup(crc: u32) -> Option<&'static RomDbEntry> {
+    ROM_DB.iter().find(|entry| entry.crc == crc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32([&b"123456789"[..]]), 0xCBF4_3926);
+        assert_eq!(crc32([&b"1234"[..], &b"56789"[..]]), 0xCBF4_3926);
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Bytes per bank in FCEUX label file names and in `Bus::prg_bank`
+const PRG_BANK_SIZE: usize = 0x4000;
+const INES_HEADER_SIZE: usize = 16;
+
+/// Names for addresses, used to label trace and disassembly output.
+///
+/// Addresses in $8000-$FFFF mean different things depending on the PRG bank
+/// mapped there, so labels for them are usually tied to a bank. A label
+/// without a bank matches whatever is mapped.
+#[derive(Debug, Default)]
+pub struct Symbols {
+    labels: HashMap<(Option<u8>, u16), String>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Symbols {
+            labels: HashMap::new(),
+        }
+    }
+
+    /// Keeps the first name given to an address
+    pub fn insert(&mut self, bank: Option<u8>, addr: u16, name: &str) {
+        self.labels
+            .entry((bank, addr))
+            .or_insert_with(|| name.to_string());
+    }
+
+    /// Looks for a label in `bank` first, then for one without a bank
+    pub fn lookup(&self, bank: Option<u8>, addr: u16) -> Option<&str> {
+        bank.and_then(|bank| self.labels.get(&(Some(bank), addr)))
+            .or_else(|| self.labels.get(&(None, addr)))
+            .map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Loads an FCEUX `.nl` file. FCEUX picks the bank from the file name:
+    /// `game.nes.ram.nl` holds $0000-$7FFF and `game.nes.2.nl` holds PRG bank
+    /// 2 (in hex). Other names load without a bank.
+    pub fn load_nl_file(&mut self, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let bank = path
+            .file_stem()
+            .and_then(|stem| Path::new(stem).extension())
+            .and_then(|ext| u8::from_str_radix(&ext.to_string_lossy(), 16).ok());
+        self.load_nl(&text, bank)
+    }
+
+    /// Parses FCEUX label lines of the form `$C000#Reset#comment`. A size
+    /// after the address, as in `$0300/10#buffer#`, names every byte of the
+    /// array, the later ones as `buffer+1` and so on.
+    pub fn load_nl(&mut self, text: &str, bank: Option<u8>) -> Result<(), String> {
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = || format!("line {}: invalid label `{}`", index + 1, line);
+
+            let mut fields = line.splitn(3, '#');
+            let addr = fields.next().ok_or_else(error)?;
+            let name = fields.next().ok_or_else(error)?.trim();
+            if name.is_empty() {
+                continue;
+            }
+
+            let addr = addr.strip_prefix('$').ok_or_else(error)?;
+            let (addr, size) = match addr.split_once('/') {
+                Some((addr, size)) => (addr, u16::from_str_radix(size, 16).map_err(|_| error())?),
+                None => (addr, 1),
+            };
+            let addr = u16::from_str_radix(addr, 16).map_err(|_| error())?;
+
+            let bank = if addr >= 0x8000 { bank } else { None };
+            self.insert(bank, addr, name);
+            for i in 1..size {
+                self.insert(bank, addr.wrapping_add(i), &format!("{}+{}", name, i));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load_ca65_dbg_file(&mut self, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        self.load_ca65_dbg(&text)
+    }
+
+    /// Loads the labels from an ld65 debug file (`ld65 --dbgfile`). The bank
+    /// of a PRG-ROM label comes from where its segment sits in the output
+    /// file, assuming that file is the iNES image.
+    pub fn load_ca65_dbg(&mut self, text: &str) -> Result<(), String> {
+        // segment id -> (start address, offset in the ROM file)
+        let mut segments = HashMap::new();
+        let mut symbols = vec![];
+
+        for (index, line) in text.lines().enumerate() {
+            let Some((kind, attrs)) = line.split_once(char::is_whitespace) else {
+                continue;
+            };
+            let attrs = parse_dbg_attributes(attrs);
+            let number = |key: &str| -> Result<Option<usize>, String> {
+                attrs
+                    .get(key)
+                    .map(|value| parse_dbg_number(value))
+                    .transpose()
+                    .map_err(|_| format!("line {}: invalid `{}`", index + 1, key))
+            };
+
+            match kind {
+                "seg" => {
+                    if let (Some(id), Some(start)) = (number("id")?, number("start")?) {
+                        segments.insert(id, (start, number("ooffs")?));
+                    }
+                }
+                // labels only: equates are constants, imports are duplicates
+                "sym" if attrs.get("type") == Some(&"lab") => {
+                    if let (Some(name), Some(val)) = (attrs.get("name"), number("val")?) {
+                        symbols.push((name.trim_matches('"').to_string(), val, number("seg")?));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        for (name, val, seg) in symbols {
+            let addr = val as u16;
+            let bank = match seg.and_then(|seg| segments.get(&seg)) {
+                Some(&(start, Some(file_offset))) if addr >= 0x8000 => {
+                    let rom_offset = (file_offset + val)
+                        .checked_sub(start + INES_HEADER_SIZE)
+                        .ok_or_else(|| format!("label `{}` lies before PRG-ROM", name))?;
+                    Some((rom_offset / PRG_BANK_SIZE) as u8)
+                }
+                _ => None,
+            };
+            self.insert(bank, addr, &name);
+        }
+        Ok(())
+    }
+}
+
+/// Splits `id=0,name="reset",val=0xC000` into its key/value pairs
+fn parse_dbg_attributes(attrs: &str) -> HashMap<&str, &str> {
+    let mut result = HashMap::new();
+    let mut in_quotes = false;
+    let mut start = 0;
+    for (i, c) in attrs.char_indices().chain([(attrs.len(), ',')]) {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                if let Some((key, value)) = attrs[start..i].split_once('=') {
+                    result.insert(key.trim(), value.trim());
+                }
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    result
+}
+
+fn parse_dbg_number(value: &str) -> Result<usize, std::num::ParseIntError> {
+    match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+}
@@ -1,13 +1,29 @@
+pub mod apu;
+pub mod assembler;
 pub mod bus;
+pub mod call_stack;
+pub mod cheats;
+#[cfg(feature = "image")]
+pub mod clip;
+pub mod clock;
 pub mod core;
+pub mod coverage;
+pub mod debug_view;
+pub mod disasm;
+pub mod expansion;
+pub mod filter;
 pub mod frame;
 pub mod opcodes;
 pub mod ppu;
 pub mod ppu_registers;
 pub mod rom;
+pub mod romdb;
+pub mod symbols;
 pub mod trace;
 pub mod joypad;
+pub mod mapper;
 pub mod render;
+pub mod video;
 
 
 use bus::Bus;
@@ -124,6 +140,11 @@ fn main() {
     //load the game
     let bytes: Vec<u8> = std::fs::read("/home/adarsh/Adarsh_Data/Adarsh_Coding/nes_emulator/roms/Pac-Man.nes").unwrap();
     let rom = Rom::new(&bytes).unwrap();
+    if rom.chr_rom.is_empty() {
+        // CHR-RAM games only have tiles once they've written them
+        eprintln!("this game has CHR-RAM, there are no tiles to show before it runs");
+        std::process::exit(1);
+    }
 
     let right_bank = show_tile_bank(&rom.chr_rom, 1);
 
@@ -1,70 +1,87 @@
 use crate::core::*;
-use crate::opcodes;
 use crate::opcodes::*;
-use std::collections::HashMap;
 
-pub fn trace(cpu: &mut Cpu) -> String {
-    let ref opscodes: HashMap<u8, &'static opcodes::Opcode> = *opcodes::OPCODES_MAP;
-
-    let code = cpu.mem_read(cpu.program_counter);
-
-    let ops = match opscodes.get(&code) {
-        Some(s) => s,
-        None => panic!("Code 0x{:X} doesn't exist", code),
+/// Formats the instruction at PC the way the canonical nestest.log does, so
+/// the output can be diffed line by line against the golden log. Memory is
+/// only peeked, so tracing never disturbs registers with read side effects.
+///
+/// With symbols set on the CPU, operands show labels instead of addresses.
+pub fn trace(cpu: &Cpu) -> String {
+    let bus = cpu.bus();
+    let peek_u16 = |addr: u16| {
+        let lo = bus.peek(addr) as u16;
+        let hi = bus.peek(addr.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    };
+    let label = |addr: u16, hex: String| {
+        let bank = (addr >= 0x8000).then(|| bus.prg_bank(addr));
+        cpu.symbols()
+            .and_then(|symbols| symbols.lookup(bank, addr))
+            .map_or(hex, String::from)
     };
+    let zero_page = |addr: u8| label(addr as u16, format!("${:02X}", addr));
+    let absolute = |addr: u16| label(addr, format!("${:04X}", addr));
 
     let begin = cpu.program_counter;
+    let code = bus.peek(begin);
+    let ops = &OPCODE_TABLE[code as usize];
+
     let mut hex_dump = vec![];
     hex_dump.push(code);
 
-    let (mem_addr, stored_value) = match ops.mode {
-        AddressingMode::Immediate | AddressingMode::NoneAddressing => (0, 0),
-        _ => {
-            let addr = cpu.get_absolute_address(&ops.mode, begin + 1);
-            (addr, cpu.mem_read(addr))
-        }
+    let mem_addr = match ops.mode {
+        AddressingMode::Immediate
+        | AddressingMode::Accumulator
+        | AddressingMode::Relative
+        | AddressingMode::Indirect
+        | AddressingMode::NoneAddressing => 0,
+        _ => effective_address(cpu, &ops.mode, begin.wrapping_add(1)),
     };
+    let stored_value = bus.peek(mem_addr);
 
     let tmp = match ops.len {
-        1 => match ops.code {
-            0x0a | 0x4a | 0x2a | 0x6a => format!("A "),
+        1 => match ops.mode {
+            AddressingMode::Accumulator => String::from("A"),
             _ => String::from(""),
         },
         2 => {
-            let address: u8 = cpu.mem_read(begin + 1);
-            // let value = cpu.mem_read(address));
+            let address: u8 = bus.peek(begin.wrapping_add(1));
             hex_dump.push(address);
 
             match ops.mode {
-                AddressingMode::Immediate => format!("#${:02x}", address),
-                AddressingMode::ZeroPage => format!("${:02x} = {:02x}", mem_addr, stored_value),
+                AddressingMode::Immediate => format!("#${:02X}", address),
+                AddressingMode::ZeroPage => {
+                    format!("{} = {:02X}", zero_page(address), stored_value)
+                }
                 AddressingMode::ZeroPage_X => format!(
-                    "${:02x},X @ {:02x} = {:02x}",
-                    address, mem_addr, stored_value
+                    "{},X @ {:02X} = {:02X}",
+                    zero_page(address),
+                    mem_addr,
+                    stored_value
                 ),
                 AddressingMode::ZeroPage_Y => format!(
-                    "${:02x},Y @ {:02x} = {:02x}",
-                    address, mem_addr, stored_value
+                    "{},Y @ {:02X} = {:02X}",
+                    zero_page(address),
+                    mem_addr,
+                    stored_value
                 ),
                 AddressingMode::Indirect_X => format!(
-                    "(${:02x},X) @ {:02x} = {:04x} = {:02x}",
-                    address,
+                    "({},X) @ {:02X} = {:04X} = {:02X}",
+                    zero_page(address),
                     (address.wrapping_add(cpu.register_x)),
                     mem_addr,
                     stored_value
                 ),
                 AddressingMode::Indirect_Y => format!(
-                    "(${:02x}),Y = {:04x} @ {:04x} = {:02x}",
-                    address,
+                    "({}),Y = {:04X} @ {:04X} = {:02X}",
+                    zero_page(address),
                     (mem_addr.wrapping_sub(cpu.register_y as u16)),
                     mem_addr,
                     stored_value
                 ),
-                AddressingMode::NoneAddressing => {
-                    // assuming local jumps: BNE, BVS, etc....
-                    let address: usize =
-                        (begin as usize + 2).wrapping_add((address as i8) as usize);
-                    format!("${:04x}", address)
+                AddressingMode::Relative => {
+                    let address = begin.wrapping_add(2).wrapping_add((address as i8) as u16);
+                    absolute(address)
                 }
 
                 _ => panic!(
@@ -74,39 +91,46 @@ pub fn trace(cpu: &mut Cpu) -> String {
             }
         }
         3 => {
-            let address_lo = cpu.mem_read(begin + 1);
-            let address_hi = cpu.mem_read(begin + 2);
+            let address_lo = bus.peek(begin.wrapping_add(1));
+            let address_hi = bus.peek(begin.wrapping_add(2));
             hex_dump.push(address_lo);
             hex_dump.push(address_hi);
 
-            let address = cpu.mem_read_u16(begin + 1);
+            let address = peek_u16(begin.wrapping_add(1));
 
             match ops.mode {
-                AddressingMode::NoneAddressing => {
-                    if ops.code == 0x6c {
-                        //jmp indirect
-                        let jmp_addr = if address & 0x00FF == 0x00FF {
-                            let lo = cpu.mem_read(address);
-                            let hi = cpu.mem_read(address & 0xFF00);
-                            (hi as u16) << 8 | (lo as u16)
-                        } else {
-                            cpu.mem_read_u16(address)
-                        };
-
-                        // let jmp_addr = cpu.mem_read_u16(address);
-                        format!("(${:04x}) = {:04x}", address, jmp_addr)
+                AddressingMode::Indirect => {
+                    // with the 6502 page wrap bug
+                    let jmp_addr = if address & 0x00FF == 0x00FF {
+                        let lo = bus.peek(address);
+                        let hi = bus.peek(address & 0xFF00);
+                        (hi as u16) << 8 | (lo as u16)
                     } else {
-                        format!("${:04x}", address)
-                    }
+                        peek_u16(address)
+                    };
+
+                    format!("({}) = {:04X}", absolute(address), jmp_addr)
+                }
+                // jump targets are not data, so nestest prints no value for them
+                AddressingMode::Absolute
+                    if matches!(ops.mnemonic, Mnemonic::JMP | Mnemonic::JSR) =>
+                {
+                    absolute(address)
+                }
+                AddressingMode::Absolute => {
+                    format!("{} = {:02X}", absolute(address), stored_value)
                 }
-                AddressingMode::Absolute => format!("${:04x} = {:02x}", mem_addr, stored_value),
                 AddressingMode::Absolute_X => format!(
-                    "${:04x},X @ {:04x} = {:02x}",
-                    address, mem_addr, stored_value
+                    "{},X @ {:04X} = {:02X}",
+                    absolute(address),
+                    mem_addr,
+                    stored_value
                 ),
                 AddressingMode::Absolute_Y => format!(
-                    "${:04x},Y @ {:04x} = {:02x}",
-                    address, mem_addr, stored_value
+                    "{},Y @ {:04X} = {:02X}",
+                    absolute(address),
+                    mem_addr,
+                    stored_value
                 ),
                 _ => panic!(
                     "unexpected addressing mode {:?} has ops-len 3. code {:02x}",
@@ -119,16 +143,70 @@ pub fn trace(cpu: &mut Cpu) -> String {
 
     let hex_str = hex_dump
         .iter()
-        .map(|z| format!("{:02x}", z))
+        .map(|z| format!("{:02X}", z))
         .collect::<Vec<String>>()
         .join(" ");
-    let asm_str = format!("{:04x}  {:8} {: >4} {}", begin, hex_str, ops.mnemonic, tmp)
+    let mnemonic = format!(
+        "{}{}",
+        if ops.unofficial { "*" } else { "" },
+        nestest_name(ops.mnemonic)
+    );
+    let asm_str = format!("{:04X}  {:8} {: >4} {}", begin, hex_str, mnemonic, tmp)
         .trim()
         .to_string();
 
+    let ppu = bus.ppu();
     format!(
-        "{:47} A:{:02x} X:{:02x} Y:{:02x} P:{:02X} SP:{:02x}",
-        asm_str, cpu.register_a, cpu.register_x, cpu.register_y, cpu.status, cpu.stack_pointer,
+        "{:47} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:3},{:3} CYC:{}",
+        asm_str,
+        cpu.register_a,
+        cpu.register_x,
+        cpu.register_y,
+        cpu.status,
+        cpu.stack_pointer,
+        ppu.scanline,
+        ppu.dot(),
+        bus.cycles(),
     )
-    .to_ascii_uppercase()
-}
\ No newline at end of file
+}
+
+/// Resolves an operand address like the CPU does, without touching the bus
+fn effective_address(cpu: &Cpu, mode: &AddressingMode, addr: u16) -> u16 {
+    let bus = cpu.bus();
+    let peek_zp_u16 = |ptr: u8| {
+        let lo = bus.peek(ptr as u16) as u16;
+        let hi = bus.peek(ptr.wrapping_add(1) as u16) as u16;
+        (hi << 8) | lo
+    };
+    let peek_u16 = |addr: u16| {
+        let lo = bus.peek(addr) as u16;
+        let hi = bus.peek(addr.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    };
+
+    match mode {
+        AddressingMode::ZeroPage => bus.peek(addr) as u16,
+        AddressingMode::Absolute => peek_u16(addr),
+        AddressingMode::ZeroPage_X => bus.peek(addr).wrapping_add(cpu.register_x) as u16,
+        AddressingMode::ZeroPage_Y => bus.peek(addr).wrapping_add(cpu.register_y) as u16,
+        AddressingMode::Absolute_X => peek_u16(addr).wrapping_add(cpu.register_x as u16),
+        AddressingMode::Absolute_Y => peek_u16(addr).wrapping_add(cpu.register_y as u16),
+        AddressingMode::Indirect_X => peek_zp_u16(bus.peek(addr).wrapping_add(cpu.register_x)),
+        AddressingMode::Indirect_Y => {
+            peek_zp_u16(bus.peek(addr)).wrapping_add(cpu.register_y as u16)
+        }
+        AddressingMode::Immediate
+        | AddressingMode::Accumulator
+        | AddressingMode::Relative
+        | AddressingMode::Indirect
+        | AddressingMode::NoneAddressing => addr,
+    }
+}
+
+fn nestest_name(mnemonic: Mnemonic) -> &'static str {
+    match mnemonic {
+        Mnemonic::DOP | Mnemonic::TOP => "NOP",
+        Mnemonic::AAX => "SAX",
+        _ => mnemonic.name(),
+    }
+}
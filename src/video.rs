@@ -0,0 +1,201 @@
+// Dumping every frame, uncompressed, for ffmpeg or any other encoder to
+// pick up. Frames go out as they are drawn, so a dump can be piped
+// straight into an encoder. The frame rate is the console's exact one.
+//
+// The sound can go along in a WAV file. Its length is worked out from the
+// number of frames dumped, not counted on its own, so the two line up to
+// the sample however long the dump runs. It is silence for now: the APU
+// doesn't make any sound yet.
+
+use crate::frame::Frame;
+use crate::rom::Region;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Samples per second in an audio dump
+pub const SAMPLE_RATE: u32 = 48000;
+/// Bytes in a WAV header for plain PCM
+const WAV_HEADER_SIZE: u32 = 44;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoFormat {
+    /// YUV4MPEG2 with full resolution chroma. It carries its own size, frame
+    /// rate and pixel aspect, so `ffmpeg -i dump.y4m` needs nothing more.
+    Y4m,
+    /// Bare 256x240 RGB24 frames, exactly as drawn. The encoder has to be
+    /// told the geometry and rate, see `VideoDump::ffmpeg_input`.
+    Rgb,
+}
+
+impl VideoFormat {
+    /// Y4M for a .y4m file or for `-`, standard output, and raw RGB for
+    /// anything else
+    pub fn for_path(path: &str) -> VideoFormat {
+        if path == "-" || path.to_ascii_lowercase().ends_with(".y4m") {
+            VideoFormat::Y4m
+        } else {
+            VideoFormat::Rgb
+        }
+    }
+}
+
+pub struct VideoDump<W: Write> {
+    out: W,
+    format: VideoFormat,
+    /// Frames per second, as a numerator and a denominator
+    frame_rate: (u32, u32),
+    frames: u64,
+    /// A frame converted to planes, kept to save allocating one each time
+    planes: Vec<u8>,
+    audio: Option<AudioDump>,
+}
+
+impl<W: Write> VideoDump<W> {
+    /// Starts a dump at the frame rate of `region`. Y4M writes its header
+    /// here.
+    pub fn new(mut out: W, format: VideoFormat, region: Region) -> io::Result<Self> {
+        let frame_rate = region.frame_rate();
+        if format == VideoFormat::Y4m {
+            let aspect = region.pixel_aspect();
+            writeln!(
+                out,
+                "YUV4MPEG2 W{} H{} F{}:{} Ip A{}:{} C444",
+                Frame::WIDTH,
+                Frame::HIGHT,
+                frame_rate.0,
+                frame_rate.1,
+                aspect.0,
+                aspect.1
+            )?;
+        }
+        Ok(VideoDump {
+            out,
+            format,
+            frame_rate,
+            frames: 0,
+            planes: Vec::new(),
+            audio: None,
+        })
+    }
+
+    /// Writes the sound to `audio` from the next frame on, as many samples
+    /// each frame as the frame lasts
+    pub fn set_audio(&mut self, audio: AudioDump) {
+        self.audio = Some(audio);
+    }
+
+    pub fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        match self.format {
+            VideoFormat::Rgb => self.out.write_all(&frame.to_rgb24())?,
+            VideoFormat::Y4m => {
+                // BT.601 at TV levels, one plane after the other
+                let pixels = Frame::WIDTH * Frame::HIGHT;
+                self.planes.resize(pixels * 3, 0);
+                for (i, rgb) in frame.to_rgb24().chunks_exact(3).enumerate() {
+                    let (r, g, b) = (rgb[0] as i32, rgb[1] as i32, rgb[2] as i32);
+                    self.planes[i] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+                    self.planes[pixels + i] =
+                        (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+                    self.planes[2 * pixels + i] =
+                        (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+                }
+                self.out.write_all(b"FRAME\n")?;
+                self.out.write_all(&self.planes)?;
+            }
+        }
+        self.frames += 1;
+
+        if let Some(audio) = self.audio.as_mut() {
+            let (num, den) = self.frame_rate;
+            let samples = self.frames * SAMPLE_RATE as u64 * den as u64 / num as u64;
+            audio.write_silence(samples - audio.samples)?;
+        }
+        Ok(())
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// The ffmpeg options that describe the dump as an input, to go before
+    /// `-i`
+    pub fn ffmpeg_input(&self) -> String {
+        match self.format {
+            VideoFormat::Y4m => String::new(),
+            VideoFormat::Rgb => format!(
+                "-f rawvideo -pixel_format rgb24 -video_size {}x{} -framerate {}/{}",
+                Frame::WIDTH,
+                Frame::HIGHT,
+                self.frame_rate.0,
+                self.frame_rate.1
+            ),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let Some(audio) = self.audio.as_mut() {
+            audio.flush()?;
+        }
+        self.out.flush()
+    }
+}
+
+/// 16-bit mono PCM at `SAMPLE_RATE`, written as a `VideoDump` goes. The
+/// sizes in the header are filled in on `flush` and when the dump is
+/// dropped.
+pub struct AudioDump {
+    out: BufWriter<File>,
+    samples: u64,
+}
+
+impl AudioDump {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<AudioDump> {
+        let mut dump = AudioDump {
+            out: BufWriter::new(File::create(path)?),
+            samples: 0,
+        };
+        dump.write_header()?;
+        Ok(dump)
+    }
+
+    fn write_silence(&mut self, samples: u64) -> io::Result<()> {
+        for _ in 0..samples {
+            self.out.write_all(&0i16.to_le_bytes())?;
+        }
+        self.samples += samples;
+        Ok(())
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let data_size = (self.samples * 2).min((u32::MAX - WAV_HEADER_SIZE) as u64) as u32;
+        let out = &mut self.out;
+        out.write_all(b"RIFF")?;
+        out.write_all(&(data_size + WAV_HEADER_SIZE - 8).to_le_bytes())?;
+        out.write_all(b"WAVEfmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        // PCM, one channel
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        out.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?;
+        // bytes per sample, bits per sample
+        out.write_all(&2u16.to_le_bytes())?;
+        out.write_all(&16u16.to_le_bytes())?;
+        out.write_all(b"data")?;
+        out.write_all(&data_size.to_le_bytes())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()
+    }
+}
+
+impl Drop for AudioDump {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}